@@ -1,16 +1,40 @@
 //! Command line arguments and parameters management/parsing.
-use std::path::PathBuf;
+use std::{net::SocketAddr, path::PathBuf};
 
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "ungoliant", about = "corpus generation tool.")]
 /// Holds every command that is callable by the `oscar-tools` command.
+// parsed once, size does not matter
+#[allow(clippy::large_enum_variant)]
 pub enum Ungoliant {
     #[structopt(about = "Download a CommonCrawl release")]
     Download(Download),
     #[structopt(about = "Run pipeline")]
     Pipeline(Pipeline),
+    #[structopt(about = "Validate a wet.paths file, optionally rewriting it for a mirror.")]
+    Paths(Paths),
+    #[structopt(
+        about = "Merge pipeline outputs of several crawls, tagging documents with their crawl."
+    )]
+    Merge(Merge),
+    #[structopt(
+        about = "Produce detached signatures for archives and checksum manifests of a packaged corpus."
+    )]
+    Sign(Sign),
+    #[structopt(about = "Remove near-duplicate documents of a corpus using their TLSH hashes.")]
+    NearDedup(NearDedup),
+    #[structopt(
+        about = "Split documents of a corpus into paragraphs with their own language identification."
+    )]
+    Paragraphs(Paragraphs),
+    #[structopt(about = "Pretty-print the first or randomly sampled documents of a language.")]
+    Inspect(Inspect),
+    #[structopt(
+        about = "Report tokens per language and bytes per token on sampled documents of a corpus."
+    )]
+    TokStats(TokStats),
     // #[structopt(about = "Deduplicate a generated, not split corpus.")]
     // Dedup(Dedup),
     // #[structopt(about = "Split a not split corpus")]
@@ -108,29 +132,311 @@ pub struct Split {
 /// Downloading of CommonCrawl
 ///
 /// USAGE:
-///     ungoliant download [OPTIONS] <paths-file> <dst>
+///     ungoliant download [OPTIONS] [paths-file] <dst>
 ///
 /// FLAGS:
 ///     -h, --help       Prints help information
 ///     -V, --version    Prints version information
 ///
 /// OPTIONS:
-///     -t <n-tasks>        number of tokio tasks. Default is 4.
+///     -t, --download-jobs <n-tasks>        number of concurrent downloads. Default is 4.
 ///     -o <offset>         number of files to skip. Default is 0.
+///         --crawl <crawl>    crawl identifier (e.g. CC-MAIN-2023-50), whose wet.paths file is fetched.
+///         --retry-failed <retry-failed>    error log of a previous download, whose failed shards are downloaded again.
 ///
 /// ARGS:
 ///     <paths-file>    path to wet.paths file
 ///     <dst>           download destination
 /// ```
+#[structopt(setting = structopt::clap::AppSettings::AllowMissingPositional)]
 pub struct Download {
-    #[structopt(parse(from_os_str), help = "path to wet.paths file")]
-    pub paths_file: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        required_unless_one = &["crawl", "retry-failed"],
+        conflicts_with_all = &["crawl", "retry-failed"],
+        help = "path to wet.paths file"
+    )]
+    pub paths_file: Option<PathBuf>,
     #[structopt(parse(from_os_str), help = "download destination")]
     pub dst: PathBuf,
-    #[structopt(short = "t", help = "number of tokio tasks. Default is 4.")]
+    #[structopt(
+        short = "t",
+        long = "download-jobs",
+        help = "number of concurrent downloads (tokio tasks). Default is 4."
+    )]
     pub n_tasks: Option<usize>,
     #[structopt(short = "o", help = "number of files to skip. Default is 0.")]
     pub offset: Option<usize>,
+    #[structopt(
+        long = "crawl",
+        help = "Crawl identifier (e.g. CC-MAIN-2023-50) to download instead of a wet.paths file. Its wet.paths file is fetched and cached in the destination."
+    )]
+    pub crawl: Option<String>,
+    #[structopt(
+        parse(from_os_str),
+        long = "retry-failed",
+        conflicts_with = "crawl",
+        help = "Error log (errors.jsonl) of a previous download, whose failed shards are downloaded again instead of a wet.paths file."
+    )]
+    pub retry_failed: Option<PathBuf>,
+    #[structopt(
+        long = "segments",
+        help = "Optional range of segments to download (e.g. 0..100), segments being numbered by appearance in the wet.paths file."
+    )]
+    pub segments: Option<String>,
+    #[structopt(
+        long = "paths-regex",
+        help = "Optional regular expression selecting the paths of the wet.paths file to download."
+    )]
+    pub paths_regex: Option<String>,
+    #[structopt(
+        long = "max-attempts",
+        default_value = "5",
+        help = "Maximum number of attempts per file, including the first one."
+    )]
+    pub max_attempts: u32,
+    #[structopt(
+        long = "backoff",
+        default_value = "1",
+        help = "Base delay between attempts, in seconds. Doubled after each attempt, with jitter."
+    )]
+    pub backoff: f64,
+    #[structopt(
+        long = "max-backoff",
+        default_value = "60",
+        help = "Maximum delay between attempts, in seconds."
+    )]
+    pub max_backoff: f64,
+    #[structopt(
+        long = "retry-on",
+        help = "HTTP status to retry on (default: 429, 500, 502, 503, 504). Can be repeated."
+    )]
+    pub retry_on: Vec<u16>,
+    #[structopt(
+        long = "max-bandwidth",
+        help = "Optional maximum aggregate download throughput, in bytes per second (e.g. 10M)."
+    )]
+    pub max_bandwidth: Option<String>,
+    #[structopt(
+        long = "source",
+        default_value = "https",
+        help = "Where to download from: https (CommonCrawl gateway) or s3 (requester-pays commoncrawl bucket, requires the s3 feature)."
+    )]
+    pub source: String,
+    #[structopt(
+        long = "chunk-size",
+        help = "Optional chunk size (e.g. 16M): files are downloaded in chunks over parallel connections, and verified. HTTPS source only."
+    )]
+    pub chunk_size: Option<String>,
+    #[structopt(
+        long = "chunk-jobs",
+        default_value = "4",
+        help = "Number of chunks of a file downloaded concurrently, with --chunk-size."
+    )]
+    pub chunk_jobs: usize,
+    #[structopt(
+        long = "mirror",
+        help = "Base URL of a mirror of data.commoncrawl.org, tried when a download fails. Can be repeated."
+    )]
+    pub mirrors: Vec<String>,
+    #[structopt(
+        long = "proxy",
+        env = "UNGOLIANT_PROXY",
+        help = "Proxy of all requests (e.g. http://proxy.example.org:3128). HTTPS_PROXY/HTTP_PROXY are used otherwise."
+    )]
+    pub proxy: Option<String>,
+    #[structopt(
+        long = "proxy-auth",
+        env = "UNGOLIANT_PROXY_AUTH",
+        hide_env_values = true,
+        help = "Proxy credentials, as user:password."
+    )]
+    pub proxy_auth: Option<String>,
+    #[structopt(
+        parse(from_os_str),
+        long = "ca-bundle",
+        env = "UNGOLIANT_CA_BUNDLE",
+        help = "PEM file of additional root certificates to trust (e.g. of a TLS-intercepting proxy)."
+    )]
+    pub ca_bundle: Option<PathBuf>,
+    #[structopt(
+        long = "progress-every",
+        default_value = "10",
+        help = "Period of progress logging (bytes, shards, throughput and ETA), in seconds. 0 disables it."
+    )]
+    pub progress_every: u64,
+    #[structopt(
+        long = "verify",
+        help = "Verify the gzip stream of downloaded shards, moving corrupt ones to the quarantine directory and listing them in errors.jsonl."
+    )]
+    pub verify: bool,
+    #[structopt(
+        parse(from_os_str),
+        long = "quarantine",
+        help = "Quarantine directory of corrupt shards, with --verify. Default is <dst>/quarantine."
+    )]
+    pub quarantine: Option<PathBuf>,
+    #[structopt(
+        long = "sync",
+        help = "Only download shards that are missing from the destination or have another size than the remote ones."
+    )]
+    pub sync: bool,
+    #[structopt(
+        long = "max-disk",
+        help = "Optional maximum size of the destination (e.g. 500G). Downloads pause until other processes delete shards."
+    )]
+    pub max_disk: Option<String>,
+    #[structopt(
+        long = "min-free",
+        help = "Optional space to keep free on the destination filesystem (e.g. 10G). Downloads pause until other processes free space."
+    )]
+    pub min_free: Option<String>,
+    #[structopt(
+        parse(from_os_str),
+        long = "manifest",
+        help = "Optional JSONL manifest of downloaded shards (url, size, SHA-256, date and HTTP headers)."
+    )]
+    pub manifest: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+/// Token statistics command and parameters.
+pub struct TokStats {
+    #[structopt(parse(from_os_str), help = "corpus location")]
+    pub src: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        long = "tokenizer",
+        help = "HuggingFace tokenizer file (tokenizer.json)."
+    )]
+    pub tokenizer: PathBuf,
+    #[structopt(
+        long = "sample-rate",
+        default_value = "0.01",
+        help = "Probability for a document to be tokenized."
+    )]
+    pub sample_rate: f64,
+    #[structopt(long = "seed", default_value = "0", help = "Sampling seed.")]
+    pub seed: u64,
+    #[structopt(
+        parse(from_os_str),
+        long = "dst",
+        help = "Optional JSON report destination."
+    )]
+    pub dst: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+/// Near-duplicate removal command and parameters.
+pub struct NearDedup {
+    #[structopt(parse(from_os_str), help = "source corpus location")]
+    pub src: PathBuf,
+    #[structopt(parse(from_os_str), help = "destination corpus location")]
+    pub dst: PathBuf,
+    #[structopt(
+        long = "threshold",
+        default_value = "40",
+        help = "Maximum TLSH distance between near-duplicates."
+    )]
+    pub threshold: usize,
+    #[structopt(
+        long = "clusters",
+        help = "Write <lang>_clusters.jsonl files, mapping kept documents to their removed near-duplicates."
+    )]
+    pub clusters: bool,
+}
+
+#[derive(Debug, StructOpt)]
+/// Paragraph segmentation command and parameters.
+pub struct Paragraphs {
+    #[structopt(parse(from_os_str), help = "source corpus location")]
+    pub src: PathBuf,
+    #[structopt(parse(from_os_str), help = "paragraphs destination location")]
+    pub dst: PathBuf,
+    #[structopt(
+        long = "multilingual-only",
+        help = "Only write paragraphs of documents having paragraphs in several languages."
+    )]
+    pub multilingual_only: bool,
+}
+
+#[derive(Debug, StructOpt)]
+/// Corpus inspection command and parameters.
+pub struct Inspect {
+    #[structopt(parse(from_os_str), help = "corpus location")]
+    pub src: PathBuf,
+    #[structopt(help = "language of the documents (e.g. en)")]
+    pub lang: String,
+    #[structopt(
+        short = "n",
+        long = "count",
+        default_value = "10",
+        help = "Number of documents to print."
+    )]
+    pub count: usize,
+    #[structopt(
+        long = "random",
+        help = "Sample documents randomly instead of taking the first ones."
+    )]
+    pub random: bool,
+    #[structopt(long = "seed", default_value = "0", help = "Sampling seed.")]
+    pub seed: u64,
+    #[structopt(
+        long = "max-chars",
+        default_value = "500",
+        help = "Maximum number of printed content characters per document."
+    )]
+    pub max_chars: usize,
+    #[structopt(long = "no-color", help = "Disable colored output.")]
+    pub no_color: bool,
+}
+
+#[derive(Debug, StructOpt)]
+/// Release signing command and parameters.
+pub struct Sign {
+    #[structopt(parse(from_os_str), help = "packaged corpus location")]
+    pub src: PathBuf,
+    #[structopt(
+        long = "tool",
+        default_value = "gpg",
+        help = "Signing tool (minisign or gpg). It has to be installed."
+    )]
+    pub tool: String,
+    #[structopt(
+        long = "key",
+        help = "Secret key file for minisign (required), key id for gpg (default key otherwise)."
+    )]
+    pub key: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+/// Multi-crawl merge command and parameters.
+pub struct Merge {
+    #[structopt(parse(from_os_str), help = "merged corpus destination")]
+    pub dst: PathBuf,
+    #[structopt(
+        long = "crawl",
+        help = "Pipeline output to merge, as <crawl id>=<folder> (e.g. CC-MAIN-2023-06=out/2023-06). Can be repeated."
+    )]
+    pub crawls: Vec<String>,
+}
+
+#[derive(Debug, StructOpt)]
+/// wet.paths validation command and parameters.
+pub struct Paths {
+    #[structopt(parse(from_os_str), help = "path to wet.paths file")]
+    pub src: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        long = "dst",
+        help = "Optional destination of the normalized (valid, deduplicated) paths."
+    )]
+    pub dst: Option<PathBuf>,
+    #[structopt(
+        long = "mirror",
+        help = "Optional base URL to rewrite paths to (e.g. https://data.commoncrawl.org/). Paths are kept relative otherwise."
+    )]
+    pub mirror: Option<String>,
 }
 
 #[derive(Debug, StructOpt)]
@@ -153,17 +459,109 @@ pub struct Download {
 ///     <dst>    pipeline result destination
 /// ```
 pub struct Pipeline {
-    #[structopt(parse(from_os_str), help = "source (contains n.txt.gz)")]
+    #[structopt(
+        parse(from_os_str),
+        help = "source: folder containing n.txt.gz shards, single shard, tar archive of shards (.tar, .tar.gz, .tgz) or glob pattern"
+    )]
     pub src: PathBuf,
     #[structopt(parse(from_os_str), help = "pipeline result destination")]
     pub dst: PathBuf,
     #[structopt(
         parse(from_os_str),
         long = "lid-path",
-        help = "Path to 176.lid.bin",
+        help = "Path to the fastText language identification model (e.g. lid.176.bin, GlotLID or in-house models), or to the ONNX model directory.",
         default_value = "lid.176.bin"
     )]
     pub lid_path: PathBuf,
+    #[structopt(
+        long = "lid-threshold",
+        default_value = "0.8",
+        help = "Minimum probability of line identifications."
+    )]
+    pub lid_threshold: f32,
+    #[structopt(
+        long = "lang-threshold",
+        help = "Minimum probability of line identifications for a given language, as <lang>=<threshold> (e.g. en=0.9). Can be repeated."
+    )]
+    pub lang_threshold: Vec<String>,
+    #[structopt(
+        long = "lid-k",
+        default_value = "1",
+        help = "Number of fastText predictions per line."
+    )]
+    pub lid_k: i32,
+    #[structopt(
+        long = "lid-candidates",
+        help = "Add the confidence of the k most present languages of documents to their quality signals (lid_candidate:<lang> in the quality-signals header)."
+    )]
+    pub lid_candidates: Option<usize>,
+    #[structopt(
+        long = "lid-granularity",
+        default_value = "line",
+        help = "Identify documents by line, paragraph (lines between blank lines) or document. Other granularities than line are recorded as lid_granularity:<granularity>."
+    )]
+    pub lid_granularity: String,
+    #[structopt(
+        long = "label-prefix",
+        help = "Prefix of fastText model labels (default is __label__)."
+    )]
+    pub label_prefix: Option<String>,
+    #[structopt(
+        long = "model-kind",
+        default_value = "lid176",
+        help = "Label format of the fastText model: lid176 (e.g. __label__en) or glotlid/nllb (e.g. __label__eng_Latn, default scripts being dropped)."
+    )]
+    pub model_kind: String,
+    #[structopt(
+        long = "identifier",
+        help = "Language identifier: fasttext (model at --lid-path, default), whatlang (default without the fasttext feature), cld3, lingua or onnx (model directory at --lid-path), which need the corresponding feature."
+    )]
+    pub identifier: Option<String>,
+    #[structopt(
+        long = "onnx-batch-size",
+        default_value = "32",
+        help = "Number of lines identified at once by the onnx identifier."
+    )]
+    pub onnx_batch_size: usize,
+    #[structopt(
+        long = "onnx-threads",
+        default_value = "4",
+        help = "Number of threads used by the onnx identifier to identify a batch."
+    )]
+    pub onnx_threads: usize,
+    #[structopt(
+        long = "onnx-sessions",
+        default_value = "1",
+        help = "Number of onnx model sessions, i.e. of batches identified in parallel."
+    )]
+    pub onnx_sessions: usize,
+    #[structopt(
+        long = "fallback-identifier",
+        help = "Identify lines again with this identifier when --identifier is unsure about them (e.g. lingua for short lines)."
+    )]
+    pub fallback_identifier: Option<String>,
+    #[structopt(
+        long = "fallback-below",
+        default_value = "0.8",
+        help = "Use the fallback identifier on lines identified with a lower probability."
+    )]
+    pub fallback_below: f32,
+    #[structopt(
+        long = "ensemble",
+        help = "Identify lines with several identifiers, as <identifier> or <identifier>=<weight> (e.g. fasttext=2). Can be repeated, overrides --identifier."
+    )]
+    pub ensemble: Vec<String>,
+    #[structopt(
+        long = "ensemble-combination",
+        default_value = "vote",
+        help = "Combination of --ensemble predictions: agreement (identifiers have to agree), vote (weighted vote) or cascade[=<min_prob>] (first identifier reaching min_prob, 0.8 by default)."
+    )]
+    pub ensemble_combination: String,
+    #[structopt(
+        long = "lid-cache",
+        help = "Cache the identifications of this many lines, so that repeated lines (boilerplate, cookie banners) are identified once."
+    )]
+    pub lid_cache: Option<usize>,
     #[structopt(
         parse(from_os_str),
         long = "blocklist-path",
@@ -184,4 +582,499 @@ pub struct Pipeline {
         help = "Optional path to kenlm folder. for the language xx, you have to have a xx.binary file."
     )]
     pub kenlms_path: Option<PathBuf>,
+
+    #[structopt(
+        parse(from_os_str),
+        long = "geoip-db",
+        help = "Optional path to a MaxMind-format (Country, City or ASN) database. Can be repeated. Adds country:xx/asn:n annotations from WARC-IP-Address."
+    )]
+    pub geoip_dbs: Vec<PathBuf>,
+
+    #[structopt(
+        long = "remove-repeated-paragraphs",
+        help = "Collapse paragraphs repeated at least this number of times inside a document. Removed lines are recorded in rebuild files, so that rebuilt corpora don't have them either."
+    )]
+    pub repeated_paragraphs: Option<usize>,
+
+    #[structopt(
+        long = "compression-ratio",
+        help = "Annotate documents with an extreme deflate compression ratio as repetitive/incompressible."
+    )]
+    pub compression_ratio: bool,
+
+    #[structopt(
+        long = "min-compression-ratio",
+        help = "Compression ratio under which documents are annotated as repetitive.",
+        default_value = "0.2"
+    )]
+    pub min_compression_ratio: f64,
+
+    #[structopt(
+        long = "max-compression-ratio",
+        help = "Compression ratio over which documents are annotated as incompressible.",
+        default_value = "0.7"
+    )]
+    pub max_compression_ratio: f64,
+
+    #[structopt(
+        long = "readability",
+        help = "Add the mean word length and type-token ratio of documents to their quality signals (quality-signals header), rather than to annotations."
+    )]
+    pub readability: bool,
+
+    #[structopt(
+        long = "ttr-window",
+        help = "Number of words of the type-token ratio sliding window.",
+        default_value = "100"
+    )]
+    pub ttr_window: usize,
+
+    #[structopt(
+        long = "code-detection",
+        help = "Annotate documents dominated by source code as code:<language> (or code if the language can't be guessed)."
+    )]
+    pub code_detection: bool,
+
+    #[structopt(
+        long = "min-code-ratio",
+        help = "Proportion of code lines over which documents are annotated as code.",
+        default_value = "0.5"
+    )]
+    pub min_code_ratio: f64,
+
+    #[structopt(
+        long = "math-detection",
+        help = "Annotate documents dominated by LaTeX/MathML markup or math symbols as math:latex, math:mathml or math:symbols."
+    )]
+    pub math_detection: bool,
+
+    #[structopt(
+        long = "min-math-density",
+        help = "Number of math tokens per word over which documents are annotated as math.",
+        default_value = "0.1"
+    )]
+    pub min_math_density: f64,
+
+    #[structopt(
+        parse(from_os_str),
+        long = "harmful-model",
+        help = "Classify document content with this adult/harmful content model (fastText .bin file, or ONNX model directory with the onnx feature), adding harmful_score:<label> to quality signals and annotating harmful:<label> for labels scoring at least --harmful-min-score."
+    )]
+    pub harmful_model: Option<PathBuf>,
+
+    #[structopt(
+        long = "harmful-min-score",
+        help = "Score over which documents are annotated as harmful:<label>.",
+        default_value = "0.5"
+    )]
+    pub harmful_min_score: f32,
+
+    #[structopt(
+        long = "harmful-negative-label",
+        help = "Label of the harmful content model meaning that content isn't harmful.",
+        default_value = "safe"
+    )]
+    pub harmful_negative_label: String,
+
+    #[structopt(
+        parse(from_os_str),
+        long = "register-model",
+        help = "Classify documents by register/genre (news, forum, legal…) with this model (fastText .bin file, or ONNX model directory with the onnx feature), annotating register:<label> and adding its score to quality signals."
+    )]
+    pub register_model: Option<PathBuf>,
+
+    #[structopt(
+        long = "register-min-score",
+        help = "Score over which the most probable register of documents is annotated.",
+        default_value = "0.5"
+    )]
+    pub register_min_score: f32,
+
+    #[structopt(
+        long = "script-detection",
+        help = "Annotate documents with their dominant script (script:Latn, script:Cyrl, script:Hans…)."
+    )]
+    pub script_detection: bool,
+
+    #[structopt(
+        long = "script-subtags",
+        help = "Add the detected script to the language of documents in languages written in several scripts (e.g. sr-Cyrl/sr-Latn, zh-Hans/zh-Hant). Implies --script-detection."
+    )]
+    pub script_subtags: bool,
+
+    #[structopt(
+        long = "min-line-length",
+        help = "Minimum number of unicode codepoints for a line to be considered valid. Consider lowering it for CJK languages.",
+        default_value = "100"
+    )]
+    pub min_line_length: usize,
+
+    #[structopt(
+        long = "min-alphabetic-ratio",
+        help = "Optional minimum ratio of alphabetic (any script) characters for a line to be considered valid."
+    )]
+    pub min_alphabetic_ratio: Option<f32>,
+
+    #[structopt(
+        long = "max-url-density",
+        help = "Optional maximum ratio of characters in URLs for a line to be considered valid."
+    )]
+    pub max_url_density: Option<f32>,
+
+    #[structopt(
+        long = "shard-stats",
+        help = "Write per-shard statistics (record/language/error counts, timings) in <dst>/stats/<shard_id>.json"
+    )]
+    pub shard_stats: bool,
+
+    #[structopt(
+        long = "tui",
+        help = "Show a terminal dashboard of the run. Logs are written to ungoliant.log. Requires the tui feature."
+    )]
+    pub tui: bool,
+
+    #[structopt(
+        long = "status-addr",
+        help = "Optional address (e.g. 0.0.0.0:8080) to serve a status page (/) and a JSON endpoint (/status.json) on. No authentication is done."
+    )]
+    pub status_addr: Option<SocketAddr>,
+
+    #[structopt(
+        long = "webhook",
+        help = "Optional URL to POST a JSON run summary to on completion, failure, error budget breach and milestones. Can be repeated."
+    )]
+    pub webhooks: Vec<String>,
+
+    #[structopt(
+        long = "error-budget",
+        help = "Notify webhooks once when the number of errors and failed shards exceeds this."
+    )]
+    pub error_budget: Option<usize>,
+
+    #[structopt(
+        long = "milestone-every",
+        help = "Notify webhooks every n processed shards."
+    )]
+    pub milestone_every: Option<usize>,
+
+    #[structopt(
+        long = "no-retry",
+        help = "Do not retry failed shards at the end of the run."
+    )]
+    pub no_retry: bool,
+
+    #[structopt(
+        parse(from_os_str),
+        long = "lang-registry",
+        help = "Optional path to a JSON language registry (label mappings, per-language thresholds, part sizes and compressions)."
+    )]
+    pub lang_registry: Option<PathBuf>,
+
+    #[structopt(
+        parse(from_os_str),
+        long = "calibration",
+        help = "Optional path to a JSON calibration (temperature or isotonic) applied to line identification probabilities before thresholding."
+    )]
+    pub calibration: Option<PathBuf>,
+
+    #[structopt(
+        long = "annotation-policy",
+        help = "What to do with documents holding an annotation, as <annotation>=<keep|annotate|drop> (e.g. noisy=drop). Can be repeated."
+    )]
+    pub annotation_policy: Vec<String>,
+
+    #[structopt(
+        long = "annotated-tree",
+        help = "Write annotated documents in a separate annotated/ folder, keeping the main one free of flagged content."
+    )]
+    pub annotated_tree: bool,
+
+    #[structopt(
+        long = "annotated-tree-on",
+        help = "Annotation type that routes documents to the annotated/ folder (default: quality flags, i.e. tiny, noisy, short_sentences, header, footer, repetitive, incompressible, repeated_paragraphs, adult, harmful, code and math, but not informational annotations such as country or script). Can be repeated."
+    )]
+    pub annotated_tree_on: Vec<String>,
+
+    #[structopt(
+        long = "unknown-sink",
+        help = "Write documents without a confident identification in an unknown/ folder, identified as their most present language (und if none), rather than dropping them."
+    )]
+    pub unknown_sink: bool,
+
+    #[structopt(
+        long = "category-split",
+        help = "Additionally write documents in categories/<lang>/<category>/ for each of their UT1 categories (requires --blocklist-path)."
+    )]
+    pub category_split: bool,
+
+    #[structopt(
+        long = "flush-every",
+        default_value = "1",
+        help = "Flush rebuild files every n shards written to a given language."
+    )]
+    pub flush_every: usize,
+
+    #[structopt(
+        long = "fsync",
+        help = "Sync output files to disk at each flush and at the end of the run."
+    )]
+    pub fsync: bool,
+
+    #[structopt(
+        long = "flush-interval",
+        help = "Also flush rebuild files of all languages (and sync output files with --fsync) every n seconds, checked between shards, bounding what is lost if the node is preempted."
+    )]
+    pub flush_interval: Option<u64>,
+
+    #[structopt(
+        long = "fsync-parts",
+        help = "Sync parts of text/metadata files to disk when they're finished, before renaming them, so that complete parts are durable."
+    )]
+    pub fsync_parts: bool,
+
+    #[structopt(
+        long = "pre-dedup",
+        help = "Skip records whose payload digest or normalized URI was already seen during the run, before classification."
+    )]
+    pub pre_dedup: bool,
+
+    #[structopt(
+        long = "checkpoint-interval",
+        help = "Record written shards and writer positions in <dst>/checkpoint.json every n seconds (or suffixed by s/m/h/d), checked between shards, so that the run can be resumed with --resume."
+    )]
+    pub checkpoint_interval: Option<String>,
+
+    #[structopt(
+        long = "resume",
+        help = "Resume a crashed or preempted run from <dst>/checkpoint.json: written shards are skipped and parts are truncated to their recorded positions. Checkpoints are then taken every 5 minutes unless --checkpoint-interval is set."
+    )]
+    pub resume: bool,
+
+    #[structopt(
+        long = "max-duration",
+        help = "Stop processing new shards after this duration (seconds, or suffixed by s/m/h/d). Unprocessed shards are listed in <dst>/remaining_shards.txt."
+    )]
+    pub max_duration: Option<String>,
+
+    #[structopt(
+        long = "max-output-bytes",
+        help = "Stop processing new shards once the documents written during the run reach this size (compressed bytes of JSONL/WARC parts, or suffixed by K/M/G/T). Unprocessed shards are listed in <dst>/remaining_shards.txt."
+    )]
+    pub max_output_bytes: Option<String>,
+
+    #[structopt(
+        long = "hash",
+        default_value = "blake3",
+        help = "Hash algorithm for dedup keys: xxh64 (fast, may collide at crawl scale) or blake3 (collision-safe)."
+    )]
+    pub hash: String,
+
+    #[structopt(
+        long = "discarded",
+        help = "Write discarded records in <dst>/discarded/<reason>.jsonl, with their content (documents) or without (metadata)."
+    )]
+    pub discarded: Option<String>,
+
+    #[structopt(
+        parse(from_os_str),
+        long = "src",
+        help = "Additional source (folder, shard, tar archive or glob pattern), merged with <src>. Can be repeated."
+    )]
+    pub srcs: Vec<PathBuf>,
+
+    #[structopt(
+        parse(from_os_str),
+        long = "stream-from",
+        help = "wet.paths file whose shards are streamed and processed without being stored, in addition to <src>."
+    )]
+    pub stream_from: Option<PathBuf>,
+
+    #[structopt(
+        long = "stream-base-url",
+        default_value = "https://data.commoncrawl.org/",
+        help = "Base URL of relative paths given with --stream-from (e.g. a mirror)."
+    )]
+    pub stream_base_url: String,
+
+    #[structopt(
+        long = "segments",
+        help = "Optional range of segments to stream with --stream-from (e.g. 0..100), segments being numbered by appearance in the wet.paths file."
+    )]
+    pub segments: Option<String>,
+
+    #[structopt(
+        long = "paths-regex",
+        help = "Optional regular expression selecting the paths to stream with --stream-from."
+    )]
+    pub paths_regex: Option<String>,
+
+    #[structopt(
+        parse(from_os_str),
+        long = "records",
+        help = "Only process records listed in this file (one WARC-Record-ID or tab-separated URI and digest per line)."
+    )]
+    pub records: Option<PathBuf>,
+
+    #[structopt(
+        parse(from_os_str),
+        long = "records-index",
+        help = "Rebuild folder of a previous run, used to only open shards holding the records given with --records."
+    )]
+    pub records_index: Option<PathBuf>,
+
+    #[structopt(
+        parse(from_os_str),
+        long = "domain-allowlist",
+        help = "Only process records whose domain (or a parent domain) is listed in this file, one domain per line."
+    )]
+    pub domain_allowlist: Option<PathBuf>,
+
+    #[structopt(
+        long = "langs-include",
+        help = "Only keep documents identified in this language or one of its variants (e.g. sr also keeps sr-Cyrl). Can be repeated."
+    )]
+    pub langs_include: Vec<String>,
+
+    #[structopt(
+        long = "langs-exclude",
+        help = "Discard documents identified in this language or one of its variants. Can be repeated."
+    )]
+    pub langs_exclude: Vec<String>,
+
+    #[structopt(
+        long = "max-open-writers",
+        help = "Keep at most this many text/metadata files open, closing least recently used ones and reopening them when needed. Use if runs hit the open files limit."
+    )]
+    pub max_open_writers: Option<usize>,
+
+    #[structopt(
+        parse(from_os_str),
+        long = "field-mapping",
+        help = "Optional path to a JSON object renaming (\"content\": \"text\") or omitting (\"warc_headers\": null) output fields. Nested fields are separated by dots."
+    )]
+    pub field_mapping: Option<PathBuf>,
+
+    #[structopt(
+        long = "output-format",
+        default_value = "jsonl",
+        help = "Format of written documents: jsonl (<lang>_meta.jsonl files), warc (<lang>_meta.warc files of conversion records, keeping the original WARC headers), arrow (<lang>/ datasets of Arrow IPC files, needs the arrow feature) or parquet (<lang>/ datasets, needs the parquet feature)."
+    )]
+    pub output_format: String,
+
+    #[structopt(
+        long = "row-group-size",
+        default_value = "10000",
+        help = "Number of documents of Parquet row groups."
+    )]
+    pub row_group_size: usize,
+
+    #[structopt(
+        long = "compression",
+        default_value = "none",
+        help = "Compression of JSONL files: none, zstd (<lang>_meta.jsonl.zst files) gzip (multistream <lang>_meta.jsonl.gz files, with a <lang>_meta.jsonl.gz.idx index of members) or xz (<lang>_meta.jsonl.xz files)."
+    )]
+    pub compression: String,
+
+    #[structopt(
+        long = "compression-level",
+        help = "Compression level: 1-22 for zstd (defaults to 3, negative levels are faster), 0-9 for gzip and xz (defaults to 6)."
+    )]
+    pub compression_level: Option<i32>,
+
+    #[structopt(
+        long = "gzip-member-docs",
+        default_value = "1000",
+        help = "Maximum number of documents of gzip members."
+    )]
+    pub gzip_member_docs: usize,
+
+    #[structopt(
+        long = "xz-threads",
+        default_value = "1",
+        help = "Number of threads encoding each xz stream. Languages are already written concurrently, so more threads only help when few languages are written at once."
+    )]
+    pub xz_threads: u32,
+
+    #[structopt(
+        long = "layout",
+        default_value = "flat",
+        help = "Layout of JSONL files: flat (<lang>_meta.jsonl files in dst) or per-language (<lang>/<lang>_part_<n>.jsonl files, with a <lang>/meta.json summary of parts)."
+    )]
+    pub layout: String,
+
+    #[structopt(
+        long = "filename-template",
+        help = "Name JSONL parts from a template relative to dst, e.g. {lang}/{crawl}_{lang}_{part:05}.{ext}. Variables are {lang}, {part} (or {part:0N} to zero-pad it to N digits), {crawl} and {ext}. Can't be combined with --layout."
+    )]
+    pub filename_template: Option<String>,
+
+    #[structopt(
+        long = "crawl",
+        help = "Crawl identifier (e.g. CC-MAIN-2023-50), used for {crawl} in filename templates."
+    )]
+    pub crawl: Option<String>,
+
+    #[structopt(
+        long = "append",
+        help = "Resume writing after the JSONL parts of a previous run in dst, in new parts, instead of overwriting them."
+    )]
+    pub append: bool,
+
+    #[structopt(
+        long = "writer-shards",
+        default_value = "1",
+        help = "Write each language with this number of concurrent writers, numbering their parts from a shared counter, so that threads writing high-resource languages don't wait for each other. Parts are always numbered when greater than 1. Can't be combined with --append."
+    )]
+    pub writer_shards: usize,
+
+    #[structopt(
+        long = "max-shards",
+        help = "Only process the first n selected shards (e.g. for smoke tests)."
+    )]
+    pub max_shards: Option<usize>,
+
+    #[structopt(
+        long = "max-records",
+        help = "Only process the first n records of each shard (e.g. for smoke tests)."
+    )]
+    pub max_records: Option<usize>,
+
+    #[structopt(
+        long = "text-meta-dst",
+        parse(from_os_str),
+        help = "Also write kept documents in the legacy text/metadata layout (<lang>.txt and <lang>_meta.jsonl files) in this folder, in the same pass."
+    )]
+    pub text_meta_dst: Option<PathBuf>,
+
+    #[structopt(
+        long = "part-size",
+        help = "Split JSONL files in parts once they reach this size (bytes, or suffixed by K/M/G/T), checked between writes."
+    )]
+    pub part_size: Option<String>,
+
+    #[structopt(
+        long = "part-docs",
+        help = "Split JSONL files in parts of at most this number of documents. Can be combined with --part-size."
+    )]
+    pub part_docs: Option<usize>,
+
+    #[structopt(
+        long = "upload-to",
+        help = "Upload JSONL files under an object store URL (s3://bucket/prefix, gs://bucket/prefix or az://container/prefix) instead of writing them in dst. Needs the object-store feature."
+    )]
+    pub upload_to: Option<String>,
+
+    #[structopt(
+        long = "upload-retries",
+        default_value = "10",
+        help = "Maximum number of retries of failed object store requests."
+    )]
+    pub upload_retries: usize,
+
+    #[structopt(
+        long = "warc-headers",
+        default_value = "default",
+        help = "WARC headers kept in documents: default (URI, date, record id and digests), all, or a comma-separated list of header names."
+    )]
+    pub warc_headers: String,
 }
@@ -0,0 +1,166 @@
+//! S3 downloading.
+//!
+//! Downloads CommonCrawl shards from the `commoncrawl` S3 bucket, which is much faster than the HTTPS gateway from EC2.
+//! `wet.paths` entries are used as object keys, and requests are made as requester-pays requests.
+//!
+//! Credentials and region are taken from the environment (see [aws_config]), the region defaulting to `us-east-1`.
+//! Like HTTPS downloads, interrupted downloads are resumed using ranged requests.
+//! Objects of a crawl don't change, so no ETag is kept.
+//!
+//! Only available with the `s3` feature.
+use std::path::{Path, PathBuf};
+
+use aws_config::{meta::region::RegionProviderChain, retry::RetryConfig, BehaviorVersion};
+use aws_sdk_s3::{
+    error::{DisplayErrorContext, SdkError},
+    operation::get_object::GetObjectOutput,
+    types::RequestPayer,
+    Client,
+};
+use tokio::io::AsyncWriteExt;
+
+use super::{check_len, invalid_data, parse_content_range, Error, Progress, Throttle};
+
+/// CommonCrawl bucket.
+pub const BUCKET: &str = "commoncrawl";
+
+/// Downloader of objects of a bucket.
+pub struct S3Client {
+    client: Client,
+    bucket: String,
+}
+
+impl S3Client {
+    /// Create a client for `bucket`, the SDK attempting requests at most `max_attempts` times.
+    pub async fn new(bucket: &str, max_attempts: u32) -> Self {
+        let region = RegionProviderChain::default_provider().or_else("us-east-1");
+        let config = aws_config::defaults(BehaviorVersion::latest())
+            .region(region)
+            .retry_config(RetryConfig::standard().with_max_attempts(max_attempts.max(1)))
+            .load()
+            .await;
+        Self {
+            client: Client::new(&config),
+            bucket: bucket.to_string(),
+        }
+    }
+
+    /// get the object from byte `start`.
+    ///
+    /// Returns [None] if the object ends before `start`.
+    async fn get(&self, key: &str, start: u64) -> Result<Option<GetObjectOutput>, Error> {
+        debug!("getting s3://{}/{} from byte {}", self.bucket, key, start);
+        let mut request = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .request_payer(RequestPayer::Requester);
+        if start > 0 {
+            request = request.range(format!("bytes={}-", start));
+        }
+        match request.send().await {
+            Ok(resp) => Ok(Some(resp)),
+            Err(SdkError::ServiceError(e)) if e.raw().status().as_u16() == 416 => Ok(None),
+            Err(e) => Err(Error::S3(format!(
+                "s3://{}/{}: {}",
+                self.bucket,
+                key,
+                DisplayErrorContext(&e)
+            ))),
+        }
+    }
+
+    /// get the size of the object.
+    pub(super) async fn size(&self, key: &str) -> Result<Option<u64>, Error> {
+        let head = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .request_payer(RequestPayer::Requester)
+            .send()
+            .await
+            .map_err(|e| {
+                Error::S3(format!(
+                    "s3://{}/{}: {}",
+                    self.bucket,
+                    key,
+                    DisplayErrorContext(&e)
+                ))
+            })?;
+        Ok(head.content_length().map(|len| len as u64))
+    }
+
+    /// download the object and save it to provided destination.
+    ///
+    /// If the destination already exists, the download is resumed from its end.
+    pub async fn save_to(
+        &self,
+        key: &str,
+        dst: &Path,
+        throttle: Option<&Throttle>,
+        progress: Option<&Progress>,
+    ) -> Result<PathBuf, Error> {
+        let local_len = match tokio::fs::metadata(dst).await {
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        };
+
+        let resp = match self.get(key, local_len).await? {
+            Some(resp) => resp,
+            // nothing after the end of the local file
+            None => {
+                if self.size(key).await? == Some(local_len) {
+                    info!("{:?} already downloaded", dst);
+                    return Ok(PathBuf::from(dst));
+                }
+                warn!("{:?} does not match remote object, restarting", dst);
+                self.get(key, 0)
+                    .await?
+                    .ok_or_else(|| invalid_data(format!("could not get {}", key)))?
+            }
+        };
+
+        // resume if the rest of the object has been sent, restart otherwise.
+        let (mut file, expected_len) = match resp.content_range() {
+            Some(range) => {
+                let (start, total) = parse_content_range(range)
+                    .ok_or_else(|| invalid_data(format!("invalid Content-Range for {}", key)))?;
+                if start != local_len {
+                    return Err(invalid_data(format!(
+                        "{} resumed at byte {} instead of {}",
+                        key, start, local_len
+                    )));
+                }
+                info!("resuming {:?} at byte {}", dst, local_len);
+                let file = tokio::fs::OpenOptions::new().append(true).open(dst).await?;
+                (file, Some(total))
+            }
+            None => (
+                tokio::fs::File::create(dst).await?,
+                resp.content_length().map(|len| len as u64),
+            ),
+        };
+        if let Some(progress) = progress {
+            progress.started(dst, file.metadata().await?.len(), expected_len);
+        }
+
+        // copy bytes from response to file
+        let mut body = resp.body;
+        while let Some(chunk) = body.try_next().await.map_err(std::io::Error::other)? {
+            if let Some(throttle) = throttle {
+                throttle.consume(chunk.len()).await;
+            }
+            file.write_all(&chunk).await?;
+            if let Some(progress) = progress {
+                progress.received(dst, chunk.len());
+            }
+        }
+        file.flush().await?;
+        check_len(dst, file.metadata().await?.len(), expected_len)?;
+
+        info!("saved to {:?}", dst);
+        Ok(PathBuf::from(dst))
+    }
+}
@@ -24,6 +24,23 @@ pub enum Error {
     Avro(avro_rs::Error),
     Csv(csv::Error),
     OscarIo(oscar_io::Error),
+    MaxMind(maxminddb::MaxMindDBError),
+    Url(url::ParseError),
+    Http(reqwest::Error),
+}
+
+#[cfg(not(tarpaulin_include))]
+impl From<reqwest::Error> for Error {
+    fn from(v: reqwest::Error) -> Self {
+        Self::Http(v)
+    }
+}
+
+#[cfg(not(tarpaulin_include))]
+impl From<url::ParseError> for Error {
+    fn from(v: url::ParseError) -> Self {
+        Self::Url(v)
+    }
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -33,6 +50,13 @@ impl From<oscar_io::Error> for Error {
     }
 }
 
+#[cfg(not(tarpaulin_include))]
+impl From<maxminddb::MaxMindDBError> for Error {
+    fn from(v: maxminddb::MaxMindDBError) -> Self {
+        Self::MaxMind(v)
+    }
+}
+
 #[cfg(not(tarpaulin_include))]
 impl From<LanguageTagParseError> for Error {
     fn from(v: LanguageTagParseError) -> Self {
@@ -0,0 +1,128 @@
+/*! Domain allowlist
+
+Only keeps records whose domain is allowlisted, in order to build curated-source corpora (news-only, gov-only…)
+from the same shards.
+
+Domains are listed in a file, one per line (`lemonde.fr`, `gov.uk`…). Empty lines and lines starting with `#` are ignored.
+
+A record is kept if the host of its `WARC-Target-URI` is an allowlisted domain or one of its subdomains:
+`lemonde.fr` keeps `www.lemonde.fr` and `lemonde.fr`, and `gov.uk` keeps every `*.gov.uk` website.
+Matching is done on whole labels, so `lemonde.fr` does not keep `notlemonde.fr`.
+
+Records without a valid URI are discarded.
+!*/
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use log::info;
+use url::Url;
+use warc::{BufferedBody, Record, WarcHeader};
+
+use crate::error::Error;
+
+use super::Filter;
+
+#[derive(Debug, Default)]
+pub struct DomainAllowlist {
+    domains: HashSet<String>,
+}
+
+impl DomainAllowlist {
+    /// Load an allowlist from a file.
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Load an allowlist from a reader.
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, Error> {
+        let mut allowlist = Self::default();
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // accept wildcard notations, since subdomains are always allowed
+            let domain = line.trim_start_matches("*.").trim_matches('.');
+            allowlist.domains.insert(domain.to_lowercase());
+        }
+
+        info!("Allowlisted {} domains", allowlist.domains.len());
+        Ok(allowlist)
+    }
+
+    /// Returns true if `host` or one of its parent domains is allowlisted.
+    pub fn allows(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_lowercase();
+        let mut domain = host.as_str();
+        loop {
+            if self.domains.contains(domain) {
+                return true;
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) => domain = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+impl Filter<&Record<BufferedBody>> for DomainAllowlist {
+    /// Returns true if the record's domain is allowlisted.
+    fn detect(&self, record: &Record<BufferedBody>) -> bool {
+        record
+            .header(WarcHeader::TargetURI)
+            .and_then(|uri| Url::parse(&uri).ok())
+            .and_then(|url| url.host_str().map(|host| self.allows(host)))
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use warc::{BufferedBody, Record, WarcHeader};
+
+    use crate::filtering::Filter;
+
+    use super::DomainAllowlist;
+
+    fn allowlist() -> DomainAllowlist {
+        let content = "# news\nlemonde.fr\n\n*.gov.uk\nExample.COM.\n";
+        DomainAllowlist::from_reader(content.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_allows() {
+        let allowlist = allowlist();
+        assert!(allowlist.allows("lemonde.fr"));
+        assert!(allowlist.allows("www.lemonde.fr"));
+        assert!(allowlist.allows("www.LEMONDE.fr."));
+        assert!(!allowlist.allows("notlemonde.fr"));
+        assert!(!allowlist.allows("fr"));
+        assert!(allowlist.allows("www.ons.gov.uk"));
+        assert!(!allowlist.allows("gov.fr"));
+        assert!(allowlist.allows("example.com"));
+    }
+
+    #[test]
+    fn test_detect() {
+        let allowlist = allowlist();
+        let record = |uri: Option<&str>| {
+            let mut record: Record<BufferedBody> = Record::default().add_body("");
+            if let Some(uri) = uri {
+                record.set_header(WarcHeader::TargetURI, uri).unwrap();
+            }
+            record
+        };
+
+        assert!(allowlist.detect(&record(Some("https://www.lemonde.fr/politique/"))));
+        assert!(!allowlist.detect(&record(Some("https://example.org/"))));
+        assert!(!allowlist.detect(&record(Some("not a url"))));
+        assert!(!allowlist.detect(&record(None)));
+    }
+}
@@ -0,0 +1,305 @@
+/*! Annotation policies
+
+Annotations only tag documents. An [AnnotationPolicy] decides, per annotation type, what happens to annotated documents:
+
+- [Action::Keep]: the annotation is removed and the document is kept,
+- [Action::Annotate] (default): the annotation is kept, as is the document,
+- [Action::Drop]: the document is discarded.
+
+The annotation type is the part before `:` (`repeated_paragraphs:3` is of type `repeated_paragraphs`),
+so that valued annotations can be handled as a whole.
+
+Policies are built from `<annotation>=<action>` specs:
+
+```
+use ungoliant::filtering::annotation::AnnotationPolicy;
+let policy = AnnotationPolicy::from_specs(&["noisy=drop", "header=keep"]).unwrap();
+```
+
+An [AnnotationSelector] tells apart documents holding some annotation types, to route them elsewhere.
+By default, it selects quality flags ([QUALITY_FLAGS]) rather than any annotation,
+since informational annotations (`country:`, `script:`, `lid_member:`…) are on most documents.
+!*/
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use oscar_io::v3::{Document, Metadata};
+
+use crate::error::Error;
+
+/// Annotation types flagging low-quality or unwanted content, as opposed to informational ones.
+///
+/// Code and math pages are flagged since they pollute natural-language corpora.
+pub const QUALITY_FLAGS: &[&str] = &[
+    "tiny",
+    "noisy",
+    "short_sentences",
+    "header",
+    "footer",
+    "repetitive",
+    "incompressible",
+    "repeated_paragraphs",
+    "adult",
+    "harmful",
+    "code",
+    "math",
+];
+
+/// Get the type of an annotation (the part before `:`).
+fn annotation_type(annotation: &str) -> &str {
+    annotation
+        .split_once(':')
+        .map_or(annotation, |(annotation_type, _)| annotation_type)
+}
+
+/// Returns true if the document holds annotations of all given types, whatever its other annotations.
+pub fn has_types(doc: &Document, types: &[&str]) -> bool {
+    doc.metadata().annotation().is_some_and(|annotations| {
+        types
+            .iter()
+            .all(|t| annotations.iter().any(|a| annotation_type(a) == *t))
+    })
+}
+
+/// What to do with documents holding a given annotation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Action {
+    Keep,
+    #[default]
+    Annotate,
+    Drop,
+}
+
+impl FromStr for Action {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(Self::Keep),
+            "annotate" => Ok(Self::Annotate),
+            "drop" => Ok(Self::Drop),
+            other => Err(Error::Custom(format!(
+                "unknown annotation action {other} (expected keep, annotate or drop)"
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AnnotationPolicy {
+    actions: HashMap<String, Action>,
+}
+
+impl AnnotationPolicy {
+    /// Build a policy from `<annotation>=<action>` specs.
+    pub fn from_specs<T: AsRef<str>>(specs: &[T]) -> Result<Self, Error> {
+        let mut policy = Self::default();
+        for spec in specs {
+            let spec = spec.as_ref();
+            let (annotation, action) = spec.split_once('=').ok_or_else(|| {
+                Error::Custom(format!(
+                    "invalid annotation policy {spec} (expected <annotation>=<action>)"
+                ))
+            })?;
+            policy.set(annotation.to_string(), action.parse()?);
+        }
+
+        Ok(policy)
+    }
+
+    /// Set the action of an annotation type.
+    pub fn set(&mut self, annotation: String, action: Action) {
+        self.actions.insert(annotation, action);
+    }
+
+    /// Returns true if no annotation has a non-default action.
+    pub fn is_empty(&self) -> bool {
+        self.actions
+            .values()
+            .all(|action| *action == Action::Annotate)
+    }
+
+    /// Get the action of an annotation.
+    pub fn action(&self, annotation: &str) -> Action {
+        self.actions
+            .get(annotation_type(annotation))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Apply the policy on a document.
+    ///
+    /// Returns the type of the first annotation that makes the document dropped, if any.
+    /// Otherwise, annotations whose action is [Action::Keep] are removed.
+    pub fn apply(&self, doc: &mut Document) -> Option<String> {
+        let annotations = doc.metadata().annotation()?;
+
+        if let Some(dropping) = annotations
+            .iter()
+            .find(|annotation| self.action(annotation) == Action::Drop)
+        {
+            return Some(annotation_type(dropping).to_string());
+        }
+
+        if annotations
+            .iter()
+            .any(|annotation| self.action(annotation) == Action::Keep)
+        {
+            let kept: Vec<String> = annotations
+                .iter()
+                .filter(|annotation| self.action(annotation) != Action::Keep)
+                .cloned()
+                .collect();
+            Self::set_annotations(doc, kept);
+        }
+
+        None
+    }
+
+    /// Replace document annotations.
+    ///
+    /// Annotations can only be added to [Metadata], so it is rebuilt.
+    fn set_annotations(doc: &mut Document, annotations: Vec<String>) {
+        let old = doc.metadata();
+        let mut metadata = Metadata::new(doc.identification(), old.sentence_identifications());
+        metadata.set_categories(old.categories().cloned());
+        metadata.set_harmful_pp(old.harmful_pp());
+        metadata.set_tlsh(old.tlsh().cloned());
+        for annotation in annotations {
+            metadata.add_annotation(annotation);
+        }
+
+        *doc.metadata_mut() = metadata;
+    }
+}
+
+/// Selects documents holding given annotation types, or quality flags ([QUALITY_FLAGS]) if no type is given.
+#[derive(Debug, Clone)]
+pub struct AnnotationSelector {
+    types: HashSet<String>,
+}
+
+impl AnnotationSelector {
+    pub fn new(types: Vec<String>) -> Self {
+        if types.is_empty() {
+            return Self::default();
+        }
+        Self {
+            types: types.into_iter().collect(),
+        }
+    }
+
+    /// Returns true if the document holds a selected annotation.
+    pub fn matches(&self, doc: &Document) -> bool {
+        doc.metadata().annotation().is_some_and(|annotations| {
+            annotations
+                .iter()
+                .any(|annotation| self.types.contains(annotation_type(annotation)))
+        })
+    }
+}
+
+impl Default for AnnotationSelector {
+    /// Selects quality flags.
+    fn default() -> Self {
+        Self {
+            types: QUALITY_FLAGS.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oscar_io::{
+        common::Identification,
+        v3::{Document, Metadata},
+    };
+    use oxilangtag::LanguageTag;
+
+    use super::{has_types, Action, AnnotationPolicy, AnnotationSelector};
+
+    fn gen_doc(annotations: &[&str]) -> Document {
+        let id = Identification::new(LanguageTag::parse("en".to_string()).unwrap(), 1.0);
+        let mut metadata = Metadata::new(&id, &[Some(id.clone())]);
+        for annotation in annotations {
+            metadata.add_annotation(annotation.to_string());
+        }
+        metadata.add_category("adult".to_string());
+        Document::new("foo".to_string(), HashMap::new(), metadata)
+    }
+
+    #[test]
+    fn test_from_specs() {
+        let policy = AnnotationPolicy::from_specs(&["noisy=drop", "header=keep"]).unwrap();
+        assert_eq!(policy.action("noisy"), Action::Drop);
+        assert_eq!(policy.action("header"), Action::Keep);
+        assert_eq!(policy.action("tiny"), Action::Annotate);
+        assert!(!policy.is_empty());
+
+        assert!(AnnotationPolicy::from_specs(&["noisy"]).is_err());
+        assert!(AnnotationPolicy::from_specs(&["noisy=remove"]).is_err());
+        assert!(AnnotationPolicy::from_specs(&["noisy=annotate"])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_drop() {
+        let policy = AnnotationPolicy::from_specs(&["repeated_paragraphs=drop"]).unwrap();
+        let mut doc = gen_doc(&["header", "repeated_paragraphs:4"]);
+        assert_eq!(
+            policy.apply(&mut doc),
+            Some("repeated_paragraphs".to_string())
+        );
+
+        let mut doc = gen_doc(&["header"]);
+        assert_eq!(policy.apply(&mut doc), None);
+        let mut doc = gen_doc(&[]);
+        assert_eq!(policy.apply(&mut doc), None);
+    }
+
+    #[test]
+    fn test_keep() {
+        let policy = AnnotationPolicy::from_specs(&["header=keep", "country=keep"]).unwrap();
+        let mut doc = gen_doc(&["header", "tiny", "country:FR"]);
+        assert_eq!(policy.apply(&mut doc), None);
+        assert_eq!(doc.metadata().annotation(), Some(&vec!["tiny".to_string()]));
+        // other metadata is preserved
+        assert_eq!(
+            doc.metadata().categories(),
+            Some(&vec!["adult".to_string()])
+        );
+        assert_eq!(doc.metadata().sentence_identifications().len(), 1);
+    }
+
+    #[test]
+    fn test_has_types() {
+        let types = ["noisy", "tiny"];
+        assert!(has_types(&gen_doc(&["noisy", "tiny"]), &types));
+        assert!(has_types(
+            &gen_doc(&["country:FR", "tiny", "noisy"]),
+            &types
+        ));
+        assert!(!has_types(&gen_doc(&["noisy", "country:FR"]), &types));
+        assert!(!has_types(&gen_doc(&[]), &types));
+    }
+
+    #[test]
+    fn test_selector() {
+        let flags = AnnotationSelector::new(Vec::new());
+        assert!(flags.matches(&gen_doc(&["tiny"])));
+        assert!(flags.matches(&gen_doc(&["country:FR", "harmful:hate"])));
+        assert!(flags.matches(&gen_doc(&["code:rust"])));
+        assert!(flags.matches(&gen_doc(&["math:latex"])));
+        assert!(!flags.matches(&gen_doc(&["country:FR", "script:Latn"])));
+        assert!(!flags.matches(&gen_doc(&[])));
+
+        let some = AnnotationSelector::new(vec!["noisy".to_string(), "country".to_string()]);
+        assert!(some.matches(&gen_doc(&["tiny", "country:FR"])));
+        assert!(!some.matches(&gen_doc(&["tiny", "header"])));
+    }
+}
@@ -0,0 +1,112 @@
+/*! Content hashing
+
+Hash algorithms used for deduplication keys and content digests.
+
+- `xxh64` (XxHash64): fast, but 64-bit hashes collide in practice at crawl scale (billions of keys), falsely merging distinct documents.
+- `blake3`: slower, cryptographic 256-bit hashes, for releases where a false merge is unacceptable.
+
+Hashes are tagged with their algorithm (see [ContentHash]).
+!*/
+use std::{fmt, hash::Hasher, str::FromStr};
+
+use twox_hash::XxHash64;
+
+use crate::error::Error;
+
+/// Hash algorithm.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    XxHash64,
+    #[default]
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// Hash bytes.
+    pub fn hash(&self, bytes: &[u8]) -> ContentHash {
+        match self {
+            Self::XxHash64 => {
+                let mut hasher = XxHash64::with_seed(0);
+                hasher.write(bytes);
+                ContentHash::XxHash64(hasher.finish())
+            }
+            Self::Blake3 => ContentHash::Blake3(*blake3::hash(bytes).as_bytes()),
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "xxh64" | "xxhash64" => Ok(Self::XxHash64),
+            "blake3" => Ok(Self::Blake3),
+            other => Err(Error::Custom(format!(
+                "unknown hash algorithm {other} (expected xxh64 or blake3)"
+            ))),
+        }
+    }
+}
+
+/// Hash of some content, tagged by its algorithm so that hashes of different algorithms never compare equal.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ContentHash {
+    XxHash64(u64),
+    Blake3([u8; 32]),
+}
+
+impl fmt::Display for ContentHash {
+    /// Format as `<algorithm>:<hex digest>`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::XxHash64(hash) => write!(f, "xxh64:{hash:016x}"),
+            Self::Blake3(hash) => {
+                write!(f, "blake3:")?;
+                hash.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ContentHash, HashAlgorithm};
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(
+            "xxh64".parse::<HashAlgorithm>().unwrap(),
+            HashAlgorithm::XxHash64
+        );
+        assert_eq!(
+            "blake3".parse::<HashAlgorithm>().unwrap(),
+            HashAlgorithm::Blake3
+        );
+        assert!("md5".parse::<HashAlgorithm>().is_err());
+    }
+
+    #[test]
+    fn test_hash() {
+        for algorithm in [HashAlgorithm::XxHash64, HashAlgorithm::Blake3] {
+            assert_eq!(algorithm.hash(b"foo"), algorithm.hash(b"foo"));
+            assert_ne!(algorithm.hash(b"foo"), algorithm.hash(b"bar"));
+        }
+        assert!(matches!(
+            HashAlgorithm::XxHash64.hash(b"foo"),
+            ContentHash::XxHash64(_)
+        ));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            HashAlgorithm::Blake3.hash(b"").to_string(),
+            "blake3:af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+        assert!(HashAlgorithm::XxHash64
+            .hash(b"")
+            .to_string()
+            .starts_with("xxh64:"));
+    }
+}
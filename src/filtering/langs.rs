@@ -0,0 +1,115 @@
+/*! Language selection
+
+Only keeps documents identified in included languages and/or not identified in excluded ones,
+so that runs targeting a few languages don't write (nor post-process) the others.
+
+Languages are given as language tags. A tag also selects its more specific variants:
+`sr` selects `sr-Cyrl` and `sr-Latn`, but `sr-Cyrl` does not select `sr`.
+Matching is done on whole subtags, so `zh` does not select `zza`.
+
+Multilingual documents are identified as `multi`, which can be included or excluded like any other language.
+!*/
+use std::collections::HashSet;
+
+use oxilangtag::LanguageTag;
+
+use crate::error::Error;
+
+use super::Filter;
+
+#[derive(Debug, Clone, Default)]
+pub struct LanguageSelection {
+    include: HashSet<LanguageTag<String>>,
+    exclude: HashSet<LanguageTag<String>>,
+}
+
+impl LanguageSelection {
+    /// Build a selection from included and excluded language tags.
+    ///
+    /// If no language is included, every language that isn't excluded is kept.
+    pub fn from_specs<T: AsRef<str>>(include: &[T], exclude: &[T]) -> Result<Self, Error> {
+        let parse = |langs: &[T]| {
+            langs
+                .iter()
+                .map(|lang| LanguageTag::parse_and_normalize(lang.as_ref()))
+                .collect::<Result<HashSet<_>, _>>()
+        };
+
+        Ok(Self {
+            include: parse(include)?,
+            exclude: parse(exclude)?,
+        })
+    }
+
+    /// Returns true if every language is kept.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Returns true if `lang` is `selected` or one of its variants.
+    fn matches(selected: &LanguageTag<String>, lang: &LanguageTag<String>) -> bool {
+        lang.as_str()
+            .strip_prefix(selected.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('-'))
+    }
+
+    fn any_matches(langs: &HashSet<LanguageTag<String>>, lang: &LanguageTag<String>) -> bool {
+        langs.iter().any(|selected| Self::matches(selected, lang))
+    }
+}
+
+impl Filter<&LanguageTag<String>> for LanguageSelection {
+    /// Returns true if `lang` is kept.
+    fn detect(&self, lang: &LanguageTag<String>) -> bool {
+        (self.include.is_empty() || Self::any_matches(&self.include, lang))
+            && !Self::any_matches(&self.exclude, lang)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use oxilangtag::LanguageTag;
+
+    use crate::filtering::Filter;
+
+    use super::LanguageSelection;
+
+    fn lang(tag: &str) -> LanguageTag<String> {
+        LanguageTag::parse(tag.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_include() {
+        let selection = LanguageSelection::from_specs(&["fr", "SR", "zh-Hant"], &[]).unwrap();
+        assert!(!selection.is_empty());
+        assert!(selection.detect(&lang("fr")));
+        assert!(selection.detect(&lang("sr")));
+        assert!(selection.detect(&lang("sr-Cyrl")));
+        assert!(selection.detect(&lang("zh-Hant-TW")));
+        assert!(!selection.detect(&lang("zh")));
+        assert!(!selection.detect(&lang("frr")));
+        assert!(!selection.detect(&lang("multi")));
+    }
+
+    #[test]
+    fn test_exclude() {
+        let selection = LanguageSelection::from_specs(&[], &["en", "multi"]).unwrap();
+        assert!(selection.detect(&lang("fr")));
+        assert!(!selection.detect(&lang("en")));
+        assert!(!selection.detect(&lang("en-GB")));
+        assert!(!selection.detect(&lang("multi")));
+
+        let selection = LanguageSelection::from_specs(&["sr"], &["sr-Latn"]).unwrap();
+        assert!(selection.detect(&lang("sr-Cyrl")));
+        assert!(!selection.detect(&lang("sr-Latn")));
+        assert!(!selection.detect(&lang("hr")));
+    }
+
+    #[test]
+    fn test_empty() {
+        let selection = LanguageSelection::default();
+        assert!(selection.is_empty());
+        assert!(selection.detect(&lang("en")));
+        assert!(LanguageSelection::from_specs(&["not a tag"], &[]).is_err());
+    }
+}
@@ -9,8 +9,13 @@ Filters implement [filter::Filter], [filter::FilterMut] or both:
 Both can be implemented for a given filter,
 in order to provide a mutable detection that could be used to "train" the filter, then an immutable one to effectively filter content.
 !*/
+pub mod allowlist;
+pub mod annotation;
 mod filter;
+pub mod hash;
+pub mod langs;
 pub mod record;
+pub mod selection;
 pub mod sentence;
 
 pub use filter::Filter;
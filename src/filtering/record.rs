@@ -1,11 +1,16 @@
 //! Document-level filtering.
 //!
 //! Those filters take a WARC [warc::Record] as a parameter.
+use std::collections::HashSet;
 use std::convert::TryFrom;
+use std::sync::Mutex;
 
-use warc::{BufferedBody, Record};
+use log::debug;
+use url::Url;
+use warc::{BufferedBody, Record, WarcHeader};
 
-use super::sentence::Length;
+use super::hash::{ContentHash, HashAlgorithm};
+use super::sentence::LineValidity;
 use super::Filter;
 use std::cmp::Ordering;
 pub enum FilterKind {
@@ -29,21 +34,31 @@ impl Filter<&Record<BufferedBody>> for FilterKind {
 /// Filters out documents that doesn't have its content enough in long newline-separated strings.
 ///
 /// For each document, we compute the size (in bytes) of newline-separated strings, that we bucket in two bins
-/// depending on their size. The threshold size is specified in the [PFilter::min_length].
+/// depending on their size. The threshold size is specified in the [LineValidity::min_chars].
 /// If the `>min_length` bin makes for at least sentence_threshold of the document, we keep it.
+///
+/// Lines that are long enough but fail the other [LineValidity] criteria go in the lower bin.
 pub struct PFilter {
     sentence_threshold: f64,
-    sentence_filter: Length,
+    sentence_filter: LineValidity,
 }
 
 impl PFilter {
     /// Create a new PFilter with specific parameters.
-    pub fn new(sentence_threshold: f64, sentence_filter: Length) -> Self {
+    pub fn new(sentence_threshold: f64, sentence_filter: LineValidity) -> Self {
         PFilter {
             sentence_threshold,
             sentence_filter,
         }
     }
+
+    /// Create a new PFilter with the default threshold and a custom line validity predicate.
+    pub fn with_line_validity(sentence_filter: LineValidity) -> Self {
+        PFilter {
+            sentence_filter,
+            ..Default::default()
+        }
+    }
 }
 
 impl Filter<&Record<BufferedBody>> for PFilter {
@@ -60,12 +75,14 @@ impl Filter<&Record<BufferedBody>> for PFilter {
             // we do not use sentence_filter since we'd compute sentence length two times.
             let count = line.chars().count();
 
-            // if count is >= than minimum filter size, we add to upper bucket
-            match count.cmp(self.sentence_filter.min_size()) {
-                Ordering::Less => bucket_lower += u32::try_from(count).unwrap(),
-                Ordering::Equal | Ordering::Greater => {
+            // if count is >= than minimum filter size and the line is otherwise valid, we add to upper bucket
+            match count.cmp(self.sentence_filter.min_chars()) {
+                Ordering::Equal | Ordering::Greater
+                    if self.sentence_filter.detect_composition(line) =>
+                {
                     bucket_upper += u32::try_from(count).unwrap()
                 }
+                _ => bucket_lower += u32::try_from(count).unwrap(),
             }
         }
 
@@ -91,18 +108,133 @@ impl Default for PFilter {
     fn default() -> Self {
         PFilter {
             sentence_threshold: 0.6,
-            sentence_filter: Length::default(),
+            sentence_filter: LineValidity::default(),
         }
     }
 }
 
+/// Filters out records whose payload digest or normalized URI has already been seen.
+///
+/// This is meant to be run before classification, since Common Crawl holds many identical payloads.
+/// The digest is the `WARC-Payload-Digest`, or the `WARC-Block-Digest` if there's none (WET records only have the latter).
+/// URIs are normalized by dropping fragments and trailing slashes (scheme and host are lowercased by parsing).
+///
+/// Seen keys are kept as hashes (see [HashAlgorithm]) for the whole run, and [Filter::detect] is thread-safe:
+/// which record of a duplicate group is kept depends on processing order.
+#[derive(Default)]
+pub struct RecordDedup {
+    seen: Mutex<HashSet<ContentHash>>,
+    hash: HashAlgorithm,
+}
+
+impl RecordDedup {
+    pub fn new(hash: HashAlgorithm) -> Self {
+        Self {
+            seen: Mutex::new(HashSet::new()),
+            hash,
+        }
+    }
+
+    /// Normalize a URI, returning it as-is if it can't be parsed.
+    fn normalize_uri(uri: &str) -> String {
+        match Url::parse(uri) {
+            Ok(mut url) => {
+                url.set_fragment(None);
+                url.as_str().trim_end_matches('/').to_string()
+            }
+            Err(_) => uri.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Get the dedup keys of a record.
+    fn keys(record: &Record<BufferedBody>) -> Vec<String> {
+        let mut keys = Vec::with_capacity(2);
+        if let Some(digest) = record
+            .header(WarcHeader::PayloadDigest)
+            .or_else(|| record.header(WarcHeader::BlockDigest))
+        {
+            keys.push(format!("digest:{digest}"));
+        }
+        if let Some(uri) = record.header(WarcHeader::TargetURI) {
+            keys.push(format!("uri:{}", Self::normalize_uri(&uri)));
+        }
+        keys
+    }
+
+    /// Get the number of seen keys.
+    pub fn nb_seen(&self) -> usize {
+        self.seen.lock().unwrap().len()
+    }
+}
+
+impl Filter<&Record<BufferedBody>> for RecordDedup {
+    /// Returns false if the record is a duplicate, marking it as seen otherwise.
+    fn detect(&self, record: &Record<BufferedBody>) -> bool {
+        let keys = Self::keys(record);
+        let hashes: Vec<_> = keys
+            .iter()
+            .map(|key| self.hash.hash(key.as_bytes()))
+            .collect();
+        let mut seen = self.seen.lock().unwrap();
+        if let Some((key, _)) = keys
+            .iter()
+            .zip(&hashes)
+            .find(|(_, hash)| seen.contains(*hash))
+        {
+            debug!("record {}: duplicate ({})", record.warc_id(), key);
+            return false;
+        }
+
+        seen.extend(hashes);
+        true
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use warc::Record;
+    use warc::{BufferedBody, Record, WarcHeader};
+
+    use crate::filtering::{hash::HashAlgorithm, sentence::LineValidity, Filter};
+
+    use super::{PFilter, RecordDedup};
+
+    fn gen_record(digest: Option<&str>, uri: &str) -> Record<BufferedBody> {
+        let mut r = Record::default();
+        if let Some(digest) = digest {
+            r.set_header(WarcHeader::BlockDigest, digest).unwrap();
+        }
+        r.set_header(WarcHeader::TargetURI, uri).unwrap();
+        r.add_body("foo")
+    }
+
+    #[test]
+    fn test_dedup_digest() {
+        let dedup = RecordDedup::default();
+        assert!(dedup.detect(&gen_record(Some("sha1:AAA"), "http://a.com/")));
+        assert!(!dedup.detect(&gen_record(Some("sha1:AAA"), "http://b.com/")));
+        assert!(dedup.detect(&gen_record(Some("sha1:BBB"), "http://c.com/")));
+        assert!(dedup.detect(&gen_record(None, "http://d.com/")));
+    }
 
-    use crate::filtering::Filter;
+    #[test]
+    fn test_dedup_uri() {
+        let dedup = RecordDedup::default();
+        assert!(dedup.detect(&gen_record(Some("sha1:AAA"), "http://EXAMPLE.com/page/")));
+        assert!(!dedup.detect(&gen_record(
+            Some("sha1:BBB"),
+            "http://example.com/page#section"
+        )));
+        assert!(dedup.detect(&gen_record(Some("sha1:CCC"), "http://example.com/other")));
+        assert_eq!(dedup.nb_seen(), 4);
+    }
 
-    use super::PFilter;
+    #[test]
+    fn test_dedup_xxh64() {
+        let dedup = RecordDedup::new(HashAlgorithm::XxHash64);
+        assert!(dedup.detect(&gen_record(Some("sha1:AAA"), "http://a.com/")));
+        assert!(!dedup.detect(&gen_record(Some("sha1:AAA"), "http://b.com/")));
+        assert!(!dedup.detect(&gen_record(Some("sha1:BBB"), "http://a.com")));
+    }
 
     #[test]
     fn test_pfilter_fail() {
@@ -146,4 +278,18 @@ mod tests {
         let f = PFilter::default();
         assert_eq!(f.detect(&r), true);
     }
+
+    #[test]
+    fn test_pfilter_url_density() {
+        let r = Record::default();
+        let long_links = "https://example.com/a-very-long-link-to-some-page-that-has-no-real-content-but-is-long-enough-anyway";
+        let body = format!("{long_links}\n{long_links}\nshort sentence");
+        let r = r.add_body(body);
+
+        let f = PFilter::default();
+        assert!(f.detect(&r));
+
+        let f = PFilter::with_line_validity(LineValidity::new(100, None, Some(0.5)));
+        assert!(!f.detect(&r));
+    }
 }
@@ -0,0 +1,227 @@
+/*! Record selection
+
+Restricts processing to a given set of records, to debug takedown requests or filter regressions.
+
+Records are listed in a file, one per line:
+
+- a `WARC-Record-ID` (`<urn:uuid:...>`),
+- or a tab-separated `WARC-Target-URI` and digest pair (`WARC-Payload-Digest`, or `WARC-Block-Digest` for WET records).
+
+Empty lines and lines starting with `#` are ignored.
+
+Since rebuild files hold the record ids of each shard, they can be used as an index to only open
+the shards that contain selected records (see [RecordSelection::indexed_shards]).
+!*/
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use log::{debug, info, warn};
+use warc::{BufferedBody, Record, WarcHeader};
+
+use crate::{error::Error, pipelines::oscardoc::types::ShardResult};
+
+use super::Filter;
+
+#[derive(Debug, Default)]
+pub struct RecordSelection {
+    record_ids: HashSet<String>,
+    uri_digests: HashSet<(String, String)>,
+}
+
+impl RecordSelection {
+    /// Load a selection from a file.
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Load a selection from a reader.
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, Error> {
+        let mut selection = Self::default();
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match line.split_once('\t') {
+                Some((uri, digest)) => {
+                    selection
+                        .uri_digests
+                        .insert((uri.trim().to_string(), digest.trim().to_string()));
+                }
+                None => {
+                    selection.record_ids.insert(line.to_string());
+                }
+            }
+        }
+
+        info!(
+            "Selected {} record ids and {} URI/digest pairs",
+            selection.record_ids.len(),
+            selection.uri_digests.len()
+        );
+        Ok(selection)
+    }
+
+    /// Get the shard ids of the selected records, using rebuild files (`<rebuild_dir>/*.avro`).
+    ///
+    /// Returns [None] if some records are selected by URI/digest, since rebuild files only hold record ids.
+    pub fn indexed_shards(&self, rebuild_dir: &Path) -> Result<Option<HashSet<usize>>, Error> {
+        if !self.uri_digests.is_empty() {
+            warn!(
+                "URI/digest pairs can't be looked up in rebuild files, all shards will be scanned"
+            );
+            return Ok(None);
+        }
+
+        let mut shard_ids = HashSet::new();
+        let mut found = HashSet::new();
+        for entry in std::fs::read_dir(rebuild_dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("avro") {
+                continue;
+            }
+
+            debug!("looking up selected records in {:?}", path);
+            let reader = avro_rs::Reader::new(BufReader::new(File::open(&path)?))?;
+            for value in reader {
+                let shard_result: ShardResult = avro_rs::from_value(&value?)?;
+                for info in shard_result.rebuild_info() {
+                    if self.record_ids.contains(info.record_id()) {
+                        shard_ids.insert(info.shard_id());
+                        found.insert(info.record_id().to_string());
+                    }
+                }
+            }
+        }
+
+        if found.len() < self.record_ids.len() {
+            warn!(
+                "{} selected records are not in rebuild files (they may have been filtered out), all shards will be scanned",
+                self.record_ids.len() - found.len()
+            );
+            return Ok(None);
+        }
+
+        Ok(Some(shard_ids))
+    }
+}
+
+impl Filter<&Record<BufferedBody>> for RecordSelection {
+    /// Returns true if the record is selected.
+    fn detect(&self, record: &Record<BufferedBody>) -> bool {
+        if self.record_ids.contains(record.warc_id()) {
+            return true;
+        }
+
+        if self.uri_digests.is_empty() {
+            return false;
+        }
+        match (
+            record.header(WarcHeader::TargetURI),
+            record
+                .header(WarcHeader::PayloadDigest)
+                .or_else(|| record.header(WarcHeader::BlockDigest)),
+        ) {
+            (Some(uri), Some(digest)) => self
+                .uri_digests
+                .contains(&(uri.into_owned(), digest.into_owned())),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use oscar_io::common::Identification;
+    use oxilangtag::LanguageTag;
+    use warc::{BufferedBody, Record, WarcHeader};
+
+    use crate::{
+        filtering::Filter,
+        pipelines::oscardoc::types::{Location, Metadata, RebuildWriters, ShardResult},
+    };
+
+    use super::RecordSelection;
+
+    const SELECTION: &str = "# takedown 42
+<urn:uuid:00000000-0000-0000-0000-000000000001>
+
+http://example.com/page\tsha1:AAA
+";
+
+    fn gen_record(id: &str, uri: &str, digest: &str) -> Record<BufferedBody> {
+        let mut r = Record::default();
+        r.set_warc_id(id);
+        r.set_header(WarcHeader::TargetURI, uri).unwrap();
+        r.set_header(WarcHeader::BlockDigest, digest).unwrap();
+        r.add_body("foo")
+    }
+
+    #[test]
+    fn test_detect() {
+        let selection = RecordSelection::from_reader(SELECTION.as_bytes()).unwrap();
+
+        assert!(selection.detect(&gen_record(
+            "<urn:uuid:00000000-0000-0000-0000-000000000001>",
+            "http://foo.com/",
+            "sha1:BBB"
+        )));
+        assert!(selection.detect(&gen_record(
+            "<urn:uuid:00000000-0000-0000-0000-000000000002>",
+            "http://example.com/page",
+            "sha1:AAA"
+        )));
+        assert!(!selection.detect(&gen_record(
+            "<urn:uuid:00000000-0000-0000-0000-000000000002>",
+            "http://example.com/page",
+            "sha1:BBB"
+        )));
+    }
+
+    #[test]
+    fn test_indexed_shards_uri() {
+        let selection = RecordSelection::from_reader(SELECTION.as_bytes()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(selection.indexed_shards(dir.path()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_indexed_shards() {
+        let dir = tempfile::tempdir().unwrap();
+        let lang = LanguageTag::parse("en".to_string()).unwrap();
+        let id = Identification::new(lang.clone(), 1.0);
+        let writers = RebuildWriters::with_dst(dir.path()).unwrap();
+        writers.insert(dir.path(), &lang).unwrap();
+        for shard_id in 0..3 {
+            let location = Location::new(shard_id, format!("<urn:uuid:{shard_id}>"), 0, 0, 0);
+            let sr = ShardResult::new(
+                shard_id as i64,
+                vec![location],
+                vec![Metadata::new(&id, &[Some(id.clone())])],
+            );
+            let writers_lock = writers.writers();
+            let mut writer = writers_lock.get(&lang).unwrap().lock().unwrap();
+            writer.append_ser(sr).unwrap();
+            writer.flush().unwrap();
+        }
+
+        let selection =
+            RecordSelection::from_reader("<urn:uuid:0>\n<urn:uuid:2>\n".as_bytes()).unwrap();
+        assert_eq!(
+            selection.indexed_shards(dir.path()).unwrap(),
+            Some(HashSet::from([0, 2]))
+        );
+
+        // missing record
+        let selection = RecordSelection::from_reader("<urn:uuid:5>\n".as_bytes()).unwrap();
+        assert_eq!(selection.indexed_shards(dir.path()).unwrap(), None);
+    }
+}
@@ -7,6 +7,7 @@ use std::convert::TryInto;
 /// Returns `false` if provided sentence is less than [Length::min_size] unicode codepoints.
 ///
 /// [Length::min_size] is 100 by default.
+#[derive(Debug, Clone)]
 pub struct Length {
     min_size: usize,
 }
@@ -36,6 +37,105 @@ impl Default for Length {
     }
 }
 
+/// Configurable line validity predicate.
+///
+/// A line is valid if it is longer than [LineValidity::min_chars] unicode codepoints and,
+/// if set, if it has at least `min_alphabetic_ratio` alphabetic codepoints and at most
+/// `max_url_density` of its codepoints in URLs.
+///
+/// The default predicate only checks length (100 codepoints, see [Length]).
+/// Lower `min_chars` for scripts that pack more information per codepoint (Han, Kana, Hangul...).
+#[derive(Debug, Clone, Default)]
+pub struct LineValidity {
+    length: Length,
+    min_alphabetic_ratio: Option<f32>,
+    max_url_density: Option<f32>,
+}
+
+impl LineValidity {
+    pub fn new(
+        min_chars: usize,
+        min_alphabetic_ratio: Option<f32>,
+        max_url_density: Option<f32>,
+    ) -> Self {
+        Self {
+            length: Length::with_min_size(min_chars),
+            min_alphabetic_ratio,
+            max_url_density,
+        }
+    }
+
+    /// Only check length.
+    pub fn with_min_chars(min_chars: usize) -> Self {
+        Self::new(min_chars, None, None)
+    }
+
+    /// Get a reference to the minimum number of codepoints.
+    pub fn min_chars(&self) -> &usize {
+        self.length.min_size()
+    }
+
+    /// Check the non-length criteria (alphabetic ratio, URL density).
+    ///
+    /// Useful for callers that already have the line length.
+    pub(crate) fn detect_composition(&self, line: &str) -> bool {
+        if let Some(min_ratio) = self.min_alphabetic_ratio {
+            if Self::alphabetic_ratio(line) < min_ratio {
+                return false;
+            }
+        }
+
+        if let Some(max_density) = self.max_url_density {
+            if Self::url_density(line) > max_density {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Ratio of alphabetic codepoints (in any script) over non-whitespace ones.
+    fn alphabetic_ratio(line: &str) -> f32 {
+        let (alphabetic, total) = line
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .fold((0usize, 0usize), |(alphabetic, total), c| {
+                (alphabetic + usize::from(c.is_alphabetic()), total + 1)
+            });
+
+        if total == 0 {
+            0.0
+        } else {
+            alphabetic as f32 / total as f32
+        }
+    }
+
+    /// Ratio of non-whitespace codepoints that are part of URL-like tokens.
+    fn url_density(line: &str) -> f32 {
+        let (url, total) = line
+            .split_whitespace()
+            .fold((0usize, 0usize), |(url, total), token| {
+                let count = token.chars().count();
+                let is_url = token.starts_with("http://")
+                    || token.starts_with("https://")
+                    || token.starts_with("www.");
+                (url + if is_url { count } else { 0 }, total + count)
+            });
+
+        if total == 0 {
+            0.0
+        } else {
+            url as f32 / total as f32
+        }
+    }
+}
+
+impl Filter<&str> for LineValidity {
+    fn detect(&self, sentence: &str) -> bool {
+        self.length.detect(sentence) && self.detect_composition(sentence)
+    }
+}
+
 /// Mean filter: Keeps track of mean length of proposed sentences
 ///
 /// Detects sentences that are within the stdandard deviation.
@@ -116,7 +216,7 @@ mod tests {
     use rand::thread_rng;
     use rand_distr::{Distribution, Normal};
 
-    use super::{Filter, Length, MeanLength};
+    use super::{Filter, Length, LineValidity, MeanLength};
     use crate::filtering::filter::FilterMut;
 
     #[test]
@@ -129,6 +229,39 @@ mod tests {
         assert_eq!(false, f.detect(&invalid));
     }
 
+    #[test]
+    fn line_validity_default() {
+        let valid: String = ['z'; 101].iter().collect();
+        let invalid: String = ['z'; 99].iter().collect();
+
+        let f = LineValidity::default();
+        assert!(f.detect(&valid));
+        assert!(!f.detect(&invalid));
+    }
+
+    #[test]
+    fn line_validity_min_chars() {
+        // 13 codepoints
+        let sentence = "これは日本語の短い文です。";
+
+        assert!(!LineValidity::default().detect(sentence));
+        assert!(LineValidity::with_min_chars(10).detect(sentence));
+    }
+
+    #[test]
+    fn line_validity_alphabetic_ratio() {
+        let f = LineValidity::new(5, Some(0.5), None);
+        assert!(f.detect("some regular sentence, 2022"));
+        assert!(!f.detect("12/04/2022 - 13:45:00 - 0.25$"));
+    }
+
+    #[test]
+    fn line_validity_url_density() {
+        let f = LineValidity::new(5, None, Some(0.5));
+        assert!(f.detect("read more on https://example.com about the topic at hand"));
+        assert!(!f.detect("links: https://example.com/a https://example.com/b www.example.org"));
+    }
+
     #[test]
     fn mean_default() {
         let mut rng = thread_rng();
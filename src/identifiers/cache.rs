@@ -0,0 +1,309 @@
+/*! Identification cache
+
+Many lines (boilerplate, cookie banners, navigation) repeat millions of times across a crawl.
+[Cached] keeps the identifications of recently seen lines, so that repeated lines skip the identifier entirely.
+
+Lines are keyed by their XxHash64 hash and length.
+The cache is split in shards to limit contention between threads, each shard keeping two generations of entries:
+hits in the old generation are moved to the new one, and the old generation is dropped when the new one is full.
+This approximates a least recently used eviction policy in constant time.
+!*/
+use std::{
+    collections::HashMap,
+    hash::Hasher,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use serde::Serialize;
+use twox_hash::XxHash64;
+
+use crate::error::Error;
+
+use super::{
+    identification::Identification,
+    model::{LanguageIdentifier, LineIds, MemberIds, ModelMetadata, Predict},
+    registry::Registry,
+};
+
+/// Number of cache shards.
+const SHARDS: usize = 16;
+
+/// Line hash and byte length.
+type Key = (u64, usize);
+
+/// Cached identification of a line, along with the ones of ensemble members.
+#[derive(Debug, Clone)]
+struct Entry {
+    id: Option<Identification<String>>,
+    members: Vec<(String, Option<Identification<String>>)>,
+}
+
+/// Cache shard, holding at most twice `generation_size` entries.
+#[derive(Debug)]
+struct Shard {
+    generation_size: usize,
+    current: HashMap<Key, Entry>,
+    previous: HashMap<Key, Entry>,
+}
+
+impl Shard {
+    fn new(generation_size: usize) -> Self {
+        Self {
+            generation_size,
+            current: HashMap::new(),
+            previous: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, key: &Key) -> Option<Entry> {
+        if let Some(entry) = self.current.get(key) {
+            return Some(entry.clone());
+        }
+        let entry = self.previous.remove(key)?;
+        self.insert(*key, entry.clone());
+        Some(entry)
+    }
+
+    fn insert(&mut self, key: Key, entry: Entry) {
+        if self.current.len() >= self.generation_size {
+            self.previous = std::mem::take(&mut self.current);
+        }
+        self.current.insert(key, entry);
+    }
+}
+
+/// Cache hit and miss counts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl CacheStats {
+    /// Get the proportion of lines found in the cache.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+}
+
+/// Identifier caching the identifications of another one.
+pub struct Cached {
+    inner: Arc<dyn LanguageIdentifier>,
+    shards: Vec<Mutex<Shard>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl Cached {
+    /// Cache the identifications of about `capacity` lines.
+    pub fn new(inner: Arc<dyn LanguageIdentifier>, capacity: usize) -> Self {
+        let generation_size = (capacity / SHARDS / 2).max(1);
+        Self {
+            inner,
+            shards: (0..SHARDS)
+                .map(|_| Mutex::new(Shard::new(generation_size)))
+                .collect(),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    fn key(line: &str) -> Key {
+        let mut hasher = XxHash64::with_seed(0);
+        hasher.write(line.as_bytes());
+        (hasher.finish(), line.len())
+    }
+
+    fn shard(&self, key: &Key) -> &Mutex<Shard> {
+        &self.shards[key.0 as usize % SHARDS]
+    }
+}
+
+impl Predict<String> for Cached {
+    fn predict_one(&self, line: &str) -> Result<Option<Identification<String>>, Error> {
+        Ok(self.predict_batch(&[line])?.pop().flatten())
+    }
+
+    /// Multiple predictions are not cached.
+    fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+        self.inner.predict(line)
+    }
+}
+
+impl LanguageIdentifier for Cached {
+    fn metadata(&self) -> ModelMetadata {
+        self.inner.metadata()
+    }
+
+    fn registry(&self) -> Option<&Registry> {
+        self.inner.registry()
+    }
+
+    fn predict_batch(&self, lines: &[&str]) -> Result<LineIds, Error> {
+        Ok(self.predict_batch_with_members(lines)?.0)
+    }
+
+    /// Look lines up in the cache, and identify missing ones in a single batch.
+    fn predict_batch_with_members(&self, lines: &[&str]) -> Result<(LineIds, MemberIds), Error> {
+        let keys: Vec<_> = lines.iter().map(|line| Self::key(line)).collect();
+        let mut entries: Vec<_> = keys
+            .iter()
+            .map(|key| self.shard(key).lock().unwrap().get(key))
+            .collect();
+
+        let missing: Vec<_> = (0..lines.len())
+            .filter(|idx| entries[*idx].is_none())
+            .collect();
+        self.hits
+            .fetch_add(lines.len() - missing.len(), Ordering::Relaxed);
+        self.misses.fetch_add(missing.len(), Ordering::Relaxed);
+
+        if !missing.is_empty() {
+            let missing_lines: Vec<_> = missing.iter().map(|idx| lines[*idx]).collect();
+            let (ids, members) = self.inner.predict_batch_with_members(&missing_lines)?;
+            for (batch_idx, (idx, id)) in missing.iter().zip(ids).enumerate() {
+                let entry = Entry {
+                    id,
+                    members: members
+                        .iter()
+                        .map(|(backend, ids)| (backend.clone(), ids[batch_idx].clone()))
+                        .collect(),
+                };
+                let key = &keys[*idx];
+                self.shard(key).lock().unwrap().insert(*key, entry.clone());
+                entries[*idx] = Some(entry);
+            }
+        }
+
+        let entries: Vec<_> = entries.into_iter().flatten().collect();
+        let members = match entries.first() {
+            Some(entry) => (0..entry.members.len())
+                .map(|member| {
+                    let backend = entry.members[member].0.clone();
+                    let ids = entries
+                        .iter()
+                        .map(|entry| entry.members[member].1.clone())
+                        .collect();
+                    (backend, ids)
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        let ids = entries.into_iter().map(|entry| entry.id).collect();
+
+        Ok((ids, members))
+    }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        Some(CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use oxilangtag::LanguageTag;
+
+    use crate::{
+        error::Error,
+        identifiers::{
+            identification::Identification,
+            model::{LanguageIdentifier, ModelMetadata, Predict},
+        },
+    };
+
+    use super::{CacheStats, Cached, Shard};
+
+    /// Identifies lines as their first word, counting predictions.
+    #[derive(Default)]
+    struct FirstWord(AtomicUsize);
+
+    impl Predict<String> for FirstWord {
+        fn predict_one(&self, line: &str) -> Result<Option<Identification<String>>, Error> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(line.split_whitespace().next().map(|word| {
+                Identification::new(LanguageTag::parse(word.to_string()).unwrap(), 0.9)
+            }))
+        }
+
+        fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+            Ok(self.predict_one(line)?.map(|id| vec![id]))
+        }
+    }
+
+    impl LanguageIdentifier for FirstWord {
+        fn metadata(&self) -> ModelMetadata {
+            ModelMetadata {
+                backend: "first-word".to_string(),
+                path: None,
+                nb_labels: 0,
+                threshold: 0.0,
+                calibration: None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_cached() {
+        let inner = Arc::new(FirstWord::default());
+        let cached = Cached::new(inner.clone(), 1000);
+
+        let ids = cached
+            .predict_batch(&["en cookies", "fr texte", "en cookies", ""])
+            .unwrap();
+        let labels: Vec<_> = ids
+            .iter()
+            .map(|id| id.as_ref().map(|id| id.label().to_string()))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                Some("en".to_string()),
+                Some("fr".to_string()),
+                Some("en".to_string()),
+                None
+            ]
+        );
+
+        let id = cached.predict_one("en cookies").unwrap().unwrap();
+        assert_eq!(id.label().as_str(), "en");
+        assert_eq!(inner.0.load(Ordering::Relaxed), 4);
+        assert_eq!(
+            cached.cache_stats(),
+            Some(CacheStats { hits: 1, misses: 4 })
+        );
+        assert_eq!(cached.cache_stats().unwrap().hit_rate(), 0.2);
+    }
+
+    #[test]
+    fn test_eviction() {
+        let mut shard = Shard::new(2);
+        let entry = super::Entry {
+            id: None,
+            members: Vec::new(),
+        };
+        for key in 0..3 {
+            shard.insert((key, 0), entry.clone());
+        }
+        // 0 and 1 are in the previous generation, 2 in the current one
+        assert!(shard.get(&(0, 0)).is_some());
+        // getting 0 moved it to the current generation, which is full: 1 is dropped
+        shard.insert((3, 0), entry);
+        assert!(shard.get(&(1, 0)).is_none());
+        assert!(shard.get(&(0, 0)).is_some());
+        assert!(shard.get(&(3, 0)).is_some());
+    }
+}
@@ -0,0 +1,207 @@
+/*! Probability calibration
+
+Raw identifier probabilities (especially fastText's) are poorly calibrated across languages.
+A [Calibration] maps them to calibrated ones before they're thresholded.
+
+Calibrations are loaded from JSON files, either as a temperature:
+
+```json
+{"method": "temperature", "temperature": 1.5}
+```
+
+or as an isotonic mapping, given as `[raw, calibrated]` points sorted by raw probability:
+
+```json
+{"method": "isotonic", "points": [[0.0, 0.0], [0.5, 0.3], [0.9, 0.85], [1.0, 1.0]]}
+```
+
+Identifiers only report the probabilities of their top predictions, so temperature scaling is done on
+the binary distribution of each prediction (`p` vs `1 - p`): `p' = p^(1/T) / (p^(1/T) + (1 - p)^(1/T))`.
+Isotonic mappings linearly interpolate between points, and clamp probabilities outside of them.
+!*/
+use std::{fs::File, io::BufReader, path::Path, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+use super::{
+    identification::Identification,
+    model::{LanguageIdentifier, LineIds, MemberIds, ModelMetadata, Predict},
+    registry::Registry,
+};
+
+/// Mapping from raw to calibrated probabilities.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "lowercase")]
+pub enum Calibration {
+    /// Temperature scaling: temperatures over 1 lower confident probabilities, under 1 raise them.
+    Temperature { temperature: f32 },
+    /// Piecewise-linear, non-decreasing mapping.
+    Isotonic { points: Vec<(f32, f32)> },
+}
+
+impl Calibration {
+    /// Load a calibration from a JSON file.
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let f = BufReader::new(File::open(path)?);
+        let calibration: Self = serde_json::from_reader(f)?;
+        calibration.validate()?;
+        Ok(calibration)
+    }
+
+    /// Check that temperatures are positive and that isotonic points are sorted.
+    fn validate(&self) -> Result<(), Error> {
+        match self {
+            Self::Temperature { temperature } if *temperature <= 0.0 => Err(Error::Custom(
+                format!("calibration temperature must be positive, got {temperature}"),
+            )),
+            Self::Isotonic { points } if points.is_empty() => Err(Error::Custom(
+                "isotonic calibration needs at least one point".to_string(),
+            )),
+            Self::Isotonic { points }
+                if points
+                    .windows(2)
+                    .any(|pair| pair[1].0 < pair[0].0 || pair[1].1 < pair[0].1) =>
+            {
+                Err(Error::Custom(
+                    "isotonic calibration points must be non-decreasing".to_string(),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Calibrate a probability.
+    pub fn calibrate(&self, prob: f32) -> f32 {
+        match self {
+            Self::Temperature { temperature } => {
+                let scaled = prob.powf(1.0 / temperature);
+                let complement = (1.0 - prob).powf(1.0 / temperature);
+                scaled / (scaled + complement)
+            }
+            Self::Isotonic { points } => {
+                // index of the first point whose raw probability is greater than prob
+                let idx = points.partition_point(|(raw, _)| *raw <= prob);
+                match (
+                    idx.checked_sub(1).map(|idx| points[idx]),
+                    points.get(idx).copied(),
+                ) {
+                    (Some((x0, y0)), Some((x1, y1))) => y0 + (prob - x0) * (y1 - y0) / (x1 - x0),
+                    (Some((_, y)), None) | (None, Some((_, y))) => y,
+                    (None, None) => prob,
+                }
+            }
+        }
+    }
+}
+
+/// Identifier calibrating the probabilities of another one.
+pub struct Calibrated {
+    inner: Arc<dyn LanguageIdentifier>,
+    calibration: Calibration,
+}
+
+impl Calibrated {
+    /// Calibrate the probabilities of `inner`, which should be built without a threshold:
+    /// thresholds are to be applied on calibrated probabilities (see [super::Thresholded]).
+    pub fn new(inner: Arc<dyn LanguageIdentifier>, calibration: Calibration) -> Self {
+        Self { inner, calibration }
+    }
+
+    fn calibrate(&self, id: Identification<String>) -> Identification<String> {
+        let prob = self.calibration.calibrate(*id.prob());
+        Identification::new(id.label().clone(), prob)
+    }
+}
+
+impl Predict<String> for Calibrated {
+    fn predict_one(&self, line: &str) -> Result<Option<Identification<String>>, Error> {
+        Ok(self.inner.predict_one(line)?.map(|id| self.calibrate(id)))
+    }
+
+    fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+        Ok(self
+            .inner
+            .predict(line)?
+            .map(|ids| ids.into_iter().map(|id| self.calibrate(id)).collect()))
+    }
+}
+
+impl LanguageIdentifier for Calibrated {
+    fn metadata(&self) -> ModelMetadata {
+        ModelMetadata {
+            calibration: Some(self.calibration.clone()),
+            ..self.inner.metadata()
+        }
+    }
+
+    fn registry(&self) -> Option<&Registry> {
+        self.inner.registry()
+    }
+
+    fn predict_batch(&self, lines: &[&str]) -> Result<Vec<Option<Identification<String>>>, Error> {
+        Ok(self
+            .inner
+            .predict_batch(lines)?
+            .into_iter()
+            .map(|id| id.map(|id| self.calibrate(id)))
+            .collect())
+    }
+
+    /// Only identifications are calibrated, members' ones are kept as is.
+    fn predict_batch_with_members(&self, lines: &[&str]) -> Result<(LineIds, MemberIds), Error> {
+        let (ids, members) = self.inner.predict_batch_with_members(lines)?;
+        let ids = ids
+            .into_iter()
+            .map(|id| id.map(|id| self.calibrate(id)))
+            .collect();
+        Ok((ids, members))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::Calibration;
+
+    fn load(json: &str) -> Result<Calibration, crate::error::Error> {
+        let mut f = tempfile::NamedTempFile::new().unwrap();
+        f.write_all(json.as_bytes()).unwrap();
+        Calibration::from_path(f.path())
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn test_temperature() {
+        let calibration = load(r#"{"method": "temperature", "temperature": 2.0}"#).unwrap();
+        assert_close(calibration.calibrate(0.5), 0.5);
+        assert_close(calibration.calibrate(0.9), 0.75);
+        assert_close(calibration.calibrate(1.0), 1.0);
+
+        let identity = Calibration::Temperature { temperature: 1.0 };
+        assert_close(identity.calibrate(0.8), 0.8);
+
+        assert!(load(r#"{"method": "temperature", "temperature": 0.0}"#).is_err());
+    }
+
+    #[test]
+    fn test_isotonic() {
+        let calibration =
+            load(r#"{"method": "isotonic", "points": [[0.2, 0.1], [0.6, 0.3], [1.0, 0.9]]}"#)
+                .unwrap();
+        assert_close(calibration.calibrate(0.0), 0.1);
+        assert_close(calibration.calibrate(0.4), 0.2);
+        assert_close(calibration.calibrate(0.6), 0.3);
+        assert_close(calibration.calibrate(0.8), 0.6);
+        assert_close(calibration.calibrate(1.0), 0.9);
+
+        assert!(load(r#"{"method": "isotonic", "points": []}"#).is_err());
+        assert!(load(r#"{"method": "isotonic", "points": [[0.5, 0.5], [0.6, 0.4]]}"#).is_err());
+        assert!(load(r#"{"method": "platt"}"#).is_err());
+    }
+}
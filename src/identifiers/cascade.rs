@@ -0,0 +1,177 @@
+/*! Cascading identifiers
+
+Lines are identified by a primary identifier first,
+and the ones it is unsure about (no prediction, or a probability under a threshold)
+are identified again by a fallback identifier.
+
+The primary identifier should be built without a threshold (or a lower one),
+so that it still reports the predictions the fallback may replace.
+!*/
+use std::sync::Arc;
+
+use crate::error::Error;
+
+use super::{
+    identification::Identification,
+    model::{LanguageIdentifier, ModelMetadata, Predict},
+    registry::Registry,
+};
+
+/// Identifier falling back to another one on low-confidence lines.
+pub struct Cascade {
+    primary: Arc<dyn LanguageIdentifier>,
+    fallback: Arc<dyn LanguageIdentifier>,
+    min_prob: f32,
+}
+
+impl Cascade {
+    /// Use `fallback` on lines where `primary` has no prediction, or one with a probability under `min_prob`.
+    pub fn new(
+        primary: Arc<dyn LanguageIdentifier>,
+        fallback: Arc<dyn LanguageIdentifier>,
+        min_prob: f32,
+    ) -> Self {
+        Self {
+            primary,
+            fallback,
+            min_prob,
+        }
+    }
+
+    fn is_confident(&self, id: &Option<Identification<String>>) -> bool {
+        id.as_ref().is_some_and(|id| *id.prob() >= self.min_prob)
+    }
+}
+
+impl Predict<String> for Cascade {
+    fn predict_one(&self, line: &str) -> Result<Option<Identification<String>>, Error> {
+        let id = self.primary.predict_one(line)?;
+        if self.is_confident(&id) {
+            Ok(id)
+        } else {
+            self.fallback.predict_one(line)
+        }
+    }
+
+    fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+        match self.primary.predict(line)? {
+            Some(ids) if ids.first().is_some_and(|id| *id.prob() >= self.min_prob) => Ok(Some(ids)),
+            _ => self.fallback.predict(line),
+        }
+    }
+}
+
+impl LanguageIdentifier for Cascade {
+    fn metadata(&self) -> ModelMetadata {
+        let primary = self.primary.metadata();
+        ModelMetadata {
+            backend: format!("{}+{}", primary.backend, self.fallback.metadata().backend),
+            threshold: self.min_prob,
+            ..primary
+        }
+    }
+
+    fn registry(&self) -> Option<&Registry> {
+        self.primary.registry()
+    }
+
+    /// Identify lines with the primary identifier, then unsure ones with the fallback, in batches.
+    fn predict_batch(&self, lines: &[&str]) -> Result<Vec<Option<Identification<String>>>, Error> {
+        let mut ids = self.primary.predict_batch(lines)?;
+        let unsure: Vec<usize> = (0..ids.len())
+            .filter(|&idx| !self.is_confident(&ids[idx]))
+            .collect();
+        if unsure.is_empty() {
+            return Ok(ids);
+        }
+
+        let unsure_lines: Vec<&str> = unsure.iter().map(|&idx| lines[idx]).collect();
+        let fallback_ids = self.fallback.predict_batch(&unsure_lines)?;
+        for (idx, id) in unsure.into_iter().zip(fallback_ids) {
+            ids[idx] = id;
+        }
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use oxilangtag::LanguageTag;
+
+    use crate::{
+        error::Error,
+        identifiers::{
+            identification::Identification,
+            model::{LanguageIdentifier, ModelMetadata, Predict},
+        },
+    };
+
+    use super::Cascade;
+
+    /// Identifies every line as `label` with probability `prob`.
+    struct Constant {
+        label: &'static str,
+        prob: f32,
+    }
+
+    impl Predict<String> for Constant {
+        fn predict_one(&self, _: &str) -> Result<Option<Identification<String>>, Error> {
+            Ok(Some(Identification::new(
+                LanguageTag::parse(self.label.to_string()).unwrap(),
+                self.prob,
+            )))
+        }
+
+        fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+            Ok(self.predict_one(line)?.map(|id| vec![id]))
+        }
+    }
+
+    impl LanguageIdentifier for Constant {
+        fn metadata(&self) -> ModelMetadata {
+            ModelMetadata {
+                backend: self.label.to_string(),
+                path: None,
+                nb_labels: 1,
+                threshold: 0.0,
+                calibration: None,
+            }
+        }
+    }
+
+    fn label(id: &Option<Identification<String>>) -> String {
+        id.as_ref().unwrap().label().to_string()
+    }
+
+    #[test]
+    fn test_cascade() {
+        let fallback = Arc::new(Constant {
+            label: "fr",
+            prob: 0.9,
+        });
+        let confident = Cascade::new(
+            Arc::new(Constant {
+                label: "en",
+                prob: 0.85,
+            }),
+            fallback.clone(),
+            0.8,
+        );
+        let unsure = Cascade::new(
+            Arc::new(Constant {
+                label: "en",
+                prob: 0.5,
+            }),
+            fallback,
+            0.8,
+        );
+
+        assert_eq!(label(&confident.predict_one("line").unwrap()), "en");
+        assert_eq!(label(&unsure.predict_one("line").unwrap()), "fr");
+        let ids = unsure.predict_batch(&["a", "b"]).unwrap();
+        assert_eq!(ids.iter().map(label).collect::<Vec<_>>(), vec!["fr", "fr"]);
+        assert_eq!(unsure.metadata().backend, "en+fr");
+    }
+}
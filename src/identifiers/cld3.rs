@@ -0,0 +1,154 @@
+/*! CLD3 identifier
+
+[CLD3](https://github.com/google/cld3) backend, enabled by the `cld3` feature (which needs `libprotobuf`).
+
+CLD3 identifiers are mutated by predictions, so they can't be shared between threads:
+each prediction takes an identifier from a pool, creating one if the pool is empty.
+!*/
+use std::sync::{Arc, Mutex};
+
+use cld3::{DetectResult, NNetLanguageIdentifier};
+
+use crate::error::Error;
+
+use super::{
+    identification::Identification,
+    model::{LanguageIdentifier, ModelMetadata, Predict},
+    registry::{resolve_label, Registry},
+};
+
+/// Bytes of a line that are considered.
+const MIN_BYTES: i32 = 0;
+const MAX_BYTES: i32 = 1000;
+
+/// Number of languages supported by the CLD3 model.
+const NB_LABELS: usize = 107;
+
+/// Label of unknown languages.
+const UNKNOWN: &str = "und";
+
+/// CLD3 identifier, sendable between threads.
+struct Model(NNetLanguageIdentifier);
+
+// SAFETY: a model owns its state and is only used by the thread that took it from the pool.
+unsafe impl Send for Model {}
+
+/// CLD3 language identifier.
+pub struct Cld3 {
+    pool: Mutex<Vec<Model>>,
+    threshold: f32,
+    registry: Option<Arc<Registry>>,
+}
+
+impl Cld3 {
+    /// Create an identifier keeping predictions whose probability is at least `threshold`,
+    /// converting labels with `registry` if there's one.
+    pub fn new(threshold: f32, registry: Option<Registry>) -> Result<Self, Error> {
+        let cld3 = Self {
+            pool: Mutex::new(Vec::new()),
+            threshold,
+            registry: registry.map(Arc::new),
+        };
+        // fail early if identifiers can't be created
+        let model = cld3.model()?;
+        cld3.pool.lock().unwrap().push(model);
+        Ok(cld3)
+    }
+
+    /// Take an identifier from the pool, or create one.
+    fn model(&self) -> Result<Model, Error> {
+        if let Some(model) = self.pool.lock().unwrap().pop() {
+            return Ok(model);
+        }
+        NNetLanguageIdentifier::new(MIN_BYTES, MAX_BYTES)
+            .map(Model)
+            .map_err(|e| Error::Custom(format!("Could not create CLD3 identifier: {e}")))
+    }
+
+    /// Run `f` with an identifier of the pool.
+    fn with_model<T>(&self, f: impl FnOnce(&mut NNetLanguageIdentifier) -> T) -> Result<T, Error> {
+        let mut model = self.model()?;
+        let result = f(&mut model.0);
+        self.pool.lock().unwrap().push(model);
+        Ok(result)
+    }
+
+    /// Convert a CLD3 result, dropping unknown and low-probability ones.
+    fn identification(
+        &self,
+        result: DetectResult,
+    ) -> Result<Option<Identification<String>>, Error> {
+        let prob = result.probability as f32;
+        if result.language == UNKNOWN || prob < self.threshold {
+            return Ok(None);
+        }
+        let label = resolve_label(self.registry.as_deref(), &result.language)?;
+        Ok(Some(Identification::new(label, prob)))
+    }
+}
+
+impl Predict<String> for Cld3 {
+    fn predict_one(&self, line: &str) -> Result<Option<Identification<String>>, Error> {
+        let result = self.with_model(|model| model.find_language(line))?;
+        self.identification(result)
+    }
+
+    /// CLD3 only gives the most probable language of a line, so there's at most one prediction.
+    fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+        Ok(self.predict_one(line)?.map(|id| vec![id]))
+    }
+}
+
+impl LanguageIdentifier for Cld3 {
+    fn metadata(&self) -> ModelMetadata {
+        ModelMetadata {
+            backend: "cld3".to_string(),
+            path: None,
+            nb_labels: NB_LABELS,
+            threshold: self.threshold,
+            calibration: None,
+        }
+    }
+
+    fn registry(&self) -> Option<&Registry> {
+        self.registry.as_deref()
+    }
+
+    /// Identify lines with a single identifier of the pool.
+    fn predict_batch(&self, lines: &[&str]) -> Result<Vec<Option<Identification<String>>>, Error> {
+        let results = self.with_model(|model| {
+            lines
+                .iter()
+                .map(|line| model.find_language(line))
+                .collect::<Vec<_>>()
+        })?;
+        results
+            .into_iter()
+            .map(|result| self.identification(result))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::identifiers::model::{LanguageIdentifier, Predict};
+
+    use super::Cld3;
+
+    #[test]
+    fn test_predict() {
+        let cld3 = Cld3::new(0.7, None).unwrap();
+        let id = cld3.predict_one("This is English text.").unwrap().unwrap();
+        assert_eq!(id.label().to_string(), "en");
+
+        let ids = cld3
+            .predict_batch(&["Dies ist deutscher Text.", "Questo è un testo italiano."])
+            .unwrap();
+        let labels: Vec<_> = ids
+            .iter()
+            .map(|id| id.as_ref().unwrap().label().to_string())
+            .collect();
+        assert_eq!(labels, vec!["de", "it"]);
+        assert_eq!(cld3.metadata().backend, "cld3");
+    }
+}
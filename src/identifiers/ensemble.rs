@@ -0,0 +1,379 @@
+/*! Ensemble identification
+
+Lines are identified by several identifiers (members), whose predictions are combined (see [Combination]):
+
+- `agreement`: members have to agree on a language, whose probability is their mean one,
+- `vote`: languages get the weighted sum of the probabilities members give them, the best one wins
+  with its score divided by the total weight as probability,
+- `cascade`: the first member reaching a minimum probability decides, the last one deciding if none does.
+
+Members should be built without a threshold so that they report the predictions that get combined:
+the threshold of the ensemble applies to combined probabilities.
+
+The identifications of each member are kept alongside the combined ones (see [LanguageIdentifier::predict_batch_with_members]),
+so that documents can be annotated with what each member made of them.
+!*/
+use std::{collections::HashMap, fmt, str::FromStr, sync::Arc};
+
+use crate::error::Error;
+
+use super::{
+    identification::Identification,
+    model::{Backend, LanguageIdentifier, LineIds, MemberIds, ModelMetadata, Predict},
+    registry::Registry,
+};
+
+/// Parse a `<backend>` or `<backend>=<weight>` ensemble member (default weight is `1`).
+pub fn parse_member(spec: &str) -> Result<(Backend, f32), Error> {
+    match spec.split_once('=') {
+        None => Ok((spec.parse()?, 1.0)),
+        Some((backend, weight)) => {
+            let weight = weight.parse().map_err(|_| {
+                Error::Custom(format!(
+                    "invalid ensemble member {spec} (expected <backend>[=<weight>])"
+                ))
+            })?;
+            Ok((backend.parse()?, weight))
+        }
+    }
+}
+
+/// Default minimum probability of [Combination::Cascade].
+pub const DEFAULT_CASCADE_PROB: f32 = 0.8;
+
+/// Way of combining the predictions of an ensemble's members.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Combination {
+    /// Members have to agree.
+    Agreement,
+    /// Weighted vote.
+    WeightedVote,
+    /// First member reaching a minimum probability.
+    Cascade { min_prob: f32 },
+}
+
+impl FromStr for Combination {
+    type Err = Error;
+
+    /// Parse `agreement`, `vote`, `cascade` or `cascade=<min_prob>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            None if s == "agreement" => Ok(Self::Agreement),
+            None if s == "vote" => Ok(Self::WeightedVote),
+            None if s == "cascade" => Ok(Self::Cascade {
+                min_prob: DEFAULT_CASCADE_PROB,
+            }),
+            Some(("cascade", min_prob)) => min_prob
+                .parse()
+                .map(|min_prob| Self::Cascade { min_prob })
+                .map_err(|_| Error::Custom(format!("invalid cascade probability {min_prob}"))),
+            _ => Err(Error::Custom(format!(
+                "unknown ensemble combination {s} (expected agreement, vote or cascade[=<min_prob>])"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Combination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Agreement => write!(f, "agreement"),
+            Self::WeightedVote => write!(f, "vote"),
+            Self::Cascade { min_prob } => write!(f, "cascade={min_prob}"),
+        }
+    }
+}
+
+/// Identifier combining the predictions of several ones.
+pub struct EnsembleIdentifier {
+    members: Vec<(Arc<dyn LanguageIdentifier>, f32)>,
+    combination: Combination,
+    threshold: f32,
+}
+
+impl EnsembleIdentifier {
+    /// Combine the predictions of at least two weighted `members`,
+    /// keeping combined predictions whose probability is at least `threshold`.
+    ///
+    /// Weights are only used by [Combination::WeightedVote].
+    pub fn new(
+        members: Vec<(Arc<dyn LanguageIdentifier>, f32)>,
+        combination: Combination,
+        threshold: f32,
+    ) -> Result<Self, Error> {
+        if members.len() < 2 {
+            return Err(Error::Custom(
+                "an ensemble needs at least two identifiers".to_string(),
+            ));
+        }
+        if members.iter().any(|(_, weight)| *weight <= 0.0) {
+            return Err(Error::Custom(
+                "ensemble weights must be positive".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            members,
+            combination,
+            threshold,
+        })
+    }
+
+    /// Combine the predictions of each member for a line.
+    fn combine(&self, ids: &[Option<Identification<String>>]) -> Option<Identification<String>> {
+        let combined = match self.combination {
+            Combination::Agreement => {
+                let label = ids.first()?.as_ref()?.label();
+                let mut prob = 0.0;
+                for id in ids {
+                    let id = id.as_ref().filter(|id| id.label() == label)?;
+                    prob += id.prob();
+                }
+                Identification::new(label.clone(), prob / ids.len() as f32)
+            }
+            Combination::WeightedVote => {
+                let mut scores = HashMap::new();
+                for (id, (_, weight)) in ids.iter().zip(&self.members) {
+                    if let Some(id) = id {
+                        *scores.entry(id.label()).or_insert(0.0) += weight * id.prob();
+                    }
+                }
+                let total_weight: f32 = self.members.iter().map(|(_, weight)| weight).sum();
+                // ties are broken by tag so that results don't depend on map order
+                let (label, score) = scores.into_iter().max_by(|(a, a_score), (b, b_score)| {
+                    a_score
+                        .total_cmp(b_score)
+                        .then_with(|| b.as_str().cmp(a.as_str()))
+                })?;
+                Identification::new(label.clone(), score / total_weight)
+            }
+            Combination::Cascade { min_prob } => ids
+                .iter()
+                .flatten()
+                .find(|id| *id.prob() >= min_prob)
+                .or_else(|| ids.last()?.as_ref())?
+                .clone(),
+        };
+
+        (*combined.prob() >= self.threshold).then_some(combined)
+    }
+}
+
+impl Predict<String> for EnsembleIdentifier {
+    fn predict_one(&self, line: &str) -> Result<Option<Identification<String>>, Error> {
+        Ok(self.predict_batch(&[line])?.pop().flatten())
+    }
+
+    /// Ensembles only give their combined prediction, so there's at most one.
+    fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+        Ok(self.predict_one(line)?.map(|id| vec![id]))
+    }
+}
+
+impl LanguageIdentifier for EnsembleIdentifier {
+    fn metadata(&self) -> ModelMetadata {
+        let backends: Vec<_> = self
+            .members
+            .iter()
+            .map(|(member, _)| member.metadata().backend)
+            .collect();
+        ModelMetadata {
+            backend: format!("{}({})", self.combination, backends.join(",")),
+            threshold: self.threshold,
+            ..self.members[0].0.metadata()
+        }
+    }
+
+    fn registry(&self) -> Option<&Registry> {
+        self.members[0].0.registry()
+    }
+
+    fn predict_batch(&self, lines: &[&str]) -> Result<Vec<Option<Identification<String>>>, Error> {
+        Ok(self.predict_batch_with_members(lines)?.0)
+    }
+
+    fn predict_batch_with_members(&self, lines: &[&str]) -> Result<(LineIds, MemberIds), Error> {
+        let members = self
+            .members
+            .iter()
+            .map(|(member, _)| Ok((member.metadata().backend, member.predict_batch(lines)?)))
+            .collect::<Result<MemberIds, Error>>()?;
+
+        let ids = (0..lines.len())
+            .map(|idx| {
+                let line_ids: Vec<_> = members.iter().map(|(_, ids)| ids[idx].clone()).collect();
+                self.combine(&line_ids)
+            })
+            .collect();
+
+        Ok((ids, members))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use oxilangtag::LanguageTag;
+
+    use crate::{
+        error::Error,
+        identifiers::{
+            identification::Identification,
+            model::{LanguageIdentifier, ModelMetadata, Predict},
+        },
+    };
+
+    use super::{parse_member, Combination, EnsembleIdentifier};
+
+    /// Identifies lines as the word at a given position, with a given probability.
+    struct Nth(&'static str, usize, f32);
+
+    impl Predict<String> for Nth {
+        fn predict_one(&self, line: &str) -> Result<Option<Identification<String>>, Error> {
+            Ok(line.split_whitespace().nth(self.1).map(|word| {
+                Identification::new(LanguageTag::parse(word.to_string()).unwrap(), self.2)
+            }))
+        }
+
+        fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+            Ok(self.predict_one(line)?.map(|id| vec![id]))
+        }
+    }
+
+    impl LanguageIdentifier for Nth {
+        fn metadata(&self) -> ModelMetadata {
+            ModelMetadata {
+                backend: self.0.to_string(),
+                path: None,
+                nb_labels: 0,
+                threshold: 0.0,
+                calibration: None,
+            }
+        }
+    }
+
+    fn build(combination: Combination, weights: [f32; 2]) -> EnsembleIdentifier {
+        EnsembleIdentifier::new(
+            vec![
+                (Arc::new(Nth("first", 0, 0.6)), weights[0]),
+                (Arc::new(Nth("second", 1, 0.9)), weights[1]),
+            ],
+            combination,
+            0.4,
+        )
+        .unwrap()
+    }
+
+    fn label(id: Option<Identification<String>>) -> Option<String> {
+        id.map(|id| id.label().to_string())
+    }
+
+    #[test]
+    fn test_combination_from_str() {
+        assert_eq!(
+            "agreement".parse::<Combination>().unwrap(),
+            Combination::Agreement
+        );
+        assert_eq!(
+            "vote".parse::<Combination>().unwrap(),
+            Combination::WeightedVote
+        );
+        assert_eq!(
+            "cascade=0.7".parse::<Combination>().unwrap(),
+            Combination::Cascade { min_prob: 0.7 }
+        );
+        assert!("cascade=high".parse::<Combination>().is_err());
+        assert!("majority".parse::<Combination>().is_err());
+    }
+
+    #[test]
+    fn test_agreement() {
+        let ensemble = build(Combination::Agreement, [1.0, 1.0]);
+        let id = ensemble.predict_one("fr fr").unwrap().unwrap();
+        assert_eq!(id.label().as_str(), "fr");
+        assert!((id.prob() - 0.75).abs() < 1e-6);
+        assert!(ensemble.predict_one("fr en").unwrap().is_none());
+        assert!(ensemble.predict_one("fr").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_vote() {
+        let ensemble = build(Combination::WeightedVote, [1.0, 1.0]);
+        let id = ensemble.predict_one("fr en").unwrap().unwrap();
+        assert_eq!(id.label().as_str(), "en");
+        assert!((id.prob() - 0.45).abs() < 1e-6);
+        // 0.6 / 2 is under the threshold
+        assert!(ensemble.predict_one("fr").unwrap().is_none());
+
+        let ensemble = build(Combination::WeightedVote, [3.0, 1.0]);
+        assert_eq!(
+            label(ensemble.predict_one("fr en").unwrap()),
+            Some("fr".to_string())
+        );
+    }
+
+    #[test]
+    fn test_cascade() {
+        let ensemble = build(Combination::Cascade { min_prob: 0.8 }, [1.0, 1.0]);
+        assert_eq!(
+            label(ensemble.predict_one("fr en").unwrap()),
+            Some("en".to_string())
+        );
+        assert_eq!(label(ensemble.predict_one("fr").unwrap()), None);
+    }
+
+    #[test]
+    fn test_members() {
+        let ensemble = build(Combination::Agreement, [1.0, 1.0]);
+        let (ids, members) = ensemble
+            .predict_batch_with_members(&["fr fr", "fr en"])
+            .unwrap();
+        assert_eq!(
+            ids.into_iter().map(label).collect::<Vec<_>>(),
+            vec![Some("fr".to_string()), None]
+        );
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[1].0, "second");
+        assert_eq!(
+            members[1].1.iter().cloned().map(label).collect::<Vec<_>>(),
+            vec![Some("fr".to_string()), Some("en".to_string())]
+        );
+        assert_eq!(ensemble.metadata().backend, "agreement(first,second)");
+
+        let lines = "fr fr\nfr en\nfr fr";
+        let doc_ids = ensemble.weighted_ids(lines.lines()).unwrap();
+        let members: Vec<_> = doc_ids
+            .members()
+            .iter()
+            .map(|(backend, id)| (backend.as_str(), label(id.clone())))
+            .collect();
+        assert_eq!(
+            members,
+            vec![
+                ("first", Some("fr".to_string())),
+                ("second", Some("fr".to_string()))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_member() {
+        let (_, weight) = parse_member("whatlang").unwrap();
+        assert_eq!(weight, 1.0);
+        let (_, weight) = parse_member("whatlang=2.5").unwrap();
+        assert_eq!(weight, 2.5);
+        assert!(parse_member("whatlang=heavy").is_err());
+        assert!(parse_member("unknown=1").is_err());
+    }
+
+    #[test]
+    fn test_new() {
+        assert!(EnsembleIdentifier::new(
+            vec![(Arc::new(Nth("first", 0, 0.6)), 1.0)],
+            Combination::Agreement,
+            0.5
+        )
+        .is_err());
+    }
+}
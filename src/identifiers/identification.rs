@@ -5,14 +5,20 @@ All identifiers should implement [Identifier] to be useable in processing and pi
 use std::ops::Deref;
 
 use crate::error::Error;
+#[cfg(feature = "fasttext")]
 use fasttext::Prediction;
 
-use oxilangtag::{LanguageTag, LanguageTagParseError};
+use oxilangtag::LanguageTag;
+#[cfg(feature = "fasttext")]
+use oxilangtag::LanguageTagParseError;
 
 use oscar_io::common::Identification as IdentificationExternal;
 
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "fasttext")]
+use super::normalize;
+
 /// newtype idiom over [oscar_io::Identification]
 #[derive(Debug, Clone)]
 pub struct Identification<T: Deref<Target = str> + Clone>(IdentificationExternal<T>);
@@ -34,17 +40,12 @@ impl<T: Deref<Target = str> + Clone> Deref for Identification<T> {
     }
 }
 /// for fasttext2 predictions
+#[cfg(feature = "fasttext")]
 impl TryFrom<Prediction> for Identification<String> {
     type Error = LanguageTagParseError;
     fn try_from(prediction: Prediction) -> Result<Self, LanguageTagParseError> {
-        // skip __label__
-        let label = prediction.label.chars().skip(9).collect::<String>();
-
-        //convert to valid bcp47
-        let label = label.replace('_', "-");
-
         Ok(Self(IdentificationExternal::new(
-            LanguageTag::parse_and_normalize(&label)?,
+            normalize(&prediction.label)?,
             prediction.prob,
         )))
         // debug!("{prediction:?}");
@@ -57,7 +58,7 @@ impl TryFrom<Prediction> for Identification<String> {
 
 //
 
-#[cfg(test)]
+#[cfg(all(test, feature = "fasttext"))]
 mod tests {
     use fasttext::Prediction;
 
@@ -0,0 +1,117 @@
+/*! Lingua identifier
+
+[Lingua](https://github.com/pemistahl/lingua-rs) backend, enabled by the `lingua` feature.
+
+Lingua is slower than fastText, but more accurate on short lines:
+it is mostly meant to be used as a fallback for lines fastText is unsure about (see [super::Cascade]).
+Language models are loaded lazily, on first use.
+!*/
+use std::sync::Arc;
+
+use lingua::{Language, LanguageDetector, LanguageDetectorBuilder};
+
+use crate::error::Error;
+
+use super::{
+    identification::Identification,
+    model::{LanguageIdentifier, ModelMetadata, Predict},
+    registry::{resolve_label, Registry},
+};
+
+/// Lingua language identifier, over all of Lingua's languages.
+pub struct Lingua {
+    detector: LanguageDetector,
+    threshold: f32,
+    registry: Option<Arc<Registry>>,
+}
+
+impl Lingua {
+    /// Create an identifier keeping predictions whose confidence is at least `threshold`,
+    /// converting labels with `registry` if there's one.
+    pub fn new(threshold: f32, registry: Option<Registry>) -> Self {
+        Self {
+            detector: LanguageDetectorBuilder::from_all_languages().build(),
+            threshold,
+            registry: registry.map(Arc::new),
+        }
+    }
+
+    /// Convert a Lingua prediction, dropping low-confidence ones.
+    fn identification(
+        &self,
+        language: Language,
+        confidence: f64,
+    ) -> Result<Option<Identification<String>>, Error> {
+        let prob = confidence as f32;
+        if prob < self.threshold {
+            return Ok(None);
+        }
+        let code = language.iso_code_639_1().to_string();
+        let label = resolve_label(self.registry.as_deref(), &code)?;
+        Ok(Some(Identification::new(label, prob)))
+    }
+}
+
+impl Predict<String> for Lingua {
+    fn predict_one(&self, line: &str) -> Result<Option<Identification<String>>, Error> {
+        match self
+            .detector
+            .compute_language_confidence_values(line)
+            .into_iter()
+            .next()
+        {
+            Some((language, confidence)) => self.identification(language, confidence),
+            None => Ok(None),
+        }
+    }
+
+    fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+        let mut identifications = Vec::new();
+        // confidence values are sorted in descending order
+        for (language, confidence) in self.detector.compute_language_confidence_values(line) {
+            match self.identification(language, confidence)? {
+                Some(id) => identifications.push(id),
+                None => break,
+            }
+        }
+        if identifications.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(identifications))
+        }
+    }
+}
+
+impl LanguageIdentifier for Lingua {
+    fn metadata(&self) -> ModelMetadata {
+        ModelMetadata {
+            backend: "lingua".to_string(),
+            path: None,
+            nb_labels: Language::all().len(),
+            threshold: self.threshold,
+            calibration: None,
+        }
+    }
+
+    fn registry(&self) -> Option<&Registry> {
+        self.registry.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::identifiers::model::Predict;
+
+    use super::Lingua;
+
+    #[test]
+    fn test_predict() {
+        let lingua = Lingua::new(0.5, None);
+        let id = lingua
+            .predict_one("Ceci est une phrase en français.")
+            .unwrap()
+            .unwrap();
+        assert_eq!(id.label().to_string(), "fr");
+        assert!(lingua.predict_one("").unwrap().is_none());
+    }
+}
@@ -1,12 +1,50 @@
 /*! Language identification models
 
-Holds an [Identifier] trait for implementing other ones.
+Holds a [LanguageIdentifier] trait for implementing other ones.
 
-The current identifier used is [fasttext](https://fasttext.cc) !*/
+The default identifier is [fasttext](https://fasttext.cc) (`fasttext` feature, enabled by default).
+Without it, the pure-Rust [Whatlang] identifier is used instead.
+[CLD3](https://github.com/google/cld3) and [Lingua](https://github.com/pemistahl/lingua-rs)
+can be used instead with the `cld3` and `lingua` features (see [Backend]),
+as well as transformer-based models exported to ONNX with the `onnx` feature,
+or as a fallback on lines the main identifier is unsure about (see [Cascade]).
+Several identifiers can also be combined (see [EnsembleIdentifier]).
+Line thresholds can be set per language (see [LineThresholds]),
+and apply to probabilities that can be calibrated first (see [Calibration]).
+Identifications of repeated lines can be cached (see [Cached]). !*/
+mod cache;
+mod calibration;
+mod cascade;
+#[cfg(feature = "cld3")]
+mod cld3;
+mod ensemble;
 pub(crate) mod identification;
+#[cfg(feature = "lingua")]
+mod lingua;
 pub(crate) mod model;
 mod multilingual;
+#[cfg(feature = "onnx")]
+mod onnx;
+pub mod registry;
 mod tag_convert;
+mod thresholds;
+mod whatlang;
 
+pub use cache::{CacheStats, Cached};
+pub use calibration::{Calibrated, Calibration};
+pub use cascade::Cascade;
+#[cfg(feature = "cld3")]
+pub use cld3::Cld3;
+pub use ensemble::{parse_member, Combination, EnsembleIdentifier};
+#[cfg(feature = "lingua")]
+pub use lingua::Lingua;
+pub use model::{Backend, Granularity, LanguageIdentifier, ModelKind, ModelMetadata, Predict};
 pub use multilingual::Multilingual;
 pub use multilingual::StrictMultilingual;
+#[cfg(feature = "onnx")]
+pub(crate) use onnx::OnnxClassifier;
+#[cfg(feature = "onnx")]
+pub use onnx::{Onnx, OnnxOptions};
+pub use tag_convert::{normalize, suppress_script};
+pub use thresholds::{LineThresholds, Thresholded};
+pub use whatlang::Whatlang;
@@ -2,19 +2,35 @@
    Uses [oxilangtag::LanguageTag] rather than Lang.
 * !*/
 use std::{
-    collections::{HashMap, HashSet},
-    ops::Deref,
-    path::Path,
-    str::Lines,
+    collections::HashMap,
+    ops::{Deref, Range},
+    path::PathBuf,
+    str::{FromStr, Lines},
 };
+#[cfg(feature = "fasttext")]
+use std::{path::Path, sync::Arc};
 
+#[cfg(feature = "fasttext")]
 use fasttext::FastText as FastTextLib;
-use log::error;
+#[cfg(feature = "fasttext")]
+use log::{debug, error};
 use oxilangtag::LanguageTag;
+#[cfg(feature = "fasttext")]
+use rayon::prelude::*;
+use serde::Serialize;
 
 use crate::error::Error;
 
-use super::{identification::Identification, tag_convert::Tag};
+use super::tag_convert::{normalize, suppress_script};
+use super::{
+    cache::CacheStats, calibration::Calibration, identification::Identification, registry::Registry,
+};
+
+/// Identifications of a batch of lines.
+pub type LineIds = Vec<Option<Identification<String>>>;
+
+/// Line identifications of each member of an ensemble identifier, labelled by backend.
+pub type MemberIds = Vec<(String, LineIds)>;
 
 /// Covers individual sentence identifications, lang bins and total size of document in bytes
 #[derive(Debug)]
@@ -22,6 +38,7 @@ pub struct DocIdentification<T: Deref<Target = str> + Clone> {
     line_ids: Vec<Option<Identification<T>>>,
     lang_bins: HashMap<Option<LanguageTag<T>>, (usize, f32)>,
     total_size: usize,
+    members: Vec<(String, Option<Identification<T>>)>,
 }
 
 impl<T: Deref<Target = str> + Clone> DocIdentification<T> {
@@ -36,31 +53,462 @@ impl<T: Deref<Target = str> + Clone> DocIdentification<T> {
     pub fn total_size(&self) -> usize {
         self.total_size
     }
+
+    /// Get the most present language of the document according to each member of an ensemble identifier,
+    /// labelled by backend (empty for other identifiers).
+    pub fn members(&self) -> &[(String, Option<Identification<T>>)] {
+        &self.members
+    }
+
+    /// Get the `k` languages covering most bytes, most present first,
+    /// with their byte-weighted confidence as probability.
+    ///
+    /// Lines without identification are not counted as a candidate.
+    pub fn candidates(&self, k: usize) -> Vec<Identification<T>> {
+        let mut candidates: Vec<_> = self
+            .lang_bins
+            .iter()
+            .filter_map(|(lang, (count, confidence))| {
+                lang.as_ref().map(|lang| (lang, *count, *confidence))
+            })
+            .collect();
+        // ties are broken by tag so that candidates don't depend on map order
+        candidates.sort_by(|(a, a_count, _), (b, b_count, _)| {
+            b_count
+                .cmp(a_count)
+                .then_with(|| a.as_str().cmp(b.as_str()))
+        });
+        candidates
+            .into_iter()
+            .take(k)
+            .map(|(lang, _, confidence)| Identification::new(lang.clone(), confidence))
+            .collect()
+    }
+}
+
+impl DocIdentification<String> {
+    /// Weight the languages of line identifications by their line's byte count.
+    fn from_ids(lines: &[String], ids: Vec<Option<Identification<String>>>) -> Self {
+        // per-lang and total byte counts
+        // lang_count maps Lang -> (lang_byte_count, sum(byte_count*prob))
+        let mut lang_count = HashMap::new();
+        let mut total_count = 0;
+        for (line, id) in lines.iter().zip(&ids) {
+            // map Identification to its lang, or keep None to store the "None" language identification
+            let ide_label = id.as_ref().map(|i| i.label().clone());
+            let ide_prob = id.as_ref().map(|i| *i.prob());
+            // get length of current line
+            let byte_count = line.len();
+
+            lang_count
+                .entry(ide_label)
+                .and_modify(|(count, count_times_prob)| {
+                    *count += byte_count;
+                    *count_times_prob += byte_count as f32 * ide_prob.unwrap_or(1.0f32);
+                })
+                .or_insert((byte_count, byte_count as f32 * ide_prob.unwrap_or(1.0f32)));
+
+            total_count += byte_count;
+        }
+
+        // divide by total count to get probs between 0 and 1.
+        for (_, count_times_prob) in lang_count.values_mut() {
+            *count_times_prob /= total_count as f32;
+        }
+
+        Self {
+            line_ids: ids,
+            lang_bins: lang_count,
+            total_size: total_count,
+            members: Vec::new(),
+        }
+    }
+}
+
+/// Label format of a fastText model, conditioning the conversion of its labels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ModelKind {
+    /// `lid.176.bin`-style labels, holding a language code (`__label__en`, `__label__als`).
+    #[default]
+    Lid176,
+    /// GlotLID/NLLB-style labels, holding an ISO 639-3 code and a script (`__label__eng_Latn`).
+    ///
+    /// Scripts are dropped when they are the default one of the language (see [suppress_script]),
+    /// so that `eng_Latn` and `srp_Cyrl` become `en` and `sr-Cyrl`.
+    GlotLid,
+}
+
+impl ModelKind {
+    /// Convert a label (without its prefix) into a language tag.
+    pub fn to_languagetag(self, label: &str) -> Result<LanguageTag<String>, Error> {
+        let tag = normalize(label)?;
+        Ok(match self {
+            Self::Lid176 => tag,
+            Self::GlotLid => suppress_script(tag),
+        })
+    }
 }
 
-pub trait ModelKind {
-    fn labels() -> &'static HashSet<LanguageTag<String>>;
+impl FromStr for ModelKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lid176" => Ok(Self::Lid176),
+            "glotlid" | "nllb" => Ok(Self::GlotLid),
+            other => Err(Error::Custom(format!(
+                "unknown model kind {other} (expected lid176, glotlid or nllb)"
+            ))),
+        }
+    }
 }
 
 /// Prediction trait.
 ///
-/// Enables prediction on a single line (top-1 and top-k) and on a set of lines.
+/// Enables prediction on a single line (top-1 and top-k).
 pub trait Predict<T: Deref<Target = str> + Clone> {
     fn predict_one(&self, line: &str) -> Result<Option<Identification<T>>, Error>;
     fn predict(&self, line: &str) -> Result<Option<Vec<Identification<T>>>, Error>;
-    fn weighted_ids(&self, lines: Lines) -> Result<DocIdentification<T>, Error>;
+}
+
+/// Description of a language identification model.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModelMetadata {
+    /// Name of the backend (e.g. `fasttext`).
+    pub backend: String,
+    /// Model file, for backends that load one.
+    pub path: Option<PathBuf>,
+    /// Number of labels the model can predict.
+    pub nb_labels: usize,
+    /// Minimum probability of line identifications.
+    pub threshold: f32,
+    /// Calibration of probabilities, if any (see [Calibration]).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calibration: Option<Calibration>,
+}
+
+/// Available language identification backends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// fastText model (e.g. `lid.176.bin`).
+    #[cfg(feature = "fasttext")]
+    #[default]
+    FastText,
+    /// Whatlang, whose model is embedded.
+    #[cfg_attr(not(feature = "fasttext"), default)]
+    Whatlang,
+    /// CLD3, whose model is embedded.
+    #[cfg(feature = "cld3")]
+    Cld3,
+    /// Lingua, whose models are embedded.
+    #[cfg(feature = "lingua")]
+    Lingua,
+    /// ONNX sequence classification model (directory at the model path).
+    #[cfg(feature = "onnx")]
+    Onnx,
+}
+
+impl FromStr for Backend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            #[cfg(feature = "fasttext")]
+            "fasttext" => Ok(Self::FastText),
+            "whatlang" => Ok(Self::Whatlang),
+            #[cfg(feature = "cld3")]
+            "cld3" => Ok(Self::Cld3),
+            #[cfg(feature = "lingua")]
+            "lingua" => Ok(Self::Lingua),
+            #[cfg(feature = "onnx")]
+            "onnx" => Ok(Self::Onnx),
+            #[cfg(not(feature = "fasttext"))]
+            "fasttext" => Err(Error::Custom(
+                "ungoliant was built without the fasttext feature".to_string(),
+            )),
+            #[cfg(not(feature = "cld3"))]
+            "cld3" => Err(Error::Custom(
+                "ungoliant was built without the cld3 feature".to_string(),
+            )),
+            #[cfg(not(feature = "lingua"))]
+            "lingua" => Err(Error::Custom(
+                "ungoliant was built without the lingua feature".to_string(),
+            )),
+            #[cfg(not(feature = "onnx"))]
+            "onnx" => Err(Error::Custom(
+                "ungoliant was built without the onnx feature".to_string(),
+            )),
+            other => Err(Error::Custom(format!(
+                "unknown identifier {other} (expected fasttext, whatlang, cld3, lingua or onnx)"
+            ))),
+        }
+    }
+}
+
+/// Granularity of the identification of documents.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Granularity {
+    /// Each line is identified.
+    #[default]
+    Line,
+    /// Each paragraph (lines between blank lines) is identified, its lines sharing its identification.
+    Paragraph,
+    /// The whole document is identified, its lines sharing its identification.
+    Document,
+}
+
+impl Granularity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Line => "line",
+            Self::Paragraph => "paragraph",
+            Self::Document => "document",
+        }
+    }
+
+    /// Get the line ranges of the units to identify.
+    ///
+    /// Blank lines are not part of any paragraph.
+    fn units(&self, lines: &[String]) -> Vec<Range<usize>> {
+        match self {
+            Self::Line => (0..lines.len()).map(|idx| idx..idx + 1).collect(),
+            Self::Document => std::iter::once(0..lines.len()).collect(),
+            Self::Paragraph => {
+                let mut units = Vec::new();
+                let mut start = None;
+                for (idx, line) in lines.iter().enumerate() {
+                    match (line.trim().is_empty(), start) {
+                        (false, None) => start = Some(idx),
+                        (true, Some(paragraph_start)) => {
+                            units.push(paragraph_start..idx);
+                            start = None;
+                        }
+                        _ => (),
+                    }
+                }
+                if let Some(paragraph_start) = start {
+                    units.push(paragraph_start..lines.len());
+                }
+                units
+            }
+        }
+    }
+}
+
+impl FromStr for Granularity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "line" => Ok(Self::Line),
+            "paragraph" => Ok(Self::Paragraph),
+            "document" => Ok(Self::Document),
+            other => Err(Error::Custom(format!(
+                "unknown identification granularity {other} (expected line, paragraph or document)"
+            ))),
+        }
+    }
+}
+
+/// Language identification backend.
+///
+/// Pipelines use identifiers through this trait, so that backends other than fastText can be plugged in.
+/// Backends implement [Predict] and [LanguageIdentifier::metadata],
+/// and get batch and document-level identification from the provided methods.
+pub trait LanguageIdentifier: Predict<String> + Send + Sync {
+    /// Get model metadata.
+    fn metadata(&self) -> ModelMetadata;
+
+    /// Get the language registry, if the identifier uses one (see [Registry]).
+    fn registry(&self) -> Option<&Registry> {
+        None
+    }
+
+    /// Identify a batch of lines, one identification per line.
+    ///
+    /// Backends that can batch predictions should override this.
+    fn predict_batch(&self, lines: &[&str]) -> Result<Vec<Option<Identification<String>>>, Error> {
+        lines.iter().map(|line| self.predict_one(line)).collect()
+    }
+
+    /// Identify a batch of lines, along with the identifications of each member of ensemble identifiers
+    /// (see [super::EnsembleIdentifier]).
+    ///
+    /// Identifiers wrapping others should forward members.
+    fn predict_batch_with_members(&self, lines: &[&str]) -> Result<(LineIds, MemberIds), Error> {
+        Ok((self.predict_batch(lines)?, Vec::new()))
+    }
+
+    /// Get cache hit statistics, if the identifier caches identifications (see [super::Cached]).
+    fn cache_stats(&self) -> Option<CacheStats> {
+        None
+    }
+
+    /// Identify each line of a document, and weight languages by their byte count.
+    fn weighted_ids(&self, lines: Lines) -> Result<DocIdentification<String>, Error> {
+        self.weighted_ids_by(lines, Granularity::Line)
+    }
+
+    /// Identify a document at a given granularity, and weight languages by their byte count.
+    ///
+    /// There's still one identification per line, lines of a same unit sharing the unit's identification.
+    fn weighted_ids_by(
+        &self,
+        lines: Lines,
+        granularity: Granularity,
+    ) -> Result<DocIdentification<String>, Error> {
+        // filter out unicode null chars
+        // this prevents fasttext errors and hopefully improves
+        // corpus quality
+        // TODO: check if we need this line
+        let lines: Vec<String> = lines.map(|l| l.replace(char::from(0), "")).collect();
+        let (ids, members) = match granularity {
+            Granularity::Line => self.predict_batch_with_members(
+                &lines.iter().map(String::as_str).collect::<Vec<_>>(),
+            )?,
+            _ => {
+                let units = granularity.units(&lines);
+                // identifiers such as fastText only read the first line of what they're given
+                let texts: Vec<String> = units
+                    .iter()
+                    .map(|unit| lines[unit.clone()].join(" "))
+                    .collect();
+                let (unit_ids, unit_members) = self.predict_batch_with_members(
+                    &texts.iter().map(String::as_str).collect::<Vec<_>>(),
+                )?;
+
+                // lines of a unit share its identification
+                let spread = |unit_ids: Vec<Option<Identification<String>>>| {
+                    let mut ids = vec![None; lines.len()];
+                    for (unit, id) in units.iter().zip(unit_ids) {
+                        for line_id in &mut ids[unit.clone()] {
+                            line_id.clone_from(&id);
+                        }
+                    }
+                    ids
+                };
+                let members = unit_members
+                    .into_iter()
+                    .map(|(backend, ids)| (backend, spread(ids)))
+                    .collect();
+                (spread(unit_ids), members)
+            }
+        };
+
+        let mut doc_ids = DocIdentification::from_ids(&lines, ids);
+        doc_ids.members = members
+            .into_iter()
+            .map(|(backend, ids)| {
+                let best = DocIdentification::from_ids(&lines, ids)
+                    .candidates(1)
+                    .into_iter()
+                    .next();
+                (backend, best)
+            })
+            .collect();
+
+        Ok(doc_ids)
+    }
 }
 
 /// FastTextModel.
 ///
 /// ModelKind will condition the implementation of the tag conversion
+#[cfg(feature = "fasttext")]
 pub struct FastText {
     inner: FastTextLib,
+    path: PathBuf,
     pub k: i32,
     pub threshold: f32,
+    label_prefix: String,
+    kind: ModelKind,
+    registry: Option<Arc<Registry>>,
+}
+
+#[cfg(feature = "fasttext")]
+impl FastText {
+    /// Convert a label using the registry if there's one.
+    ///
+    /// Registry labels are matched as is, while the label prefix is stripped before converting the label
+    /// according to the model kind.
+    fn to_languagetag(&self, label: &str) -> Result<LanguageTag<String>, Error> {
+        if let Some(tag) = self
+            .registry
+            .as_ref()
+            .and_then(|registry| registry.label(label))
+        {
+            return Ok(tag.clone());
+        }
+        let label = label.strip_prefix(&self.label_prefix).unwrap_or(label);
+        self.kind.to_languagetag(label)
+    }
+}
+
+#[cfg(feature = "fasttext")]
+impl LanguageIdentifier for FastText {
+    fn metadata(&self) -> ModelMetadata {
+        let nb_labels = match self.inner.get_labels() {
+            Ok((labels, _)) => labels.len(),
+            Err(e) => {
+                error!("Could not get model labels: {e}");
+                0
+            }
+        };
+        ModelMetadata {
+            backend: "fasttext".to_string(),
+            path: Some(self.path.clone()),
+            nb_labels,
+            threshold: self.threshold,
+            calibration: None,
+        }
+    }
+
+    fn registry(&self) -> Option<&Registry> {
+        self.registry.as_deref()
+    }
+
+    /// Identify lines in parallel, converting each distinct label only once per batch.
+    fn predict_batch(&self, lines: &[&str]) -> Result<Vec<Option<Identification<String>>>, Error> {
+        let predictions = lines
+            .par_iter()
+            .with_min_len(BATCH_MIN_LEN)
+            .map(|line| self.inner.predict(line, 1, self.threshold))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let mut tags: HashMap<String, LanguageTag<String>> = HashMap::new();
+        predictions
+            .into_iter()
+            .map(|pred| {
+                let pred = match pred.into_iter().next() {
+                    Some(pred) => pred,
+                    None => return Ok(None),
+                };
+                let label = match tags.get(&pred.label) {
+                    Some(label) => label.clone(),
+                    None => {
+                        let label = self.to_languagetag(&pred.label).map_err(|e| {
+                            // unknown labels can occur on a lot of lines, it's up to the caller to report them.
+                            debug!("Couldn't parse label {}: {e:?}", &pred.label);
+                            Error::UnknownLang(pred.label.clone())
+                        })?;
+                        tags.insert(pred.label, label.clone());
+                        label
+                    }
+                };
+                Ok(Some(Identification::new(label, pred.prob)))
+            })
+            .collect()
+    }
 }
 
+/// Minimum number of lines predicted by each task of [FastText::predict_batch].
+///
+/// Documents are already processed in parallel, so small documents are better predicted on a single thread.
+#[cfg(feature = "fasttext")]
+const BATCH_MIN_LEN: usize = 64;
+
 /// Prediction for new tags/model
+#[cfg(feature = "fasttext")]
 impl Predict<String> for FastText {
     fn predict_one(&self, line: &str) -> Result<Option<Identification<String>>, Error> {
         let pred = self.inner.predict(line, 1, self.threshold)?;
@@ -72,8 +520,7 @@ impl Predict<String> for FastText {
             // The idea is to move out of pred, since we won't need it afterwards.
             let pred = pred.into_iter().next().unwrap();
             // convert prediction to newtag
-            let pred_to_languagetag: Result<LanguageTag<String>, _> =
-                Tag::new(&pred.label).try_into();
+            let pred_to_languagetag = self.to_languagetag(&pred.label);
             match pred_to_languagetag {
                 Ok(label) => {
                     let id = Identification::new(label, pred.prob);
@@ -81,8 +528,9 @@ impl Predict<String> for FastText {
                     Ok(Some(id))
                 }
                 Err(e) => {
-                    error!("Couldn't parse label {}: {e:?}", &pred.label);
-                    Err(e.into())
+                    // unknown labels can occur on a lot of lines, it's up to the caller to report them.
+                    debug!("Couldn't parse label {}: {e:?}", &pred.label);
+                    Err(Error::UnknownLang(pred.label))
                 }
             }
         }
@@ -97,7 +545,7 @@ impl Predict<String> for FastText {
                 .into_iter()
                 //TODO: try_into coerces into OldTag?
                 .map(|pred| {
-                    let label: Result<LanguageTag<String>, _> = Tag::new(&pred.label).try_into();
+                    let label = self.to_languagetag(&pred.label);
                     match label {
                         Ok(l) => Ok(Identification::new(l, pred.prob)),
                         Err(e) => Err(e),
@@ -106,7 +554,7 @@ impl Predict<String> for FastText {
                 .filter_map(|pred_result| match pred_result {
                     Ok(p) => Some(p),
                     Err(e) => {
-                        error!("Error with tag: {e}");
+                        error!("Error with tag: {e:?}");
                         None
                     }
                 })
@@ -115,66 +563,24 @@ impl Predict<String> for FastText {
             Ok(Some(identifications))
         }
     }
-
-    fn weighted_ids(&self, lines: Lines) -> Result<DocIdentification<String>, Error> {
-        // per-lang and total byte counts
-        // lang_count maps Lang -> (lang_byte_count, sum(byte_count*prob))
-        let mut lang_count = HashMap::new();
-        let mut total_count = 0;
-
-        // filter out unicode null chars
-        // this prevents fasttext errors and hopefully improves
-        // corpus quality
-        // TODO: check if we need this line
-        let lines = lines.map(|l| l.replace(char::from(0), ""));
-
-        let ids: Vec<Option<Identification<_>>> = lines
-            .map(|line| {
-                // identify
-                let id = self.predict_one(&line);
-
-                // add to byte count for document-level identification
-                if let Ok(ref ide) = id {
-                    // map Identification to its lang, or keep None to store the "None" language identification
-                    let ide_label = ide.as_ref().map(|i| i.label().clone());
-                    let ide_prob = ide.as_ref().map(|i| *i.prob());
-                    // get length of current line
-                    let byte_count = line.len();
-
-                    lang_count
-                        .entry(ide_label)
-                        .and_modify(|(count, count_times_prob)| {
-                            *count += byte_count;
-                            *count_times_prob += byte_count as f32 * ide_prob.unwrap_or(1.0f32);
-                        })
-                        .or_insert((byte_count, byte_count as f32 * ide_prob.unwrap_or(1.0f32)));
-
-                    total_count += byte_count;
-                }
-                id
-            })
-            .collect::<Result<_, Error>>()?;
-
-        // divide by total count to get probs between 0 and 1.
-        for (_, count_times_prob) in lang_count.values_mut() {
-            *count_times_prob /= total_count as f32;
-        }
-
-        Ok(DocIdentification {
-            line_ids: ids,
-            lang_bins: lang_count,
-            total_size: total_count,
-        })
-    }
 }
 
+/// Default prefix of fastText labels.
+#[cfg(feature = "fasttext")]
+pub const LABEL_PREFIX: &str = "__label__";
+
 /// Fasttext builder.
+#[cfg(feature = "fasttext")]
 pub struct FastTextBuilder<'a> {
     path: Option<&'a Path>,
     k: Option<i32>,
     threshold: Option<f32>,
+    label_prefix: Option<String>,
+    kind: ModelKind,
+    registry: Option<Arc<Registry>>,
 }
 
+#[cfg(feature = "fasttext")]
 impl<'a> FastTextBuilder<'a> {
     fn init_fasttextlib(path: &str) -> Result<fasttext::FastText, Error> {
         let mut ft = FastTextLib::new();
@@ -186,23 +592,25 @@ impl<'a> FastTextBuilder<'a> {
     /// - k: 1
     /// threshold: 0.8
     pub fn build_or_default(&self) -> Result<FastText, Error> {
-        let inner = {
-            let path = match self.path {
-                Some(p) => p
-                    .to_str()
-                    .ok_or(Error::Custom("Could not parse path.".to_string()))?,
-                None => "lid.208a.bin",
-            };
-            Self::init_fasttextlib(path)?
+        let path = match self.path {
+            Some(p) => p
+                .to_str()
+                .ok_or(Error::Custom("Could not parse path.".to_string()))?,
+            None => "lid.208a.bin",
         };
+        let inner = Self::init_fasttextlib(path)?;
 
         let k = self.k.unwrap_or(1);
         let threshold = self.threshold.unwrap_or(0.8);
 
         Ok(FastText {
             inner,
+            path: PathBuf::from(path),
             k,
             threshold,
+            label_prefix: self.label_prefix_or_default(),
+            kind: self.kind,
+            registry: self.registry.clone(),
         })
     }
 
@@ -228,10 +636,20 @@ impl<'a> FastTextBuilder<'a> {
             .ok_or(Error::Custom("Couldn't parse path".to_string()))?;
         Ok(FastText {
             inner: Self::init_fasttextlib(path)?,
+            path: PathBuf::from(path),
             k: self.k.unwrap(),
             threshold: self.threshold.unwrap(),
+            label_prefix: self.label_prefix_or_default(),
+            kind: self.kind,
+            registry: self.registry.clone(),
         })
     }
+
+    fn label_prefix_or_default(&self) -> String {
+        self.label_prefix
+            .clone()
+            .unwrap_or_else(|| LABEL_PREFIX.to_string())
+    }
     pub fn path<'b>(&'b mut self, path: &'a Path) -> &'b mut FastTextBuilder<'a> {
         self.path = Some(path);
         self
@@ -246,14 +664,36 @@ impl<'a> FastTextBuilder<'a> {
         self.threshold = Some(threshold);
         self
     }
+
+    /// Set the prefix of model labels (default is `__label__`).
+    pub fn label_prefix<'b>(&'b mut self, prefix: &str) -> &'b mut FastTextBuilder<'a> {
+        self.label_prefix = Some(prefix.to_string());
+        self
+    }
+
+    /// Set the label format of the model (default is [ModelKind::Lid176]).
+    pub fn kind<'b>(&'b mut self, kind: ModelKind) -> &'b mut FastTextBuilder<'a> {
+        self.kind = kind;
+        self
+    }
+
+    /// Use a language registry to convert labels (see [Registry]).
+    pub fn registry<'b>(&'b mut self, registry: Registry) -> &'b mut FastTextBuilder<'a> {
+        self.registry = Some(Arc::new(registry));
+        self
+    }
 }
 
+#[cfg(feature = "fasttext")]
 impl<'a> Default for FastTextBuilder<'a> {
     fn default() -> Self {
         Self {
             path: Some(Path::new("lid.176.bin")),
             k: Some(1),
             threshold: Some(0.8),
+            label_prefix: None,
+            kind: ModelKind::default(),
+            registry: None,
         }
     }
 }
@@ -262,9 +702,114 @@ impl<'a> Default for FastTextBuilder<'a> {
 mod tests {
     use std::path::Path;
 
-    use super::{FastText, FastTextBuilder, Predict};
+    use oxilangtag::LanguageTag;
+
+    #[cfg(feature = "fasttext")]
+    use super::{FastText, FastTextBuilder};
+    use super::{Granularity, LanguageIdentifier, ModelKind, ModelMetadata, Predict};
+    use crate::{error::Error, identifiers::identification::Identification};
+
+    /// Identifies lines starting with `en`/`fr` as such, with probability 0.5.
+    struct Prefix;
+
+    impl Predict<String> for Prefix {
+        fn predict_one(&self, line: &str) -> Result<Option<Identification<String>>, Error> {
+            Ok(line
+                .get(..2)
+                .filter(|prefix| ["en", "fr"].contains(prefix))
+                .map(|prefix| {
+                    Identification::new(LanguageTag::parse(prefix.to_string()).unwrap(), 0.5)
+                }))
+        }
+
+        fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+            Ok(self.predict_one(line)?.map(|id| vec![id]))
+        }
+    }
+
+    impl LanguageIdentifier for Prefix {
+        fn metadata(&self) -> ModelMetadata {
+            ModelMetadata {
+                backend: "prefix".to_string(),
+                path: None,
+                nb_labels: 2,
+                threshold: 0.0,
+                calibration: None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_weighted_ids() {
+        let doc = "en one\nfr two\nen three\n??";
+        let ids = Prefix.weighted_ids(doc.lines()).unwrap();
+
+        assert_eq!(ids.line_ids().len(), 4);
+        assert!(ids.line_ids()[3].is_none());
+        assert_eq!(ids.total_size(), 22);
+        let en = LanguageTag::parse("en".to_string()).unwrap();
+        let (count, confidence) = ids.lang_bins()[&Some(en)];
+        assert_eq!(count, 14);
+        assert_eq!(confidence, 14.0 * 0.5 / 22.0);
+        assert_eq!(ids.lang_bins()[&None], (2, 2.0 / 22.0));
+
+        let candidates = ids.candidates(3);
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].label().as_str(), "en");
+        assert_eq!(*candidates[0].prob(), 14.0 * 0.5 / 22.0);
+        assert_eq!(candidates[1].label().as_str(), "fr");
+        assert_eq!(ids.candidates(1).len(), 1);
+    }
 
     #[test]
+    fn test_weighted_ids_by() {
+        let doc = "en one\nfr two\n\nfr three";
+        let labels = |granularity| {
+            Prefix
+                .weighted_ids_by(doc.lines(), granularity)
+                .unwrap()
+                .line_ids()
+                .iter()
+                .map(|id| id.as_ref().map(|id| id.label().to_string()))
+                .collect::<Vec<_>>()
+        };
+        let (en, fr) = (Some("en".to_string()), Some("fr".to_string()));
+
+        assert_eq!(
+            labels(Granularity::Line),
+            vec![en.clone(), fr.clone(), None, fr.clone()]
+        );
+        assert_eq!(
+            labels(Granularity::Paragraph),
+            vec![en.clone(), en.clone(), None, fr]
+        );
+        assert_eq!(
+            labels(Granularity::Document),
+            vec![en.clone(), en.clone(), en.clone(), en]
+        );
+        assert_eq!(
+            "paragraph".parse::<Granularity>().unwrap(),
+            Granularity::Paragraph
+        );
+        assert!("sentence".parse::<Granularity>().is_err());
+    }
+
+    #[test]
+    fn test_model_kind() {
+        let lid176 = ModelKind::default();
+        let glotlid: ModelKind = "glotlid".parse().unwrap();
+
+        assert_eq!(lid176.to_languagetag("en").unwrap(), "en");
+        assert_eq!(lid176.to_languagetag("eng_Latn").unwrap(), "en-Latn");
+        assert_eq!(glotlid.to_languagetag("eng_Latn").unwrap(), "en");
+        assert_eq!(glotlid.to_languagetag("srp_Cyrl").unwrap(), "sr-Cyrl");
+        assert_eq!(glotlid.to_languagetag("zho_Hans").unwrap(), "zh-Hans");
+        assert_eq!("nllb".parse::<ModelKind>().unwrap(), ModelKind::GlotLid);
+        assert!("lid218".parse::<ModelKind>().is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "fasttext")]
     fn test_new_one_sentence() {
         let model: FastText = FastTextBuilder::default()
             .path(Path::new("lid.176.bin"))
@@ -279,6 +824,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "fasttext")]
     fn test_old_one_sentence() {
         let model: FastText = FastTextBuilder::default()
             .path(Path::new("lid.176.bin"))
@@ -293,6 +839,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "fasttext")]
     fn test_old_and_new_coherence() {
         let old_model: FastText = FastTextBuilder::default()
             .path(Path::new("lid.176.bin"))
@@ -310,4 +857,30 @@ mod tests {
 
         assert_eq!(old_pred.unwrap().label(), new_pred.unwrap().label());
     }
+
+    #[test]
+    #[cfg(feature = "fasttext")]
+    fn test_predict_batch() {
+        let model: FastText = FastTextBuilder::default()
+            .path(Path::new("lid.176.bin"))
+            .build_or_default()
+            .unwrap();
+
+        let lines = [
+            "Ceci est une phrase en Français :)",
+            "This is an English sentence.",
+            "",
+            "Dies ist ein deutscher Satz.",
+        ];
+        let ids = model.predict_batch(&lines).unwrap();
+
+        assert_eq!(ids.len(), lines.len());
+        for (line, id) in lines.iter().zip(ids) {
+            let expected = model.predict_one(line).unwrap();
+            assert_eq!(
+                id.map(|id| id.label().to_string()),
+                expected.map(|id| id.label().to_string())
+            );
+        }
+    }
 }
@@ -0,0 +1,350 @@
+/*! ONNX identifier
+
+Backend running transformer-based sequence classification models with [ONNX Runtime](https://onnxruntime.ai),
+enabled by the `onnx` feature.
+ONNX Runtime is loaded at runtime: set `ORT_DYLIB_PATH` to the path of `libonnxruntime.so` if it's not in the library path.
+
+Models are HuggingFace-style exports, as a directory holding:
+
+- `model.onnx`, taking `input_ids` and `attention_mask` and outputting logits of shape `[batch, labels]`,
+- `tokenizer.json`,
+- `config.json`, whose `id2label` maps outputs to labels (`{"id2label": {"0": "eng_Latn", "1": "fra_Latn"}}`).
+
+These models are much slower than fastText but can be more accurate.
+Lines are identified in batches (see [OnnxOptions]).
+Running a session needs exclusive access, so the model is loaded in a pool of sessions:
+batches are identified in parallel, each one by a session taken from the pool,
+and also use ONNX Runtime's intra-op threads.
+!*/
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+};
+
+use ort::{session::Session, value::Tensor};
+use rayon::prelude::*;
+use serde::Deserialize;
+use tokenizers::{PaddingParams, Tokenizer, TruncationParams};
+
+use crate::error::Error;
+
+use super::{
+    identification::Identification,
+    model::{LanguageIdentifier, ModelMetadata, Predict},
+    registry::{resolve_label, Registry},
+};
+
+const MODEL_FILE: &str = "model.onnx";
+const TOKENIZER_FILE: &str = "tokenizer.json";
+const CONFIG_FILE: &str = "config.json";
+
+/// Maximum number of tokens of a line, longer ones are truncated.
+const MAX_TOKENS: usize = 512;
+
+/// Batching and threading of ONNX inference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OnnxOptions {
+    /// Number of lines identified at once.
+    pub batch_size: usize,
+    /// Number of threads used by ONNX Runtime to run a batch.
+    pub intra_threads: usize,
+    /// Number of sessions, i.e. of batches identified in parallel.
+    pub sessions: usize,
+}
+
+impl Default for OnnxOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 32,
+            intra_threads: 4,
+            sessions: 1,
+        }
+    }
+}
+
+/// Part of `config.json` describing the model outputs.
+#[derive(Debug, Deserialize)]
+struct ModelConfig {
+    id2label: BTreeMap<usize, String>,
+}
+
+impl ModelConfig {
+    /// Get labels ordered by output index, checking that there's one per index.
+    fn labels(self) -> Result<Vec<String>, Error> {
+        let nb_labels = self.id2label.len();
+        if self.id2label.keys().copied().ne(0..nb_labels) {
+            return Err(Error::Custom(format!(
+                "{CONFIG_FILE}: id2label should map every index from 0 to {}",
+                nb_labels.saturating_sub(1)
+            )));
+        }
+
+        Ok(self.id2label.into_values().collect())
+    }
+}
+
+/// Sessions that are not running a batch.
+struct SessionPool {
+    sessions: Mutex<Vec<Session>>,
+    available: Condvar,
+}
+
+impl SessionPool {
+    fn new(sessions: Vec<Session>) -> Self {
+        Self {
+            sessions: Mutex::new(sessions),
+            available: Condvar::new(),
+        }
+    }
+
+    /// Run `f` with a session of the pool, waiting for one to be available.
+    fn with_session<T>(&self, f: impl FnOnce(&mut Session) -> T) -> T {
+        let mut session = {
+            let mut sessions = self.sessions.lock().unwrap();
+            loop {
+                match sessions.pop() {
+                    Some(session) => break session,
+                    None => sessions = self.available.wait(sessions).unwrap(),
+                }
+            }
+        };
+        let result = f(&mut session);
+        self.sessions.lock().unwrap().push(session);
+        self.available.notify_one();
+        result
+    }
+}
+
+/// ONNX sequence classification model, giving label probabilities of texts.
+///
+/// Also used to classify documents (see [crate::transformers::HarmfulContent]).
+pub(crate) struct OnnxClassifier {
+    pool: SessionPool,
+    tokenizer: Tokenizer,
+    labels: Vec<String>,
+}
+
+impl OnnxClassifier {
+    /// Load the model in the `path` directory.
+    pub(crate) fn new(path: &Path, options: OnnxOptions) -> Result<Self, Error> {
+        if options.batch_size == 0 || options.intra_threads == 0 || options.sessions == 0 {
+            return Err(Error::Custom(
+                "ONNX batch size, threads and sessions must be positive".to_string(),
+            ));
+        }
+
+        let sessions = (0..options.sessions)
+            .map(|_| {
+                Session::builder()
+                    .and_then(|builder| builder.with_intra_threads(options.intra_threads))
+                    .and_then(|builder| builder.commit_from_file(path.join(MODEL_FILE)))
+                    .map_err(|e| Error::Custom(format!("could not load ONNX model {path:?}: {e}")))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let mut tokenizer = Tokenizer::from_file(path.join(TOKENIZER_FILE))
+            .map_err(|e| Error::Custom(format!("could not load tokenizer {path:?}: {e}")))?;
+        // lines of a batch are padded to the longest one
+        tokenizer.with_padding(Some(PaddingParams::default()));
+        tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length: MAX_TOKENS,
+                ..Default::default()
+            }))
+            .map_err(|e| Error::Custom(format!("could not set truncation: {e}")))?;
+
+        let config: ModelConfig =
+            serde_json::from_reader(BufReader::new(File::open(path.join(CONFIG_FILE))?))?;
+
+        Ok(Self {
+            pool: SessionPool::new(sessions),
+            tokenizer,
+            labels: config.labels()?,
+        })
+    }
+
+    /// Get labels, in the order of probabilities.
+    pub(crate) fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Get the label probabilities of each line of a batch.
+    pub(crate) fn probabilities(&self, lines: &[&str]) -> Result<Vec<Vec<f32>>, Error> {
+        let encodings = self
+            .tokenizer
+            .encode_batch(lines.to_vec(), true)
+            .map_err(|e| Error::Custom(format!("tokenization error: {e}")))?;
+        let nb_tokens = encodings.first().map_or(0, |encoding| encoding.len());
+        let shape = [encodings.len(), nb_tokens];
+        let ids: Vec<i64> = encodings
+            .iter()
+            .flat_map(|encoding| encoding.get_ids().iter().map(|&id| id as i64))
+            .collect();
+        let mask: Vec<i64> = encodings
+            .iter()
+            .flat_map(|encoding| encoding.get_attention_mask().iter().map(|&m| m as i64))
+            .collect();
+
+        let ort_error = |e: ort::Error| Error::Custom(format!("ONNX inference error: {e}"));
+        let inputs = ort::inputs![
+            "input_ids" => Tensor::from_array((shape, ids)).map_err(ort_error)?,
+            "attention_mask" => Tensor::from_array((shape, mask)).map_err(ort_error)?,
+        ];
+        self.pool.with_session(|session| {
+            let outputs = session.run(inputs).map_err(ort_error)?;
+            let (_, logits) = outputs[0].try_extract_tensor::<f32>().map_err(ort_error)?;
+
+            Ok(logits.chunks(self.labels.len()).map(softmax).collect())
+        })
+    }
+}
+
+/// ONNX sequence classification language identifier.
+pub struct Onnx {
+    classifier: OnnxClassifier,
+    path: PathBuf,
+    options: OnnxOptions,
+    threshold: f32,
+    registry: Option<Arc<Registry>>,
+}
+
+impl Onnx {
+    /// Load the model in the `path` directory, keeping predictions whose probability is at least `threshold`
+    /// and converting labels with `registry` if there's one.
+    pub fn new(
+        path: &Path,
+        options: OnnxOptions,
+        threshold: f32,
+        registry: Option<Registry>,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            classifier: OnnxClassifier::new(path, options)?,
+            path: path.to_path_buf(),
+            options,
+            threshold,
+            registry: registry.map(Arc::new),
+        })
+    }
+
+    /// Convert label probabilities to identifications reaching the threshold, most probable first.
+    fn identifications(&self, probs: &[f32]) -> Result<Vec<Identification<String>>, Error> {
+        let mut probs: Vec<_> = probs
+            .iter()
+            .enumerate()
+            .filter(|(_, prob)| **prob >= self.threshold)
+            .collect();
+        probs.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+        probs
+            .into_iter()
+            .map(|(idx, prob)| {
+                let code = &self.classifier.labels()[idx];
+                let label = resolve_label(self.registry.as_deref(), code)?;
+                Ok(Identification::new(label, *prob))
+            })
+            .collect()
+    }
+}
+
+/// Convert logits to probabilities.
+fn softmax(logits: &[f32]) -> Vec<f32> {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let exps: Vec<f32> = logits.iter().map(|logit| (logit - max).exp()).collect();
+    let sum: f32 = exps.iter().sum();
+    exps.into_iter().map(|exp| exp / sum).collect()
+}
+
+impl Predict<String> for Onnx {
+    fn predict_one(&self, line: &str) -> Result<Option<Identification<String>>, Error> {
+        Ok(self
+            .predict(line)?
+            .and_then(|identifications| identifications.into_iter().next()))
+    }
+
+    fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+        if line.trim().is_empty() {
+            return Ok(None);
+        }
+        let identifications = match self.classifier.probabilities(&[line])?.first() {
+            Some(probs) => self.identifications(probs)?,
+            None => Vec::new(),
+        };
+        if identifications.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(identifications))
+        }
+    }
+}
+
+impl LanguageIdentifier for Onnx {
+    fn metadata(&self) -> ModelMetadata {
+        ModelMetadata {
+            backend: "onnx".to_string(),
+            path: Some(self.path.clone()),
+            nb_labels: self.classifier.labels().len(),
+            threshold: self.threshold,
+            calibration: None,
+        }
+    }
+
+    fn registry(&self) -> Option<&Registry> {
+        self.registry.as_deref()
+    }
+
+    /// Identify non-empty lines in batches of [OnnxOptions::batch_size], in parallel.
+    fn predict_batch(&self, lines: &[&str]) -> Result<Vec<Option<Identification<String>>>, Error> {
+        let mut ids = vec![None; lines.len()];
+        let non_empty: Vec<_> = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .collect();
+
+        let batch_ids = non_empty
+            .par_chunks(self.options.batch_size)
+            .map(|batch| {
+                let batch_lines: Vec<&str> = batch.iter().map(|(_, line)| **line).collect();
+                self.classifier
+                    .probabilities(&batch_lines)?
+                    .iter()
+                    .map(|probs| Ok(self.identifications(probs)?.into_iter().next()))
+                    .collect::<Result<Vec<_>, Error>>()
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        for ((idx, _), id) in non_empty.iter().zip(batch_ids.into_iter().flatten()) {
+            ids[*idx] = id;
+        }
+
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{softmax, ModelConfig};
+
+    #[test]
+    fn test_softmax() {
+        let probs = softmax(&[1.0, 1.0, 1.0, 1.0]);
+        assert!(probs.iter().all(|prob| (prob - 0.25).abs() < 1e-6));
+
+        let probs = softmax(&[1000.0, 0.0]);
+        assert!((probs[0] - 1.0).abs() < 1e-6);
+        assert!(probs[1] < 1e-6);
+    }
+
+    #[test]
+    fn test_labels() {
+        let config: ModelConfig =
+            serde_json::from_str(r#"{"id2label": {"1": "fra_Latn", "0": "eng_Latn"}}"#).unwrap();
+        assert_eq!(config.labels().unwrap(), vec!["eng_Latn", "fra_Latn"]);
+
+        let config: ModelConfig =
+            serde_json::from_str(r#"{"id2label": {"0": "eng_Latn", "2": "fra_Latn"}}"#).unwrap();
+        assert!(config.labels().is_err());
+    }
+}
@@ -0,0 +1,244 @@
+/*! Language registry
+
+Runtime-loadable language information, so that supporting a new language/label does not require a new release.
+
+The registry is a JSON array of entries:
+
+```json
+[
+    {"tag": "gsw", "name": "Alemannic German", "script": "Latn", "labels": ["__label__als"]},
+    {"tag": "zh-Hans", "name": "Chinese (Simplified)", "labels": ["__label__zho_Hans"], "threshold": 0.8},
+    {"tag": "en", "part_size": 2147483648, "compression": "zstd"}
+]
+```
+
+- `tag` (required): BCP47 tag of the language,
+- `name`, `script` (optional): informational. `script` has to match the tag's script subtag if there's one,
+- `labels` (optional): backend labels that map to `tag`. They take precedence over [super::normalize],
+- `threshold` (optional): minimum document identification confidence, replacing the pipeline default,
+- `part_size` (optional): size of JSONL/WARC parts in bytes, replacing `--part-size`,
+- `compression` (optional): `none`, `zstd`, `gzip` or `xz` compression of JSONL/WARC files at its default level,
+  replacing `--compression`.
+
+Writer settings apply to every output tree (main, annotated and categories) but not to Arrow/Parquet datasets.
+
+!*/
+use std::{collections::HashMap, fs::File, io::BufReader, path::Path};
+
+use log::debug;
+use oxilangtag::LanguageTag;
+use serde::Deserialize;
+
+use crate::{error::Error, io::writer::Compression};
+
+use super::normalize;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LanguageEntry {
+    tag: String,
+    name: Option<String>,
+    script: Option<String>,
+    #[serde(default)]
+    labels: Vec<String>,
+    threshold: Option<f32>,
+    part_size: Option<u64>,
+    compression: Option<String>,
+}
+
+impl LanguageEntry {
+    /// Get the language name.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Get the language script.
+    pub fn script(&self) -> Option<&str> {
+        self.script.as_deref()
+    }
+
+    /// Get the document confidence threshold.
+    pub fn threshold(&self) -> Option<f32> {
+        self.threshold
+    }
+
+    /// Get the size of parts, in bytes.
+    pub fn part_size(&self) -> Option<u64> {
+        self.part_size
+    }
+
+    /// Get the compression of files. Compressions are checked when the registry is built.
+    pub fn compression(&self) -> Option<Compression> {
+        self.compression
+            .as_deref()
+            .and_then(|compression| compression.parse().ok())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Registry {
+    entries: HashMap<LanguageTag<String>, LanguageEntry>,
+    labels: HashMap<String, LanguageTag<String>>,
+}
+
+impl Registry {
+    /// Load a registry from a JSON file.
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let f = BufReader::new(File::open(path)?);
+        let entries: Vec<LanguageEntry> = serde_json::from_reader(f)?;
+        Self::from_entries(entries)
+    }
+
+    /// Build a registry, failing if a tag is not valid BCP47.
+    pub fn from_entries(entries: Vec<LanguageEntry>) -> Result<Self, Error> {
+        let mut registry = Self::default();
+        for entry in entries {
+            let tag = LanguageTag::parse_and_normalize(&entry.tag)?;
+            if let (Some(script), Some(tag_script)) = (entry.script(), tag.script()) {
+                if !script.eq_ignore_ascii_case(tag_script) {
+                    return Err(Error::Custom(format!(
+                        "registry: script {script} does not match tag {tag}"
+                    )));
+                }
+            }
+
+            if let Some(compression) = &entry.compression {
+                compression
+                    .parse::<Compression>()
+                    .map_err(|e| Error::Custom(format!("registry: {tag}: {e:?}")))?;
+            }
+
+            debug!("registry: {} ({})", tag, entry.name().unwrap_or("no name"));
+            for label in &entry.labels {
+                registry.labels.insert(label.clone(), tag.clone());
+            }
+            registry.entries.insert(tag, entry);
+        }
+
+        Ok(registry)
+    }
+
+    /// Convert a backend label into a language tag, using registry labels first and [normalize] otherwise.
+    pub fn resolve(&self, label: &str) -> Result<LanguageTag<String>, Error> {
+        match self.label(label) {
+            Some(tag) => Ok(tag.clone()),
+            None => Ok(normalize(label)?),
+        }
+    }
+
+    /// Get the language tag a backend label maps to, if it is listed in the registry.
+    pub fn label(&self, label: &str) -> Option<&LanguageTag<String>> {
+        self.labels.get(label)
+    }
+
+    /// Get the registry entry of a language.
+    pub fn get(&self, lang: &LanguageTag<String>) -> Option<&LanguageEntry> {
+        self.entries.get(lang)
+    }
+
+    /// Get the document confidence threshold of a language, if set.
+    pub fn threshold(&self, lang: &LanguageTag<String>) -> Option<f32> {
+        self.get(lang).and_then(LanguageEntry::threshold)
+    }
+}
+
+/// Convert a backend label into a language tag, through `registry` if there's one and [normalize] otherwise.
+///
+/// Errors are [Error::UnknownLang] holding the label.
+/// They're only logged at debug level: unknown labels can occur on a lot of lines, it's up to the caller to report them.
+pub fn resolve_label(
+    registry: Option<&Registry>,
+    label: &str,
+) -> Result<LanguageTag<String>, Error> {
+    let tag = match registry {
+        Some(registry) => registry.resolve(label),
+        None => normalize(label).map_err(Error::from),
+    };
+    tag.map_err(|e| {
+        debug!("Couldn't parse label {label}: {e:?}");
+        Error::UnknownLang(label.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use oxilangtag::LanguageTag;
+
+    use crate::{error::Error, io::writer::Compression};
+
+    use super::{resolve_label, Registry};
+
+    const REGISTRY: &str = r#"[
+        {"tag": "gsw", "name": "Alemannic German", "script": "Latn", "labels": ["__label__als", "__label__gsw_Latn"]},
+        {"tag": "zh-hans", "labels": ["__label__zho_Hans"], "threshold": 0.8, "part_size": 1024, "compression": "zstd"}
+    ]"#;
+
+    fn gen_registry() -> Registry {
+        let mut f = tempfile::NamedTempFile::new().unwrap();
+        f.write_all(REGISTRY.as_bytes()).unwrap();
+        Registry::from_path(f.path()).unwrap()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let registry = gen_registry();
+        assert_eq!(registry.resolve("__label__gsw_Latn").unwrap(), "gsw");
+        assert_eq!(registry.resolve("__label__zho_Hans").unwrap(), "zh-Hans");
+        // fallback to normalization
+        assert_eq!(registry.resolve("__label__fra").unwrap(), "fr");
+        assert_eq!(registry.label("__label__als").unwrap(), "gsw");
+        assert!(registry.label("__label__fra").is_none());
+    }
+
+    #[test]
+    fn test_resolve_label() {
+        let registry = gen_registry();
+        assert_eq!(
+            resolve_label(Some(&registry), "__label__als").unwrap(),
+            "gsw"
+        );
+        assert_eq!(resolve_label(None, "__label__fra").unwrap(), "fr");
+        assert!(matches!(
+            resolve_label(None, "__label__???"),
+            Err(Error::UnknownLang(label)) if label == "__label__???"
+        ));
+    }
+
+    #[test]
+    fn test_entries() {
+        let registry = gen_registry();
+        let gsw = LanguageTag::parse("gsw".to_string()).unwrap();
+        let zh = LanguageTag::parse("zh-Hans".to_string()).unwrap();
+
+        assert_eq!(registry.get(&gsw).unwrap().name(), Some("Alemannic German"));
+        assert_eq!(registry.get(&gsw).unwrap().script(), Some("Latn"));
+        assert_eq!(registry.threshold(&gsw), None);
+        assert_eq!(registry.threshold(&zh), Some(0.8));
+
+        assert_eq!(registry.get(&gsw).unwrap().part_size(), None);
+        assert_eq!(registry.get(&gsw).unwrap().compression(), None);
+        assert_eq!(registry.get(&zh).unwrap().part_size(), Some(1024));
+        assert_eq!(
+            registry.get(&zh).unwrap().compression(),
+            Some(Compression::Zstd { level: 3 })
+        );
+    }
+
+    #[test]
+    fn test_invalid_tag() {
+        let mut f = tempfile::NamedTempFile::new().unwrap();
+        f.write_all(br#"[{"tag": "not a tag"}]"#).unwrap();
+        assert!(Registry::from_path(f.path()).is_err());
+
+        let mut f = tempfile::NamedTempFile::new().unwrap();
+        f.write_all(br#"[{"tag": "sr-Cyrl", "script": "Latn"}]"#)
+            .unwrap();
+        assert!(Registry::from_path(f.path()).is_err());
+
+        let mut f = tempfile::NamedTempFile::new().unwrap();
+        f.write_all(br#"[{"tag": "en", "compression": "rar"}]"#)
+            .unwrap();
+        assert!(Registry::from_path(f.path()).is_err());
+    }
+}
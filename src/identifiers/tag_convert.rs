@@ -1,4 +1,15 @@
-//! Conversion utilities or fasttext tags to standardized BCP47.
+//! Conversion utilities or classifier tags to standardized BCP47.
+//!
+//! Every backend label should go through [normalize] (or [Tag]), which:
+//!
+//! 1. strips the `__label__` prefix,
+//! 1. replaces whole labels that are known to be wrong or that have a better BCP47 equivalent ([NEW_TAG_REPLACE]),
+//! 1. replaces deprecated language subtags with their preferred value ([DEPRECATED_REPLACE]),
+//! 1. converts ISO 639-3 language subtags to their ISO 639-1 equivalent when it exists, keeping script/region subtags (`spa_Latn` -> `es-Latn`),
+//! 1. uses `-` as a separator and normalizes case (`zho_HANS` -> `zh-Hans`).
+//!
+//! Labels of models that always hold a script (GlotLID, NLLB: `eng_Latn`) can have it dropped
+//! when it is the default script of the language ([suppress_script], `eng_Latn` -> `en`, but `srp_Latn` -> `sr-Latn`).
 use std::{borrow::Cow, collections::HashMap, convert::TryFrom};
 
 use lazy_static::lazy_static;
@@ -164,6 +175,164 @@ lazy_static! {
     ]
     .into_iter()
     .collect();
+
+    /// Deprecated language subtags with a preferred value (from the IANA language subtag registry).
+    pub static ref DEPRECATED_REPLACE: HashMap<&'static str, &'static str> = [
+        ("in", "id"),
+        ("iw", "he"),
+        ("ji", "yi"),
+        ("jw", "jv"),
+        ("mo", "ro"),
+        ("mol", "ro"),
+    ]
+    .into_iter()
+    .collect();
+
+    /// Default scripts of languages, that don't need a script subtag (mostly from the `Suppress-Script` fields of the IANA language subtag registry).
+    pub static ref DEFAULT_SCRIPTS: HashMap<&'static str, &'static str> = [
+        ("af", "Latn"),
+        ("am", "Ethi"),
+        ("ar", "Arab"),
+        ("as", "Beng"),
+        ("be", "Cyrl"),
+        ("bg", "Cyrl"),
+        ("bn", "Beng"),
+        ("bo", "Tibt"),
+        ("ca", "Latn"),
+        ("cs", "Latn"),
+        ("cy", "Latn"),
+        ("da", "Latn"),
+        ("de", "Latn"),
+        ("dsb", "Latn"),
+        ("dv", "Thaa"),
+        ("dz", "Tibt"),
+        ("el", "Grek"),
+        ("en", "Latn"),
+        ("eo", "Latn"),
+        ("es", "Latn"),
+        ("et", "Latn"),
+        ("eu", "Latn"),
+        ("fa", "Arab"),
+        ("fi", "Latn"),
+        ("fo", "Latn"),
+        ("fr", "Latn"),
+        ("frr", "Latn"),
+        ("frs", "Latn"),
+        ("fy", "Latn"),
+        ("ga", "Latn"),
+        ("gl", "Latn"),
+        ("gn", "Latn"),
+        ("gsw", "Latn"),
+        ("gu", "Gujr"),
+        ("gv", "Latn"),
+        ("he", "Hebr"),
+        ("hi", "Deva"),
+        ("hr", "Latn"),
+        ("hsb", "Latn"),
+        ("ht", "Latn"),
+        ("hu", "Latn"),
+        ("hy", "Armn"),
+        ("id", "Latn"),
+        ("is", "Latn"),
+        ("it", "Latn"),
+        ("ja", "Jpan"),
+        ("ka", "Geor"),
+        ("km", "Khmr"),
+        ("kn", "Knda"),
+        ("ko", "Hang"),
+        ("kok", "Deva"),
+        ("la", "Latn"),
+        ("lb", "Latn"),
+        ("ln", "Latn"),
+        ("lo", "Laoo"),
+        ("lt", "Latn"),
+        ("lv", "Latn"),
+        ("mai", "Deva"),
+        ("mg", "Latn"),
+        ("mh", "Latn"),
+        ("mk", "Cyrl"),
+        ("ml", "Mlym"),
+        ("mr", "Deva"),
+        ("ms", "Latn"),
+        ("mt", "Latn"),
+        ("my", "Mymr"),
+        ("na", "Latn"),
+        ("nb", "Latn"),
+        ("nd", "Latn"),
+        ("nds", "Latn"),
+        ("ne", "Deva"),
+        ("nl", "Latn"),
+        ("nn", "Latn"),
+        ("no", "Latn"),
+        ("nr", "Latn"),
+        ("nso", "Latn"),
+        ("ny", "Latn"),
+        ("om", "Latn"),
+        ("or", "Orya"),
+        ("pl", "Latn"),
+        ("ps", "Arab"),
+        ("pt", "Latn"),
+        ("qu", "Latn"),
+        ("rm", "Latn"),
+        ("rn", "Latn"),
+        ("ro", "Latn"),
+        ("ru", "Cyrl"),
+        ("rw", "Latn"),
+        ("sg", "Latn"),
+        ("si", "Sinh"),
+        ("sk", "Latn"),
+        ("sl", "Latn"),
+        ("sm", "Latn"),
+        ("so", "Latn"),
+        ("sq", "Latn"),
+        ("ss", "Latn"),
+        ("st", "Latn"),
+        ("sv", "Latn"),
+        ("sw", "Latn"),
+        ("ta", "Taml"),
+        ("te", "Telu"),
+        ("th", "Thai"),
+        ("ti", "Ethi"),
+        ("tl", "Latn"),
+        ("tn", "Latn"),
+        ("to", "Latn"),
+        ("tr", "Latn"),
+        ("ts", "Latn"),
+        ("uk", "Cyrl"),
+        ("ur", "Arab"),
+        ("ve", "Latn"),
+        ("vi", "Latn"),
+        ("xh", "Latn"),
+        ("yi", "Hebr"),
+        ("zu", "Latn"),
+    ]
+    .into_iter()
+    .collect();
+}
+
+/// Convert a backend label (`__label__als`, `__label__spa_Latn`, `en`...) into a normalized BCP47 tag.
+pub fn normalize(label: &str) -> Result<LanguageTag<String>, LanguageTagParseError> {
+    Tag::new(label).try_into()
+}
+
+/// Drop the script subtag of a tag if it is the default script of its language (`en-Latn` -> `en`).
+pub fn suppress_script(tag: LanguageTag<String>) -> LanguageTag<String> {
+    let is_default = match (tag.script(), DEFAULT_SCRIPTS.get(tag.primary_language())) {
+        (Some(script), Some(default)) => script == *default,
+        _ => false,
+    };
+    if !is_default {
+        return tag;
+    }
+
+    // the script subtag directly follows the language subtags
+    let language_len = tag.full_language().len();
+    let suppressed = format!(
+        "{}{}",
+        &tag.as_str()[..language_len],
+        &tag.as_str()[language_len + "-Xxxx".len()..]
+    );
+    LanguageTag::parse(suppressed).unwrap_or(tag)
 }
 
 pub struct Tag<'a> {
@@ -173,24 +342,32 @@ pub struct Tag<'a> {
 impl<'a> Tag<'a> {
     pub fn new(tag: &'a str) -> Self {
         Self {
-            // attempt to remove first nine chars or pass the whole thing.
-            inner: Tag::fix(&tag.get(9..).unwrap_or(tag)),
+            inner: Tag::fix(tag.strip_prefix("__label__").unwrap_or(tag)),
         }
     }
 
     #[inline]
     fn fix(tag: &'a str) -> Cow<'a, str> {
-        // go from __label__foo_bar to foo_bar
-        let tag = match NEW_TAG_REPLACE.get(&tag) {
+        // whole tag replacement
+        if let Some(replacement) = NEW_TAG_REPLACE.get(tag) {
+            return Cow::from(*replacement);
+        }
+
+        // split language subtag from the rest: spa_Latn -> (spa, _Latn)
+        let (language, rest) = tag.split_at(tag.find(['_', '-']).unwrap_or(tag.len()));
+
+        // only use language-only replacements (fas -> fa, but not prs -> fa-AF)
+        let replacement = DEPRECATED_REPLACE.get(language).or_else(|| {
+            NEW_TAG_REPLACE
+                .get(language)
+                .filter(|replacement| !replacement.contains('-'))
+        });
+
+        match replacement {
+            Some(language) => Cow::from(format!("{language}{}", rest.replace('_', "-"))),
+            // go from foo_bar to foo-bar
+            None if tag.contains('_') => Cow::from(tag.replace('_', "-")),
             None => Cow::from(tag),
-            Some(x) => Cow::from(x.to_string()),
-        };
-
-        // go from foo_bar to foo-bar
-        if tag.contains("_") {
-            Cow::from(tag.replace("_", "-"))
-        } else {
-            tag
         }
     }
 
@@ -204,7 +381,7 @@ impl<'a> TryFrom<Tag<'a>> for LanguageTag<String> {
     //TODO: remove cloning, use generics to provide a ref
     // if applicable
     fn try_from(tag: Tag<'a>) -> Result<Self, Self::Error> {
-        LanguageTag::parse(tag.inner.into_owned())
+        LanguageTag::parse_and_normalize(&tag.inner)
     }
 }
 #[cfg(test)]
@@ -212,7 +389,7 @@ mod tests {
 
     use oxilangtag::LanguageTag;
 
-    use crate::identifiers::tag_convert::Tag;
+    use crate::identifiers::tag_convert::{normalize, suppress_script, Tag};
 
     // use super::{NewTag, OldTag};
 
@@ -230,9 +407,59 @@ mod tests {
     fn test_langcode_script() {
         let langcode = "__label__fra_Latn";
         let parsed: LanguageTag<String> = Tag::new(langcode).try_into().unwrap();
-        let expected = "fra-Latn";
+        let expected = "fr-Latn";
         assert_eq!(parsed, expected);
     }
+
+    #[test]
+    fn test_glotlid_style() {
+        for (label, expected) in [
+            ("__label__spa_Latn", "es-Latn"),
+            ("__label__zho_Hans", "zh-Hans"),
+            ("__label__por_Latn", "pt-Latn"),
+            ("__label__prs_Arab", "prs-Arab"),
+            ("__label__xyz_Latn", "xyz-Latn"),
+            ("spa_LATN", "es-Latn"),
+        ] {
+            assert_eq!(normalize(label).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_suppress_script() {
+        for (label, expected) in [
+            ("__label__eng_Latn", "en"),
+            ("__label__rus_Cyrl", "ru"),
+            ("__label__jpn_Jpan", "ja"),
+            ("__label__srp_Cyrl", "sr-Cyrl"),
+            ("__label__zho_Hant", "zh-Hant"),
+            ("__label__rus_Latn", "ru-Latn"),
+            ("__label__gsw_Latn", "gsw"),
+            ("en-Latn-US", "en-US"),
+        ] {
+            assert_eq!(suppress_script(normalize(label).unwrap()), expected);
+        }
+    }
+
+    #[test]
+    fn test_deprecated() {
+        for (label, expected) in [
+            ("__label__iw", "he"),
+            ("__label__in", "id"),
+            ("__label__mo", "ro"),
+            ("__label__jw_Latn", "jv-Latn"),
+            ("__label__als", "gsw"),
+        ] {
+            assert_eq!(normalize(label).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_no_prefix() {
+        // labels longer than the prefix should not be truncated
+        assert_eq!(normalize("eng_Latn_x_foo").unwrap(), "en-Latn-x-foo");
+        assert_eq!(normalize("en").unwrap(), "en");
+    }
     #[test]
     fn quality_at_a_glance_table10() {
         // table 10: Miscellaneous errors in language codes.
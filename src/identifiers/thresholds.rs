@@ -0,0 +1,243 @@
+/*! Per-language line thresholds
+
+Line identifications are kept when their probability reaches the threshold of their language,
+so that high-resource languages can require a higher confidence than low-resource ones
+(whose corpora would otherwise get polluted by misidentified high-resource text).
+
+Thresholds are built from a default one and `<lang>=<threshold>` specs:
+
+```
+use ungoliant::identifiers::LineThresholds;
+let thresholds = LineThresholds::from_specs(0.8, &["en=0.9", "gsw=0.6"]).unwrap();
+assert_eq!(thresholds.min(), 0.6);
+```
+
+The underlying identifier has to be built with the lowest threshold (see [LineThresholds::min]),
+[Thresholded] then drops the identifications that don't reach their language's threshold.
+!*/
+use std::{collections::HashMap, sync::Arc};
+
+use oxilangtag::LanguageTag;
+
+use crate::error::Error;
+
+use super::{
+    identification::Identification,
+    model::{LanguageIdentifier, LineIds, MemberIds, ModelMetadata, Predict},
+    registry::Registry,
+};
+
+/// Minimum line identification probability, per language.
+#[derive(Debug, Clone)]
+pub struct LineThresholds {
+    default: f32,
+    thresholds: HashMap<LanguageTag<String>, f32>,
+}
+
+impl LineThresholds {
+    /// Use `default` for every language.
+    pub fn new(default: f32) -> Self {
+        Self {
+            default,
+            thresholds: HashMap::new(),
+        }
+    }
+
+    /// Build thresholds from `<lang>=<threshold>` specs, using `default` for other languages.
+    pub fn from_specs<T: AsRef<str>>(default: f32, specs: &[T]) -> Result<Self, Error> {
+        let mut thresholds = Self::new(default);
+        for spec in specs {
+            let spec = spec.as_ref();
+            let invalid = || {
+                Error::Custom(format!(
+                    "invalid threshold {spec} (expected <lang>=<threshold>)"
+                ))
+            };
+            let (lang, threshold) = spec.split_once('=').ok_or_else(invalid)?;
+            let threshold = threshold.parse().map_err(|_| invalid())?;
+            thresholds.set(LanguageTag::parse_and_normalize(lang)?, threshold);
+        }
+
+        Ok(thresholds)
+    }
+
+    /// Set the threshold of a language.
+    pub fn set(&mut self, lang: LanguageTag<String>, threshold: f32) {
+        self.thresholds.insert(lang, threshold);
+    }
+
+    /// Get the threshold of a language.
+    pub fn get(&self, lang: &LanguageTag<String>) -> f32 {
+        self.thresholds.get(lang).copied().unwrap_or(self.default)
+    }
+
+    /// Get the default threshold.
+    pub fn default_threshold(&self) -> f32 {
+        self.default
+    }
+
+    /// Get the lowest threshold.
+    pub fn min(&self) -> f32 {
+        self.thresholds
+            .values()
+            .copied()
+            .fold(self.default, f32::min)
+    }
+
+    /// Returns true if every language uses the default threshold.
+    pub fn is_empty(&self) -> bool {
+        self.thresholds.is_empty()
+    }
+
+    /// Returns true if `id` reaches the threshold of its language.
+    pub fn accepts(&self, id: &Identification<String>) -> bool {
+        *id.prob() >= self.get(id.label())
+    }
+}
+
+/// Identifier dropping identifications under their language's threshold.
+pub struct Thresholded {
+    inner: Arc<dyn LanguageIdentifier>,
+    thresholds: LineThresholds,
+}
+
+impl Thresholded {
+    /// Filter the identifications of `inner`, which should be built with [LineThresholds::min] as threshold.
+    pub fn new(inner: Arc<dyn LanguageIdentifier>, thresholds: LineThresholds) -> Self {
+        Self { inner, thresholds }
+    }
+}
+
+impl Predict<String> for Thresholded {
+    fn predict_one(&self, line: &str) -> Result<Option<Identification<String>>, Error> {
+        Ok(self
+            .inner
+            .predict_one(line)?
+            .filter(|id| self.thresholds.accepts(id)))
+    }
+
+    fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+        Ok(self.inner.predict(line)?.and_then(|mut ids| {
+            ids.retain(|id| self.thresholds.accepts(id));
+            (!ids.is_empty()).then_some(ids)
+        }))
+    }
+}
+
+impl LanguageIdentifier for Thresholded {
+    fn metadata(&self) -> ModelMetadata {
+        ModelMetadata {
+            threshold: self.thresholds.default_threshold(),
+            ..self.inner.metadata()
+        }
+    }
+
+    fn registry(&self) -> Option<&Registry> {
+        self.inner.registry()
+    }
+
+    fn predict_batch(&self, lines: &[&str]) -> Result<Vec<Option<Identification<String>>>, Error> {
+        Ok(self
+            .inner
+            .predict_batch(lines)?
+            .into_iter()
+            .map(|id| id.filter(|id| self.thresholds.accepts(id)))
+            .collect())
+    }
+
+    /// Only identifications are filtered, members' ones are kept as is.
+    fn predict_batch_with_members(&self, lines: &[&str]) -> Result<(LineIds, MemberIds), Error> {
+        let (ids, members) = self.inner.predict_batch_with_members(lines)?;
+        let ids = ids
+            .into_iter()
+            .map(|id| id.filter(|id| self.thresholds.accepts(id)))
+            .collect();
+        Ok((ids, members))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use oxilangtag::LanguageTag;
+
+    use crate::{
+        error::Error,
+        identifiers::{
+            identification::Identification,
+            model::{LanguageIdentifier, ModelMetadata, Predict},
+        },
+    };
+
+    use super::{LineThresholds, Thresholded};
+
+    /// Identifies lines as their first word, with probability 0.7.
+    struct FirstWord;
+
+    impl Predict<String> for FirstWord {
+        fn predict_one(&self, line: &str) -> Result<Option<Identification<String>>, Error> {
+            Ok(line.split_whitespace().next().map(|word| {
+                Identification::new(LanguageTag::parse(word.to_string()).unwrap(), 0.7)
+            }))
+        }
+
+        fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+            Ok(self.predict_one(line)?.map(|id| vec![id]))
+        }
+    }
+
+    impl LanguageIdentifier for FirstWord {
+        fn metadata(&self) -> ModelMetadata {
+            ModelMetadata {
+                backend: "first-word".to_string(),
+                path: None,
+                nb_labels: 0,
+                threshold: 0.0,
+                calibration: None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_specs() {
+        let thresholds = LineThresholds::from_specs(0.8, &["en=0.9", "GSW=0.6"]).unwrap();
+        let lang = |tag: &str| LanguageTag::parse(tag.to_string()).unwrap();
+
+        assert_eq!(thresholds.get(&lang("en")), 0.9);
+        assert_eq!(thresholds.get(&lang("gsw")), 0.6);
+        assert_eq!(thresholds.get(&lang("fr")), 0.8);
+        assert_eq!(thresholds.min(), 0.6);
+        assert!(LineThresholds::new(0.8).is_empty());
+
+        assert!(LineThresholds::from_specs(0.8, &["en"]).is_err());
+        assert!(LineThresholds::from_specs(0.8, &["en=high"]).is_err());
+        assert!(LineThresholds::from_specs(0.8, &["not a tag=0.5"]).is_err());
+    }
+
+    #[test]
+    fn test_thresholded() {
+        let thresholds = LineThresholds::from_specs(0.8, &["gsw=0.6"]).unwrap();
+        let identifier = Thresholded::new(Arc::new(FirstWord), thresholds);
+
+        assert!(identifier.predict_one("en line").unwrap().is_none());
+        assert_eq!(
+            identifier
+                .predict_one("gsw line")
+                .unwrap()
+                .unwrap()
+                .label()
+                .to_string(),
+            "gsw"
+        );
+
+        let ids = identifier
+            .predict_batch(&["gsw a", "en b", "fr c"])
+            .unwrap();
+        assert_eq!(
+            ids.iter().map(Option::is_some).collect::<Vec<_>>(),
+            vec![true, false, false]
+        );
+        assert_eq!(identifier.metadata().threshold, 0.8);
+    }
+}
@@ -0,0 +1,93 @@
+/*! Whatlang identifier
+
+[Whatlang](https://github.com/greyblake/whatlang-rs) backend.
+
+Whatlang is pure Rust and has its model embedded, so it is always available:
+it is the default identifier when ungoliant is built without the `fasttext` feature (e.g. for quick tests and CI).
+It is less accurate than fastText and only knows about 70 languages.
+!*/
+use std::sync::Arc;
+
+use whatlang::Lang;
+
+use crate::error::Error;
+
+use super::{
+    identification::Identification,
+    model::{LanguageIdentifier, ModelMetadata, Predict},
+    registry::{resolve_label, Registry},
+};
+
+/// Whatlang language identifier.
+pub struct Whatlang {
+    threshold: f32,
+    registry: Option<Arc<Registry>>,
+}
+
+impl Whatlang {
+    /// Create an identifier keeping predictions whose confidence is at least `threshold`,
+    /// converting labels with `registry` if there's one.
+    pub fn new(threshold: f32, registry: Option<Registry>) -> Self {
+        Self {
+            threshold,
+            registry: registry.map(Arc::new),
+        }
+    }
+}
+
+impl Predict<String> for Whatlang {
+    fn predict_one(&self, line: &str) -> Result<Option<Identification<String>>, Error> {
+        let info = match whatlang::detect(line) {
+            Some(info) => info,
+            None => return Ok(None),
+        };
+        let prob = info.confidence() as f32;
+        if prob < self.threshold {
+            return Ok(None);
+        }
+
+        // whatlang uses ISO 639-3 codes
+        let code = info.lang().code();
+        let label = resolve_label(self.registry.as_deref(), code)?;
+        Ok(Some(Identification::new(label, prob)))
+    }
+
+    /// Whatlang only gives the most probable language of a line, so there's at most one prediction.
+    fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+        Ok(self.predict_one(line)?.map(|id| vec![id]))
+    }
+}
+
+impl LanguageIdentifier for Whatlang {
+    fn metadata(&self) -> ModelMetadata {
+        ModelMetadata {
+            backend: "whatlang".to_string(),
+            path: None,
+            nb_labels: Lang::all().len(),
+            threshold: self.threshold,
+            calibration: None,
+        }
+    }
+
+    fn registry(&self) -> Option<&Registry> {
+        self.registry.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::identifiers::model::Predict;
+
+    use super::Whatlang;
+
+    #[test]
+    fn test_predict() {
+        let whatlang = Whatlang::new(0.5, None);
+        let id = whatlang
+            .predict_one("Ceci est une phrase en français, assez longue pour être identifiée.")
+            .unwrap()
+            .unwrap();
+        assert_eq!(id.label().to_string(), "fr");
+        assert!(whatlang.predict_one("").unwrap().is_none());
+    }
+}
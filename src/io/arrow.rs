@@ -0,0 +1,227 @@
+/*! Columnar document datasets
+
+Documents of a given language can be written as a dataset of Arrow IPC (see [super::ipc], `arrow` feature)
+or Parquet (see [super::parquet], `parquet` feature) files: a `<dst>/<lang>/` directory holding `part-<n>.<ext>` files.
+
+Each row is a document, with the following columns:
+
+- `warc_id`, `url`: nullable record identifier and target URI,
+- `content`,
+- `lang`, `prob`: document identification,
+- `annotations`, `categories`: nullable lists of strings,
+- `warc_headers`, `metadata`: JSON objects, holding everything there is to know about the document.
+
+Columnar files can't be appended to, so closing a writer finishes the current part:
+it is reopened as a new part on next write (see [super::OpenWriters]).
+Existing parts are removed when the writer is first opened, so that reruns don't leave stale documents.
+!*/
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use arrow_array::{
+    builder::{ListBuilder, StringBuilder},
+    ArrayRef, Float32Array, RecordBatch, StringArray,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use log::debug;
+use oscar_io::v3::Document;
+use oxilangtag::LanguageTag;
+use warc::WarcHeader;
+
+/// Dataset writer, implemented by columnar writers so that [super::writer::Writer] can use them.
+pub trait DatasetWriter: Send {
+    /// Write documents in the current part, opening one if needed.
+    fn write(&mut self, docs: Vec<Document>) -> Result<(), oscar_io::Error>;
+
+    /// Finish the current part, if open. A new part is opened on next write.
+    fn close(&mut self) -> Result<(), oscar_io::Error>;
+
+    /// Get the dataset directory.
+    fn dir(&self) -> &Path;
+}
+
+pub(crate) fn arrow_error(e: impl std::fmt::Display) -> oscar_io::Error {
+    oscar_io::Error::Custom(format!("arrow error: {e}"))
+}
+
+/// Part files of a dataset.
+pub(crate) struct Parts {
+    dir: PathBuf,
+    extension: &'static str,
+    /// number of parts created so far
+    nb_parts: usize,
+}
+
+impl Parts {
+    /// Parts of the `<dst>/<lang>/` dataset, with a given file extension.
+    pub(crate) fn new(dst: &Path, lang: &LanguageTag<String>, extension: &'static str) -> Self {
+        Self {
+            dir: dst.join(lang.as_str()),
+            extension,
+            nb_parts: 0,
+        }
+    }
+
+    pub(crate) fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Create the next part, removing existing ones first if it's the first one.
+    pub(crate) fn create(&mut self) -> std::io::Result<File> {
+        if self.nb_parts == 0 {
+            if self.dir.exists() {
+                std::fs::remove_dir_all(&self.dir)?;
+            }
+            std::fs::create_dir_all(&self.dir)?;
+        }
+        let path = self
+            .dir
+            .join(format!("part-{:05}.{}", self.nb_parts, self.extension));
+        debug!("opening {:?}", path);
+        self.nb_parts += 1;
+        File::create(path)
+    }
+}
+
+/// Get the schema of document datasets.
+pub(crate) fn schema() -> SchemaRef {
+    let list = || DataType::List(Arc::new(Field::new("item", DataType::Utf8, true)));
+    Arc::new(Schema::new(vec![
+        Field::new("warc_id", DataType::Utf8, true),
+        Field::new("url", DataType::Utf8, true),
+        Field::new("content", DataType::Utf8, false),
+        Field::new("lang", DataType::Utf8, false),
+        Field::new("prob", DataType::Float32, false),
+        Field::new("annotations", list(), true),
+        Field::new("categories", list(), true),
+        Field::new("warc_headers", DataType::Utf8, false),
+        Field::new("metadata", DataType::Utf8, false),
+    ]))
+}
+
+/// Build a record batch from documents.
+pub(crate) fn batch(schema: &SchemaRef, docs: &[Document]) -> Result<RecordBatch, oscar_io::Error> {
+    let strings = |f: &dyn Fn(&Document) -> Option<String>| -> ArrayRef {
+        Arc::new(docs.iter().map(f).collect::<StringArray>())
+    };
+    let lists = |f: &dyn Fn(&Document) -> Option<&Vec<String>>| -> ArrayRef {
+        let mut builder = ListBuilder::new(StringBuilder::new());
+        for doc in docs {
+            match f(doc) {
+                Some(values) => {
+                    for value in values {
+                        builder.values().append_value(value);
+                    }
+                    builder.append(true);
+                }
+                None => builder.append(false),
+            }
+        }
+        Arc::new(builder.finish())
+    };
+    // documents serialize headers as strings
+    let (mut warc_headers, mut metadata) = (Vec::new(), Vec::new());
+    for doc in docs {
+        let mut value = serde_json::to_value(doc)?;
+        warc_headers.push(value["warc_headers"].take().to_string());
+        metadata.push(value["metadata"].take().to_string());
+    }
+
+    let columns = vec![
+        // record ids may not be kept (see `--warc-headers`)
+        strings(&|doc| {
+            doc.warc_headers()
+                .get(&WarcHeader::RecordID)
+                .map(|id| String::from_utf8_lossy(id).into_owned())
+        }),
+        strings(&|doc| doc.url()),
+        strings(&|doc| Some(doc.content().clone())),
+        strings(&|doc| Some(doc.identification().label().to_string())),
+        Arc::new(
+            docs.iter()
+                .map(|doc| *doc.identification().prob())
+                .collect::<Float32Array>(),
+        ),
+        lists(&|doc| doc.metadata().annotation()),
+        lists(&|doc| doc.metadata().categories()),
+        Arc::new(StringArray::from(warc_headers)),
+        Arc::new(StringArray::from(metadata)),
+    ];
+
+    RecordBatch::try_new(schema.clone(), columns).map_err(arrow_error)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashMap;
+
+    use arrow_array::{cast::AsArray, types::Float32Type, RecordBatch};
+    use oscar_io::{
+        common::Identification,
+        v3::{Document, Metadata},
+    };
+    use oxilangtag::LanguageTag;
+
+    use super::{batch, schema, Parts};
+
+    pub(crate) fn doc(content: &str, annotation: Option<&str>) -> Document {
+        let id = Identification::new(LanguageTag::parse("fr".to_string()).unwrap(), 0.9);
+        let mut metadata = Metadata::new(&id, &[Some(id.clone())]);
+        if let Some(annotation) = annotation {
+            metadata.add_annotation(annotation.to_string());
+        }
+        Document::new(content.to_string(), HashMap::new(), metadata)
+    }
+
+    /// Get the contents of a batch.
+    pub(crate) fn contents(batch: &RecordBatch) -> Vec<String> {
+        let content = batch.column_by_name("content").unwrap().as_string::<i32>();
+        content.iter().map(|c| c.unwrap().to_string()).collect()
+    }
+
+    #[test]
+    fn test_batch() {
+        let batch = batch(&schema(), &[doc("foo", None), doc("bar", Some("tiny"))]).unwrap();
+        assert_eq!(contents(&batch), vec!["foo", "bar"]);
+
+        let annotations: Vec<Option<Vec<String>>> = batch
+            .column_by_name("annotations")
+            .unwrap()
+            .as_list::<i32>()
+            .iter()
+            .map(|values| {
+                values.map(|values| {
+                    values
+                        .as_string::<i32>()
+                        .iter()
+                        .map(|v| v.unwrap().to_string())
+                        .collect()
+                })
+            })
+            .collect();
+        assert_eq!(annotations, vec![None, Some(vec!["tiny".to_string()])]);
+
+        let probs = batch.column_by_name("prob").unwrap();
+        assert_eq!(probs.as_primitive::<Float32Type>().values(), &[0.9, 0.9]);
+        assert!(batch.column_by_name("warc_id").unwrap().is_null(0));
+    }
+
+    #[test]
+    fn test_parts() {
+        let dst = tempfile::tempdir().unwrap();
+        let stale = dst.path().join("fr").join("part-00003.arrow");
+        std::fs::create_dir(dst.path().join("fr")).unwrap();
+        std::fs::write(&stale, "stale").unwrap();
+
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let mut parts = Parts::new(dst.path(), &lang, "arrow");
+        parts.create().unwrap();
+        assert!(!stale.exists());
+        parts.create().unwrap();
+        assert!(parts.dir().join("part-00000.arrow").exists());
+        assert!(parts.dir().join("part-00001.arrow").exists());
+    }
+}
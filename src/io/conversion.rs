@@ -0,0 +1,165 @@
+/*! WARC conversion records
+
+Serializes kept documents back as WARC records, so that filtered corpora can be processed by WARC tooling.
+
+Each document becomes a `conversion` record holding its text, with the WARC headers of the original (WET) record preserved,
+except for the ones describing the record itself:
+
+- `WARC-Record-ID` is a new identifier, and the original one is kept in `WARC-Refers-To`,
+- `Content-Type` and `Content-Length` describe the text,
+- `WARC-Block-Digest`/`WARC-Payload-Digest` are dropped, since the text may have been modified,
+- `WARC-Identified-Content-Language` is set to the language of the document,
+- the `quality-signals` header (see [crate::transformers::signals]) is dropped, since it isn't an original header.
+
+Headers are written in a fixed order (mandatory ones first), so that parts are reproducible.
+!*/
+use std::io::Write;
+
+use oscar_io::v3::Document;
+use warc::{BufferedBody, Record, WarcHeader};
+
+use crate::transformers::signals::QUALITY_SIGNALS_HEADER;
+
+/// Version of written records.
+const WARC_VERSION: &str = "WARC/1.0";
+
+/// Header holding the language of documents, like in CommonCrawl WARC files.
+const CONTENT_LANGUAGE: &str = "warc-identified-content-language";
+
+/// Serialize a document of a given language as a WARC conversion record.
+pub fn conversion_record(doc: &Document, lang: &str) -> std::io::Result<Vec<u8>> {
+    let content = doc.content().as_bytes();
+    let mut headers = doc.warc_headers().clone();
+    let original_id = headers.remove(&WarcHeader::RecordID);
+    for header in [
+        WarcHeader::WarcType,
+        WarcHeader::ContentType,
+        WarcHeader::ContentLength,
+        WarcHeader::Date,
+        WarcHeader::RefersTo,
+        WarcHeader::BlockDigest,
+        WarcHeader::PayloadDigest,
+        WarcHeader::Unknown(CONTENT_LANGUAGE.to_string()),
+        WarcHeader::Unknown(QUALITY_SIGNALS_HEADER.to_string()),
+    ] {
+        headers.remove(&header);
+    }
+    let date = doc
+        .warc_headers()
+        .get(&WarcHeader::Date)
+        .cloned()
+        .unwrap_or_else(|| {
+            Record::<BufferedBody>::new()
+                .date()
+                .format("%Y-%m-%dT%H:%M:%SZ")
+                .to_string()
+                .into_bytes()
+        });
+
+    let mut fixed = vec![
+        (WarcHeader::WarcType, b"conversion".to_vec()),
+        (
+            WarcHeader::RecordID,
+            Record::<BufferedBody>::generate_record_id().into_bytes(),
+        ),
+        (WarcHeader::Date, date),
+    ];
+    if let Some(original_id) = original_id {
+        fixed.push((WarcHeader::RefersTo, original_id));
+    }
+    fixed.extend([
+        (
+            WarcHeader::Unknown(CONTENT_LANGUAGE.to_string()),
+            lang.as_bytes().to_vec(),
+        ),
+        (
+            WarcHeader::ContentType,
+            b"text/plain; charset=utf-8".to_vec(),
+        ),
+        (
+            WarcHeader::ContentLength,
+            content.len().to_string().into_bytes(),
+        ),
+    ]);
+    let mut preserved: Vec<_> = headers.into_iter().collect();
+    preserved.sort_by_key(|(header, _)| header.to_string());
+
+    let mut record = Vec::with_capacity(content.len() + 1024);
+    write!(record, "{WARC_VERSION}\r\n")?;
+    for (header, value) in fixed.into_iter().chain(preserved) {
+        write!(record, "{header}: ")?;
+        record.extend_from_slice(&value);
+        record.extend_from_slice(b"\r\n");
+    }
+    record.extend_from_slice(b"\r\n");
+    record.extend_from_slice(content);
+    record.extend_from_slice(b"\r\n\r\n");
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oscar_io::v3::{Document, Metadata};
+    use warc::{WarcHeader, WarcReader};
+
+    use super::conversion_record;
+
+    #[test]
+    fn test_conversion_record() {
+        let headers = HashMap::from([
+            (WarcHeader::WarcType, b"conversion".to_vec()),
+            (WarcHeader::RecordID, b"<urn:uuid:original>".to_vec()),
+            (WarcHeader::Date, b"2023-12-01T10:00:00Z".to_vec()),
+            (WarcHeader::TargetURI, b"https://example.com/".to_vec()),
+            (WarcHeader::BlockDigest, b"sha1:STALE".to_vec()),
+            (WarcHeader::ContentLength, b"1".to_vec()),
+            (
+                WarcHeader::Unknown("quality-signals".to_string()),
+                b"{\"ttr\":0.5}".to_vec(),
+            ),
+        ]);
+        let doc = Document::new(
+            "Bonjour\nle monde\n".to_string(),
+            headers,
+            Metadata::default(),
+        );
+
+        let mut records = Vec::new();
+        records.extend(conversion_record(&doc, "fr").unwrap());
+        records.extend(conversion_record(&doc, "fr").unwrap());
+        assert!(records.starts_with(b"WARC/1.0\r\nwarc-type: conversion\r\n"));
+
+        let records: Vec<_> = WarcReader::new(&records[..])
+            .iter_records()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_ne!(records[0].warc_id(), records[1].warc_id());
+        let record = &records[0];
+        assert_eq!(record.body(), b"Bonjour\nle monde\n");
+        assert_eq!(
+            record.header(WarcHeader::RefersTo).as_deref(),
+            Some("<urn:uuid:original>")
+        );
+        assert_eq!(
+            record.header(WarcHeader::TargetURI).as_deref(),
+            Some("https://example.com/")
+        );
+        assert_eq!(record.date().to_rfc3339(), "2023-12-01T10:00:00+00:00");
+        assert_eq!(
+            record
+                .header(WarcHeader::Unknown(
+                    "warc-identified-content-language".to_string()
+                ))
+                .as_deref(),
+            Some("fr")
+        );
+        assert_eq!(record.header(WarcHeader::BlockDigest), None);
+        assert_eq!(
+            record.header(WarcHeader::Unknown("quality-signals".to_string())),
+            None
+        );
+    }
+}
@@ -0,0 +1,293 @@
+/*! Discarded documents audit
+
+Writes the records/documents that were discarded by the pipeline in `<dst>/<reason>.jsonl`,
+so that filters can be tuned on evidence rather than on aggregated counts.
+
+Each line holds the rejection reason, the shard id, the record id and URI of a discarded record,
+and depending on the [DiscardMode], its content and metadata (when it got identified).
+
+Content is the one seen by the filter that discarded the record:
+records discarded for being too short hold their content before short lines removal,
+while records discarded later hold their content after short lines removal.
+
+Audit files are large when content is kept: this is meant for tuning runs on samples, not for full runs.
+!*/
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fmt,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+};
+
+use log::info;
+use oscar_io::v3::{Document, Metadata};
+use serde::Serialize;
+use warc::{BufferedBody, Record, WarcHeader};
+
+use crate::error::Error;
+
+/// What is kept from discarded records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscardMode {
+    /// Provenance, content and metadata.
+    Documents,
+    /// Provenance and metadata only.
+    Metadata,
+}
+
+impl FromStr for DiscardMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "documents" => Ok(Self::Documents),
+            "metadata" => Ok(Self::Metadata),
+            other => Err(Error::Custom(format!(
+                "unknown discard mode {other} (expected documents or metadata)"
+            ))),
+        }
+    }
+}
+
+/// Why a record was discarded.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DiscardReason {
+    /// Not matched by the record selection.
+    Unselected,
+    /// From a domain that isn't in the domain allowlist.
+    DomainNotAllowed,
+    /// Payload digest or URI already seen during the run.
+    Duplicate,
+    /// No line kept after short lines removal.
+    TooShort,
+    /// Rejected by the record quality filter.
+    LowQuality,
+    /// Identification below the language threshold, or no identified language.
+    LowConfidence,
+    /// Identified label that is not a valid language tag.
+    UnknownLabel,
+    /// Both noisy and tiny.
+    NoisyTiny,
+    /// Dropped by the annotation policy, with the annotation type (e.g. `adult`).
+    Annotation(String),
+    /// Identified in a language that isn't selected.
+    Language,
+}
+
+impl fmt::Display for DiscardReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unselected => write!(f, "unselected"),
+            Self::DomainNotAllowed => write!(f, "domain_not_allowed"),
+            Self::Duplicate => write!(f, "duplicate"),
+            Self::TooShort => write!(f, "too_short"),
+            Self::LowQuality => write!(f, "low_quality"),
+            Self::LowConfidence => write!(f, "low_confidence"),
+            Self::UnknownLabel => write!(f, "unknown_label"),
+            Self::NoisyTiny => write!(f, "noisy_tiny"),
+            Self::Annotation(annotation) => write!(f, "annotation_{annotation}"),
+            Self::Language => write!(f, "language"),
+        }
+    }
+}
+
+/// A discarded record, as written in audit files.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Discarded {
+    pub record_id: String,
+    pub uri: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Metadata>,
+}
+
+/// Audit file line.
+#[derive(Serialize)]
+struct Line<'a> {
+    reason: String,
+    shard_id: usize,
+    #[serde(flatten)]
+    discarded: &'a Discarded,
+}
+
+/// Thread-safe writer of discarded records, with one file per reason.
+pub struct DiscardWriter {
+    dst: PathBuf,
+    mode: DiscardMode,
+    writers: Mutex<HashMap<DiscardReason, BufWriter<File>>>,
+}
+
+impl DiscardWriter {
+    /// Create a new writer in `dst`, creating the folder if needed.
+    pub fn new(dst: &Path, mode: DiscardMode) -> Result<Self, Error> {
+        std::fs::create_dir_all(dst)?;
+        Ok(Self {
+            dst: dst.to_path_buf(),
+            mode,
+            writers: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Get what has to be kept from a record.
+    pub fn record(&self, record: &Record<BufferedBody>) -> Discarded {
+        Discarded {
+            record_id: record.warc_id().to_string(),
+            uri: record
+                .header(WarcHeader::TargetURI)
+                .map(|uri| uri.into_owned()),
+            content: (self.mode == DiscardMode::Documents)
+                .then(|| String::from_utf8_lossy(record.body()).into_owned()),
+            metadata: None,
+        }
+    }
+
+    /// Get what has to be kept from a document.
+    pub fn document(&self, doc: &Document) -> Discarded {
+        Discarded {
+            record_id: doc.warc_id().into_owned(),
+            uri: doc.url(),
+            content: (self.mode == DiscardMode::Documents).then(|| doc.content().to_string()),
+            metadata: Some(doc.metadata().clone()),
+        }
+    }
+
+    /// Write a discarded record in `<dst>/<reason>.jsonl`.
+    pub fn write(
+        &self,
+        reason: DiscardReason,
+        shard_id: usize,
+        discarded: &Discarded,
+    ) -> Result<(), Error> {
+        let line = Line {
+            reason: reason.to_string(),
+            shard_id,
+            discarded,
+        };
+
+        let mut writers = self.writers.lock().unwrap();
+        let writer = match writers.entry(reason) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => {
+                let path = self.dst.join(format!("{}.jsonl", e.key()));
+                info!("Creating discarded documents file {:?}", path);
+                e.insert(BufWriter::new(File::create(path)?))
+            }
+        };
+        serde_json::to_writer(&mut *writer, &line)?;
+        writeln!(writer)?;
+        Ok(())
+    }
+
+    /// Flush all files.
+    pub fn flush(&self) -> Result<(), Error> {
+        for writer in self.writers.lock().unwrap().values_mut() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, io::BufRead};
+
+    use oscar_io::v3::{Document, Metadata};
+    use warc::{BufferedBody, Record, WarcHeader};
+
+    use super::{DiscardMode, DiscardReason, DiscardWriter};
+
+    fn read_lines(path: &std::path::Path) -> Vec<serde_json::Value> {
+        let f = std::fs::File::open(path).unwrap();
+        std::io::BufReader::new(f)
+            .lines()
+            .map(|l| serde_json::from_str(&l.unwrap()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(
+            "documents".parse::<DiscardMode>().unwrap(),
+            DiscardMode::Documents
+        );
+        assert_eq!(
+            "metadata".parse::<DiscardMode>().unwrap(),
+            DiscardMode::Metadata
+        );
+        assert!("all".parse::<DiscardMode>().is_err());
+    }
+
+    #[test]
+    fn test_reason_display() {
+        assert_eq!(DiscardReason::TooShort.to_string(), "too_short");
+        assert_eq!(
+            DiscardReason::DomainNotAllowed.to_string(),
+            "domain_not_allowed"
+        );
+        assert_eq!(
+            DiscardReason::Annotation("adult".to_string()).to_string(),
+            "annotation_adult"
+        );
+    }
+
+    #[test]
+    fn test_write() {
+        let dst = tempfile::tempdir().unwrap();
+        let dst = dst.path().join("discarded");
+        let writer = DiscardWriter::new(&dst, DiscardMode::Documents).unwrap();
+
+        let mut record: Record<BufferedBody> = Record::default().add_body("foo");
+        record.set_warc_id("<urn:uuid:0>");
+        record
+            .set_header(WarcHeader::TargetURI, "http://example.com")
+            .unwrap();
+        let discarded = writer.record(&record);
+        writer
+            .write(DiscardReason::TooShort, 3, &discarded)
+            .unwrap();
+        writer
+            .write(DiscardReason::TooShort, 4, &discarded)
+            .unwrap();
+
+        let headers = HashMap::from([(WarcHeader::RecordID, b"<urn:uuid:1>".to_vec())]);
+        let doc = Document::new("bar".to_string(), headers, Metadata::default());
+        writer
+            .write(DiscardReason::NoisyTiny, 4, &writer.document(&doc))
+            .unwrap();
+        writer.flush().unwrap();
+
+        let too_short = read_lines(&dst.join("too_short.jsonl"));
+        assert_eq!(too_short.len(), 2);
+        assert_eq!(too_short[0]["reason"], "too_short");
+        assert_eq!(too_short[0]["shard_id"], 3);
+        assert_eq!(too_short[0]["record_id"], "<urn:uuid:0>");
+        assert_eq!(too_short[0]["uri"], "http://example.com");
+        assert_eq!(too_short[0]["content"], "foo");
+        assert!(too_short[0].get("metadata").is_none());
+
+        let noisy_tiny = read_lines(&dst.join("noisy_tiny.jsonl"));
+        assert_eq!(noisy_tiny.len(), 1);
+        assert_eq!(noisy_tiny[0]["content"], "bar");
+        assert!(noisy_tiny[0].get("metadata").is_some());
+    }
+
+    #[test]
+    fn test_metadata_only() {
+        let dst = tempfile::tempdir().unwrap();
+        let writer = DiscardWriter::new(dst.path(), DiscardMode::Metadata).unwrap();
+        let record: Record<BufferedBody> = Record::default().add_body("foo");
+        let discarded = writer.record(&record);
+        assert_eq!(discarded.content, None);
+        writer
+            .write(DiscardReason::Duplicate, 0, &discarded)
+            .unwrap();
+        writer.flush().unwrap();
+
+        let duplicates = read_lines(&dst.path().join("duplicate.jsonl"));
+        assert!(duplicates[0].get("content").is_none());
+    }
+}
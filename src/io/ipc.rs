@@ -0,0 +1,137 @@
+/*! Arrow IPC document writer
+
+[IpcWriter] writes documents of a given language as a dataset of [Arrow IPC](https://arrow.apache.org/docs/format/Columnar.html#ipc-file-format)
+files (also known as Feather v2), enabled by the `arrow` feature (see [super::arrow] for the dataset layout and columns).
+
+Files use the IPC file format rather than the stream one, so that consumers (pyarrow, polars…) can memory-map them.
+They are not compressed for the same reason.
+!*/
+use std::{fs::File, io::BufWriter, path::Path};
+
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::SchemaRef;
+use log::{debug, error};
+use oscar_io::v3::{Document, WriterTrait};
+use oxilangtag::LanguageTag;
+
+use super::arrow::{self, arrow_error, DatasetWriter, Parts};
+
+pub struct IpcWriter {
+    parts: Parts,
+    writer: Option<FileWriter<BufWriter<File>>>,
+    schema: SchemaRef,
+}
+
+impl IpcWriter {
+    /// Get the writer of the current part, opening a new one if needed.
+    fn writer(&mut self) -> Result<&mut FileWriter<BufWriter<File>>, oscar_io::Error> {
+        if self.writer.is_none() {
+            let file = BufWriter::new(self.parts.create()?);
+            let writer = FileWriter::try_new(file, &self.schema).map_err(arrow_error)?;
+            self.writer = Some(writer);
+        }
+
+        Ok(self.writer.as_mut().unwrap())
+    }
+}
+
+impl DatasetWriter for IpcWriter {
+    fn write(&mut self, docs: Vec<Document>) -> Result<(), oscar_io::Error> {
+        if docs.is_empty() {
+            return Ok(());
+        }
+        let batch = arrow::batch(&self.schema, &docs)?;
+        self.writer()?.write(&batch).map_err(arrow_error)
+    }
+
+    fn close(&mut self) -> Result<(), oscar_io::Error> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.finish().map_err(arrow_error)?;
+            debug!("closed part of {:?}", self.parts.dir());
+        }
+        Ok(())
+    }
+
+    fn dir(&self) -> &Path {
+        self.parts.dir()
+    }
+}
+
+impl WriterTrait for IpcWriter {
+    type Item = Document;
+
+    /// Create a new writer for the `<dst>/<lang>/` dataset. Files are only opened on first write.
+    ///
+    /// Parts are split on close rather than on size, so `max_file_size` is ignored.
+    fn new(
+        dst: &Path,
+        lang: LanguageTag<String>,
+        _max_file_size: Option<u64>,
+    ) -> Result<Self, oscar_io::Error> {
+        Ok(Self {
+            parts: Parts::new(dst, &lang, "arrow"),
+            writer: None,
+            schema: arrow::schema(),
+        })
+    }
+
+    fn write(&mut self, vals: Vec<Document>) -> Result<(), oscar_io::Error> {
+        DatasetWriter::write(self, vals)
+    }
+
+    fn write_single(&mut self, val: &Document) -> Result<(), oscar_io::Error> {
+        DatasetWriter::write(self, vec![val.clone()])
+    }
+
+    fn close_meta(&mut self) -> Result<(), oscar_io::Error> {
+        self.close()
+    }
+}
+
+impl Drop for IpcWriter {
+    /// Finish the current part, so that it's readable even if the writer wasn't closed.
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            error!("could not close {:?}: {:?}", self.parts.dir(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use arrow_ipc::reader::FileReader;
+    use oscar_io::v3::WriterTrait;
+    use oxilangtag::LanguageTag;
+
+    use crate::io::arrow::tests::{contents, doc};
+
+    use super::IpcWriter;
+
+    /// Read the contents of a part.
+    fn read(path: &std::path::Path) -> Vec<String> {
+        let reader = FileReader::try_new(File::open(path).unwrap(), None).unwrap();
+        reader.flat_map(|batch| contents(&batch.unwrap())).collect()
+    }
+
+    #[test]
+    fn test_write() {
+        let dst = tempfile::tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let mut w = IpcWriter::new(dst.path(), lang, None).unwrap();
+        w.write(vec![doc("foo", None), doc("bar", Some("tiny"))])
+            .unwrap();
+        w.write(vec![doc("baz", None)]).unwrap();
+        w.close_meta().unwrap();
+        w.write(vec![doc("qux", None)]).unwrap();
+        drop(w);
+
+        let dir = dst.path().join("fr");
+        assert_eq!(
+            read(&dir.join("part-00000.arrow")),
+            vec!["foo", "bar", "baz"]
+        );
+        assert_eq!(read(&dir.join("part-00001.arrow")), vec!["qux"]);
+    }
+}
@@ -2,96 +2,251 @@
 
 Each language (provided by [crate::lang::LANG]) is given a [self::Writer] wrapped into an [Arc<Mutex<Writer>>].
 
+[CategoryFilesDoc] buckets documents by content category under each language, in `<dst>/<lang>/<category>/`,
+with one [LangFilesDoc] per language and category.
+
+Both can share an [OpenWriters] to cap the number of open files, writers being reopened when needed,
+and a [FieldMapping] to rename/omit fields of written documents.
+Writers only get closed when written through [LangFilesDoc::write], or by [LangFilesDoc::close_all].
+
+Documents are written as JSONL by default, optionally compressed (see [Compression]),
+or as WARC conversion records or Arrow IPC/Parquet (see [OutputFormat]).
+JSONL files can also be uploaded to an object store (see [super::remote], `object-store` feature):
+uploads are only finished by [LangFilesDoc::close_all].
+
 ## Warning
 
 !*/
 use std::{
-    collections::HashMap,
+    collections::{hash_map::Entry, BTreeMap, HashMap},
     path::{Path, PathBuf},
-    sync::{Arc, Mutex, RwLock},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
 };
 
 use log::info;
 use oxilangtag::LanguageTag;
 
-// use crate::lang::LANG;
-use crate::error;
-use crate::error::Error;
+use crate::{
+    error::Error,
+    identifiers::registry::{LanguageEntry, Registry},
+};
+
+#[cfg(feature = "arrow")]
+use super::ipc::IpcWriter;
+#[cfg(feature = "parquet")]
+use super::parquet::ParquetWriter;
+#[cfg(feature = "object-store")]
+use super::remote::RemoteDst;
+use super::{
+    mapping::FieldMapping,
+    template::FilenameTemplate,
+    writer::{Compression, Layout, OpenWriters, OutputFormat, Writer, WriterPosition, WriterStats},
+};
+use oscar_io::v3::{Document, WriterTrait};
 
-// use super::writer::{WriterDoc, WriterTrait};
-use oscar_io::v3::{Writer, WriterTrait};
-/// Holds references to [Writer].
-// pub struct LangFiles {
-//     writers: HashMap<&'static str, Arc<Mutex<Writer>>>,
-// }
+/// Writers of each language: a single one, or several sharing part numbers (see [LangFilesDoc::set_shards]).
+type LanguageMap = HashMap<LanguageTag<String>, Vec<Arc<Mutex<Writer>>>>;
 
-type LanguageMap = HashMap<LanguageTag<String>, Arc<Mutex<Writer>>>;
+/// Holds references to [Writer]s, created on first write of a language.
+///
+/// Documents are written as JSONL, one document per line, so that every complete line of a crashed run
+/// can still be parsed.
 pub struct LangFilesDoc {
     writers: Arc<RwLock<LanguageMap>>,
     dst: PathBuf,
     part_size_bytes: Option<u64>,
+    open_writers: Option<Arc<OpenWriters>>,
+    field_mapping: Option<Arc<FieldMapping>>,
+    format: OutputFormat,
+    compression: Compression,
+    max_part_docs: Option<usize>,
+    layout: Layout,
+    template: Option<Arc<FilenameTemplate>>,
+    append: bool,
+    fsync_parts: bool,
+    written_bytes: Option<Arc<AtomicU64>>,
+    registry: Option<Arc<Registry>>,
+    nb_shards: usize,
+    /// shard tried first by the next write
+    next_shard: AtomicUsize,
+    #[cfg(feature = "object-store")]
+    remote: Option<Arc<RemoteDst>>,
 }
 
-// impl LangFiles {
-//     /// Create a new LangFiles. `part_size_bytes` sets an indication of the maximum size
-//     /// by part.
-//     /// Note that if it is set too low and a unique record can't be stored in an unique part
-//     /// then a part will still be created, being larger than the `part_size_bytes`. This is expected behaviour.
-//     ///
-//     /// Also keep in mind that [Self::close_meta] has to be called once every write is done.
-//     ///
-//     // [Self::close_meta] could be integrated in an `impl Drop`
-//     pub fn new(dst: &Path, part_size_bytes: Option<u64>) -> Result<Self, error::Error> {
-//         let mut writers = HashMap::with_capacity(LANG.len());
-//         let mut w;
-//         for lang in LANG.iter() {
-//             w = Writer::new(dst, lang, part_size_bytes)?;
-//             writers.insert(*lang, Arc::new(Mutex::new(w)));
-//         }
-
-//         Ok(Self { writers })
-//     }
-
-//     /// Get a non-mutable reference to the writers.
-//     pub fn writers(&self) -> &HashMap<&'static str, Arc<Mutex<Writer>>> {
-//         &self.writers
-//     }
-
-//     /// Fix open metadata files by removing trailing comma and closing the array.
-//     pub fn close_meta(&self) -> Result<(), error::Error> {
-//         for writer in self.writers.values() {
-//             let mut writer_lock = writer.lock().unwrap();
-//             writer_lock.close_meta()?;
-//         }
-//         Ok(())
-//     }
-// }
-
 impl LangFilesDoc {
     /// Create a new LangFiles. `part_size_bytes` sets an indication of the maximum size
     /// by part.
     /// Note that if it is set too low and a unique record can't be stored in an unique part
     /// then a part will still be created, being larger than the `part_size_bytes`. This is expected behaviour.
     ///
-    /// Also keep in mind that [Self::close_meta] has to be called once every write is done.
-    ///
-    // [Self::close_meta] could be integrated in an `impl Drop`
+    /// [Self::close_all] has to be called once every write is done, to finish parts and write their checksums.
     pub fn new(dst: &Path, part_size_bytes: Option<u64>) -> Self {
         Self {
             writers: Arc::new(RwLock::new(HashMap::new())),
             dst: dst.to_path_buf(),
             part_size_bytes,
+            open_writers: None,
+            field_mapping: None,
+            format: OutputFormat::default(),
+            compression: Compression::default(),
+            max_part_docs: None,
+            layout: Layout::default(),
+            template: None,
+            append: false,
+            fsync_parts: false,
+            written_bytes: None,
+            registry: None,
+            nb_shards: 1,
+            next_shard: AtomicUsize::new(0),
+            #[cfg(feature = "object-store")]
+            remote: None,
         }
     }
 
-    fn new_writer(
-        dst: &Path,
-        lang: LanguageTag<String>,
-        part_size_bytes: Option<u64>,
-    ) -> Result<Arc<Mutex<Writer>>, Error> {
-        let w = Writer::new(dst, lang, part_size_bytes)?;
+    /// Cap the number of open writers (see [OpenWriters]).
+    pub fn set_open_writers(&mut self, open_writers: Arc<OpenWriters>) {
+        self.open_writers = Some(open_writers);
+    }
+
+    /// Rename/omit fields of written documents (see [FieldMapping]).
+    pub fn set_field_mapping(&mut self, field_mapping: Arc<FieldMapping>) {
+        self.field_mapping = Some(field_mapping);
+    }
+
+    /// Set the format of written documents.
+    pub fn set_format(&mut self, format: OutputFormat) {
+        self.format = format;
+    }
+
+    /// Set the compression of JSONL files.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Set the layout of JSONL files (see [Layout]).
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+    }
+
+    /// Name JSONL parts from a template (see [FilenameTemplate]).
+    pub fn set_template(&mut self, template: Arc<FilenameTemplate>) {
+        self.template = Some(template);
+    }
+
+    /// Resume writing after the JSONL parts of a previous run instead of overwriting them (see [Writer::resume]).
+    pub fn set_append(&mut self, append: bool) {
+        self.append = append;
+    }
+
+    /// Sync parts to disk when they're finished (see [Writer::set_fsync_parts]).
+    pub fn set_fsync_parts(&mut self, fsync_parts: bool) {
+        self.fsync_parts = fsync_parts;
+    }
+
+    /// Count written bytes in a shared counter (see [Writer::set_written_bytes]).
+    pub fn set_written_bytes(&mut self, written_bytes: Arc<AtomicU64>) {
+        self.written_bytes = Some(written_bytes);
+    }
+
+    /// Use the part sizes and compressions of languages listed in a registry (see [Registry]).
+    pub fn set_registry(&mut self, registry: Arc<Registry>) {
+        self.registry = Some(registry);
+    }
+
+    /// Write each language with `nb_shards` writers (clamped to 1), so that threads writing the same language
+    /// don't wait for each other. Writers number their parts from a shared counter (see [Writer::set_shared_numbering]),
+    /// so that a language still has parts `1..n`, and checksums of all of them are merged by [Self::close_all].
+    ///
+    /// Only applies to JSONL/WARC files, and can't be combined with [Self::set_append].
+    pub fn set_shards(&mut self, nb_shards: usize) {
+        self.nb_shards = nb_shards.max(1);
+    }
+
+    /// Rotate JSONL parts once they hold `max_part_docs` documents (see [Writer::set_max_part_docs]).
+    pub fn set_max_part_docs(&mut self, max_part_docs: Option<usize>) {
+        self.max_part_docs = max_part_docs;
+    }
+
+    /// Upload files to an object store rather than writing them on local disk (see [RemoteDst]).
+    #[cfg(feature = "object-store")]
+    pub fn set_remote(&mut self, remote: Arc<RemoteDst>) {
+        self.remote = Some(remote);
+    }
+
+    /// Create the writers of a language, removing stale parts beforehand if they're sharded.
+    fn new_writers(&self, lang: LanguageTag<String>) -> Result<Vec<Arc<Mutex<Writer>>>, Error> {
+        if self.nb_shards == 1 || !self.format.is_file() || self.append {
+            return Ok(vec![Arc::new(Mutex::new(self.new_writer(lang)?))]);
+        }
+        let part_numbers = Arc::new(AtomicUsize::new(0));
+        let mut writers = Vec::with_capacity(self.nb_shards);
+        for _ in 0..self.nb_shards {
+            let mut w = self.new_writer(lang.clone())?;
+            w.set_shared_numbering(part_numbers.clone());
+            writers.push(w);
+        }
+        writers[0].remove_stale_parts()?;
+        Ok(writers
+            .into_iter()
+            .map(|w| Arc::new(Mutex::new(w)))
+            .collect())
+    }
+
+    fn new_writer(&self, lang: LanguageTag<String>) -> Result<Writer, Error> {
+        let entry = self
+            .registry
+            .as_ref()
+            .and_then(|registry| registry.get(&lang));
+        let part_size_bytes = entry
+            .and_then(LanguageEntry::part_size)
+            .or(self.part_size_bytes);
+        let dst = &self.dst;
+        let mut w = Writer::new(dst, lang.clone(), part_size_bytes)?;
+        if let Some(field_mapping) = &self.field_mapping {
+            w.set_field_mapping(field_mapping.clone());
+        }
+        w.set_compression(
+            entry
+                .and_then(LanguageEntry::compression)
+                .unwrap_or(self.compression),
+        );
+        w.set_layout(self.layout);
+        if let Some(template) = &self.template {
+            w.set_template(template.clone());
+        }
+        w.set_max_part_docs(self.max_part_docs);
+        w.set_fsync_parts(self.fsync_parts);
+        if let Some(written_bytes) = &self.written_bytes {
+            w.set_written_bytes(written_bytes.clone());
+        }
+        #[cfg(feature = "object-store")]
+        if let Some(remote) = &self.remote {
+            w.set_remote(remote.clone());
+        }
+        if self.format == OutputFormat::Warc {
+            w.set_warc();
+        }
+        if self.append && self.format.is_file() {
+            w.resume()?;
+        }
+        match self.format {
+            OutputFormat::Jsonl | OutputFormat::Warc => (),
+            #[cfg(feature = "arrow")]
+            OutputFormat::Arrow => {
+                w.set_dataset(Box::new(IpcWriter::new(dst, lang, part_size_bytes)?));
+            }
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet { row_group_size } => {
+                let mut parquet = ParquetWriter::new(dst, lang, part_size_bytes)?;
+                parquet.set_row_group_size(row_group_size);
+                w.set_dataset(Box::new(parquet));
+            }
+        }
 
-        Ok(Arc::new(Mutex::new(w)))
+        Ok(w)
     }
 
     pub fn contains(&self, k: &LanguageTag<String>) -> bool {
@@ -109,24 +264,341 @@ impl LangFilesDoc {
             .write()
             .expect("Problem with locking writers (in write)");
 
-        // we use the entry API rather than insert to keep the
-        // old writer if the lang already exists
-        writer.entry(k.clone()).or_insert(Self::new_writer(
-            &self.dst,
-            k.clone(),
-            self.part_size_bytes,
-        )?);
+        // keep the old writer if the lang has been inserted since checked,
+        // without creating a new one (it may resume previous parts)
+        if let Entry::Vacant(entry) = writer.entry(k.clone()) {
+            entry.insert(self.new_writers(k.clone())?);
+        }
 
         info!("{k}: Done");
         Ok(())
     }
     /// Get a non-mutable reference to the writers.
-    // pub fn writers(&self) -> Arc<HashMap<LanguageTag<String>, Arc<Mutex<WriterDoc>>>> {
-    pub fn writers(
-        &self,
-    ) -> std::sync::RwLockReadGuard<HashMap<LanguageTag<String>, Arc<Mutex<Writer>>>> {
+    pub fn writers(&self) -> std::sync::RwLockReadGuard<'_, LanguageMap> {
         self.writers.read().unwrap()
     }
+
+    /// Get the (first) writer of a language, if it has been created.
+    pub fn writer(&self, lang: &LanguageTag<String>) -> Option<Arc<Mutex<Writer>>> {
+        self.writers().get(lang).map(|writers| writers[0].clone())
+    }
+
+    /// Write documents of a given language, creating its writer if needed.
+    ///
+    /// If there's a cap on open writers, least recently used writers are closed after writing.
+    pub fn write(&self, lang: &LanguageTag<String>, docs: Vec<Document>) -> Result<(), Error> {
+        if !self.contains(lang) {
+            self.insert_writer(lang.clone())?;
+        }
+        let writers = self.writers().get(lang).unwrap().clone();
+
+        // write with the first idle writer, or wait for the first one tried if they're all busy
+        let start = self.next_shard.fetch_add(1, Ordering::Relaxed) % writers.len();
+        let (writer, mut locked) = (0..writers.len())
+            .map(|i| &writers[(start + i) % writers.len()])
+            .find_map(|writer| Some((writer, writer.try_lock().ok()?)))
+            .unwrap_or_else(|| (&writers[start], writers[start].lock().unwrap()));
+        locked.write(docs)?;
+        drop(locked);
+        if let Some(open_writers) = &self.open_writers {
+            open_writers.touch(writer);
+        }
+        Ok(())
+    }
+
+    /// Sync the files of a language to disk (see [super::policy::sync_path]).
+    ///
+    /// This syncs every part of the language (or its `<dst>/<lang>/` dataset).
+    pub fn sync(&self, lang: &LanguageTag<String>) -> Result<(), Error> {
+        for writer in self.writers().get(lang).into_iter().flatten() {
+            writer.lock().unwrap().sync()?;
+        }
+        Ok(())
+    }
+
+    /// Sync the files of all languages to disk.
+    pub fn sync_all(&self) -> Result<(), Error> {
+        for lang in self.writers().keys() {
+            self.sync(lang)?;
+        }
+        Ok(())
+    }
+
+    /// Get the documents, bytes and parts written per language.
+    pub fn stats(&self) -> BTreeMap<String, WriterStats> {
+        self.writers()
+            .iter()
+            .map(|(lang, writers)| {
+                let mut stats = WriterStats::default();
+                for writer in writers {
+                    stats += writer.lock().unwrap().stats();
+                }
+                (lang.to_string(), stats)
+            })
+            .collect()
+    }
+
+    /// Get the position of the writer of each language, to be restored by [Self::restore] (see [Writer::position]).
+    ///
+    /// Only applies to JSONL/WARC files written by a single writer per language.
+    pub fn positions(&self) -> Result<BTreeMap<String, WriterPosition>, Error> {
+        self.writers()
+            .iter()
+            .map(|(lang, writers)| Ok((lang.to_string(), writers[0].lock().unwrap().position()?)))
+            .collect()
+    }
+
+    /// Create the writers of a previous run at their positions (see [Writer::restore]).
+    ///
+    /// Has to be called before the first write.
+    pub fn restore(&self, positions: &BTreeMap<String, WriterPosition>) -> Result<(), Error> {
+        let mut writers = self.writers.write().unwrap();
+        for (lang, position) in positions {
+            let lang = LanguageTag::parse(lang.clone())?;
+            let mut writer = self.new_writer(lang.clone())?;
+            writer.restore(position)?;
+            writers.insert(lang, vec![Arc::new(Mutex::new(writer))]);
+        }
+        Ok(())
+    }
+
+    /// Close the writers of all languages, finishing parts, writing their checksums and finishing Arrow IPC/Parquet files.
+    pub fn close_all(&self) -> Result<(), Error> {
+        for writers in self.writers().values() {
+            for writer in writers {
+                writer.lock().unwrap().close_meta()?;
+            }
+            if writers.len() > 1 {
+                let parts: Vec<_> = writers
+                    .iter()
+                    .flat_map(|writer| writer.lock().unwrap().parts().to_vec())
+                    .collect();
+                writers[0].lock().unwrap().write_summaries(&parts)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Language-separated writers, bucketed by content category.
+pub struct CategoryFilesDoc {
+    /// writers per category and language
+    categories: RwLock<HashMap<(String, String), LangFilesDoc>>,
+    dst: PathBuf,
+    open_writers: Option<Arc<OpenWriters>>,
+    field_mapping: Option<Arc<FieldMapping>>,
+    format: OutputFormat,
+    compression: Compression,
+    part_size_bytes: Option<u64>,
+    max_part_docs: Option<usize>,
+    layout: Layout,
+    template: Option<Arc<FilenameTemplate>>,
+    append: bool,
+    fsync_parts: bool,
+    written_bytes: Option<Arc<AtomicU64>>,
+    registry: Option<Arc<Registry>>,
+    nb_shards: usize,
+    #[cfg(feature = "object-store")]
+    remote: Option<Arc<RemoteDst>>,
+}
+
+impl CategoryFilesDoc {
+    pub fn new(dst: &Path) -> Self {
+        Self {
+            categories: RwLock::new(HashMap::new()),
+            dst: dst.to_path_buf(),
+            open_writers: None,
+            field_mapping: None,
+            format: OutputFormat::default(),
+            compression: Compression::default(),
+            part_size_bytes: None,
+            max_part_docs: None,
+            layout: Layout::default(),
+            template: None,
+            append: false,
+            fsync_parts: false,
+            written_bytes: None,
+            registry: None,
+            nb_shards: 1,
+            #[cfg(feature = "object-store")]
+            remote: None,
+        }
+    }
+
+    /// Cap the number of open writers (see [OpenWriters]).
+    pub fn set_open_writers(&mut self, open_writers: Arc<OpenWriters>) {
+        self.open_writers = Some(open_writers);
+    }
+
+    /// Rename/omit fields of written documents (see [FieldMapping]).
+    pub fn set_field_mapping(&mut self, field_mapping: Arc<FieldMapping>) {
+        self.field_mapping = Some(field_mapping);
+    }
+
+    /// Set the format of written documents.
+    pub fn set_format(&mut self, format: OutputFormat) {
+        self.format = format;
+    }
+
+    /// Set the compression of JSONL files.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Set the layout of JSONL files (see [Layout]).
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+    }
+
+    /// Name JSONL parts from a template (see [FilenameTemplate]).
+    pub fn set_template(&mut self, template: Arc<FilenameTemplate>) {
+        self.template = Some(template);
+    }
+
+    /// Resume writing after the JSONL parts of a previous run instead of overwriting them (see [Writer::resume]).
+    pub fn set_append(&mut self, append: bool) {
+        self.append = append;
+    }
+
+    /// Sync parts to disk when they're finished (see [Writer::set_fsync_parts]).
+    pub fn set_fsync_parts(&mut self, fsync_parts: bool) {
+        self.fsync_parts = fsync_parts;
+    }
+
+    /// Count written bytes in a shared counter (see [Writer::set_written_bytes]).
+    pub fn set_written_bytes(&mut self, written_bytes: Arc<AtomicU64>) {
+        self.written_bytes = Some(written_bytes);
+    }
+
+    /// Use per-language writer settings of a registry (see [LangFilesDoc::set_registry]).
+    pub fn set_registry(&mut self, registry: Arc<Registry>) {
+        self.registry = Some(registry);
+    }
+
+    /// Write each language with `nb_shards` writers (see [LangFilesDoc::set_shards]).
+    pub fn set_shards(&mut self, nb_shards: usize) {
+        self.nb_shards = nb_shards;
+    }
+
+    /// Rotate JSONL parts once they reach `part_size_bytes` bytes and/or hold `max_part_docs` documents.
+    pub fn set_part_limits(&mut self, part_size_bytes: Option<u64>, max_part_docs: Option<usize>) {
+        self.part_size_bytes = part_size_bytes;
+        self.max_part_docs = max_part_docs;
+    }
+
+    /// Upload files to an object store rather than writing them on local disk (see [RemoteDst]).
+    #[cfg(feature = "object-store")]
+    pub fn set_remote(&mut self, remote: Arc<RemoteDst>) {
+        self.remote = Some(remote);
+    }
+
+    /// Create the writers of a category of a language, in `<dst>/<lang>/<category>/`.
+    fn new_category(&self, category: &str, lang: &str) -> Result<LangFilesDoc, Error> {
+        let dst = self.dst.join(lang).join(category);
+        std::fs::create_dir_all(&dst)?;
+        let mut langfiles = LangFilesDoc::new(&dst, self.part_size_bytes);
+        if let Some(open_writers) = &self.open_writers {
+            langfiles.set_open_writers(open_writers.clone());
+        }
+        if let Some(field_mapping) = &self.field_mapping {
+            langfiles.set_field_mapping(field_mapping.clone());
+        }
+        langfiles.set_format(self.format);
+        langfiles.set_compression(self.compression);
+        langfiles.set_layout(self.layout);
+        if let Some(template) = &self.template {
+            langfiles.set_template(template.clone());
+        }
+        langfiles.set_max_part_docs(self.max_part_docs);
+        langfiles.set_append(self.append);
+        langfiles.set_fsync_parts(self.fsync_parts);
+        if let Some(written_bytes) = &self.written_bytes {
+            langfiles.set_written_bytes(written_bytes.clone());
+        }
+        if let Some(registry) = &self.registry {
+            langfiles.set_registry(registry.clone());
+        }
+        langfiles.set_shards(self.nb_shards);
+        #[cfg(feature = "object-store")]
+        if let Some(remote) = &self.remote {
+            langfiles.set_remote(remote.clone());
+        }
+        Ok(langfiles)
+    }
+
+    /// Get the position of the writers of each category and language (see [LangFilesDoc::positions]).
+    pub fn positions(&self) -> Result<BTreeMap<String, BTreeMap<String, WriterPosition>>, Error> {
+        let mut positions: BTreeMap<String, BTreeMap<String, WriterPosition>> = BTreeMap::new();
+        for ((category, _), langfiles) in self.categories.read().unwrap().iter() {
+            positions
+                .entry(category.clone())
+                .or_default()
+                .extend(langfiles.positions()?);
+        }
+        Ok(positions)
+    }
+
+    /// Create the writers of a previous run at their positions (see [LangFilesDoc::restore]).
+    ///
+    /// Has to be called before the first write.
+    pub fn restore(
+        &self,
+        positions: &BTreeMap<String, BTreeMap<String, WriterPosition>>,
+    ) -> Result<(), Error> {
+        let mut categories = self.categories.write().unwrap();
+        for (category, positions) in positions {
+            for (lang, position) in positions {
+                let langfiles = self.new_category(category, lang)?;
+                langfiles.restore(&BTreeMap::from([(lang.clone(), position.clone())]))?;
+                categories.insert((category.clone(), lang.clone()), langfiles);
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the documents, bytes and parts written per category and language.
+    pub fn stats(&self) -> BTreeMap<String, BTreeMap<String, WriterStats>> {
+        let mut stats: BTreeMap<String, BTreeMap<String, WriterStats>> = BTreeMap::new();
+        for ((category, _), langfiles) in self.categories.read().unwrap().iter() {
+            stats
+                .entry(category.clone())
+                .or_default()
+                .extend(langfiles.stats());
+        }
+        stats
+    }
+
+    /// Close the writers of all categories.
+    pub fn close_all(&self) -> Result<(), Error> {
+        for langfiles in self.categories.read().unwrap().values() {
+            langfiles.close_all()?;
+        }
+        Ok(())
+    }
+
+    /// Write documents of a given language and category, in `<dst>/<lang>/<category>/`.
+    pub fn write(
+        &self,
+        category: &str,
+        lang: LanguageTag<String>,
+        docs: Vec<Document>,
+    ) -> Result<(), Error> {
+        // categories come from blocklist folder names, but are used as paths.
+        if category.is_empty() || category.starts_with('.') || category.contains(['/', '\\']) {
+            return Err(Error::Custom(format!("invalid category name {category}")));
+        }
+
+        let key = (category.to_string(), lang.to_string());
+        if !self.categories.read().unwrap().contains_key(&key) {
+            let mut categories = self.categories.write().unwrap();
+            if let Entry::Vacant(entry) = categories.entry(key.clone()) {
+                entry.insert(self.new_category(category, lang.as_str())?);
+            }
+        }
+
+        let categories = self.categories.read().unwrap();
+        categories.get(&key).unwrap().write(&lang, docs)
+    }
 }
 
 #[cfg(test)]
@@ -160,6 +632,11 @@ mod tests {
         lf.insert_writer(language.clone()).unwrap();
 
         assert!(lf.contains(&language));
+
+        // inserting again keeps the writer
+        let writer = lf.writer(&language).unwrap();
+        lf.insert_writer(language.clone()).unwrap();
+        assert!(Arc::ptr_eq(&writer, &lf.writer(&language).unwrap()));
     }
 
     #[test]
@@ -186,15 +663,12 @@ mod tests {
 
         lf.insert_writer(docs[0].identification().label().clone())
             .unwrap();
-        let w = lf
-            .writers()
-            .get(docs[0].identification().label())
-            .unwrap()
-            .clone();
+        let w = lf.writer(docs[0].identification().label()).unwrap();
 
         if let Ok(mut w) = w.try_lock() {
             w.write(docs.to_vec()).unwrap();
         }
+        lf.close_all().unwrap();
 
         let mut read_path = PathBuf::from(dst.path());
         read_path.push("en_meta.jsonl");
@@ -204,4 +678,176 @@ mod tests {
 
         assert_eq!(doc_from_file, docs[0]);
     }
+
+    #[test]
+    fn write_open_writers() {
+        let dst = tempdir().unwrap();
+        let mut lf = LangFilesDoc::new(dst.path(), None);
+        lf.set_open_writers(Arc::new(OpenWriters::new(1)));
+
+        let mut docs = Vec::new();
+        for lang in ["en", "fr", "en"] {
+            let id = Identification::new(LanguageTag::parse(lang.to_string()).unwrap(), 1.0);
+            let metadata = Metadata::new(&id, &[Some(id.clone())]);
+            let doc = Document::new(lang.to_string(), WarcHeaders::new(), metadata);
+            lf.write(id.label(), vec![doc.clone()]).unwrap();
+            docs.push(doc);
+        }
+
+        // en got closed when fr was written, but is only finished by close_all
+        assert!(dst.path().join("fr_meta.jsonl.tmp").exists());
+        lf.close_all().unwrap();
+        let en = std::fs::read_to_string(dst.path().join("en_meta.jsonl")).unwrap();
+        let en: Vec<Document> = en
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(en, vec![docs[0].clone(), docs[2].clone()]);
+        assert!(dst.path().join("fr_meta.jsonl").exists());
+        assert!(!dst.path().join("fr_meta.jsonl.tmp").exists());
+
+        let stats = lf.stats();
+        assert_eq!(stats["en"].nb_documents, 2);
+        assert_eq!(stats["en"].nb_parts, 1);
+        assert_eq!(
+            stats["fr"].nb_bytes,
+            std::fs::metadata(dst.path().join("fr_meta.jsonl"))
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[test]
+    fn write_shards() {
+        let dst = tempdir().unwrap();
+        for stale in ["en_meta.jsonl", "en_meta_part_7.jsonl"] {
+            std::fs::write(dst.path().join(stale), "stale\n").unwrap();
+        }
+        let mut lf = LangFilesDoc::new(dst.path(), None);
+        lf.set_shards(2);
+        lf.set_max_part_docs(Some(1));
+
+        let en = LanguageTag::parse("en".to_string()).unwrap();
+        let id = Identification::new(en.clone(), 1.0);
+        let doc = |content: &str| {
+            let metadata = Metadata::new(&id, &[Some(id.clone())]);
+            Document::new(content.to_string(), WarcHeaders::new(), metadata)
+        };
+
+        lf.write(&en, vec![doc("foo")]).unwrap();
+        assert!(!dst.path().join("en_meta.jsonl").exists());
+        assert!(!dst.path().join("en_meta_part_7.jsonl").exists());
+
+        // the second writer would be tried first, but it's busy
+        let busy = lf.writers()[&en][1].clone();
+        let busy = busy.lock().unwrap();
+        lf.write(&en, vec![doc("bar")]).unwrap();
+        drop(busy);
+        lf.write(&en, vec![doc("baz")]).unwrap();
+        lf.write(&en, vec![doc("qux")]).unwrap();
+        lf.close_all().unwrap();
+
+        // parts are numbered in creation order, whatever the writer
+        for (n, content) in ["foo", "bar", "baz", "qux"].iter().enumerate() {
+            let part =
+                std::fs::read_to_string(dst.path().join(format!("en_meta_part_{}.jsonl", n + 1)))
+                    .unwrap();
+            let part: Document = serde_json::from_str(part.trim()).unwrap();
+            assert_eq!(part.content(), content);
+        }
+
+        // and checksums of all writers are merged
+        let manifest = std::fs::read_to_string(dst.path().join("en_sha256.txt")).unwrap();
+        let files: Vec<_> = manifest
+            .lines()
+            .map(|line| line.split_once(' ').unwrap().1)
+            .collect();
+        assert_eq!(
+            files,
+            (1..=4)
+                .map(|n| format!("en_meta_part_{n}.jsonl"))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(lf.stats()["en"].nb_documents, 4);
+        assert_eq!(lf.stats()["en"].nb_parts, 4);
+    }
+
+    #[test]
+    #[cfg(feature = "parquet")]
+    fn write_parquet() {
+        let dst = tempdir().unwrap();
+        let mut lf = LangFilesDoc::new(dst.path(), None);
+        lf.set_format(OutputFormat::Parquet { row_group_size: 10 });
+
+        let id = Identification::new(LanguageTag::parse("en".to_string()).unwrap(), 1.0);
+        let metadata = Metadata::new(&id, &[Some(id.clone())]);
+        let doc = Document::new("Hello!".to_string(), WarcHeaders::new(), metadata);
+        lf.write(id.label(), vec![doc]).unwrap();
+        lf.close_all().unwrap();
+        lf.sync_all().unwrap();
+
+        let part = dst.path().join("en").join("part-00000.parquet");
+        assert!(std::fs::metadata(part).unwrap().len() > 0);
+        assert!(!dst.path().join("en_meta.jsonl").exists());
+    }
+
+    #[test]
+    fn write_registry_settings() {
+        use std::io::Write;
+
+        let mut registry = tempfile::NamedTempFile::new().unwrap();
+        registry
+            .write_all(br#"[{"tag": "fr", "compression": "zstd"}]"#)
+            .unwrap();
+        let registry = Registry::from_path(registry.path()).unwrap();
+
+        let dst = tempdir().unwrap();
+        let mut lf = LangFilesDoc::new(dst.path(), None);
+        lf.set_registry(Arc::new(registry));
+        for lang in ["en", "fr"] {
+            let id = Identification::new(LanguageTag::parse(lang.to_string()).unwrap(), 1.0);
+            let metadata = Metadata::new(&id, &[Some(id.clone())]);
+            let doc = Document::new(lang.to_string(), WarcHeaders::new(), metadata);
+            lf.write(id.label(), vec![doc]).unwrap();
+        }
+        lf.close_all().unwrap();
+
+        assert!(dst.path().join("en_meta.jsonl").exists());
+        assert!(dst.path().join("fr_meta.jsonl.zst").exists());
+        assert!(!dst.path().join("fr_meta.jsonl").exists());
+    }
+
+    #[test]
+    fn write_categories() {
+        let dst = tempdir().unwrap();
+        let cf = CategoryFilesDoc::new(dst.path());
+
+        let id = Identification::new(LanguageTag::parse("en".to_string()).unwrap(), 1.0);
+        let metadata = Metadata::new(&id, &[Some(id.clone())]);
+        let doc = Document::new("Hello!".to_string(), WarcHeaders::new(), metadata);
+        let lang = doc.identification().label().clone();
+
+        cf.write("adult", lang.clone(), vec![doc.clone()]).unwrap();
+        cf.write("gambling", lang.clone(), vec![doc.clone()])
+            .unwrap();
+        assert!(cf.write("../adult", lang, vec![doc.clone()]).is_err());
+        cf.close_all().unwrap();
+
+        let b = File::open(dst.path().join("en").join("adult").join("en_meta.jsonl")).unwrap();
+        let doc_from_file: Document = serde_json::from_reader(b).unwrap();
+        assert_eq!(doc_from_file, doc);
+        assert!(dst
+            .path()
+            .join("en")
+            .join("gambling")
+            .join("en_meta.jsonl")
+            .exists());
+        assert_eq!(cf.stats()["adult"]["en"].nb_documents, 1);
+        assert_eq!(
+            cf.positions().unwrap()["gambling"]
+                .keys()
+                .collect::<Vec<_>>(),
+            vec!["en"]
+        );
+    }
 }
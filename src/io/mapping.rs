@@ -0,0 +1,142 @@
+/*! JSON output field mapping
+
+Renames or omits fields of written documents, so that output files match downstream ingestion schemas
+without a conversion pass.
+
+A mapping is a JSON object whose keys are field paths (nested fields being separated by `.`),
+and whose values are either a new name or `null` to omit the field:
+
+```json
+{
+    "content": "text",
+    "warc_headers": null,
+    "metadata.quality_warnings": "annotations"
+}
+```
+
+Paths always refer to original field names, even when a parent field is renamed.
+Missing fields are ignored.
+
+## Warning
+
+Mapped files can't be read back by ungoliant (e.g. by `merge` or `near-dedup`), since they don't follow the document schema anymore.
+!*/
+use std::{fs::File, io::BufReader, path::Path};
+
+use log::info;
+use serde_json::Value;
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, Default)]
+pub struct FieldMapping {
+    /// field paths and their new names (or None to omit), deepest paths first
+    fields: Vec<(Vec<String>, Option<String>)>,
+}
+
+impl FieldMapping {
+    /// Load a mapping from a JSON file.
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let value = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        Self::from_value(value)
+    }
+
+    /// Load a mapping from a JSON object.
+    pub fn from_value(value: Value) -> Result<Self, Error> {
+        let object = match value {
+            Value::Object(object) => object,
+            other => {
+                return Err(Error::Custom(format!(
+                    "field mapping must be a JSON object, got {other}"
+                )))
+            }
+        };
+
+        let mut fields = Vec::with_capacity(object.len());
+        for (path, name) in object {
+            let path: Vec<String> = path.split('.').map(String::from).collect();
+            if path.iter().any(|field| field.is_empty()) {
+                return Err(Error::Custom(format!(
+                    "invalid field path {:?}",
+                    path.join(".")
+                )));
+            }
+            let name = match name {
+                Value::String(name) => Some(name),
+                Value::Null => None,
+                other => {
+                    return Err(Error::Custom(format!(
+                        "field {} must be mapped to a string or null, got {other}",
+                        path.join(".")
+                    )))
+                }
+            };
+            fields.push((path, name));
+        }
+
+        // map children before their parents, so that paths stay valid
+        fields.sort_by_key(|(path, _)| std::cmp::Reverse(path.len()));
+
+        info!("Using a mapping of {} fields", fields.len());
+        Ok(Self { fields })
+    }
+
+    /// Rename/omit fields of a value.
+    pub fn apply(&self, value: &mut Value) {
+        for (path, name) in &self.fields {
+            let (field, parents) = path.split_last().unwrap();
+            let parent = parents
+                .iter()
+                .try_fold(&mut *value, |value, parent| value.get_mut(parent));
+            if let Some(Value::Object(parent)) = parent {
+                if let Some(field_value) = parent.remove(field) {
+                    if let Some(name) = name {
+                        parent.insert(name.clone(), field_value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::FieldMapping;
+
+    #[test]
+    fn test_from_value() {
+        assert!(FieldMapping::from_value(json!({"content": "text", "warc_headers": null})).is_ok());
+        assert!(FieldMapping::from_value(json!(["content"])).is_err());
+        assert!(FieldMapping::from_value(json!({"content": 1})).is_err());
+        assert!(FieldMapping::from_value(json!({"metadata.": "m"})).is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mapping = FieldMapping::from_value(json!({
+            "content": "text",
+            "warc_headers": null,
+            "metadata": "meta",
+            "metadata.quality_warnings": "annotations",
+            "metadata.tlsh": null,
+            "missing.field": null,
+        }))
+        .unwrap();
+
+        let mut doc = json!({
+            "content": "foo",
+            "warc_headers": {"warc-type": "conversion"},
+            "metadata": {"quality_warnings": ["tiny"], "tlsh": "T1", "categories": null},
+        });
+        mapping.apply(&mut doc);
+        assert_eq!(
+            doc,
+            json!({
+                "text": "foo",
+                "meta": {"annotations": ["tiny"], "categories": null},
+            })
+        );
+    }
+}
@@ -5,7 +5,28 @@ Textual/contextual data saving and loading.
 
 Currently only saving is implemented but loading is planned in order to facilitate operations on already generated corpora.
 !*/
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod conversion;
+pub mod discarded;
+#[cfg(feature = "arrow")]
+pub mod ipc;
 mod langfiles;
+pub mod mapping;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod policy;
+#[cfg(feature = "object-store")]
+pub mod remote;
+pub mod template;
+pub mod textmeta;
+pub mod writer;
 // pub use langfiles::LangFiles;
+pub use discarded::{DiscardMode, DiscardReason, DiscardWriter};
+pub use langfiles::CategoryFilesDoc;
 pub use langfiles::LangFilesDoc;
-// pub use writer::Writer;
+pub use mapping::FieldMapping;
+pub use policy::WritePolicy;
+pub use template::FilenameTemplate;
+pub use textmeta::TextMetaFiles;
+pub use writer::{Compression, Layout, OpenWriters, OutputFormat, WriterPosition};
@@ -0,0 +1,167 @@
+/*! Parquet document writer
+
+[ParquetWriter] writes documents of a given language as an [Apache Parquet](https://parquet.apache.org) dataset,
+enabled by the `parquet` feature (see [super::arrow] for the dataset layout and columns).
+
+Rows are buffered in row groups of a configurable size, and compressed with Snappy.
+!*/
+use std::{fs::File, path::Path};
+
+use arrow_schema::SchemaRef;
+use log::{debug, error};
+use oscar_io::v3::{Document, WriterTrait};
+use oxilangtag::LanguageTag;
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+
+use super::arrow::{self, arrow_error, DatasetWriter, Parts};
+
+/// Default number of rows of a row group.
+pub const DEFAULT_ROW_GROUP_SIZE: usize = 10_000;
+
+pub struct ParquetWriter {
+    parts: Parts,
+    writer: Option<ArrowWriter<File>>,
+    row_group_size: usize,
+    schema: SchemaRef,
+}
+
+impl ParquetWriter {
+    /// Set the number of rows of row groups.
+    pub fn set_row_group_size(&mut self, row_group_size: usize) {
+        self.row_group_size = row_group_size.max(1);
+    }
+
+    /// Get the writer of the current part, opening a new one if needed.
+    fn writer(&mut self) -> Result<&mut ArrowWriter<File>, oscar_io::Error> {
+        if self.writer.is_none() {
+            let props = WriterProperties::builder()
+                .set_max_row_group_size(self.row_group_size)
+                .set_compression(Compression::SNAPPY)
+                .build();
+            let writer =
+                ArrowWriter::try_new(self.parts.create()?, self.schema.clone(), Some(props))
+                    .map_err(arrow_error)?;
+            self.writer = Some(writer);
+        }
+
+        Ok(self.writer.as_mut().unwrap())
+    }
+}
+
+impl DatasetWriter for ParquetWriter {
+    fn write(&mut self, docs: Vec<Document>) -> Result<(), oscar_io::Error> {
+        if docs.is_empty() {
+            return Ok(());
+        }
+        let batch = arrow::batch(&self.schema, &docs)?;
+        self.writer()?.write(&batch).map_err(arrow_error)
+    }
+
+    fn close(&mut self) -> Result<(), oscar_io::Error> {
+        if let Some(writer) = self.writer.take() {
+            writer.close().map_err(arrow_error)?;
+            debug!("closed part of {:?}", self.parts.dir());
+        }
+        Ok(())
+    }
+
+    fn dir(&self) -> &Path {
+        self.parts.dir()
+    }
+}
+
+impl WriterTrait for ParquetWriter {
+    type Item = Document;
+
+    /// Create a new writer for the `<dst>/<lang>/` dataset. Files are only opened on first write.
+    ///
+    /// Parts are split on close rather than on size, so `max_file_size` is ignored.
+    fn new(
+        dst: &Path,
+        lang: LanguageTag<String>,
+        _max_file_size: Option<u64>,
+    ) -> Result<Self, oscar_io::Error> {
+        Ok(Self {
+            parts: Parts::new(dst, &lang, "parquet"),
+            writer: None,
+            row_group_size: DEFAULT_ROW_GROUP_SIZE,
+            schema: arrow::schema(),
+        })
+    }
+
+    fn write(&mut self, vals: Vec<Document>) -> Result<(), oscar_io::Error> {
+        DatasetWriter::write(self, vals)
+    }
+
+    fn write_single(&mut self, val: &Document) -> Result<(), oscar_io::Error> {
+        DatasetWriter::write(self, vec![val.clone()])
+    }
+
+    fn close_meta(&mut self) -> Result<(), oscar_io::Error> {
+        self.close()
+    }
+}
+
+impl Drop for ParquetWriter {
+    /// Finish the current part, so that it's readable even if the writer wasn't closed.
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            error!("could not close {:?}: {:?}", self.parts.dir(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use oscar_io::v3::WriterTrait;
+    use oxilangtag::LanguageTag;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use crate::io::arrow::{
+        tests::{contents, doc},
+        DatasetWriter,
+    };
+
+    use super::ParquetWriter;
+
+    /// Read the contents of a part.
+    fn read(path: &std::path::Path) -> Vec<String> {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        reader.flat_map(|batch| contents(&batch.unwrap())).collect()
+    }
+
+    #[test]
+    fn test_write() {
+        let dst = tempfile::tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let mut w = ParquetWriter::new(dst.path(), lang, None).unwrap();
+        WriterTrait::write(&mut w, vec![doc("foo", None), doc("bar", Some("tiny"))]).unwrap();
+        w.close_meta().unwrap();
+        WriterTrait::write(&mut w, vec![doc("baz", None)]).unwrap();
+        drop(w);
+
+        let dir = dst.path().join("fr");
+        assert_eq!(read(&dir.join("part-00000.parquet")), vec!["foo", "bar"]);
+        assert_eq!(read(&dir.join("part-00001.parquet")), vec!["baz"]);
+    }
+
+    #[test]
+    fn test_row_groups() {
+        let dst = tempfile::tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let mut w = ParquetWriter::new(dst.path(), lang, None).unwrap();
+        w.set_row_group_size(2);
+        let docs = (0..5).map(|i| doc(&i.to_string(), None)).collect();
+        DatasetWriter::write(&mut w, docs).unwrap();
+        w.close().unwrap();
+
+        let file = File::open(w.dir().join("part-00000.parquet")).unwrap();
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+        assert_eq!(builder.metadata().num_row_groups(), 3);
+    }
+}
@@ -0,0 +1,153 @@
+/*! Flush and fsync policy
+
+Text/metadata files are written without buffering, while rebuild (avro) files are buffered and flushed
+after each shard by default.
+
+A [WritePolicy] makes the durability/throughput trade-off explicit:
+
+- `flush_every`: flush rebuild files every `n` shards written to a given language (and at the end of the run),
+- `flush_interval`: also flush all rebuild files once this duration elapsed since the last periodic flush, checked between shards,
+  which bounds what is lost when a node is preempted,
+- `fsync`: ask the OS to persist both text/metadata and rebuild files to disk at each flush,
+- `fsync_parts`: sync parts of text/metadata files to disk when they're finished, before they're renamed
+  (see [super::writer::Writer]), so that complete parts are durable.
+
+Periodic flushes only happen between shards: documents of the shards being written can always be lost.
+!*/
+use std::{
+    fs::File,
+    io,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone)]
+pub struct WritePolicy {
+    flush_every: usize,
+    flush_interval: Option<Duration>,
+    fsync: bool,
+    fsync_parts: bool,
+}
+
+impl WritePolicy {
+    /// Create a new policy. `flush_every` is clamped to 1.
+    pub fn new(flush_every: usize, fsync: bool) -> Self {
+        Self {
+            flush_every: flush_every.max(1),
+            flush_interval: None,
+            fsync,
+            fsync_parts: false,
+        }
+    }
+
+    /// Also flush all writers once `flush_interval` elapsed since the last periodic flush.
+    pub fn set_flush_interval(&mut self, flush_interval: Option<Duration>) {
+        self.flush_interval = flush_interval;
+    }
+
+    /// Sync parts to disk when they're finished.
+    pub fn set_fsync_parts(&mut self, fsync_parts: bool) {
+        self.fsync_parts = fsync_parts;
+    }
+
+    /// Returns true if a writer should be flushed after its `nb_writes`-th write.
+    pub fn should_flush(&self, nb_writes: usize) -> bool {
+        nb_writes.is_multiple_of(self.flush_every)
+    }
+
+    /// Returns true if the flush interval elapsed since `last_flush`, which is then reset.
+    ///
+    /// Always false without flush interval.
+    pub fn interval_elapsed(&self, last_flush: &Mutex<Instant>) -> bool {
+        let Some(flush_interval) = self.flush_interval else {
+            return false;
+        };
+        let mut last_flush = last_flush.lock().unwrap();
+        if last_flush.elapsed() < flush_interval {
+            return false;
+        }
+        *last_flush = Instant::now();
+        true
+    }
+
+    /// Returns true if files have to be synced to disk on flush.
+    pub fn fsync(&self) -> bool {
+        self.fsync
+    }
+
+    /// Returns true if parts have to be synced to disk when finished.
+    pub fn fsync_parts(&self) -> bool {
+        self.fsync_parts
+    }
+}
+
+impl Default for WritePolicy {
+    /// Flush after each shard, never fsync.
+    fn default() -> Self {
+        Self::new(1, false)
+    }
+}
+
+/// Sync a file (or a directory, persisting renames of its entries) to disk, if it exists.
+///
+/// Data written through another handle of the same file is synced too.
+pub fn sync_path(path: &Path) -> io::Result<()> {
+    if path.exists() {
+        File::open(path)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        sync::Mutex,
+        time::{Duration, Instant},
+    };
+
+    use super::{sync_path, WritePolicy};
+
+    #[test]
+    fn test_should_flush() {
+        let policy = WritePolicy::default();
+        assert!(policy.should_flush(1));
+        assert!(policy.should_flush(2));
+        assert!(!policy.fsync());
+
+        let policy = WritePolicy::new(3, true);
+        assert!(!policy.should_flush(1));
+        assert!(policy.should_flush(3));
+        assert!(policy.should_flush(6));
+        assert!(policy.fsync());
+
+        // clamped
+        assert!(WritePolicy::new(0, false).should_flush(1));
+    }
+
+    #[test]
+    fn test_interval_elapsed() {
+        let last_flush = Mutex::new(Instant::now() - Duration::from_secs(60));
+        let mut policy = WritePolicy::default();
+        assert!(!policy.interval_elapsed(&last_flush));
+
+        policy.set_flush_interval(Some(Duration::from_secs(30)));
+        assert!(policy.interval_elapsed(&last_flush));
+        // reset by the previous check
+        assert!(!policy.interval_elapsed(&last_flush));
+    }
+
+    #[test]
+    fn test_sync_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foo.jsonl");
+        // missing files are ignored
+        sync_path(&path).unwrap();
+
+        let mut f = std::fs::File::create(&path).unwrap();
+        f.write_all(b"foo").unwrap();
+        sync_path(&path).unwrap();
+        sync_path(dir.path()).unwrap();
+    }
+}
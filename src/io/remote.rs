@@ -0,0 +1,333 @@
+/*! Object store destinations
+
+With the `object-store` feature, documents can be streamed to an object store (S3, Google Cloud Storage, Azure Blob Storage)
+rather than written on local disk: [RemoteDst] maps paths of the local destination to keys under a URL prefix
+(`s3://bucket/prefix`, `gs://bucket/prefix` or `az://container/prefix`), and [Upload]s stream files as multipart uploads.
+
+Credentials, regions and endpoints are read from the usual environment variables
+(`AWS_ACCESS_KEY_ID`, `AWS_ENDPOINT`, `GOOGLE_SERVICE_ACCOUNT`, `AZURE_STORAGE_ACCOUNT_NAME`…),
+and failed requests are retried with exponential backoff.
+
+Objects can't be appended to, so uploads stay open until their writer is finished (see [super::writer::Writer]),
+each of them buffering up to [PART_SIZE] bytes in memory.
+!*/
+use std::{
+    future::Future,
+    path::{Path as LocalPath, PathBuf},
+    sync::Arc,
+};
+
+use log::{debug, error, warn};
+use object_store::{
+    aws::AmazonS3Builder, azure::MicrosoftAzureBuilder, gcp::GoogleCloudStorageBuilder, path::Path,
+    ObjectStore, RetryConfig, WriteMultipart,
+};
+use tokio::runtime::Runtime;
+use url::Url;
+
+use crate::error::Error;
+
+/// Size of uploaded parts.
+pub const PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Maximum number of concurrent part uploads of an upload.
+const MAX_CONCURRENCY: usize = 4;
+
+fn remote_error(e: object_store::Error) -> std::io::Error {
+    std::io::Error::other(format!("object store error: {e}"))
+}
+
+/// Object store destination.
+pub struct RemoteDst {
+    store: Arc<dyn ObjectStore>,
+    prefix: Path,
+    /// local destination, whose paths are mapped to keys under `prefix`
+    root: PathBuf,
+    /// runs uploads. Only taken on drop.
+    runtime: Option<Runtime>,
+}
+
+impl RemoteDst {
+    /// Upload files of the local `root` destination under `url`, retrying failed requests up to `max_retries` times.
+    pub fn new(url: &str, root: &LocalPath, max_retries: usize) -> Result<Self, Error> {
+        let parsed = Url::parse(url)?;
+        let retry = RetryConfig {
+            max_retries,
+            ..Default::default()
+        };
+        let store: Arc<dyn ObjectStore> = match parsed.scheme() {
+            "s3" | "s3a" => Arc::new(
+                AmazonS3Builder::from_env()
+                    .with_url(url)
+                    .with_retry(retry)
+                    .build()
+                    .map_err(remote_error)?,
+            ),
+            "gs" => Arc::new(
+                GoogleCloudStorageBuilder::from_env()
+                    .with_url(url)
+                    .with_retry(retry)
+                    .build()
+                    .map_err(remote_error)?,
+            ),
+            "az" | "abfs" | "abfss" => Arc::new(
+                MicrosoftAzureBuilder::from_env()
+                    .with_url(url)
+                    .with_retry(retry)
+                    .build()
+                    .map_err(remote_error)?,
+            ),
+            other => {
+                return Err(Error::Custom(format!(
+                    "unsupported object store {other} (expected s3, gs or az)"
+                )))
+            }
+        };
+
+        Ok(Self::with_store(store, Path::from(parsed.path()), root)?)
+    }
+
+    /// Upload files of the local `root` destination under `prefix` of a given store.
+    pub fn with_store(
+        store: Arc<dyn ObjectStore>,
+        prefix: Path,
+        root: &LocalPath,
+    ) -> std::io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(MAX_CONCURRENCY)
+            .thread_name("upload")
+            .enable_all()
+            .build()?;
+        Ok(Self {
+            store,
+            prefix,
+            root: root.to_path_buf(),
+            runtime: Some(runtime),
+        })
+    }
+
+    fn runtime(&self) -> &Runtime {
+        self.runtime.as_ref().unwrap()
+    }
+
+    /// Get the key of a path of the local destination.
+    pub fn key(&self, path: &LocalPath) -> std::io::Result<Path> {
+        let relative = path
+            .strip_prefix(&self.root)
+            .map_err(|_| std::io::Error::other(format!("{:?} is not in {:?}", path, self.root)))?;
+        Ok(relative
+            .components()
+            .fold(self.prefix.clone(), |key, part| {
+                key.child(part.as_os_str().to_string_lossy().as_ref())
+            }))
+    }
+
+    /// Run a future on the upload runtime, blocking until it's done.
+    fn block_on<F>(&self, fut: F) -> F::Output
+    where
+        F: Future + Send,
+        F::Output: Send,
+    {
+        // runtime threads (e.g. the main one) can't block, so we wait from another one
+        if tokio::runtime::Handle::try_current().is_ok() {
+            std::thread::scope(|s| {
+                s.spawn(|| self.runtime().block_on(fut))
+                    .join()
+                    .expect("upload thread panicked")
+            })
+        } else {
+            self.runtime().block_on(fut)
+        }
+    }
+
+    /// Start uploading a path of the local destination. Existing objects are overwritten once finished.
+    pub fn upload(self: &Arc<Self>, path: &LocalPath) -> std::io::Result<Upload> {
+        let key = self.key(path)?;
+        debug!("uploading {}", key);
+        let upload = self
+            .block_on(self.store.put_multipart(&key))
+            .map_err(remote_error)?;
+        Ok(Upload {
+            dst: self.clone(),
+            key,
+            inner: Some(WriteMultipart::new_with_chunk_size(upload, PART_SIZE)),
+        })
+    }
+
+    /// Rename an object (copying it, as most stores don't support renames).
+    pub fn rename(&self, from: &LocalPath, to: &LocalPath) -> std::io::Result<()> {
+        let (from, to) = (self.key(from)?, self.key(to)?);
+        self.block_on(self.store.rename(&from, &to))
+            .map_err(remote_error)
+    }
+
+    /// Remove objects of a local destination directory whose names match a predicate.
+    pub fn remove_matching(
+        &self,
+        dir: &LocalPath,
+        matches: impl Fn(&str) -> bool,
+    ) -> std::io::Result<()> {
+        let dir = self.key(dir)?;
+        let listing = self
+            .block_on(self.store.list_with_delimiter(Some(&dir)))
+            .map_err(remote_error)?;
+        for object in listing.objects {
+            if object.location.filename().is_some_and(&matches) {
+                debug!("removing {}", object.location);
+                self.block_on(self.store.delete(&object.location))
+                    .map_err(remote_error)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RemoteDst {
+    /// Shut the runtime down without blocking, as it may be dropped from an async context.
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// Multipart upload of an object.
+///
+/// Written bytes are buffered and uploaded by parts of [PART_SIZE] bytes, the object only being visible once finished.
+/// Unfinished uploads are aborted on drop.
+pub struct Upload {
+    dst: Arc<RemoteDst>,
+    key: Path,
+    inner: Option<WriteMultipart>,
+}
+
+impl Upload {
+    /// Finish the upload, making the object visible.
+    pub fn finish(mut self) -> std::io::Result<()> {
+        if let Some(inner) = self.inner.take() {
+            self.dst.block_on(inner.finish()).map_err(remote_error)?;
+            debug!("uploaded {}", self.key);
+        }
+        Ok(())
+    }
+}
+
+impl std::io::Write for Upload {
+    /// Buffer bytes, uploading full parts. Blocks while too many parts are being uploaded.
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let inner = self.inner.as_mut().expect("upload is finished");
+        {
+            // parts are uploaded by runtime tasks
+            let _guard = self.dst.runtime().enter();
+            inner.write(buf);
+        }
+        self.dst
+            .block_on(inner.wait_for_capacity(MAX_CONCURRENCY))
+            .map_err(remote_error)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.take() {
+            warn!("aborting unfinished upload of {}", self.key);
+            if let Err(e) = self.dst.block_on(inner.abort()) {
+                error!("could not abort upload of {}: {:?}", self.key, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{io::Write, path::Path as LocalPath, sync::Arc};
+
+    use object_store::{memory::InMemory, path::Path, ObjectStore};
+
+    use super::RemoteDst;
+
+    /// Get a destination backed by an in-memory store, with its objects under `prefix`.
+    pub(crate) fn remote(root: &LocalPath) -> (Arc<InMemory>, Arc<RemoteDst>) {
+        let store = Arc::new(InMemory::new());
+        let dst = RemoteDst::with_store(store.clone(), Path::from("prefix"), root).unwrap();
+        (store, Arc::new(dst))
+    }
+
+    /// Get the content of an object, if it exists.
+    pub(crate) fn get(remote: &RemoteDst, store: &InMemory, key: &str) -> Option<Vec<u8>> {
+        remote
+            .block_on(async {
+                match store.get(&Path::from(key)).await {
+                    Ok(result) => result.bytes().await.map(Some),
+                    Err(object_store::Error::NotFound { .. }) => Ok(None),
+                    Err(e) => Err(e),
+                }
+            })
+            .unwrap()
+            .map(|bytes| bytes.to_vec())
+    }
+
+    #[test]
+    fn test_key() {
+        let (_, remote) = remote(LocalPath::new("/dst"));
+        let key = remote.key(LocalPath::new("/dst/categories/fr/adult/fr_meta.jsonl"));
+        assert_eq!(
+            key.unwrap().as_ref(),
+            "prefix/categories/fr/adult/fr_meta.jsonl"
+        );
+        assert!(remote
+            .key(LocalPath::new("/elsewhere/fr_meta.jsonl"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_upload() {
+        let (store, remote) = remote(LocalPath::new("/dst"));
+        let mut upload = remote.upload(LocalPath::new("/dst/fr_meta.jsonl")).unwrap();
+        upload.write_all(b"foo\n").unwrap();
+        upload.write_all(b"bar\n").unwrap();
+        assert!(get(&remote, &store, "prefix/fr_meta.jsonl").is_none());
+        upload.finish().unwrap();
+        assert_eq!(
+            get(&remote, &store, "prefix/fr_meta.jsonl").unwrap(),
+            b"foo\nbar\n"
+        );
+
+        // dropped uploads are aborted
+        let mut upload = remote.upload(LocalPath::new("/dst/en_meta.jsonl")).unwrap();
+        upload.write_all(b"foo\n").unwrap();
+        drop(upload);
+        assert!(get(&remote, &store, "prefix/en_meta.jsonl").is_none());
+    }
+
+    #[test]
+    fn test_rename_remove() {
+        let (store, remote) = remote(LocalPath::new("/dst"));
+        for name in ["fr_meta.jsonl", "fr_meta_part_2.jsonl", "en_meta.jsonl"] {
+            let upload = remote.upload(&LocalPath::new("/dst").join(name)).unwrap();
+            upload.finish().unwrap();
+        }
+        remote
+            .rename(
+                LocalPath::new("/dst/fr_meta.jsonl"),
+                LocalPath::new("/dst/fr_meta_part_1.jsonl"),
+            )
+            .unwrap();
+        assert!(get(&remote, &store, "prefix/fr_meta_part_1.jsonl").is_some());
+        assert!(get(&remote, &store, "prefix/fr_meta.jsonl").is_none());
+
+        remote
+            .remove_matching(LocalPath::new("/dst"), |name| {
+                name.starts_with("fr_meta_part_")
+            })
+            .unwrap();
+        assert!(get(&remote, &store, "prefix/fr_meta_part_1.jsonl").is_none());
+        assert!(get(&remote, &store, "prefix/fr_meta_part_2.jsonl").is_none());
+        assert!(get(&remote, &store, "prefix/en_meta.jsonl").is_some());
+    }
+}
@@ -0,0 +1,248 @@
+/*! Output filename templates
+
+Names parts of JSONL files from a template, so that corpus releases can encode the crawl and zero-padded part numbers
+without renaming files afterwards:
+
+```text
+{lang}/{crawl}_{lang}_{part:05}.{ext}
+```
+
+gives `fr/CC-MAIN-2023-50_fr_00001.jsonl.zst`, relative to the destination. Variables are:
+
+- `{lang}`: language tag of the documents (required),
+- `{part}`: number of the part, starting from 1 (required, in the file name). `{part:0N}` pads it with zeros to N digits,
+- `{crawl}`: crawl identifier, that has to be given along with the template,
+- `{ext}`: extension of the files (`jsonl`, `jsonl.zst`, `jsonl.gz` or `jsonl.xz`).
+
+Parts are always numbered, so that they're never renamed when a new part is started.
+!*/
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Lang,
+    Ext,
+    /// part number, zero-padded to a width
+    Part(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilenameTemplate {
+    template: String,
+    /// segments of the directory, if any
+    dir: Vec<Segment>,
+    /// segments of the file name
+    name: Vec<Segment>,
+}
+
+impl FilenameTemplate {
+    /// Parse a template, substituting `{crawl}` with `crawl`.
+    pub fn parse(template: &str, crawl: Option<&str>) -> Result<Self, Error> {
+        let invalid = |reason: &str| {
+            Error::Custom(format!("invalid filename template {template:?}: {reason}"))
+        };
+
+        let path = Path::new(template);
+        if path.is_absolute()
+            || path
+                .components()
+                .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return Err(invalid("it has to be a relative path, without . or .."));
+        }
+        if crawl.is_some_and(|crawl| crawl.is_empty() || crawl.contains(['/', '\\'])) {
+            return Err(invalid("invalid crawl identifier"));
+        }
+
+        // variables can't hold a /, so the file name starts after the last one
+        let (dir, name) = match template.rsplit_once('/') {
+            Some((dir, name)) => (parse_segments(dir, crawl)?, parse_segments(name, crawl)?),
+            None => (Vec::new(), parse_segments(template, crawl)?),
+        };
+
+        if !dir.contains(&Segment::Lang) && !name.contains(&Segment::Lang) {
+            return Err(invalid("it has to contain {lang}"));
+        }
+        if dir
+            .iter()
+            .any(|segment| matches!(segment, Segment::Part(_)))
+        {
+            return Err(invalid("{part} has to be in the file name"));
+        }
+        if name
+            .iter()
+            .filter(|segment| matches!(segment, Segment::Part(_)))
+            .count()
+            != 1
+        {
+            return Err(invalid("the file name has to contain {part} once"));
+        }
+
+        Ok(Self {
+            template: template.to_string(),
+            dir,
+            name,
+        })
+    }
+
+    fn render_segments(segments: &[Segment], lang: &str, part: &str, ext: &str) -> String {
+        segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(literal) => literal.as_str(),
+                Segment::Lang => lang,
+                Segment::Ext => ext,
+                Segment::Part(_) => part,
+            })
+            .collect()
+    }
+
+    fn part_width(&self) -> usize {
+        self.name
+            .iter()
+            .find_map(|segment| match segment {
+                Segment::Part(width) => Some(*width),
+                _ => None,
+            })
+            .unwrap_or(0)
+    }
+
+    /// Get the path of a part, relative to the destination.
+    pub fn render(&self, lang: &str, part: usize, ext: &str) -> PathBuf {
+        let part = format!("{part:0width$}", width = self.part_width());
+        self.dir(lang, ext)
+            .join(Self::render_segments(&self.name, lang, &part, ext))
+    }
+
+    /// Get the directory of the parts of a language, relative to the destination.
+    pub fn dir(&self, lang: &str, ext: &str) -> PathBuf {
+        PathBuf::from(Self::render_segments(&self.dir, lang, "", ext))
+    }
+
+    /// Get the part number of a file name, if it's the one of a part of a language
+    /// (or of one of its temporary/index files).
+    pub fn part_number(&self, name: &str, lang: &str, ext: &str) -> Option<usize> {
+        let idx = self
+            .name
+            .iter()
+            .position(|segment| matches!(segment, Segment::Part(_)))
+            .unwrap();
+        let prefix = Self::render_segments(&self.name[..idx], lang, "", ext);
+        let suffix = Self::render_segments(&self.name[idx + 1..], lang, "", ext);
+        parse_part_number(name, &prefix, &suffix)
+    }
+}
+
+/// Get the part number of a `<prefix><number><suffix>` file name
+/// (possibly followed by the `.idx`/`.tmp` suffixes of index and temporary files).
+pub(crate) fn parse_part_number(name: &str, prefix: &str, suffix: &str) -> Option<usize> {
+    let rest = name.strip_prefix(prefix)?;
+    let number = [".idx.tmp", ".idx", ".tmp", ""]
+        .iter()
+        .find_map(|extra| rest.strip_suffix(extra)?.strip_suffix(suffix))?;
+    if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    number.parse().ok()
+}
+
+impl std::fmt::Display for FilenameTemplate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.template)
+    }
+}
+
+/// Parse the literals and variables of a template.
+fn parse_segments(template: &str, crawl: Option<&str>) -> Result<Vec<Segment>, Error> {
+    let invalid =
+        |reason: &str| Error::Custom(format!("invalid filename template {template:?}: {reason}"));
+
+    let mut segments = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        if rest[start..].starts_with('}') {
+            return Err(invalid("unmatched }"));
+        }
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| invalid("unmatched {"))?;
+        segments.push(Segment::Literal(rest[..start].to_string()));
+        segments.push(match &rest[start + 1..end] {
+            "lang" => Segment::Lang,
+            "ext" => Segment::Ext,
+            "part" => Segment::Part(0),
+            "crawl" => Segment::Literal(
+                crawl
+                    .ok_or_else(|| invalid("{crawl} needs a crawl identifier"))?
+                    .to_string(),
+            ),
+            other => match other.strip_prefix("part:0").map(str::parse) {
+                Some(Ok(width)) => Segment::Part(width),
+                _ => return Err(invalid(&format!("unknown variable {{{other}}}"))),
+            },
+        });
+        rest = &rest[end + 1..];
+    }
+    segments.push(Segment::Literal(rest.to_string()));
+    segments.retain(|segment| *segment != Segment::Literal(String::new()));
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::FilenameTemplate;
+
+    #[test]
+    fn test_render() {
+        let template = FilenameTemplate::parse(
+            "{lang}/{crawl}_{lang}_{part:05}.{ext}",
+            Some("CC-MAIN-2023-50"),
+        )
+        .unwrap();
+        assert_eq!(
+            template.render("fr", 12, "jsonl.zst"),
+            PathBuf::from("fr/CC-MAIN-2023-50_fr_00012.jsonl.zst")
+        );
+        assert_eq!(template.dir("fr", "jsonl"), PathBuf::from("fr"));
+        let part = |name| template.part_number(name, "fr", "jsonl");
+        assert_eq!(part("CC-MAIN-2023-50_fr_00012.jsonl"), Some(12));
+        assert_eq!(part("CC-MAIN-2023-50_fr_3.jsonl.tmp"), Some(3));
+        assert_eq!(part("CC-MAIN-2023-50_fr_.jsonl"), None);
+        assert_eq!(part("CC-MAIN-2023-50_fr_3.jsonl.bak"), None);
+        assert_eq!(part("fr_sha256.txt"), None);
+
+        let template = FilenameTemplate::parse("oscar_{lang}_{part}.{ext}", None).unwrap();
+        assert_eq!(
+            template.render("en", 3, "jsonl"),
+            PathBuf::from("oscar_en_3.jsonl")
+        );
+        assert_eq!(template.dir("en", "jsonl"), PathBuf::from(""));
+    }
+
+    #[test]
+    fn test_invalid() {
+        for template in [
+            "{lang}_{part}.{ext}/../x",
+            "/{lang}_{part}.{ext}",
+            "{part}.{ext}",
+            "{lang}.{ext}",
+            "{lang}_{part}_{part}.{ext}",
+            "{part}/{lang}.{ext}",
+            "{lang}_{part}.{ext",
+            "{lang}_{part}}.{ext}",
+            "{lang}_{size}_{part}.{ext}",
+            "{crawl}/{lang}_{part}.{ext}",
+        ] {
+            assert!(
+                FilenameTemplate::parse(template, None).is_err(),
+                "{template} should be invalid"
+            );
+        }
+    }
+}
@@ -0,0 +1,162 @@
+/*! Legacy text/metadata output
+
+Writes documents in the text+metadata layout of OSCAR 21.09 (the former `oscarmeta` pipeline), next to the document-oriented output,
+so that both can be generated in a single pass:
+
+- `<dst>/<lang>.txt` holds the content of documents, separated by blank lines,
+- `<dst>/<lang>_meta.jsonl` holds one line per document, with its WARC headers, the line offset of the document in the text file
+  and its number of lines:
+
+```json
+{"headers":{"warc-date":"2021-09-16T11:07:14Z","warc-target-uri":"https://example.com/",...},"offset":0,"nb_sentences":2}
+```
+
+Files are reopened in append mode on each write rather than kept open, so that languages don't each hold two open files.
+!*/
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use log::info;
+use oscar_io::v3::Document;
+use oxilangtag::LanguageTag;
+use serde::Serialize;
+
+use crate::error::Error;
+
+/// Metadata line of a document.
+#[derive(Debug, Serialize)]
+struct Metadata {
+    headers: BTreeMap<String, String>,
+    /// line of the first sentence in the text file
+    offset: usize,
+    nb_sentences: usize,
+}
+
+/// Thread-safe writer of documents in the legacy text/metadata layout.
+pub struct TextMetaFiles {
+    dst: PathBuf,
+    /// number of lines written in the text file of each language
+    offsets: Mutex<HashMap<LanguageTag<String>, usize>>,
+}
+
+impl TextMetaFiles {
+    /// Create a new writer in `dst`, creating the folder if needed.
+    pub fn new(dst: &Path) -> Result<Self, Error> {
+        std::fs::create_dir_all(dst)?;
+        Ok(Self {
+            dst: dst.to_path_buf(),
+            offsets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Open a file, truncating it on first write.
+    fn open(path: &Path, truncate: bool) -> std::io::Result<BufWriter<File>> {
+        let mut options = OpenOptions::new();
+        if truncate {
+            options.write(true).create(true).truncate(true);
+        } else {
+            options.append(true);
+        }
+        Ok(BufWriter::new(options.open(path)?))
+    }
+
+    /// Append documents to the text and metadata files of a language.
+    pub fn write(&self, lang: &LanguageTag<String>, docs: &[Document]) -> Result<(), Error> {
+        let mut offsets = self.offsets.lock().unwrap();
+        let truncate = !offsets.contains_key(lang);
+        if truncate {
+            info!("Creating text/metadata files for {lang}");
+        }
+        let offset = offsets.entry(lang.clone()).or_insert(0);
+
+        let mut text = Self::open(&self.dst.join(format!("{lang}.txt")), truncate)?;
+        let mut meta = Self::open(&self.dst.join(format!("{lang}_meta.jsonl")), truncate)?;
+        for doc in docs {
+            let content = doc.content().trim_end_matches('\n');
+            let nb_sentences = content.lines().count();
+            text.write_all(content.as_bytes())?;
+            text.write_all(b"\n\n")?;
+
+            let metadata = Metadata {
+                headers: doc
+                    .warc_headers()
+                    .iter()
+                    .map(|(header, value)| {
+                        (
+                            header.to_string(),
+                            String::from_utf8_lossy(value).into_owned(),
+                        )
+                    })
+                    .collect(),
+                offset: *offset,
+                nb_sentences,
+            };
+            serde_json::to_writer(&mut meta, &metadata)?;
+            writeln!(meta)?;
+
+            // account for the blank line separating documents
+            *offset += nb_sentences + 1;
+        }
+        text.flush()?;
+        meta.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oscar_io::v3::{Document, Metadata};
+    use oxilangtag::LanguageTag;
+    use warc::WarcHeader;
+
+    use super::TextMetaFiles;
+
+    #[test]
+    fn test_write() {
+        let dst = tempfile::tempdir().unwrap();
+        let files = TextMetaFiles::new(dst.path()).unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let doc = |content: &str| {
+            let headers =
+                HashMap::from([(WarcHeader::TargetURI, b"https://example.com/".to_vec())]);
+            Document::new(content.to_string(), headers, Metadata::default())
+        };
+
+        files
+            .write(&lang, &[doc("foo\nbar\n"), doc("baz")])
+            .unwrap();
+        files.write(&lang, &[doc("qux")]).unwrap();
+
+        let text = std::fs::read_to_string(dst.path().join("fr.txt")).unwrap();
+        assert_eq!(text, "foo\nbar\n\nbaz\n\nqux\n\n");
+
+        let meta: Vec<serde_json::Value> =
+            std::fs::read_to_string(dst.path().join("fr_meta.jsonl"))
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+        let lines: Vec<&str> = text.lines().collect();
+        for (meta, first) in meta.iter().zip(["foo", "baz", "qux"]) {
+            assert_eq!(lines[meta["offset"].as_u64().unwrap() as usize], first);
+        }
+        assert_eq!(meta[0]["nb_sentences"], 2);
+        assert_eq!(
+            meta[2]["headers"]["warc-target-uri"],
+            "https://example.com/"
+        );
+
+        // files are truncated by new runs
+        let files = TextMetaFiles::new(dst.path()).unwrap();
+        files.write(&lang, &[doc("foo")]).unwrap();
+        let text = std::fs::read_to_string(dst.path().join("fr.txt")).unwrap();
+        assert_eq!(text, "foo\n\n");
+    }
+}
@@ -0,0 +1,1810 @@
+/*! Reopenable text/metadata writer
+
+[Writer] writes documents of a given language in `<dst>/<lang>_meta.jsonl`, like [oscar_io::v3::Writer],
+but can be closed and transparently reopened in append mode on next write.
+
+This lets [OpenWriters] cap the number of open files: with 200+ languages, each with
+a text/metadata file per output tree (main, annotated, categories…), runs would otherwise hit `ulimit -n`.
+
+The file is truncated when first opened, so that reruns in the same destination don't leave stale documents.
+
+Parts are written as `<part>.tmp` files, only renamed once finished by [WriterTrait::close_meta] or when a new part is started,
+so that crashed runs don't leave truncated parts looking complete (see [orphaned_parts]).
+They can also be synced to disk before being renamed, so that finished parts survive node failures (see [Writer::set_fsync_parts]).
+
+Files can be split in parts by size and/or number of documents: like with [oscar_io::v3::Writer],
+the first part is renamed `<lang>_meta_part_1.jsonl` once a second one, `<lang>_meta_part_2.jsonl`, is started.
+Files can also be written in per-language directories instead (see [Layout]), or named from a template (see [FilenameTemplate]).
+Several writers can write the same language concurrently, numbering their parts from a shared counter (see [Writer::set_shared_numbering]).
+
+Parts are hashed while written, and their SHA-256 are written in `<dst>/<lang>_sha256.txt` by [WriterTrait::close_meta],
+so that releases don't need to read them again.
+
+Documents can be written with renamed/omitted fields (see [FieldMapping]),
+compressed with zstd, multistream gzip or xz (see [Compression]),
+as WARC conversion records (see [super::conversion]) or as an Arrow IPC/Parquet dataset instead (see [OutputFormat]).
+
+With the `object-store` feature, files can be uploaded to an object store rather than written on local disk (see [super::remote]).
+Uploads can't be reopened, so they are kept open when the writer is closed, and only finished by [WriterTrait::close_meta]
+or when a new part is started.
+!*/
+use std::{
+    collections::{HashSet, VecDeque},
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use flate2::write::GzEncoder;
+use log::debug;
+#[cfg(feature = "arrow")]
+use log::error;
+use oscar_io::v3::{Document, WriterTrait};
+use oxilangtag::LanguageTag;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use xz2::{
+    stream::{Check, MtStreamBuilder},
+    write::XzEncoder,
+};
+
+use crate::error::Error;
+
+#[cfg(feature = "arrow")]
+use super::arrow::DatasetWriter;
+use super::conversion::conversion_record;
+use super::mapping::FieldMapping;
+#[cfg(feature = "parquet")]
+use super::parquet::DEFAULT_ROW_GROUP_SIZE;
+#[cfg(feature = "object-store")]
+use super::remote::{RemoteDst, Upload};
+use super::template::{parse_part_number, FilenameTemplate};
+
+/// Format of written documents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// One JSON document per line, in `<dst>/<lang>_meta.jsonl`.
+    #[default]
+    Jsonl,
+    /// WARC conversion records, in `<dst>/<lang>_meta.warc` (see [super::conversion]).
+    Warc,
+    /// Arrow IPC dataset in `<dst>/<lang>/` (see [super::ipc]).
+    #[cfg(feature = "arrow")]
+    Arrow,
+    /// Parquet dataset in `<dst>/<lang>/`, with row groups of `row_group_size` documents
+    /// (see [super::parquet]).
+    #[cfg(feature = "parquet")]
+    Parquet { row_group_size: usize },
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" => Ok(Self::Jsonl),
+            "warc" => Ok(Self::Warc),
+            #[cfg(feature = "arrow")]
+            "arrow" => Ok(Self::Arrow),
+            #[cfg(not(feature = "arrow"))]
+            "arrow" => Err(Error::Custom(
+                "arrow output needs the arrow feature".to_string(),
+            )),
+            #[cfg(feature = "parquet")]
+            "parquet" => Ok(Self::Parquet {
+                row_group_size: DEFAULT_ROW_GROUP_SIZE,
+            }),
+            #[cfg(not(feature = "parquet"))]
+            "parquet" => Err(Error::Custom(
+                "parquet output needs the parquet feature".to_string(),
+            )),
+            other => Err(Error::Custom(format!(
+                "unknown output format {other} (expected jsonl, warc, arrow or parquet)"
+            ))),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// Check if documents are written in files by [Writer] itself, rather than as a columnar dataset.
+    ///
+    /// Compression, parts, layouts, templates and uploads only apply to those.
+    pub fn is_file(&self) -> bool {
+        matches!(self, Self::Jsonl | Self::Warc)
+    }
+}
+
+/// Compression of JSONL/WARC files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    /// zstd compression at a given level, in `<dst>/<lang>_meta.jsonl.zst`.
+    ///
+    /// Each write is compressed as its own frame, so that files can be appended to when reopened
+    /// and are readable up to the last complete write. zstd decoders read concatenated frames transparently.
+    Zstd { level: i32 },
+    /// Multistream gzip compression at a given level, in `<dst>/<lang>_meta.jsonl.gz`,
+    /// with one gzip member per `docs_per_member` documents at most
+    /// (one per record for WARC files, as expected by WARC tooling).
+    ///
+    /// Byte offsets and document counts of members are written in `<dst>/<lang>_meta.jsonl.gz.idx`
+    /// (`<offset>\t<nb_docs>` lines), so that members can be decompressed in parallel.
+    Gzip { level: u32, docs_per_member: usize },
+    /// xz compression at a given preset, in `<dst>/<lang>_meta.jsonl.xz`, encoded with `threads` threads.
+    ///
+    /// Like zstd, each write is compressed as its own xz stream (split in blocks so that threads share the work),
+    /// so that parts can be appended to, truncated at write boundaries when resuming, and are readable
+    /// up to the last complete write. xz decoders read concatenated streams transparently.
+    ///
+    /// Writes of different languages already run concurrently, so a single thread per write is usually best:
+    /// more threads only help when few languages are written at once.
+    Xz { level: u32, threads: u32 },
+}
+
+/// Default zstd compression level.
+pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+/// Default gzip compression level.
+pub const DEFAULT_GZIP_LEVEL: u32 = 6;
+
+/// Default xz preset.
+pub const DEFAULT_XZ_LEVEL: u32 = 6;
+
+/// Minimum size of xz blocks: smaller ones would hurt compression for little parallelism.
+const MIN_XZ_BLOCK_SIZE: u64 = 1 << 20;
+
+/// Default maximum number of documents of a gzip member.
+pub const DEFAULT_DOCS_PER_MEMBER: usize = 1000;
+
+impl FromStr for Compression {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "zstd" => Ok(Self::Zstd {
+                level: DEFAULT_ZSTD_LEVEL,
+            }),
+            "gzip" => Ok(Self::Gzip {
+                level: DEFAULT_GZIP_LEVEL,
+                docs_per_member: DEFAULT_DOCS_PER_MEMBER,
+            }),
+            "xz" => Ok(Self::Xz {
+                level: DEFAULT_XZ_LEVEL,
+                threads: 1,
+            }),
+            other => Err(Error::Custom(format!(
+                "unknown compression {other} (expected none, zstd, gzip or xz)"
+            ))),
+        }
+    }
+}
+
+/// Layout of JSONL files in the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    /// `<dst>/<lang>_meta.jsonl`, or `<dst>/<lang>_meta_part_<n>.jsonl` files once split in parts.
+    #[default]
+    Flat,
+    /// `<dst>/<lang>/<lang>_part_<n>.jsonl` files, along with a `<dst>/<lang>/meta.json` summary of parts.
+    ///
+    /// Parts are numbered from the first one, so that they're never renamed.
+    PerLanguage,
+}
+
+impl FromStr for Layout {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flat" => Ok(Self::Flat),
+            "per-language" => Ok(Self::PerLanguage),
+            other => Err(Error::Custom(format!(
+                "unknown layout {other} (expected flat or per-language)"
+            ))),
+        }
+    }
+}
+
+/// Summary of a finished part, as listed in `meta.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartSummary {
+    /// file name of the part
+    pub file: String,
+    pub nb_documents: usize,
+    pub nb_bytes: u64,
+    /// hex SHA-256 of the part
+    pub sha256: String,
+}
+
+/// Position of a [Writer], recorded in checkpoints so that a crashed run can be resumed from it (see [Writer::restore]).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WriterPosition {
+    /// number of parts started so far
+    pub nb_parts: usize,
+    /// path of the current part, relative to the destination
+    pub file: PathBuf,
+    /// true if the current part has been created
+    pub created: bool,
+    /// bytes written in the current part
+    pub nb_bytes: u64,
+    /// documents written in the current part
+    pub nb_documents: usize,
+    /// length of the gzip member index of the current part
+    pub index_len: u64,
+    /// finished parts
+    pub parts: Vec<PartSummary>,
+    pub stats: WriterStats,
+}
+
+/// Documents, bytes and parts written by a [Writer] during a run.
+///
+/// Bytes and parts are only counted for JSONL files, not for Arrow IPC/Parquet datasets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WriterStats {
+    pub nb_documents: usize,
+    /// written (compressed) bytes
+    pub nb_bytes: u64,
+    /// number of parts created
+    pub nb_parts: usize,
+}
+
+impl std::ops::AddAssign for WriterStats {
+    fn add_assign(&mut self, other: Self) {
+        self.nb_documents += other.nb_documents;
+        self.nb_bytes += other.nb_bytes;
+        self.nb_parts += other.nb_parts;
+    }
+}
+
+/// Summary of the parts of a language, written in `<dst>/<lang>/meta.json` with [Layout::PerLanguage].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LangSummary {
+    pub lang: String,
+    /// `none`, `zstd`, `gzip` or `xz`
+    pub compression: String,
+    pub nb_documents: usize,
+    pub nb_bytes: u64,
+    pub parts: Vec<PartSummary>,
+}
+
+/// Written file: a local file or an object store upload.
+enum Sink {
+    File(File),
+    #[cfg(feature = "object-store")]
+    Upload(Upload),
+}
+
+impl Sink {
+    /// Finish writing. Uploaded objects are only visible once finished.
+    fn finish(self) -> std::io::Result<()> {
+        match self {
+            Self::File(_) => Ok(()),
+            #[cfg(feature = "object-store")]
+            Self::Upload(upload) => upload.finish(),
+        }
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::File(file) => file.write(buf),
+            #[cfg(feature = "object-store")]
+            Self::Upload(upload) => upload.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::File(file) => file.flush(),
+            #[cfg(feature = "object-store")]
+            Self::Upload(upload) => upload.flush(),
+        }
+    }
+}
+
+pub struct Writer {
+    dst: PathBuf,
+    lang: LanguageTag<String>,
+    /// path of the current part
+    path: PathBuf,
+    /// number of parts started so far
+    nb_parts: usize,
+    max_part_bytes: Option<u64>,
+    max_part_docs: Option<usize>,
+    file: Option<Sink>,
+    /// gzip member index of the current part
+    index: Option<Sink>,
+    /// true once the file has been created/truncated
+    created: bool,
+    mapping: Option<Arc<FieldMapping>>,
+    compression: Compression,
+    /// true if documents are written as WARC records rather than JSON lines
+    warc: bool,
+    /// number of bytes written in the current part
+    offset: u64,
+    /// number of documents written in the current part
+    part_docs: usize,
+    /// SHA-256 of the bytes written in the current part
+    digest: Sha256,
+    /// finished parts
+    parts: Vec<PartSummary>,
+    layout: Layout,
+    template: Option<Arc<FilenameTemplate>>,
+    /// true if resuming after the parts of a previous run, which are then kept
+    append: bool,
+    /// true if finished parts are synced to disk
+    fsync_parts: bool,
+    /// number of the last part started by any of the writers of the language, if shared
+    part_numbers: Option<Arc<AtomicUsize>>,
+    stats: WriterStats,
+    /// bytes written by all the writers of the run, if counted
+    written_bytes: Option<Arc<AtomicU64>>,
+    /// writes documents instead of the JSONL file if set
+    #[cfg(feature = "arrow")]
+    dataset: Option<Box<dyn DatasetWriter>>,
+    /// uploads files instead of writing them on local disk if set
+    #[cfg(feature = "object-store")]
+    remote: Option<Arc<RemoteDst>>,
+}
+
+impl Writer {
+    /// Write documents as a columnar dataset rather than in the JSONL file.
+    #[cfg(feature = "arrow")]
+    pub fn set_dataset(&mut self, dataset: Box<dyn DatasetWriter>) {
+        self.dataset = Some(dataset);
+    }
+
+    /// Upload files to an object store rather than writing them on local disk.
+    #[cfg(feature = "object-store")]
+    pub fn set_remote(&mut self, remote: Arc<RemoteDst>) {
+        self.remote = Some(remote);
+    }
+
+    fn is_remote(&self) -> bool {
+        #[cfg(feature = "object-store")]
+        return self.remote.is_some();
+        #[cfg(not(feature = "object-store"))]
+        false
+    }
+
+    /// Sync written files to disk (see [super::policy::sync_path]).
+    ///
+    /// Uploads don't need to be synced.
+    pub fn sync(&self) -> std::io::Result<()> {
+        #[cfg(feature = "arrow")]
+        if let Some(dataset) = &self.dataset {
+            if dataset.dir().exists() {
+                for entry in std::fs::read_dir(dataset.dir())? {
+                    super::policy::sync_path(&entry?.path())?;
+                }
+            }
+            return Ok(());
+        }
+        if self.is_remote() {
+            return Ok(());
+        }
+        let parts = match self.nb_parts {
+            1 => vec![self.path.clone()],
+            nb_parts => (1..=nb_parts).map(|n| self.part_path(Some(n))).collect(),
+        };
+        for part in parts {
+            for path in [index_path(&part), part] {
+                // the current part may not be finished
+                if tmp_path(&path).exists() {
+                    super::policy::sync_path(&tmp_path(&path))?;
+                } else if path.exists() {
+                    super::policy::sync_path(&path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Compress written documents, appending `.zst`/`.gz`/`.xz` to the file name if needed.
+    ///
+    /// Has to be set before the first write.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+        self.path = self.part_path(None);
+    }
+
+    /// Write documents as WARC conversion records rather than JSON lines (see [super::conversion]),
+    /// in `.warc` files.
+    ///
+    /// Has to be set before the first write.
+    pub fn set_warc(&mut self) {
+        self.warc = true;
+        self.path = self.part_path(None);
+    }
+
+    /// Sync parts (and their directory, for renames) to disk when they're finished.
+    ///
+    /// Uploads are durable once finished, and don't need to be synced.
+    pub fn set_fsync_parts(&mut self, fsync_parts: bool) {
+        self.fsync_parts = fsync_parts;
+    }
+
+    /// Number parts from a counter shared with other writers of the same language, so that they can write it concurrently.
+    ///
+    /// Parts are then always numbered, and numbers are only taken when parts are created.
+    /// Stale parts of previous runs aren't removed by shared writers, but by [Writer::remove_stale_parts] before writing,
+    /// and checksum manifests have to be written for all of them (see [Writer::write_summaries]).
+    ///
+    /// Has to be set before the first write.
+    pub fn set_shared_numbering(&mut self, part_numbers: Arc<AtomicUsize>) {
+        self.part_numbers = Some(part_numbers);
+        self.path = self.part_path(None);
+    }
+
+    /// Add written bytes to a counter shared with other writers (see [crate::pipelines::oscardoc::RunBudget]).
+    ///
+    /// Like [WriterStats], only JSONL/WARC files are counted.
+    pub fn set_written_bytes(&mut self, written_bytes: Arc<AtomicU64>) {
+        self.written_bytes = Some(written_bytes);
+    }
+
+    /// Set the layout of files in the destination.
+    ///
+    /// Has to be set before the first write.
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+        self.path = self.part_path(None);
+    }
+
+    /// Name parts from a template rather than from the layout (see [FilenameTemplate]).
+    ///
+    /// Has to be set before the first write.
+    pub fn set_template(&mut self, template: Arc<FilenameTemplate>) {
+        self.template = Some(template);
+        self.path = self.part_path(None);
+    }
+
+    /// Get the directory of files: `<dst>`, or `<dst>/<lang>` with [Layout::PerLanguage].
+    fn dir(&self) -> PathBuf {
+        if let Some(template) = &self.template {
+            return self
+                .dst
+                .join(template.dir(self.lang.as_str(), self.extension()));
+        }
+        match self.layout {
+            Layout::Flat => self.dst.clone(),
+            Layout::PerLanguage => self.dst.join(self.lang.as_str()),
+        }
+    }
+
+    /// Check if parts are always numbered, and thus never renamed.
+    fn is_numbered(&self) -> bool {
+        self.template.is_some() || self.layout == Layout::PerLanguage || self.part_numbers.is_some()
+    }
+
+    fn extension(&self) -> &'static str {
+        match (self.warc, self.compression) {
+            (false, Compression::None) => "jsonl",
+            (false, Compression::Zstd { .. }) => "jsonl.zst",
+            (false, Compression::Gzip { .. }) => "jsonl.gz",
+            (false, Compression::Xz { .. }) => "jsonl.xz",
+            (true, Compression::None) => "warc",
+            (true, Compression::Zstd { .. }) => "warc.zst",
+            (true, Compression::Gzip { .. }) => "warc.gz",
+            (true, Compression::Xz { .. }) => "warc.xz",
+        }
+    }
+
+    /// Rotate parts once they hold `max_part_docs` documents,
+    /// in addition to the `max_file_size` bytes given to [Writer::new].
+    pub fn set_max_part_docs(&mut self, max_part_docs: Option<usize>) {
+        self.max_part_docs = max_part_docs.map(|max| max.max(1));
+    }
+
+    /// Get the path of a part: `<lang>_meta.<ext>` if there's a single one, `<lang>_meta_part_<n>.<ext>` otherwise.
+    ///
+    /// With [Layout::PerLanguage], parts are always `<lang>/<lang>_part_<n>.<ext>`, and with a template,
+    /// they're always named from it. Parts of shared writers are always numbered too.
+    fn part_path(&self, part: Option<usize>) -> PathBuf {
+        let extension = self.extension();
+        if let Some(template) = &self.template {
+            return self.dst.join(template.render(
+                self.lang.as_str(),
+                part.unwrap_or(1),
+                extension,
+            ));
+        }
+        match (self.layout, part) {
+            (Layout::Flat, None) if !self.is_numbered() => {
+                self.dst.join(format!("{}_meta.{extension}", self.lang))
+            }
+            (Layout::Flat, part) => self.dst.join(format!(
+                "{}_meta_part_{}.{extension}",
+                self.lang,
+                part.unwrap_or(1)
+            )),
+            (Layout::PerLanguage, part) => self.dir().join(format!(
+                "{}_part_{}.{extension}",
+                self.lang,
+                part.unwrap_or(1)
+            )),
+        }
+    }
+
+    /// Check if the current part reached a size limit.
+    fn is_full(&self) -> bool {
+        self.max_part_docs.is_some_and(|max| self.part_docs >= max)
+            || self.max_part_bytes.is_some_and(|max| self.offset >= max)
+    }
+
+    /// Start a new part. The first part is renamed `<lang>_meta_part_1.<ext>` when the second one is started,
+    /// unless it's already numbered.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.finish()?;
+        if self.nb_parts == 1 && !self.is_numbered() {
+            let first = self.part_path(Some(1));
+            self.rename(&self.path, &first)?;
+            if let Compression::Gzip { .. } = self.compression {
+                self.rename(&index_path(&self.path), &index_path(&first))?;
+            }
+            let (name, first_name) = (file_name(&self.path), file_name(&first));
+            for part in self.parts.iter_mut().filter(|part| part.file == name) {
+                part.file = first_name.clone();
+            }
+        }
+        self.nb_parts += 1;
+        self.path = self.part_path(Some(self.nb_parts));
+        self.created = false;
+        self.offset = 0;
+        self.part_docs = 0;
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        #[cfg(feature = "object-store")]
+        if let Some(remote) = &self.remote {
+            return remote.rename(from, to);
+        }
+        std::fs::rename(from, to)
+    }
+
+    /// Remove parts of previous runs (and a single `<lang>_meta.<ext>` file, with [Layout::Flat]).
+    ///
+    /// Called before writing the first part, unless writers share part numbers.
+    pub fn remove_stale_parts(&self) -> std::io::Result<()> {
+        self.remove_parts_except(&HashSet::new())
+    }
+
+    /// Remove parts of previous runs, except the ones named in `keep` (along with their index and temporary files).
+    fn remove_parts_except(&self, keep: &HashSet<String>) -> std::io::Result<()> {
+        let prefix = match self.layout {
+            Layout::Flat => format!("{}_meta_part_", self.lang),
+            Layout::PerLanguage => format!("{}_part_", self.lang),
+        };
+        let single = format!("{}_meta.{}", self.lang, self.extension());
+        let is_kept = |name: &str| {
+            let name = name.strip_suffix(TMP_SUFFIX).unwrap_or(name);
+            keep.contains(name.strip_suffix(".idx").unwrap_or(name))
+        };
+        let is_part = |name: &str| match &self.template {
+            Some(template) => template
+                .part_number(name, self.lang.as_str(), self.extension())
+                .is_some(),
+            None if self.layout == Layout::Flat => {
+                name.starts_with(&prefix)
+                    || [".idx.tmp", ".idx", ".tmp", ""]
+                        .iter()
+                        .any(|extra| name.strip_suffix(extra) == Some(single.as_str()))
+            }
+            None => name.starts_with(&prefix),
+        };
+        let is_stale = |name: &str| is_part(name) && !is_kept(name);
+        #[cfg(feature = "object-store")]
+        if let Some(remote) = &self.remote {
+            return remote.remove_matching(&self.dir(), is_stale);
+        }
+        if !self.dir().is_dir() {
+            return Ok(());
+        }
+        for entry in std::fs::read_dir(self.dir())? {
+            let path = entry?.path();
+            if path.is_file() && is_stale(&file_name(&path)) {
+                debug!("removing stale part {:?}", path);
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the part number of a file name (see [super::template::parse_part_number]).
+    fn part_number(&self, name: &str) -> Option<usize> {
+        let (lang, ext) = (self.lang.as_str(), self.extension());
+        match (&self.template, self.layout) {
+            (Some(template), _) => template.part_number(name, lang, ext),
+            (None, Layout::Flat) => {
+                parse_part_number(name, &format!("{lang}_meta_part_"), &format!(".{ext}"))
+            }
+            (None, Layout::PerLanguage) => {
+                parse_part_number(name, &format!("{lang}_part_"), &format!(".{ext}"))
+            }
+        }
+    }
+
+    /// Resume writing after the parts of a previous run, rather than overwriting them.
+    ///
+    /// Documents are written in a new part, numbered after the last existing one (finished or not),
+    /// and existing parts are kept in the checksum manifest and `meta.json`.
+    /// A single `<lang>_meta.<ext>` file is renamed `<lang>_meta_part_1.<ext>`, like when a second part is started.
+    ///
+    /// Has to be called before the first write, once compression, layout and template are set.
+    /// Uploads can't be resumed.
+    pub fn resume(&mut self) -> std::io::Result<()> {
+        if self.is_remote() {
+            return Err(std::io::Error::other("uploads can't be resumed"));
+        }
+        self.append = true;
+
+        let mut last = None;
+        if self.dir().is_dir() {
+            for entry in std::fs::read_dir(self.dir())? {
+                last = last.max(self.part_number(&file_name(&entry?.path())));
+            }
+        }
+        let single = self.part_path(None);
+        let has_single = last.is_none()
+            && !self.is_numbered()
+            && (single.exists() || tmp_path(&single).exists());
+        let last = match last {
+            Some(last) => last,
+            None if has_single => 1,
+            None => return Ok(()),
+        };
+
+        self.parts = self.load_summaries()?;
+        if has_single {
+            let first = self.part_path(Some(1));
+            for (from, to) in [
+                (single.clone(), first.clone()),
+                (index_path(&single), index_path(&first)),
+            ] {
+                for (from, to) in [(tmp_path(&from), tmp_path(&to)), (from, to)] {
+                    if from.exists() {
+                        std::fs::rename(from, to)?;
+                    }
+                }
+            }
+            let (name, first_name) = (file_name(&single), file_name(&first));
+            for part in self.parts.iter_mut().filter(|part| part.file == name) {
+                part.file = first_name.clone();
+            }
+        }
+
+        self.nb_parts = last + 1;
+        self.path = self.part_path(Some(self.nb_parts));
+        debug!("resuming {} after part {}", self.lang, last);
+        Ok(())
+    }
+
+    /// Get the position of the writer, to be restored by [Writer::restore].
+    ///
+    /// Written bytes have to be on disk (they're not buffered), which is the case between writes.
+    pub fn position(&self) -> std::io::Result<WriterPosition> {
+        let index = index_path(&self.path);
+        let index_len = match self.compression {
+            Compression::Gzip { .. } if self.created => [tmp_path(&index), index]
+                .iter()
+                .find(|path| path.exists())
+                .map_or(Ok(0), |path| std::fs::metadata(path).map(|m| m.len()))?,
+            _ => 0,
+        };
+        Ok(WriterPosition {
+            nb_parts: self.nb_parts,
+            file: self
+                .path
+                .strip_prefix(&self.dst)
+                .unwrap_or(&self.path)
+                .to_path_buf(),
+            created: self.created,
+            nb_bytes: self.offset,
+            nb_documents: self.part_docs,
+            index_len,
+            parts: self.parts.clone(),
+            stats: self.stats,
+        })
+    }
+
+    /// Restore the position of a writer of a previous run, dropping what it wrote after it:
+    /// the current part (and its gzip member index) is truncated to its recorded length and appended to,
+    /// and parts started since are removed.
+    ///
+    /// Has to be called before the first write, once compression, layout and template are set.
+    /// Uploads can't be restored.
+    pub fn restore(&mut self, position: &WriterPosition) -> std::io::Result<()> {
+        if self.is_remote() {
+            return Err(std::io::Error::other("uploads can't be restored"));
+        }
+        self.nb_parts = position.nb_parts;
+        self.path = self.dst.join(&position.file);
+        self.parts = position.parts.clone();
+        self.stats = position.stats;
+
+        // a single file is renamed when a second part is started, which may have happened since
+        let renamed = position.created
+            && position.nb_parts == 1
+            && !self.is_numbered()
+            && !self.path.exists()
+            && !tmp_path(&self.path).exists();
+        if renamed {
+            let first = self.part_path(Some(1));
+            for (from, to) in [
+                (first.clone(), self.path.clone()),
+                (index_path(&first), index_path(&self.path)),
+            ] {
+                for (from, to) in [(tmp_path(&from), tmp_path(&to)), (from, to)] {
+                    if from.exists() {
+                        std::fs::rename(from, to)?;
+                    }
+                }
+            }
+        }
+
+        let mut keep: HashSet<String> = self.parts.iter().map(|part| part.file.clone()).collect();
+        if position.created {
+            keep.insert(file_name(&self.path));
+        }
+        self.remove_parts_except(&keep)?;
+        if !position.created {
+            return Ok(());
+        }
+
+        let kept = truncate(&self.path, position.nb_bytes)?;
+        if let Compression::Gzip { .. } = self.compression {
+            truncate(&index_path(&self.path), position.index_len)?;
+        }
+        self.digest = Sha256::new();
+        let (mut file, mut buf) = (File::open(kept)?, vec![0; 1 << 16]);
+        loop {
+            match file.read(&mut buf)? {
+                0 => break,
+                n => self.digest.update(&buf[..n]),
+            }
+        }
+        self.offset = position.nb_bytes;
+        self.part_docs = position.nb_documents;
+        self.created = true;
+        debug!(
+            "restored {} at byte {} of {:?}",
+            self.lang, self.offset, self.path
+        );
+        Ok(())
+    }
+
+    /// Load the summaries of the parts of a previous run, from `meta.json` or from the checksum manifest.
+    fn load_summaries(&self) -> std::io::Result<Vec<PartSummary>> {
+        let meta = self.dir().join("meta.json");
+        if self.layout == Layout::PerLanguage && meta.exists() {
+            let summary: LangSummary = serde_json::from_slice(&std::fs::read(meta)?)?;
+            return Ok(summary.parts);
+        }
+        let manifest = self.checksums_path();
+        if !manifest.exists() {
+            return Ok(Vec::new());
+        }
+        Ok(std::fs::read_to_string(manifest)?
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .map(|(sha256, file)| PartSummary {
+                file: file.to_string(),
+                // only known from meta.json
+                nb_documents: 0,
+                nb_bytes: std::fs::metadata(self.dir().join(file)).map_or(0, |m| m.len()),
+                sha256: sha256.to_string(),
+            })
+            .collect())
+    }
+
+    /// Compress documents in gzip members of at most `docs_per_member` documents,
+    /// returning members and their index lines.
+    fn gzip_members(
+        &self,
+        docs: &[Vec<u8>],
+        level: u32,
+        docs_per_member: usize,
+    ) -> std::io::Result<(Vec<u8>, String)> {
+        let (mut members, mut index) = (Vec::new(), String::new());
+        for chunk in docs.chunks(docs_per_member.max(1)) {
+            let offset = self.offset + members.len() as u64;
+            let mut encoder = GzEncoder::new(&mut members, flate2::Compression::new(level));
+            for doc in chunk {
+                encoder.write_all(doc)?;
+            }
+            encoder.finish()?;
+            index += &format!("{offset}\t{}\n", chunk.len());
+        }
+        Ok((members, index))
+    }
+
+    /// Get the path of the checksum manifest, `<lang>_sha256.txt`.
+    pub fn checksums_path(&self) -> PathBuf {
+        self.dir().join(format!("{}_sha256.txt", self.lang))
+    }
+
+    /// Write a whole file (or upload it), through its temporary file.
+    fn write_file(&self, path: &Path, content: &[u8]) -> std::io::Result<()> {
+        let mut sink = self.open(path, false)?;
+        sink.write_all(content)?;
+        sink.finish()?;
+        if !self.is_remote() {
+            std::fs::rename(tmp_path(path), path)?;
+        }
+        debug!("wrote {:?}", path);
+        Ok(())
+    }
+
+    /// Get the parts finished so far.
+    pub fn parts(&self) -> &[PartSummary] {
+        &self.parts
+    }
+
+    /// Write the SHA-256 of finished parts in the checksum manifest,
+    /// as `<hash> <file name>` lines like the manifests of packaged corpora,
+    /// and their summary in `meta.json` with [Layout::PerLanguage].
+    ///
+    /// Parts are sorted by number, so that parts of writers sharing part numbers can be merged.
+    /// Nothing is written if no part has been finished.
+    pub fn write_summaries(&self, parts: &[PartSummary]) -> std::io::Result<()> {
+        if parts.is_empty() {
+            return Ok(());
+        }
+        let mut parts = parts.to_vec();
+        parts.sort_by_key(|part| self.part_number(&part.file));
+        let manifest: String = parts
+            .iter()
+            .map(|part| format!("{} {}\n", part.sha256, part.file))
+            .collect();
+        self.write_file(&self.checksums_path(), manifest.as_bytes())?;
+
+        if self.layout == Layout::PerLanguage {
+            let summary = LangSummary {
+                lang: self.lang.to_string(),
+                compression: match self.compression {
+                    Compression::None => "none",
+                    Compression::Zstd { .. } => "zstd",
+                    Compression::Gzip { .. } => "gzip",
+                    Compression::Xz { .. } => "xz",
+                }
+                .to_string(),
+                nb_documents: parts.iter().map(|part| part.nb_documents).sum(),
+                nb_bytes: parts.iter().map(|part| part.nb_bytes).sum(),
+                parts,
+            };
+            let summary = serde_json::to_vec_pretty(&summary).map_err(std::io::Error::other)?;
+            self.write_file(&self.dir().join("meta.json"), &summary)?;
+        }
+        Ok(())
+    }
+
+    /// Get the documents, bytes and parts written so far.
+    pub fn stats(&self) -> WriterStats {
+        self.stats
+    }
+
+    /// Rename/omit fields of written documents.
+    pub fn set_field_mapping(&mut self, mapping: Arc<FieldMapping>) {
+        self.mapping = Some(mapping);
+    }
+
+    /// Close the file, if open. It is reopened in append mode on next write.
+    ///
+    /// Uploads are kept open (see [Writer::finish]).
+    pub fn close(&mut self) {
+        #[cfg(feature = "arrow")]
+        if let Some(dataset) = &mut self.dataset {
+            if let Err(e) = dataset.close() {
+                error!("could not close {:?}: {:?}", dataset.dir(), e);
+            }
+        }
+        if self.is_remote() {
+            return;
+        }
+        self.index = None;
+        if self.file.take().is_some() {
+            debug!("closed {:?}", self.path);
+        }
+    }
+
+    /// Close the file and finish the current part, renaming its temporary files (or finishing its uploads).
+    fn finish(&mut self) -> std::io::Result<()> {
+        if let Some(index) = self.index.take() {
+            index.finish()?;
+        }
+        if let Some(file) = self.file.take() {
+            file.finish()?;
+            debug!("closed {:?}", self.path);
+        }
+        if self.created {
+            // the digest is cloned, as the part can still be reopened and appended to
+            let summary = PartSummary {
+                file: file_name(&self.path),
+                nb_documents: self.part_docs,
+                nb_bytes: self.offset,
+                sha256: format!("{:x}", self.digest.clone().finalize()),
+            };
+            match self.parts.iter_mut().find(|part| part.file == summary.file) {
+                Some(previous) => *previous = summary,
+                None => self.parts.push(summary),
+            }
+        }
+        if !self.is_remote() {
+            let mut renamed = false;
+            for path in [index_path(&self.path), self.path.clone()] {
+                if tmp_path(&path).exists() {
+                    if self.fsync_parts {
+                        super::policy::sync_path(&tmp_path(&path))?;
+                    }
+                    std::fs::rename(tmp_path(&path), &path)?;
+                    renamed = true;
+                }
+            }
+            if let Some(dir) = self.path.parent().filter(|_| renamed && self.fsync_parts) {
+                super::policy::sync_path(dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Open a file (or start its upload), truncating it if not in append mode.
+    ///
+    /// Local files are written in their temporary file, finished ones being moved back to it.
+    fn open(&self, path: &Path, append: bool) -> std::io::Result<Sink> {
+        #[cfg(feature = "object-store")]
+        if let Some(remote) = &self.remote {
+            return Ok(Sink::Upload(remote.upload(path)?));
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = tmp_path(path);
+        let mut options = OpenOptions::new();
+        if append {
+            if !tmp.exists() && path.exists() {
+                std::fs::rename(path, &tmp)?;
+            }
+            options.append(true).create(true);
+        } else {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+            options.write(true).create(true).truncate(true);
+        }
+        debug!("opening {:?}", tmp);
+        Ok(Sink::File(options.open(&tmp)?))
+    }
+
+    /// Get the file, opening it if needed.
+    fn file(&mut self) -> std::io::Result<&mut Sink> {
+        if self.file.is_none() {
+            if !self.created {
+                if let Some(part_numbers) = &self.part_numbers {
+                    self.nb_parts = part_numbers.fetch_add(1, Ordering::SeqCst) + 1;
+                    self.path = self.part_path(Some(self.nb_parts));
+                } else if self.nb_parts == 1 && !self.append {
+                    self.remove_stale_parts()?;
+                }
+                if !self.is_remote() {
+                    for index in [index_path(&self.path), tmp_path(&index_path(&self.path))] {
+                        if index.exists() {
+                            std::fs::remove_file(index)?;
+                        }
+                    }
+                }
+                self.offset = 0;
+                self.part_docs = 0;
+                self.digest = Sha256::new();
+                self.stats.nb_parts += 1;
+            }
+            self.file = Some(self.open(&self.path, self.created)?);
+            self.created = true;
+        }
+
+        Ok(self.file.as_mut().unwrap())
+    }
+
+    /// Get the gzip member index, opening it if needed.
+    fn index(&mut self) -> std::io::Result<&mut Sink> {
+        if self.index.is_none() {
+            self.index = Some(self.open(&index_path(&self.path), true)?);
+        }
+
+        Ok(self.index.as_mut().unwrap())
+    }
+
+    /// Write serialized documents in the current part.
+    fn write_part(&mut self, docs: &[Vec<u8>]) -> std::io::Result<()> {
+        let bytes = match self.compression {
+            Compression::None => docs.concat(),
+            Compression::Zstd { level } => zstd::encode_all(&docs.concat()[..], level)?,
+            Compression::Gzip {
+                level,
+                docs_per_member,
+            } => {
+                // open the file first, so that offsets start from 0 on first write
+                self.file()?;
+                let docs_per_member = if self.warc { 1 } else { docs_per_member };
+                let (members, index) = self.gzip_members(docs, level, docs_per_member)?;
+                self.index()?.write_all(index.as_bytes())?;
+                members
+            }
+            Compression::Xz { level, threads } => xz_encode(&docs.concat(), level, threads)?,
+        };
+        self.file()?.write_all(&bytes)?;
+        self.digest.update(&bytes);
+        self.stats.nb_bytes += bytes.len() as u64;
+        if let Some(written_bytes) = &self.written_bytes {
+            written_bytes.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        }
+        self.offset += bytes.len() as u64;
+        self.part_docs += docs.len();
+        Ok(())
+    }
+}
+
+/// Compress bytes as a single xz stream, using `threads` threads.
+///
+/// A single thread uses the plain encoder, which is cheaper to set up than the multi-threaded one.
+fn xz_encode(bytes: &[u8], level: u32, threads: u32) -> std::io::Result<Vec<u8>> {
+    let output = Vec::with_capacity(bytes.len() / 4);
+    if threads <= 1 {
+        let mut encoder = XzEncoder::new(output, level);
+        encoder.write_all(bytes)?;
+        return encoder.finish();
+    }
+
+    let block_size = (bytes.len() as u64 / threads as u64).max(MIN_XZ_BLOCK_SIZE);
+    let stream = MtStreamBuilder::new()
+        .preset(level)
+        .threads(threads)
+        .block_size(block_size)
+        .check(Check::Crc64)
+        .encoder()?;
+    let mut encoder = XzEncoder::new_stream(output, stream);
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.to_path_buf().into_os_string();
+    path.push(suffix);
+    path.into()
+}
+
+/// Truncate a file, or its temporary file if it's unfinished, returning the path of the truncated file.
+fn truncate(path: &Path, len: u64) -> std::io::Result<PathBuf> {
+    let path = match tmp_path(path) {
+        tmp if tmp.exists() => tmp,
+        _ => path.to_path_buf(),
+    };
+    OpenOptions::new().write(true).open(&path)?.set_len(len)?;
+    Ok(path)
+}
+
+/// Get the path of the gzip member index of a part.
+fn index_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".idx")
+}
+
+/// Get the path of an unfinished file.
+fn tmp_path(path: &Path) -> PathBuf {
+    with_suffix(path, TMP_SUFFIX)
+}
+
+/// Suffix of unfinished files.
+const TMP_SUFFIX: &str = ".tmp";
+
+/// List unfinished files left in `dst` (recursively) by crashed runs.
+pub fn orphaned_parts(dst: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut orphans = Vec::new();
+    if !dst.is_dir() {
+        return Ok(orphans);
+    }
+    for entry in std::fs::read_dir(dst)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            orphans.extend(orphaned_parts(&path)?);
+        } else if path.to_string_lossy().ends_with(TMP_SUFFIX) {
+            orphans.push(path);
+        }
+    }
+    orphans.sort();
+    Ok(orphans)
+}
+
+impl WriterTrait for Writer {
+    type Item = Document;
+
+    /// Create a new writer. The file is only opened on first write.
+    ///
+    /// Parts are rotated once they reach `max_file_size` written bytes, checked between writes
+    /// (see [Writer::set_max_part_docs] for a strict bound on documents).
+    fn new(
+        dst: &Path,
+        lang: LanguageTag<String>,
+        max_file_size: Option<u64>,
+    ) -> Result<Self, oscar_io::Error> {
+        Ok(Self {
+            path: dst.join(format!("{lang}_meta.jsonl")),
+            dst: dst.to_path_buf(),
+            lang,
+            nb_parts: 1,
+            max_part_bytes: max_file_size,
+            max_part_docs: None,
+            file: None,
+            index: None,
+            created: false,
+            mapping: None,
+            compression: Compression::None,
+            warc: false,
+            offset: 0,
+            part_docs: 0,
+            digest: Sha256::new(),
+            parts: Vec::new(),
+            layout: Layout::Flat,
+            template: None,
+            append: false,
+            fsync_parts: false,
+            part_numbers: None,
+            stats: WriterStats::default(),
+            written_bytes: None,
+            #[cfg(feature = "arrow")]
+            dataset: None,
+            #[cfg(feature = "object-store")]
+            remote: None,
+        })
+    }
+
+    fn write(&mut self, vals: Vec<Document>) -> Result<(), oscar_io::Error> {
+        self.stats.nb_documents += vals.len();
+        #[cfg(feature = "arrow")]
+        if let Some(dataset) = &mut self.dataset {
+            return dataset.write(vals);
+        }
+        let mut docs = Vec::with_capacity(vals.len());
+        for val in vals {
+            if self.warc {
+                docs.push(conversion_record(&val, self.lang.as_str())?);
+                continue;
+            }
+            let mut doc = match &self.mapping {
+                Some(mapping) => {
+                    let mut value = serde_json::to_value(&val)?;
+                    mapping.apply(&mut value);
+                    serde_json::to_vec(&value)?
+                }
+                None => serde_json::to_vec(&val)?,
+            };
+            doc.push(b'\n');
+            docs.push(doc);
+        }
+        let mut docs = &docs[..];
+        while !docs.is_empty() {
+            if self.is_full() {
+                self.rotate()?;
+            }
+            let nb_docs = match self.max_part_docs {
+                Some(max) => (max - self.part_docs).min(docs.len()),
+                None => docs.len(),
+            };
+            let (part, rest) = docs.split_at(nb_docs);
+            self.write_part(part)?;
+            docs = rest;
+        }
+        Ok(())
+    }
+
+    fn write_single(&mut self, val: &Document) -> Result<(), oscar_io::Error> {
+        self.write(vec![val.clone()])
+    }
+
+    fn close_meta(&mut self) -> Result<(), oscar_io::Error> {
+        #[cfg(feature = "arrow")]
+        if let Some(dataset) = &mut self.dataset {
+            dataset.close()?;
+        }
+        self.finish()?;
+        if self.part_numbers.is_none() {
+            self.write_summaries(&self.parts)?;
+        }
+        Ok(())
+    }
+}
+
+/// Least-recently-used set of open writers, closing writers above a maximum.
+///
+/// It can be shared between several [super::LangFilesDoc], to cap the number of open files of a whole run.
+pub struct OpenWriters {
+    max_open: usize,
+    /// writers, from least to most recently used
+    writers: Mutex<VecDeque<Arc<Mutex<Writer>>>>,
+}
+
+impl OpenWriters {
+    /// Keep at most `max_open` writers open (clamped to 1).
+    pub fn new(max_open: usize) -> Self {
+        Self {
+            max_open: max_open.max(1),
+            writers: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Mark a writer as used, closing the least recently used ones if there are too many open writers.
+    ///
+    /// The writer must not be locked by the caller.
+    pub fn touch(&self, writer: &Arc<Mutex<Writer>>) {
+        let mut writers = self.writers.lock().unwrap();
+        if let Some(idx) = writers.iter().position(|w| Arc::ptr_eq(w, writer)) {
+            writers.remove(idx);
+        }
+        writers.push_back(writer.clone());
+
+        while writers.len() > self.max_open {
+            if let Some(lru) = writers.pop_front() {
+                lru.lock().unwrap().close();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        io::Read,
+        sync::{Arc, Mutex},
+    };
+
+    use oscar_io::v3::{Document, Metadata, WriterTrait};
+    use oxilangtag::LanguageTag;
+
+    use super::{
+        Compression, FieldMapping, FilenameTemplate, LangSummary, Layout, OpenWriters, Writer,
+    };
+
+    fn writer(dst: &std::path::Path, lang: &str) -> Arc<Mutex<Writer>> {
+        let lang = LanguageTag::parse(lang.to_string()).unwrap();
+        Arc::new(Mutex::new(Writer::new(dst, lang, None).unwrap()))
+    }
+
+    fn doc(content: &str) -> Document {
+        Document::new(content.to_string(), HashMap::new(), Metadata::default())
+    }
+
+    #[test]
+    fn test_reopen() {
+        let dst = tempfile::tempdir().unwrap();
+        let path = dst.path().join("en_meta.jsonl");
+        std::fs::write(&path, "stale\n").unwrap();
+
+        let w = writer(dst.path(), "en");
+        let mut w = w.lock().unwrap();
+        assert!(w.file.is_none());
+        w.write(vec![doc("foo")]).unwrap();
+        assert!(w.file.is_some());
+        w.close();
+        assert!(w.file.is_none());
+        w.write(vec![doc("bar")]).unwrap();
+
+        // the part is only visible once finished
+        assert!(!path.exists());
+        assert!(dst.path().join("en_meta.jsonl.tmp").exists());
+        w.close_meta().unwrap();
+        assert!(!dst.path().join("en_meta.jsonl.tmp").exists());
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let docs: Vec<Document> = content
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(docs, vec![doc("foo"), doc("bar")]);
+    }
+
+    /// Read the documents of a JSONL file.
+    fn read(path: &std::path::Path) -> Vec<Document> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_rotate_docs() {
+        let dst = tempfile::tempdir().unwrap();
+        let stale = dst.path().join("en_meta_part_4.jsonl");
+        std::fs::write(&stale, "stale\n").unwrap();
+
+        let w = writer(dst.path(), "en");
+        let mut w = w.lock().unwrap();
+        w.set_max_part_docs(Some(2));
+        w.write(vec![doc("foo")]).unwrap();
+        assert!(!stale.exists());
+        assert!(dst.path().join("en_meta.jsonl.tmp").exists());
+
+        w.write(vec![doc("bar"), doc("baz"), doc("qux")]).unwrap();
+        w.close();
+        w.write(vec![doc("quux")]).unwrap();
+        w.close_meta().unwrap();
+
+        assert!(!dst.path().join("en_meta.jsonl").exists());
+        let part = |n: usize| read(&dst.path().join(format!("en_meta_part_{n}.jsonl")));
+        assert_eq!(part(1), vec![doc("foo"), doc("bar")]);
+        assert_eq!(part(2), vec![doc("baz"), doc("qux")]);
+        assert_eq!(part(3), vec![doc("quux")]);
+    }
+
+    #[test]
+    fn test_rotate_bytes() {
+        let dst = tempfile::tempdir().unwrap();
+        let lang = LanguageTag::parse("en".to_string()).unwrap();
+        let mut w = Writer::new(dst.path(), lang, Some(1)).unwrap();
+
+        // parts are only rotated between writes on size
+        w.write(vec![doc("foo"), doc("bar"), doc("baz")]).unwrap();
+        w.write(vec![doc("qux")]).unwrap();
+        w.close_meta().unwrap();
+
+        let part = |n: usize| read(&dst.path().join(format!("en_meta_part_{n}.jsonl")));
+        assert_eq!(part(1), vec![doc("foo"), doc("bar"), doc("baz")]);
+        assert_eq!(part(2), vec![doc("qux")]);
+    }
+
+    #[test]
+    fn test_restore() {
+        use sha2::{Digest, Sha256};
+
+        let dst = tempfile::tempdir().unwrap();
+        let lang = LanguageTag::parse("en".to_string()).unwrap();
+        let new_writer = || {
+            let mut w = Writer::new(dst.path(), lang.clone(), None).unwrap();
+            w.set_compression(Compression::Gzip {
+                level: 6,
+                docs_per_member: 1,
+            });
+            w.set_max_part_docs(Some(2));
+            w
+        };
+
+        let mut w = new_writer();
+        w.write(vec![doc("foo")]).unwrap();
+        let position = w.position().unwrap();
+        assert_eq!(position.file, std::path::PathBuf::from("en_meta.jsonl.gz"));
+        assert_eq!(position.nb_documents, 1);
+
+        // rotates parts, renaming the single file, then crashes
+        w.write(vec![doc("bar"), doc("baz"), doc("qux")]).unwrap();
+        drop(w);
+        assert!(dst.path().join("en_meta_part_2.jsonl.gz.tmp").exists());
+
+        let mut w = new_writer();
+        w.restore(&position).unwrap();
+        assert!(!dst.path().join("en_meta_part_1.jsonl.gz.tmp").exists());
+        assert!(!dst.path().join("en_meta_part_2.jsonl.gz.tmp").exists());
+        w.write(vec![doc("bar")]).unwrap();
+        w.close_meta().unwrap();
+
+        let bytes = std::fs::read(dst.path().join("en_meta.jsonl.gz")).unwrap();
+        let mut content = String::new();
+        flate2::read::MultiGzDecoder::new(&bytes[..])
+            .read_to_string(&mut content)
+            .unwrap();
+        let docs: Vec<Document> = content
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(docs, vec![doc("foo"), doc("bar")]);
+        let index = std::fs::read_to_string(dst.path().join("en_meta.jsonl.gz.idx")).unwrap();
+        assert_eq!(index.lines().count(), 2);
+        let manifest = std::fs::read_to_string(dst.path().join("en_sha256.txt")).unwrap();
+        assert_eq!(
+            manifest,
+            format!("{:x} en_meta.jsonl.gz\n", Sha256::digest(&bytes))
+        );
+    }
+
+    #[test]
+    fn test_xz() {
+        for threads in [1, 2] {
+            let dst = tempfile::tempdir().unwrap();
+            let w = writer(dst.path(), "en");
+            let mut w = w.lock().unwrap();
+            w.set_compression(Compression::Xz { level: 6, threads });
+            w.write(vec![doc("foo")]).unwrap();
+            w.close();
+            w.write(vec![doc("bar")]).unwrap();
+            w.close_meta().unwrap();
+
+            // reopening appends a second stream
+            let file = std::fs::File::open(dst.path().join("en_meta.jsonl.xz")).unwrap();
+            let mut content = String::new();
+            xz2::read::XzDecoder::new_multi_decoder(file)
+                .read_to_string(&mut content)
+                .unwrap();
+            let docs: Vec<Document> = content
+                .lines()
+                .map(|l| serde_json::from_str(l).unwrap())
+                .collect();
+            assert_eq!(docs, vec![doc("foo"), doc("bar")]);
+        }
+    }
+
+    #[test]
+    fn test_zstd() {
+        let dst = tempfile::tempdir().unwrap();
+        let w = writer(dst.path(), "en");
+        let mut w = w.lock().unwrap();
+        w.set_compression(Compression::Zstd { level: 3 });
+        w.write(vec![doc("foo")]).unwrap();
+        w.close();
+        w.write(vec![doc("bar")]).unwrap();
+        w.close_meta().unwrap();
+
+        // reopening appends a second frame
+        let file = std::fs::File::open(dst.path().join("en_meta.jsonl.zst")).unwrap();
+        let content = String::from_utf8(zstd::decode_all(file).unwrap()).unwrap();
+        let docs: Vec<Document> = content
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(docs, vec![doc("foo"), doc("bar")]);
+        assert!(!dst.path().join("en_meta.jsonl").exists());
+    }
+
+    #[test]
+    fn test_gzip() {
+        let dst = tempfile::tempdir().unwrap();
+        let w = writer(dst.path(), "en");
+        let mut w = w.lock().unwrap();
+        w.set_compression(Compression::Gzip {
+            level: 6,
+            docs_per_member: 2,
+        });
+        w.write(vec![doc("foo"), doc("bar"), doc("baz")]).unwrap();
+        w.close();
+        w.write(vec![doc("qux")]).unwrap();
+        w.close_meta().unwrap();
+
+        let path = dst.path().join("en_meta.jsonl.gz");
+        let bytes = std::fs::read(&path).unwrap();
+        let index = std::fs::read_to_string(dst.path().join("en_meta.jsonl.gz.idx")).unwrap();
+        let members: Vec<(usize, usize)> = index
+            .lines()
+            .map(|l| {
+                let (offset, nb_docs) = l.split_once('\t').unwrap();
+                (offset.parse().unwrap(), nb_docs.parse().unwrap())
+            })
+            .collect();
+        assert_eq!(
+            members.iter().map(|m| m.1).collect::<Vec<_>>(),
+            vec![2, 1, 1]
+        );
+
+        // each member can be decompressed on its own
+        let read = |offset: usize| {
+            let mut content = String::new();
+            flate2::read::GzDecoder::new(&bytes[offset..])
+                .read_to_string(&mut content)
+                .unwrap();
+            content.lines().count()
+        };
+        assert_eq!(read(members[1].0), 1);
+        assert_eq!(read(members[0].0), 2);
+
+        // and the whole file is a valid multistream gzip file
+        let mut content = String::new();
+        flate2::read::MultiGzDecoder::new(&bytes[..])
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content.lines().count(), 4);
+    }
+
+    #[test]
+    fn test_warc() {
+        let dst = tempfile::tempdir().unwrap();
+        let w = writer(dst.path(), "en");
+        let mut w = w.lock().unwrap();
+        w.set_compression(Compression::Gzip {
+            level: 6,
+            docs_per_member: 1000,
+        });
+        w.set_warc();
+        w.write(vec![doc("foo"), doc("bar")]).unwrap();
+        w.close();
+        w.write(vec![doc("baz")]).unwrap();
+        w.close_meta().unwrap();
+
+        // one member per record
+        let index = std::fs::read_to_string(dst.path().join("en_meta.warc.gz.idx")).unwrap();
+        assert_eq!(index.lines().count(), 3);
+
+        let records: Vec<_> = warc::WarcReader::from_path_gzip(dst.path().join("en_meta.warc.gz"))
+            .unwrap()
+            .iter_records()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let bodies: Vec<_> = records.iter().map(|r| r.body()).collect();
+        assert_eq!(bodies, vec![&b"foo"[..], b"bar", b"baz"]);
+        assert!(records
+            .iter()
+            .all(|r| r.warc_type() == &warc::RecordType::Conversion));
+        assert_eq!(w.stats().nb_documents, 3);
+    }
+
+    #[test]
+    fn test_field_mapping() {
+        let dst = tempfile::tempdir().unwrap();
+        let w = writer(dst.path(), "en");
+        let mapping =
+            FieldMapping::from_value(serde_json::json!({"content": "text", "warc_headers": null}))
+                .unwrap();
+        let mut w = w.lock().unwrap();
+        w.set_field_mapping(Arc::new(mapping));
+        w.write(vec![doc("foo")]).unwrap();
+        w.close_meta().unwrap();
+
+        let content = std::fs::read_to_string(dst.path().join("en_meta.jsonl")).unwrap();
+        let value: serde_json::Value = serde_json::from_str(content.trim()).unwrap();
+        assert_eq!(value["text"], "foo");
+        assert!(value.get("content").is_none());
+        assert!(value.get("warc_headers").is_none());
+        assert!(value.get("metadata").is_some());
+    }
+
+    #[test]
+    fn test_open_writers() {
+        let dst = tempfile::tempdir().unwrap();
+        let open_writers = OpenWriters::new(2);
+        let (en, fr, de) = (
+            writer(dst.path(), "en"),
+            writer(dst.path(), "fr"),
+            writer(dst.path(), "de"),
+        );
+
+        for w in [&en, &fr, &en, &de] {
+            w.lock().unwrap().write(vec![doc("foo")]).unwrap();
+            open_writers.touch(w);
+        }
+
+        // fr is the least recently used
+        assert_eq!(open_writers.writers.lock().unwrap().len(), 2);
+        assert!(en.lock().unwrap().file.is_some());
+        assert!(fr.lock().unwrap().file.is_none());
+        assert!(de.lock().unwrap().file.is_some());
+    }
+
+    #[test]
+    fn test_checksums() {
+        use sha2::{Digest, Sha256};
+
+        let dst = tempfile::tempdir().unwrap();
+        let w = writer(dst.path(), "en");
+        let mut w = w.lock().unwrap();
+        w.set_max_part_docs(Some(3));
+        w.write(vec![doc("foo"), doc("bar"), doc("baz")]).unwrap();
+        w.close();
+        w.write(vec![doc("qux")]).unwrap();
+        w.close_meta().unwrap();
+
+        let manifest = std::fs::read_to_string(dst.path().join("en_sha256.txt")).unwrap();
+        let lines: Vec<&str> = manifest.lines().collect();
+        let expected: Vec<String> = ["en_meta_part_1.jsonl", "en_meta_part_2.jsonl"]
+            .iter()
+            .map(|name| {
+                let content = std::fs::read(dst.path().join(name)).unwrap();
+                format!("{:x} {name}", Sha256::digest(&content))
+            })
+            .collect();
+        assert_eq!(lines, expected);
+
+        // appending after close_meta updates the checksum of the last part
+        w.write(vec![doc("quux")]).unwrap();
+        w.close_meta().unwrap();
+        let manifest = std::fs::read_to_string(dst.path().join("en_sha256.txt")).unwrap();
+        let content = std::fs::read(dst.path().join("en_meta_part_2.jsonl")).unwrap();
+        assert_eq!(
+            manifest.lines().nth(1).unwrap(),
+            format!("{:x} en_meta_part_2.jsonl", Sha256::digest(&content))
+        );
+        assert_eq!(manifest.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_per_language_layout() {
+        let dst = tempfile::tempdir().unwrap();
+        let w = writer(dst.path(), "en");
+        let mut w = w.lock().unwrap();
+        w.set_layout(Layout::PerLanguage);
+        w.set_max_part_docs(Some(2));
+        w.write(vec![doc("foo"), doc("bar"), doc("baz")]).unwrap();
+        w.close_meta().unwrap();
+
+        let dir = dst.path().join("en");
+        assert_eq!(
+            read(&dir.join("en_part_1.jsonl")),
+            vec![doc("foo"), doc("bar")]
+        );
+        assert_eq!(read(&dir.join("en_part_2.jsonl")), vec![doc("baz")]);
+        assert!(dir.join("en_sha256.txt").exists());
+
+        let summary: LangSummary =
+            serde_json::from_slice(&std::fs::read(dir.join("meta.json")).unwrap()).unwrap();
+        assert_eq!(summary.lang, "en");
+        assert_eq!(summary.nb_documents, 3);
+        assert_eq!(
+            summary
+                .parts
+                .iter()
+                .map(|p| &p.file[..])
+                .collect::<Vec<_>>(),
+            vec!["en_part_1.jsonl", "en_part_2.jsonl"]
+        );
+        assert_eq!(
+            summary.nb_bytes,
+            std::fs::metadata(dir.join("en_part_1.jsonl"))
+                .unwrap()
+                .len()
+                + std::fs::metadata(dir.join("en_part_2.jsonl"))
+                    .unwrap()
+                    .len()
+        );
+        assert_eq!(
+            "per-language".parse::<Layout>().unwrap(),
+            Layout::PerLanguage
+        );
+        assert!("nested".parse::<Layout>().is_err());
+    }
+
+    #[test]
+    fn test_template() {
+        let dst = tempfile::tempdir().unwrap();
+        let template = "{lang}/{crawl}_{lang}_{part:03}.{ext}";
+        let template = FilenameTemplate::parse(template, Some("CC-MAIN-2023-50")).unwrap();
+        let stale = dst.path().join("en/CC-MAIN-2023-50_en_004.jsonl.zst");
+        std::fs::create_dir(dst.path().join("en")).unwrap();
+        std::fs::write(&stale, "stale\n").unwrap();
+
+        let w = writer(dst.path(), "en");
+        let mut w = w.lock().unwrap();
+        w.set_compression(Compression::Zstd { level: 3 });
+        w.set_template(Arc::new(template));
+        w.set_max_part_docs(Some(2));
+        w.write(vec![doc("foo"), doc("bar"), doc("baz")]).unwrap();
+        w.close_meta().unwrap();
+
+        assert!(!stale.exists());
+        let dir = dst.path().join("en");
+        let part = |n: usize| {
+            let file = std::fs::File::open(dir.join(format!("CC-MAIN-2023-50_en_00{n}.jsonl.zst")));
+            String::from_utf8(zstd::decode_all(file.unwrap()).unwrap())
+                .unwrap()
+                .lines()
+                .count()
+        };
+        assert_eq!(part(1), 2);
+        assert_eq!(part(2), 1);
+        assert!(dir.join("en_sha256.txt").exists());
+    }
+
+    #[test]
+    fn test_resume() {
+        let dst = tempfile::tempdir().unwrap();
+        let w = writer(dst.path(), "en");
+        w.lock().unwrap().write(vec![doc("foo")]).unwrap();
+        w.lock().unwrap().close_meta().unwrap();
+
+        // the single file becomes the first part
+        let w = writer(dst.path(), "en");
+        let mut w = w.lock().unwrap();
+        w.resume().unwrap();
+        w.write(vec![doc("bar")]).unwrap();
+        w.close_meta().unwrap();
+        let part = |n: usize| read(&dst.path().join(format!("en_meta_part_{n}.jsonl")));
+        assert_eq!(part(1), vec![doc("foo")]);
+        assert_eq!(part(2), vec![doc("bar")]);
+        assert!(!dst.path().join("en_meta.jsonl").exists());
+
+        // unfinished parts are skipped, and checksums of previous parts are kept
+        std::fs::write(dst.path().join("en_meta_part_3.jsonl.tmp"), "{\"trunc").unwrap();
+        let w = writer(dst.path(), "en");
+        let mut w = w.lock().unwrap();
+        w.resume().unwrap();
+        w.write(vec![doc("baz")]).unwrap();
+        w.close_meta().unwrap();
+        assert_eq!(part(2), vec![doc("bar")]);
+        assert_eq!(part(4), vec![doc("baz")]);
+        let manifest = std::fs::read_to_string(dst.path().join("en_sha256.txt")).unwrap();
+        let files: Vec<&str> = manifest
+            .lines()
+            .map(|l| l.split_once(' ').unwrap().1)
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                "en_meta_part_1.jsonl",
+                "en_meta_part_2.jsonl",
+                "en_meta_part_4.jsonl"
+            ]
+        );
+
+        // nothing to resume from
+        let empty = tempfile::tempdir().unwrap();
+        let w = writer(empty.path(), "en");
+        let mut w = w.lock().unwrap();
+        w.resume().unwrap();
+        w.write(vec![doc("foo")]).unwrap();
+        w.close_meta().unwrap();
+        assert!(empty.path().join("en_meta.jsonl").exists());
+    }
+
+    #[test]
+    fn test_orphaned_parts() {
+        let dst = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dst.path().join("adult")).unwrap();
+        for name in [
+            "en_meta.jsonl",
+            "fr_meta.jsonl.tmp",
+            "adult/en_meta.jsonl.tmp",
+        ] {
+            std::fs::write(dst.path().join(name), "").unwrap();
+        }
+        assert_eq!(
+            super::orphaned_parts(dst.path()).unwrap(),
+            vec![
+                dst.path().join("adult/en_meta.jsonl.tmp"),
+                dst.path().join("fr_meta.jsonl.tmp")
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "object-store")]
+    fn test_remote() {
+        use crate::io::remote::tests::{get, remote};
+
+        let dst = tempfile::tempdir().unwrap();
+        let (store, remote) = remote(dst.path());
+        let w = writer(dst.path(), "en");
+        let mut w = w.lock().unwrap();
+        w.set_remote(remote.clone());
+        w.set_max_part_docs(Some(2));
+        w.write(vec![doc("foo")]).unwrap();
+
+        // uploads are kept open when closed
+        w.close();
+        assert!(w.file.is_some());
+        w.write(vec![doc("bar"), doc("baz")]).unwrap();
+        w.close_meta().unwrap();
+
+        let part = |n: usize| -> Vec<Document> {
+            let key = format!("prefix/en_meta_part_{n}.jsonl");
+            String::from_utf8(get(&remote, &store, &key).unwrap())
+                .unwrap()
+                .lines()
+                .map(|l| serde_json::from_str(l).unwrap())
+                .collect()
+        };
+        assert_eq!(part(1), vec![doc("foo"), doc("bar")]);
+        assert_eq!(part(2), vec![doc("baz")]);
+        assert!(get(&remote, &store, "prefix/en_meta.jsonl").is_none());
+        assert!(get(&remote, &store, "prefix/en_sha256.txt").is_some());
+        assert_eq!(std::fs::read_dir(dst.path()).unwrap().count(), 0);
+    }
+}
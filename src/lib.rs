@@ -4,6 +4,7 @@ pub mod error;
 pub mod filtering;
 pub mod identifiers;
 pub mod io;
+pub mod monitor;
 pub mod pipelines;
 pub mod processing;
 pub mod sources;
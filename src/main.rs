@@ -2,7 +2,8 @@
 use download::Downloader;
 use log::LevelFilter;
 use std::fs::File;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
+use std::sync::Arc;
 use structopt::StructOpt;
 
 use crate::pipelines::Pipeline;
@@ -16,6 +17,7 @@ mod error;
 mod filtering;
 mod identifiers;
 mod io;
+mod monitor;
 mod pipelines;
 mod processing;
 mod sources;
@@ -24,50 +26,82 @@ mod transformers;
 #[tokio::main]
 #[cfg(not(tarpaulin_include))]
 async fn main() -> Result<(), error::Error> {
+    let opt = cli::Ungoliant::from_args();
+
     // set devault log level to info
     let mut builder = env_logger::Builder::new();
     builder.filter_level(LevelFilter::Info);
     builder.parse_env("RUST_LOG");
+
+    // logs would be drawn over the dashboard
+    #[cfg(feature = "tui")]
+    if let cli::Ungoliant::Pipeline(cli::Pipeline { tui: true, .. }) = &opt {
+        let log_file = File::create("ungoliant.log")?;
+        builder.target(env_logger::Target::Pipe(Box::new(log_file)));
+    }
+
     builder.init();
 
-    let opt = cli::Ungoliant::from_args();
     debug!("cli args\n{:#?}", opt);
 
     match opt {
-        cli::Ungoliant::Download(e) => {
-            let paths = File::open(e.paths_file)?;
-            let mut dl = Downloader::from_paths_file(&paths, e.n_tasks.unwrap_or(4))?;
-            let results = dl.download(&e.dst, e.offset).await;
-
-            let mut error_file = File::create("errors.txt")?;
-
-            // write eventual download errors
-            for failure in results.iter().filter(|result| result.is_err()) {
-                error!("Error during download:\n {:?}", failure);
-                // match failure.as_ref().unwrap_err() {
-                //     download::Error::Download(e) => {
-                //         write!(error_file, "{}\t{}", e.err.url().unwrap(), e.id)?;
-                //     }
-                //     _ => (),
-                // };
-                if let download::Error::Download(e) = failure.as_ref().unwrap_err() {
-                    write!(error_file, "{}\t{}", e.err.url().unwrap(), e.id)?;
-                }
-            }
+        cli::Ungoliant::Download(e) => download(e).await?,
+
+        cli::Ungoliant::Paths(p) => {
+            processing::paths::check_paths(&p.src, p.dst.as_deref(), p.mirror.as_deref())?;
+        }
+
+        cli::Ungoliant::Merge(m) => {
+            let inputs = m
+                .crawls
+                .iter()
+                .map(|spec| processing::merge::parse_input(spec))
+                .collect::<Result<Vec<_>, _>>()?;
+            processing::merge::merge(&inputs, &m.dst)?;
+        }
+
+        cli::Ungoliant::NearDedup(n) => {
+            processing::neardup::near_dedup(&n.src, &n.dst, n.threshold, n.clusters)?;
+        }
+
+        cli::Ungoliant::Inspect(i) => {
+            let lang = oxilangtag::LanguageTag::parse_and_normalize(&i.lang)?;
+            let mut stdout = std::io::stdout().lock();
+            let style = processing::inspect::Style {
+                max_chars: i.max_chars,
+                color: !i.no_color && stdout.is_terminal(),
+            };
+            let seed = i.random.then_some(i.seed);
+            processing::inspect::inspect(&i.src, &lang, i.count, seed, style, &mut stdout)?;
         }
 
-        cli::Ungoliant::Pipeline(p) => {
-            let mut schema_filepath = p.dst.clone();
-            let p =
-                pipelines::OscarDocNew::new(p.src, p.dst, p.lid_path, p.blocklist, p.kenlms_path);
-            p.run()?;
-
-            schema_filepath.push("metadata_schema.json");
-            info!("creating json schema file {:?}", schema_filepath);
-            let _f = File::create(schema_filepath)?;
-            // f.write_all(Document::get_schema().unwrap().as_bytes())?;
-            // f.write_all(Metadata::get_schema()?.as_bytes())?;
+        cli::Ungoliant::Paragraphs(p) => {
+            processing::paragraphs::paragraphs(&p.src, &p.dst, p.multilingual_only)?;
         }
+
+        cli::Ungoliant::Sign(s) => {
+            let signer = processing::sign::Signer::new(&s.tool, s.key)?;
+            processing::sign::sign_corpus(&s.src, &signer)?;
+        }
+
+        #[cfg(feature = "tokenizers")]
+        cli::Ungoliant::TokStats(t) => {
+            processing::tokstats::token_stats(
+                &t.src,
+                &t.tokenizer,
+                t.sample_rate,
+                t.seed,
+                t.dst.as_deref(),
+            )?;
+        }
+        #[cfg(not(feature = "tokenizers"))]
+        cli::Ungoliant::TokStats(_) => {
+            return Err(error::Error::Custom(
+                "ungoliant was built without the tokenizers feature".to_string(),
+            ));
+        }
+
+        cli::Ungoliant::Pipeline(p) => pipeline(p).await?,
         // cli::Ungoliant::Dedup(d) => {
         //     processing::dedup::dedup(&d.src, &d.dst, Some(d.bufsize))?;
         // }
@@ -88,3 +122,330 @@ async fn main() -> Result<(), error::Error> {
     };
     Ok(())
 }
+
+/// Download stage: download shards of a crawl.
+#[cfg(not(tarpaulin_include))]
+async fn download(e: cli::Download) -> Result<(), error::Error> {
+    let mut retry_policy = download::RetryPolicy {
+        max_attempts: e.max_attempts.max(1),
+        base_delay: std::time::Duration::from_secs_f64(e.backoff),
+        max_delay: std::time::Duration::from_secs_f64(e.max_backoff),
+        ..Default::default()
+    };
+    if !e.retry_on.is_empty() {
+        retry_policy.retry_on = e.retry_on;
+    }
+    let network = download::Network {
+        proxy: e.proxy,
+        proxy_auth: e.proxy_auth,
+        ca_bundle: e.ca_bundle,
+    };
+    let client = network
+        .client()
+        .map_err(|e| error::Error::Custom(format!("invalid network settings: {:?}", e)))?;
+
+    let paths_file = match (e.paths_file, e.crawl) {
+        (Some(paths_file), _) => Some(paths_file),
+        (None, Some(crawl)) => {
+            std::fs::create_dir_all(&e.dst)?;
+            download::crawl_paths(&crawl, &e.dst, &client, &retry_policy)
+                .await
+                .map_err(|e| {
+                    error::Error::Custom(format!("could not get wet.paths of {}: {:?}", crawl, e))
+                })
+                .map(Some)?
+        }
+        (None, None) => None,
+    };
+    let mut dl = match (paths_file, e.retry_failed) {
+        (Some(paths_file), _) => {
+            Downloader::from_paths_file(&File::open(paths_file)?, e.n_tasks.unwrap_or(4))?
+        }
+        (None, Some(failures)) => {
+            Downloader::from_failures(&File::open(failures)?, e.n_tasks.unwrap_or(4))?
+        }
+        (None, None) => unreachable!("a paths file, a crawl or failures are required"),
+    };
+    dl.set_client(client);
+    let path_filter =
+        sources::commoncrawl::PathFilter::new(e.segments.as_deref(), e.paths_regex.as_deref())?;
+    if !path_filter.is_empty() {
+        dl.filter_paths(&path_filter);
+    }
+    dl.set_retry_policy(retry_policy);
+    dl.set_source(e.source.parse().map_err(error::Error::Custom)?);
+    dl.set_max_bandwidth(
+        e.max_bandwidth
+            .as_deref()
+            .map(pipelines::oscardoc::budget::parse_bytes)
+            .transpose()?,
+    );
+    dl.set_chunking(
+        e.chunk_size
+            .as_deref()
+            .map(pipelines::oscardoc::budget::parse_bytes)
+            .transpose()?
+            .map(|chunk_size| download::Chunking {
+                chunk_size,
+                concurrency: e.chunk_jobs,
+            }),
+    );
+    dl.set_mirrors(&e.mirrors)?;
+    dl.set_progress_every(
+        Some(std::time::Duration::from_secs(e.progress_every)).filter(|every| !every.is_zero()),
+    );
+    dl.set_quarantine(e.verify.then(|| {
+        e.quarantine
+            .clone()
+            .unwrap_or_else(|| e.dst.join("quarantine"))
+    }));
+    dl.set_sync(e.sync);
+    dl.set_manifest(e.manifest);
+    let parse_bytes = pipelines::oscardoc::budget::parse_bytes;
+    let disk_limits = download::DiskLimits {
+        max_bytes: e.max_disk.as_deref().map(parse_bytes).transpose()?,
+        min_free: e.min_free.as_deref().map(parse_bytes).transpose()?,
+        check_every: std::time::Duration::from_secs(30),
+    };
+    dl.set_disk_limits(
+        (disk_limits.max_bytes.is_some() || disk_limits.min_free.is_some()).then_some(disk_limits),
+    );
+    let results = dl.download(&e.dst, e.offset).await;
+
+    let mut error_file = File::create("errors.jsonl")?;
+
+    // write eventual download errors, to be downloaded again with --retry-failed
+    for failure in results.iter().filter(|result| result.is_err()) {
+        error!("Error during download:\n {:?}", failure);
+        if let Some(failed) = failure.as_ref().unwrap_err().failed_shard() {
+            serde_json::to_writer(&mut error_file, &failed)?;
+            writeln!(error_file)?;
+        }
+    }
+    Ok(())
+}
+
+/// Processing stage: run the pipeline on downloaded (or streamed) shards.
+#[cfg(not(tarpaulin_include))]
+async fn pipeline(p: cli::Pipeline) -> Result<(), error::Error> {
+    let mut schema_filepath = p.dst.clone();
+    let mut pipeline =
+        pipelines::OscarDocNew::new(p.src, p.dst, p.lid_path, p.blocklist, p.kenlms_path);
+    pipeline.set_geoip_dbs(p.geoip_dbs);
+    pipeline.set_repeated_paragraphs(p.repeated_paragraphs);
+    pipeline.set_compression_ratio(
+        p.compression_ratio
+            .then_some((p.min_compression_ratio, p.max_compression_ratio)),
+    );
+    pipeline.set_readability(p.readability.then_some(p.ttr_window));
+    pipeline.set_code_detection(p.code_detection.then_some(p.min_code_ratio));
+    pipeline.set_math_detection(p.math_detection.then_some(p.min_math_density));
+    pipeline.set_harmful_content(
+        p.harmful_model
+            .map(|path| (path, p.harmful_negative_label, p.harmful_min_score)),
+    );
+    pipeline.set_register(p.register_model.map(|path| (path, p.register_min_score)));
+    pipeline
+        .set_script_detection((p.script_detection || p.script_subtags).then_some(p.script_subtags));
+    pipeline.set_line_validity(filtering::sentence::LineValidity::new(
+        p.min_line_length,
+        p.min_alphabetic_ratio,
+        p.max_url_density,
+    ));
+    pipeline.set_shard_stats(p.shard_stats);
+    pipeline.set_retry_failed(!p.no_retry);
+    pipeline.set_lang_registry(p.lang_registry);
+    pipeline.set_calibration(p.calibration);
+    pipeline.set_line_thresholds(identifiers::LineThresholds::from_specs(
+        p.lid_threshold,
+        &p.lang_threshold,
+    )?);
+    pipeline.set_lid_k(p.lid_k);
+    pipeline.set_lid_candidates(p.lid_candidates);
+    pipeline.set_granularity(p.lid_granularity.parse()?);
+    pipeline.set_label_prefix(p.label_prefix);
+    pipeline.set_model_kind(p.model_kind.parse()?);
+    if let Some(identifier) = p.identifier {
+        pipeline.set_backend(identifier.parse()?);
+    }
+    #[cfg(feature = "onnx")]
+    pipeline.set_onnx_options(identifiers::OnnxOptions {
+        batch_size: p.onnx_batch_size,
+        intra_threads: p.onnx_threads,
+        sessions: p.onnx_sessions,
+    });
+    pipeline.set_fallback(
+        p.fallback_identifier
+            .as_deref()
+            .map(str::parse)
+            .transpose()?
+            .map(|backend| (backend, p.fallback_below)),
+    );
+    if !p.ensemble.is_empty() {
+        let members = p
+            .ensemble
+            .iter()
+            .map(|spec| identifiers::parse_member(spec))
+            .collect::<Result<_, _>>()?;
+        pipeline.set_ensemble(Some((members, p.ensemble_combination.parse()?)));
+    }
+    pipeline.set_lid_cache(p.lid_cache);
+    pipeline.set_annotation_policy(filtering::annotation::AnnotationPolicy::from_specs(
+        &p.annotation_policy,
+    )?);
+    pipeline.set_annotated_tree(
+        p.annotated_tree
+            .then(|| filtering::annotation::AnnotationSelector::new(p.annotated_tree_on)),
+    );
+    pipeline.set_unknown_sink(p.unknown_sink);
+    pipeline.set_category_split(p.category_split);
+    let mut write_policy = io::WritePolicy::new(p.flush_every, p.fsync);
+    write_policy.set_flush_interval(p.flush_interval.map(std::time::Duration::from_secs));
+    write_policy.set_fsync_parts(p.fsync_parts);
+    pipeline.set_write_policy(write_policy);
+    pipeline.set_pre_dedup(p.pre_dedup);
+    pipeline.set_hash_algorithm(p.hash.parse()?);
+    pipeline.set_budget(pipelines::oscardoc::RunBudget::new(
+        p.max_duration
+            .as_deref()
+            .map(pipelines::oscardoc::budget::parse_duration)
+            .transpose()?,
+        p.max_output_bytes
+            .as_deref()
+            .map(pipelines::oscardoc::budget::parse_bytes)
+            .transpose()?,
+    ));
+    pipeline.set_checkpoint_interval(
+        p.checkpoint_interval
+            .as_deref()
+            .map(pipelines::oscardoc::budget::parse_duration)
+            .transpose()?,
+    );
+    pipeline.set_resume(p.resume);
+    pipeline.set_max_open_writers(p.max_open_writers);
+    pipeline.set_field_mapping(p.field_mapping);
+    pipeline.set_output_format(match p.output_format.parse()? {
+        #[cfg(feature = "parquet")]
+        io::OutputFormat::Parquet { .. } => io::OutputFormat::Parquet {
+            row_group_size: p.row_group_size,
+        },
+        output_format => output_format,
+    });
+    pipeline.set_compression(match p.compression.parse()? {
+        io::Compression::Zstd { level } => io::Compression::Zstd {
+            level: p.compression_level.unwrap_or(level),
+        },
+        io::Compression::Gzip { level, .. } => io::Compression::Gzip {
+            level: p.compression_level.map_or(level, |l| l.clamp(0, 9) as u32),
+            docs_per_member: p.gzip_member_docs,
+        },
+        io::Compression::Xz { level, .. } => io::Compression::Xz {
+            level: p.compression_level.map_or(level, |l| l.clamp(0, 9) as u32),
+            threads: p.xz_threads,
+        },
+        compression => compression,
+    });
+    pipeline.set_layout(p.layout.parse()?);
+    pipeline.set_filename_template(
+        p.filename_template
+            .as_deref()
+            .map(|template| io::FilenameTemplate::parse(template, p.crawl.as_deref()))
+            .transpose()?,
+    );
+    pipeline.set_append(p.append);
+    pipeline.set_writer_shards(p.writer_shards);
+    pipeline.set_max_shards(p.max_shards);
+    pipeline.set_max_records(p.max_records);
+    pipeline.set_text_meta_dst(p.text_meta_dst);
+    pipeline.set_part_limits(
+        p.part_size
+            .as_deref()
+            .map(pipelines::oscardoc::budget::parse_bytes)
+            .transpose()?,
+        p.part_docs,
+    );
+    #[cfg(feature = "object-store")]
+    pipeline.set_upload(p.upload_to, p.upload_retries);
+    #[cfg(not(feature = "object-store"))]
+    if p.upload_to.is_some() {
+        return Err(error::Error::Custom(
+            "ungoliant was built without the object-store feature".to_string(),
+        ));
+    }
+    pipeline.set_header_retention(p.warc_headers.parse()?);
+    pipeline.set_discarded(p.discarded.as_deref().map(str::parse).transpose()?);
+    pipeline.add_srcs(p.srcs);
+    if let Some(paths_file) = p.stream_from {
+        pipeline.set_remote_paths(paths_file, url::Url::parse(&p.stream_base_url)?);
+        pipeline.set_path_filter(sources::commoncrawl::PathFilter::new(
+            p.segments.as_deref(),
+            p.paths_regex.as_deref(),
+        )?);
+    }
+    if let Some(records) = p.records {
+        pipeline.set_record_selection(
+            filtering::selection::RecordSelection::from_path(&records)?,
+            p.records_index,
+        );
+    }
+    pipeline.set_domain_allowlist(
+        p.domain_allowlist
+            .as_deref()
+            .map(filtering::allowlist::DomainAllowlist::from_path)
+            .transpose()?,
+    );
+    pipeline.set_language_selection(filtering::langs::LanguageSelection::from_specs(
+        &p.langs_include,
+        &p.langs_exclude,
+    )?);
+
+    let webhooks = if p.webhooks.is_empty() {
+        None
+    } else {
+        let mut webhooks = monitor::webhook::Webhooks::new(p.webhooks);
+        webhooks.set_error_budget(p.error_budget);
+        webhooks.set_milestone_every(p.milestone_every);
+        let webhooks = Arc::new(webhooks);
+        pipeline.set_webhooks(webhooks.clone());
+        Some(webhooks)
+    };
+
+    if let Some(status_addr) = p.status_addr {
+        let listener = tokio::net::TcpListener::bind(status_addr).await?;
+        tokio::spawn(monitor::status::serve(listener, pipeline.progress()));
+    }
+
+    #[cfg(feature = "tui")]
+    let dashboard = if p.tui {
+        let progress = pipeline.progress();
+        Some(std::thread::spawn(move || monitor::tui::run(progress)))
+    } else {
+        None
+    };
+    #[cfg(not(feature = "tui"))]
+    if p.tui {
+        warn!("ungoliant was built without the tui feature: no dashboard will be shown.");
+    }
+
+    let result = pipeline.run();
+
+    // failed runs don't finish progress: the dashboard has to be stopped to restore the terminal
+    #[cfg(feature = "tui")]
+    if let Some(dashboard) = dashboard {
+        pipeline.progress().finish();
+        if let Ok(Err(e)) = dashboard.join() {
+            error!("Dashboard error: {:?}", e);
+        }
+    }
+    if let (Err(e), Some(webhooks)) = (&result, &webhooks) {
+        webhooks.on_failure(&pipeline.progress().snapshot(), &format!("{e:?}"));
+    }
+    result?;
+
+    schema_filepath.push("metadata_schema.json");
+    info!("creating json schema file {:?}", schema_filepath);
+    let _f = File::create(schema_filepath)?;
+    // f.write_all(Document::get_schema().unwrap().as_bytes())?;
+    // f.write_all(Metadata::get_schema()?.as_bytes())?;
+    Ok(())
+}
@@ -0,0 +1,251 @@
+/*! Run monitoring.
+
+[Progress] holds live counters of a pipeline run (shards, records, documents, errors, per-language counts).
+It is updated by the pipeline after each shard and can be read concurrently through [Progress::snapshot].
+
+- [tui] draws a terminal dashboard (`tui` feature).
+- [status] serves an HTML page and a JSON endpoint.
+- [webhook] notifies external services of completion, errors and milestones.
+
+!*/
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use serde::Serialize;
+
+use crate::pipelines::oscardoc::types::ShardStats;
+
+pub mod status;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod webhook;
+
+/// Number of kept error messages.
+const NB_RECENT_ERRORS: usize = 50;
+
+/// Live counters of a run.
+pub struct Progress {
+    start: Instant,
+    shards_total: AtomicUsize,
+    shards_done: AtomicUsize,
+    shards_failed: AtomicUsize,
+    records: AtomicUsize,
+    documents: AtomicUsize,
+    errors: AtomicUsize,
+    languages: Mutex<BTreeMap<String, usize>>,
+    unknown_labels: Mutex<BTreeMap<String, usize>>,
+    dropped: Mutex<BTreeMap<String, usize>>,
+    recent_errors: Mutex<VecDeque<String>>,
+    finished: AtomicBool,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            shards_total: AtomicUsize::new(0),
+            shards_done: AtomicUsize::new(0),
+            shards_failed: AtomicUsize::new(0),
+            records: AtomicUsize::new(0),
+            documents: AtomicUsize::new(0),
+            errors: AtomicUsize::new(0),
+            languages: Mutex::new(BTreeMap::new()),
+            unknown_labels: Mutex::new(BTreeMap::new()),
+            dropped: Mutex::new(BTreeMap::new()),
+            recent_errors: Mutex::new(VecDeque::with_capacity(NB_RECENT_ERRORS)),
+            finished: AtomicBool::new(false),
+        }
+    }
+
+    /// Set the number of shards that will be processed.
+    pub fn set_shards_total(&self, shards_total: usize) {
+        self.shards_total.store(shards_total, Ordering::Relaxed);
+    }
+
+    /// Account for a processed shard.
+    pub fn add_shard(&self, stats: &ShardStats) {
+        self.shards_done.fetch_add(1, Ordering::Relaxed);
+        self.records.fetch_add(stats.records(), Ordering::Relaxed);
+        self.documents.fetch_add(stats.kept(), Ordering::Relaxed);
+        self.errors.fetch_add(stats.errors(), Ordering::Relaxed);
+
+        let mut languages = self.languages.lock().unwrap();
+        for (lang, count) in stats.languages() {
+            *languages.entry(lang.clone()).or_insert(0) += count;
+        }
+
+        let mut unknown_labels = self.unknown_labels.lock().unwrap();
+        for (label, count) in stats.unknown_labels() {
+            *unknown_labels.entry(label.clone()).or_insert(0) += count;
+        }
+
+        let mut dropped = self.dropped.lock().unwrap();
+        for (reason, count) in stats.dropped() {
+            *dropped.entry(reason.clone()).or_insert(0) += count;
+        }
+    }
+
+    /// Account for a shard that couldn't be processed.
+    pub fn add_failed_shard(&self) {
+        self.shards_done.fetch_add(1, Ordering::Relaxed);
+        self.shards_failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Keep an error message, discarding the oldest one if there's too many.
+    ///
+    /// This does not change the error count, which is updated by [Progress::add_shard].
+    pub fn add_error(&self, error: String) {
+        let mut recent_errors = self.recent_errors.lock().unwrap();
+        if recent_errors.len() == NB_RECENT_ERRORS {
+            recent_errors.pop_front();
+        }
+        recent_errors.push_back(error);
+    }
+
+    /// Mark the run as finished.
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true when the run is finished.
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    /// Get a consistent-enough copy of the counters.
+    pub fn snapshot(&self) -> Snapshot {
+        let elapsed_secs = self.start.elapsed().as_secs_f64();
+        let records = self.records.load(Ordering::Relaxed);
+        let records_per_sec = if elapsed_secs > 0.0 {
+            records as f64 / elapsed_secs
+        } else {
+            0.0
+        };
+
+        Snapshot {
+            elapsed_secs,
+            shards_total: self.shards_total.load(Ordering::Relaxed),
+            shards_done: self.shards_done.load(Ordering::Relaxed),
+            shards_failed: self.shards_failed.load(Ordering::Relaxed),
+            records,
+            documents: self.documents.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            records_per_sec,
+            languages: self.languages.lock().unwrap().clone(),
+            unknown_labels: self.unknown_labels.lock().unwrap().clone(),
+            dropped: self.dropped.lock().unwrap().clone(),
+            recent_errors: self.recent_errors.lock().unwrap().iter().cloned().collect(),
+            finished: self.is_finished(),
+        }
+    }
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Point-in-time copy of [Progress].
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    pub elapsed_secs: f64,
+    pub shards_total: usize,
+    pub shards_done: usize,
+    pub shards_failed: usize,
+    pub records: usize,
+    pub documents: usize,
+    pub errors: usize,
+    pub records_per_sec: f64,
+    pub languages: BTreeMap<String, usize>,
+    /// number of documents discarded because of each unknown label
+    pub unknown_labels: BTreeMap<String, usize>,
+    pub dropped: BTreeMap<String, usize>,
+    pub recent_errors: Vec<String>,
+    pub finished: bool,
+}
+
+impl Snapshot {
+    /// Get languages sorted by decreasing document count.
+    pub fn top_languages(&self, n: usize) -> Vec<(&str, usize)> {
+        let mut languages: Vec<_> = self
+            .languages
+            .iter()
+            .map(|(lang, count)| (lang.as_str(), *count))
+            .collect();
+        languages.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        languages.truncate(n);
+        languages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use oxilangtag::LanguageTag;
+
+    use crate::pipelines::oscardoc::types::ShardStats;
+
+    use super::Progress;
+
+    fn gen_stats(shard_id: usize, langs: &[(&str, usize)]) -> ShardStats {
+        let mut stats = ShardStats::new(shard_id);
+        let docs: HashMap<_, _> = langs
+            .iter()
+            .map(|(lang, count)| {
+                (
+                    LanguageTag::parse(lang.to_string()).unwrap(),
+                    vec![(); *count],
+                )
+            })
+            .collect();
+        let kept = langs.iter().map(|(_, count)| count).sum();
+        stats.set_processing(100, kept, 1, 0, Duration::from_secs(1));
+        stats.set_languages(&docs);
+        stats.set_unknown_labels([("__label__xyz".to_string(), 2)].into_iter().collect());
+        stats.set_dropped([("noisy".to_string(), 1)].into_iter().collect());
+        stats
+    }
+
+    #[test]
+    fn test_add_shards() {
+        let progress = Progress::new();
+        progress.set_shards_total(3);
+        progress.add_shard(&gen_stats(0, &[("en", 10), ("fr", 2)]));
+        progress.add_shard(&gen_stats(1, &[("en", 5), ("de", 4)]));
+        progress.add_failed_shard();
+
+        let snapshot = progress.snapshot();
+        assert_eq!(snapshot.shards_done, 3);
+        assert_eq!(snapshot.shards_failed, 1);
+        assert_eq!(snapshot.records, 200);
+        assert_eq!(snapshot.documents, 19);
+        assert_eq!(snapshot.dropped.get("noisy"), Some(&2));
+        assert_eq!(snapshot.errors, 2);
+        assert_eq!(snapshot.top_languages(2), vec![("en", 15), ("de", 4)]);
+        assert_eq!(snapshot.unknown_labels.get("__label__xyz"), Some(&4));
+        assert!(!snapshot.finished);
+
+        progress.finish();
+        assert!(progress.snapshot().finished);
+    }
+
+    #[test]
+    fn test_recent_errors() {
+        let progress = Progress::new();
+        for i in 0..super::NB_RECENT_ERRORS + 5 {
+            progress.add_error(format!("error {i}"));
+        }
+
+        let recent_errors = progress.snapshot().recent_errors;
+        assert_eq!(recent_errors.len(), super::NB_RECENT_ERRORS);
+        assert_eq!(recent_errors[0], "error 5");
+    }
+}
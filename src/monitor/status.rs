@@ -0,0 +1,208 @@
+/*! HTTP status page
+
+Serves the run's [Progress] on:
+
+- `/`: a small, self-refreshing HTML page,
+- `/status.json`: a JSON serialization of a [Snapshot].
+
+This is a minimal HTTP/1.0 server: only `GET` requests are supported and connections are closed after each response.
+It is meant to be used on trusted networks, there is no authentication.
+!*/
+use std::{fmt::Write, sync::Arc};
+
+use log::{debug, error, info};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use super::{Progress, Snapshot};
+
+/// Page refresh interval, in seconds.
+const REFRESH_SECS: u32 = 10;
+
+/// Serve status pages on an already bound listener, forever.
+pub async fn serve(listener: TcpListener, progress: Arc<Progress>) {
+    if let Ok(addr) = listener.local_addr() {
+        info!("Serving status page on http://{addr}/");
+    }
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                debug!("status request from {peer}");
+                let progress = progress.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle(stream, &progress).await {
+                        debug!("status page error: {e:?}");
+                    }
+                });
+            }
+            Err(e) => error!("status page: could not accept connection: {e:?}"),
+        }
+    }
+}
+
+/// Read request line, write response.
+async fn handle(mut stream: TcpStream, progress: &Progress) -> std::io::Result<()> {
+    // we only need the request line, that should fit in the first read.
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..n]);
+
+    let (status, content_type, body) =
+        match request.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+            ["GET", path] => response(path, &progress.snapshot()),
+            _ => (
+                "405 Method Not Allowed",
+                "text/plain",
+                "method not allowed".to_string(),
+            ),
+        };
+
+    let response = format!(
+        "HTTP/1.0 {status}\r\nContent-Type: {content_type}; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Get status, content type and body for a given path.
+fn response(path: &str, snapshot: &Snapshot) -> (&'static str, &'static str, String) {
+    match path {
+        "/" | "/index.html" => ("200 OK", "text/html", html(snapshot)),
+        "/status.json" => match serde_json::to_string(snapshot) {
+            Ok(json) => ("200 OK", "application/json", json),
+            Err(e) => ("500 Internal Server Error", "text/plain", format!("{e:?}")),
+        },
+        _ => ("404 Not Found", "text/plain", "not found".to_string()),
+    }
+}
+
+/// Escape text to be put in HTML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn html(snapshot: &Snapshot) -> String {
+    let state = if snapshot.finished {
+        "finished"
+    } else {
+        "running"
+    };
+
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><meta http-equiv=\"refresh\" content=\"{REFRESH_SECS}\">\
+         <title>ungoliant: {state}</title></head><body>\n\
+         <h1>ungoliant ({state})</h1>\n\
+         <p>shards: {}/{} ({} failed)<br>records: {}<br>documents: {}<br>errors: {}<br>\
+         elapsed: {:.0}s<br>throughput: {:.1} records/s</p>\n",
+        snapshot.shards_done,
+        snapshot.shards_total,
+        snapshot.shards_failed,
+        snapshot.records,
+        snapshot.documents,
+        snapshot.errors,
+        snapshot.elapsed_secs,
+        snapshot.records_per_sec,
+    );
+
+    page.push_str("<h2>languages</h2>\n<table><tr><th>language</th><th>documents</th></tr>\n");
+    for (lang, count) in snapshot.top_languages(snapshot.languages.len()) {
+        let _ = writeln!(page, "<tr><td>{}</td><td>{count}</td></tr>", escape(lang));
+    }
+    page.push_str("</table>\n");
+    if !snapshot.unknown_labels.is_empty() {
+        page.push_str(
+            "<h2>unknown labels</h2>\n<table><tr><th>label</th><th>documents</th></tr>\n",
+        );
+        for (label, count) in &snapshot.unknown_labels {
+            let _ = writeln!(page, "<tr><td>{}</td><td>{count}</td></tr>", escape(label));
+        }
+        page.push_str("</table>\n");
+    }
+    if !snapshot.dropped.is_empty() {
+        page.push_str(
+            "<h2>dropped documents</h2>\n<table><tr><th>reason</th><th>documents</th></tr>\n",
+        );
+        for (reason, count) in &snapshot.dropped {
+            let _ = writeln!(page, "<tr><td>{}</td><td>{count}</td></tr>", escape(reason));
+        }
+        page.push_str("</table>\n");
+    }
+    page.push_str("<h2>recent errors</h2>\n<ul>\n");
+    for error in snapshot.recent_errors.iter().rev() {
+        let _ = writeln!(page, "<li><code>{}</code></li>", escape(error));
+    }
+    page.push_str("</ul>\n</body></html>\n");
+
+    page
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use crate::monitor::Progress;
+
+    use super::{escape, response, serve};
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("<a href=\"x\">&</a>"),
+            "&lt;a href=\"x\"&gt;&amp;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn test_response() {
+        let progress = Progress::new();
+        progress.add_error("<script>".to_string());
+        let snapshot = progress.snapshot();
+
+        let (status, content_type, body) = response("/", &snapshot);
+        assert_eq!(status, "200 OK");
+        assert_eq!(content_type, "text/html");
+        assert!(body.contains("&lt;script&gt;"));
+        assert!(!body.contains("<script>"));
+
+        let (_, content_type, body) = response("/status.json", &snapshot);
+        assert_eq!(content_type, "application/json");
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["recent_errors"][0], "<script>");
+
+        let (status, _, _) = response("/foo", &snapshot);
+        assert_eq!(status, "404 Not Found");
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let progress = Arc::new(Progress::new());
+        progress.set_shards_total(10);
+        tokio::spawn(serve(listener, progress));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /status.json HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.0 200 OK"));
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        let json: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(json["shards_total"], 10);
+    }
+}
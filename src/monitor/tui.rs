@@ -0,0 +1,101 @@
+/*! Terminal dashboard
+
+Draws shard progress, counters, throughput and the most present languages of a run,
+refreshing every [REFRESH_RATE] until the run is finished or `q` is pressed (the run continues).
+
+Logs would garble the dashboard: they should be redirected elsewhere while it is shown.
+!*/
+use std::{io, sync::Arc, time::Duration};
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode},
+    layout::{Constraint, Layout},
+    widgets::{Block, Borders, Gauge, Paragraph, Row, Table},
+    Frame,
+};
+
+use super::{Progress, Snapshot};
+
+const REFRESH_RATE: Duration = Duration::from_millis(500);
+
+/// Number of languages shown.
+const NB_LANGUAGES: usize = 20;
+
+/// Show the dashboard, blocking until the run is finished or the user quits.
+pub fn run(progress: Arc<Progress>) -> io::Result<()> {
+    let mut terminal = ratatui::try_init()?;
+
+    let result = loop {
+        let snapshot = progress.snapshot();
+        if let Err(e) = terminal.draw(|frame| draw(frame, &snapshot)) {
+            break Err(e);
+        }
+        if snapshot.finished {
+            break Ok(());
+        }
+
+        match event::poll(REFRESH_RATE) {
+            Ok(true) => match event::read() {
+                Ok(Event::Key(key)) if key.code == KeyCode::Char('q') => break Ok(()),
+                Ok(_) => (),
+                Err(e) => break Err(e),
+            },
+            Ok(false) => (),
+            Err(e) => break Err(e),
+        }
+    };
+
+    ratatui::restore();
+    result
+}
+
+fn draw(frame: &mut Frame, snapshot: &Snapshot) {
+    let [gauge_area, counters_area, languages_area] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Length(4),
+        Constraint::Min(0),
+    ])
+    .areas(frame.area());
+
+    let ratio = if snapshot.shards_total > 0 {
+        (snapshot.shards_done as f64 / snapshot.shards_total as f64).min(1.0)
+    } else {
+        0.0
+    };
+    let gauge = Gauge::default()
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" shards (q to hide) "),
+        )
+        .ratio(ratio)
+        .label(format!(
+            "{}/{} ({} failed)",
+            snapshot.shards_done, snapshot.shards_total, snapshot.shards_failed
+        ));
+    frame.render_widget(gauge, gauge_area);
+
+    let counters = Paragraph::new(vec![
+        format!(
+            "records: {}  documents: {}  errors: {}",
+            snapshot.records, snapshot.documents, snapshot.errors
+        )
+        .into(),
+        format!(
+            "elapsed: {:.0}s  throughput: {:.1} records/s",
+            snapshot.elapsed_secs, snapshot.records_per_sec
+        )
+        .into(),
+    ])
+    .block(Block::default().borders(Borders::ALL).title(" counters "));
+    frame.render_widget(counters, counters_area);
+
+    let rows = snapshot
+        .top_languages(NB_LANGUAGES)
+        .into_iter()
+        .map(|(lang, count)| Row::new(vec![lang.to_string(), count.to_string()]));
+    let languages = Table::new(rows, [Constraint::Length(12), Constraint::Min(10)])
+        .header(Row::new(vec!["language", "documents"]))
+        .block(Block::default().borders(Borders::ALL).title(" languages "));
+    frame.render_widget(languages, languages_area);
+}
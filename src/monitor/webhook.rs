@@ -0,0 +1,312 @@
+/*! Webhook notifications
+
+POSTs a JSON payload to each configured URL when:
+
+- the run is completed,
+- the run failed (the payload then has an `error` field),
+- the error budget (number of errors + failed shards) is exceeded (only once),
+- a milestone (every `n` processed shards) is reached.
+
+```json
+{"event": "milestone", "text": "ungoliant: 100/8000 shards processed", "summary": {...}}
+```
+
+`summary` is a [Snapshot]. The `text` field makes the payload usable as-is with Slack/Mattermost incoming webhooks.
+
+Notifications are sent in order by a background thread, so that shards aren't held up by HTTP requests.
+Pending notifications are sent before [Webhooks] is dropped.
+Notification failures are logged and never stop the run.
+!*/
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+    },
+    thread::JoinHandle,
+};
+
+use log::{error, info};
+use serde::Serialize;
+
+use super::Snapshot;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    Completed,
+    Failed,
+    ErrorBudget,
+    Milestone,
+}
+
+#[derive(Debug, Serialize)]
+struct Payload<'a> {
+    event: Event,
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+    summary: &'a Snapshot,
+}
+
+impl<'a> Payload<'a> {
+    fn new(event: Event, summary: &'a Snapshot, error: Option<&'a str>) -> Self {
+        let text = match event {
+            Event::Completed => format!(
+                "ungoliant: run completed, {} documents from {}/{} shards ({} failed, {} errors)",
+                summary.documents,
+                summary.shards_done,
+                summary.shards_total,
+                summary.shards_failed,
+                summary.errors
+            ),
+            Event::Failed => format!(
+                "ungoliant: run failed after {}/{} shards: {}",
+                summary.shards_done,
+                summary.shards_total,
+                error.unwrap_or("unknown error")
+            ),
+            Event::ErrorBudget => format!(
+                "ungoliant: error budget exceeded ({} errors, {} failed shards)",
+                summary.errors, summary.shards_failed
+            ),
+            Event::Milestone => format!(
+                "ungoliant: {}/{} shards processed",
+                summary.shards_done, summary.shards_total
+            ),
+        };
+
+        Self {
+            event,
+            text,
+            error,
+            summary,
+        }
+    }
+}
+
+pub struct Webhooks {
+    error_budget: Option<usize>,
+    milestone_every: Option<usize>,
+    last_milestone: AtomicUsize,
+    budget_exceeded: AtomicBool,
+    queue: Option<Sender<(Event, String)>>,
+    sender: Option<JoinHandle<()>>,
+}
+
+impl Webhooks {
+    pub fn new(urls: Vec<String>) -> Self {
+        let (queue, sender) = if urls.is_empty() {
+            (None, None)
+        } else {
+            let (queue, payloads) = mpsc::channel();
+            let sender = std::thread::spawn(move || Self::send(urls, payloads));
+            (Some(queue), Some(sender))
+        };
+
+        Self {
+            error_budget: None,
+            milestone_every: None,
+            last_milestone: AtomicUsize::new(0),
+            budget_exceeded: AtomicBool::new(false),
+            queue,
+            sender,
+        }
+    }
+
+    /// Notify once when the number of errors + failed shards exceeds `error_budget`.
+    pub fn set_error_budget(&mut self, error_budget: Option<usize>) {
+        self.error_budget = error_budget;
+    }
+
+    /// Notify every `milestone_every` processed shards.
+    pub fn set_milestone_every(&mut self, milestone_every: Option<usize>) {
+        self.milestone_every = milestone_every.filter(|every| *every > 0);
+    }
+
+    /// Get events that should be fired after a shard has been processed.
+    fn shard_events(&self, snapshot: &Snapshot) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        if let Some(budget) = self.error_budget {
+            if snapshot.errors + snapshot.shards_failed > budget
+                && !self.budget_exceeded.swap(true, Ordering::Relaxed)
+            {
+                events.push(Event::ErrorBudget);
+            }
+        }
+
+        if let Some(every) = self.milestone_every {
+            let milestone = snapshot.shards_done / every;
+            // only fire if no other thread did it for this milestone
+            if milestone > self.last_milestone.fetch_max(milestone, Ordering::Relaxed) {
+                events.push(Event::Milestone);
+            }
+        }
+
+        events
+    }
+
+    /// To be called after each shard.
+    pub fn on_shard(&self, snapshot: &Snapshot) {
+        for event in self.shard_events(snapshot) {
+            self.notify(Payload::new(event, snapshot, None));
+        }
+    }
+
+    /// To be called at the end of the run.
+    pub fn on_completion(&self, snapshot: &Snapshot) {
+        self.notify(Payload::new(Event::Completed, snapshot, None));
+    }
+
+    /// To be called when the run failed.
+    pub fn on_failure(&self, snapshot: &Snapshot, error: &str) {
+        self.notify(Payload::new(Event::Failed, snapshot, Some(error)));
+    }
+
+    /// Queue the payload for the sender thread.
+    fn notify(&self, payload: Payload) {
+        let Some(queue) = &self.queue else {
+            return;
+        };
+        let event = payload.event;
+        let payload = match serde_json::to_string(&payload) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("Could not serialize webhook payload: {e:?}");
+                return;
+            }
+        };
+
+        if queue.send((event, payload)).is_err() {
+            error!("Could not queue {event:?} notification: webhook thread stopped");
+        }
+    }
+
+    /// Send queued payloads to all URLs, until the queue is closed.
+    ///
+    /// Requests are done on a separate thread since the blocking client can't be used
+    /// from within an async runtime.
+    fn send(urls: Vec<String>, payloads: Receiver<(Event, String)>) {
+        let client = reqwest::blocking::Client::new();
+        for (event, payload) in payloads {
+            info!("Sending {event:?} notification");
+            for url in &urls {
+                let response = client
+                    .post(url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(payload.clone())
+                    .send()
+                    .and_then(|r| r.error_for_status());
+                if let Err(e) = response {
+                    error!("Could not notify {url}: {e:?}");
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Webhooks {
+    /// Wait for pending notifications to be sent.
+    fn drop(&mut self) {
+        // closing the queue stops the sender thread once it is empty
+        self.queue.take();
+        if let Some(sender) = self.sender.take() {
+            if sender.join().is_err() {
+                error!("Webhook thread panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    use crate::monitor::Progress;
+
+    use super::{Event, Payload, Webhooks};
+
+    #[test]
+    fn test_milestones() {
+        let mut webhooks = Webhooks::new(Vec::new());
+        webhooks.set_milestone_every(Some(2));
+        let mut snapshot = Progress::new().snapshot();
+
+        snapshot.shards_done = 1;
+        assert!(webhooks.shard_events(&snapshot).is_empty());
+        snapshot.shards_done = 2;
+        assert_eq!(webhooks.shard_events(&snapshot), vec![Event::Milestone]);
+        // already fired
+        assert!(webhooks.shard_events(&snapshot).is_empty());
+        snapshot.shards_done = 5;
+        assert_eq!(webhooks.shard_events(&snapshot), vec![Event::Milestone]);
+    }
+
+    #[test]
+    fn test_error_budget() {
+        let mut webhooks = Webhooks::new(Vec::new());
+        webhooks.set_error_budget(Some(3));
+        let mut snapshot = Progress::new().snapshot();
+
+        snapshot.errors = 3;
+        assert!(webhooks.shard_events(&snapshot).is_empty());
+        snapshot.shards_failed = 1;
+        assert_eq!(webhooks.shard_events(&snapshot), vec![Event::ErrorBudget]);
+        // only once
+        snapshot.errors = 10;
+        assert!(webhooks.shard_events(&snapshot).is_empty());
+    }
+
+    #[test]
+    fn test_payload() {
+        let snapshot = Progress::new().snapshot();
+        let payload =
+            serde_json::to_value(Payload::new(Event::Completed, &snapshot, None)).unwrap();
+
+        assert_eq!(payload["event"], "completed");
+        assert!(payload["text"].as_str().unwrap().contains("run completed"));
+        assert!(payload.get("error").is_none());
+        assert_eq!(payload["summary"]["shards_done"], 0);
+
+        let payload =
+            serde_json::to_value(Payload::new(Event::Failed, &snapshot, Some("oops"))).unwrap();
+        assert_eq!(payload["event"], "failed");
+        assert!(payload["text"].as_str().unwrap().ends_with("oops"));
+        assert_eq!(payload["error"], "oops");
+    }
+
+    #[test]
+    fn test_notify() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            let mut buf = [0u8; 4096];
+            // headers and body may come in separate reads
+            while !request.contains("\"summary\"") {
+                let n = stream.read(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.push_str(&String::from_utf8_lossy(&buf[..n]));
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            request
+        });
+
+        let webhooks = Webhooks::new(vec![url]);
+        webhooks.on_failure(&Progress::new().snapshot(), "oops");
+        // waits for the notification to be sent
+        drop(webhooks);
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /hook"));
+        assert!(request.contains("\"event\":\"failed\""));
+    }
+}
@@ -0,0 +1,218 @@
+/*! Run budgets
+
+Limits a run in wall-clock time and/or output size, so that it fits in a cluster allocation window
+instead of being killed mid-write.
+
+Budgets are checked before each shard is processed: once a budget is exhausted, no new shard is started,
+shards that are being processed are written, output files are flushed, and the paths of the shards that were not processed
+are written to `<dst>/remaining_shards.txt` to be processed by a later run.
+
+The output size is the number of bytes written to document files (JSONL/WARC parts, compressed) during the run,
+counted by writers as they write (see [RunBudget::written_bytes]).
+Files of previous runs, rebuild files, statistics and Arrow/Parquet datasets aren't counted.
+!*/
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use log::warn;
+
+use crate::error::Error;
+
+/// Wall-clock and output size limits of a run.
+pub struct RunBudget {
+    max_duration: Option<Duration>,
+    max_output_bytes: Option<u64>,
+    start: Instant,
+    output_bytes: Arc<AtomicU64>,
+    /// reason of the first exhaustion, if any
+    exhausted: Mutex<Option<String>>,
+}
+
+impl RunBudget {
+    /// Create a new budget. Time is counted from now.
+    pub fn new(max_duration: Option<Duration>, max_output_bytes: Option<u64>) -> Self {
+        Self {
+            max_duration,
+            max_output_bytes,
+            start: Instant::now(),
+            output_bytes: Arc::new(AtomicU64::new(0)),
+            exhausted: Mutex::new(None),
+        }
+    }
+
+    /// Returns true if there's no limit.
+    pub fn is_unlimited(&self) -> bool {
+        self.max_duration.is_none() && self.max_output_bytes.is_none()
+    }
+
+    /// Get the counter that writers have to add written bytes to (see [crate::io::writer::Writer::set_written_bytes]),
+    /// if there's an output size limit.
+    pub fn written_bytes(&self) -> Option<Arc<AtomicU64>> {
+        self.max_output_bytes.map(|_| self.output_bytes.clone())
+    }
+
+    /// Get the reason why the budget is exhausted, if it is.
+    ///
+    /// The first reason is kept, so that the budget stays exhausted once it has been.
+    pub fn exhausted(&self) -> Option<String> {
+        if self.is_unlimited() {
+            return None;
+        }
+
+        let mut exhausted = self.exhausted.lock().unwrap();
+        if exhausted.is_none() {
+            let elapsed = self.start.elapsed();
+            let output_bytes = self.output_bytes.load(Ordering::Relaxed);
+            *exhausted = match (self.max_duration, self.max_output_bytes) {
+                (Some(max_duration), _) if elapsed >= max_duration => Some(format!(
+                    "maximum duration reached ({:.0}s >= {}s)",
+                    elapsed.as_secs_f64(),
+                    max_duration.as_secs()
+                )),
+                (_, Some(max_output_bytes)) if output_bytes >= max_output_bytes => Some(format!(
+                    "maximum output size reached ({output_bytes} >= {max_output_bytes} bytes)"
+                )),
+                _ => None,
+            };
+            if let Some(reason) = exhausted.as_ref() {
+                warn!(
+                    "Run budget exhausted: {}. No new shard will be processed.",
+                    reason
+                );
+            }
+        }
+        exhausted.clone()
+    }
+}
+
+impl Default for RunBudget {
+    /// No limit.
+    fn default() -> Self {
+        Self::new(None, None)
+    }
+}
+
+/// Split a number from its unit suffix (`"30m"` -> `(30, "m")`).
+fn split_unit(s: &str) -> Result<(u64, &str), Error> {
+    let s = s.trim();
+    let idx = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(idx);
+    let value = value
+        .parse()
+        .map_err(|_| Error::Custom(format!("invalid value {s:?}")))?;
+    Ok((value, unit.trim()))
+}
+
+/// Parse a duration: a number of seconds, optionally suffixed by `s`, `m`, `h` or `d`.
+pub fn parse_duration(s: &str) -> Result<Duration, Error> {
+    let (value, unit) = split_unit(s)?;
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        other => {
+            return Err(Error::Custom(format!(
+                "invalid duration unit {other:?} (expected s, m, h or d)"
+            )))
+        }
+    };
+    Ok(Duration::from_secs(value * multiplier))
+}
+
+/// Parse a size: a number of bytes, optionally suffixed by `K`, `M`, `G` or `T` (powers of 1024).
+pub fn parse_bytes(s: &str) -> Result<u64, Error> {
+    let (value, unit) = split_unit(s)?;
+    let exponent = match unit.trim_end_matches(['B', 'b']) {
+        "" => 0,
+        "K" | "k" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        other => {
+            return Err(Error::Custom(format!(
+                "invalid size unit {other:?} (expected K, M, G or T)"
+            )))
+        }
+    };
+    Ok(value * 1024u64.pow(exponent))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::atomic::Ordering, time::Duration};
+
+    use oxilangtag::LanguageTag;
+
+    use crate::{
+        io::LangFilesDoc,
+        pipelines::oscardoc::types::{Document, Metadata},
+    };
+
+    use super::{parse_bytes, parse_duration, RunBudget};
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(1800));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert!(parse_duration("2w").is_err());
+        assert!(parse_duration("h").is_err());
+    }
+
+    #[test]
+    fn test_parse_bytes() {
+        assert_eq!(parse_bytes("512").unwrap(), 512);
+        assert_eq!(parse_bytes("10K").unwrap(), 10 * 1024);
+        assert_eq!(parse_bytes("2GB").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert!(parse_bytes("1P").is_err());
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let budget = RunBudget::default();
+        assert!(budget.is_unlimited());
+        assert_eq!(budget.exhausted(), None);
+    }
+
+    #[test]
+    fn test_duration() {
+        let budget = RunBudget::new(Some(Duration::ZERO), None);
+        assert!(budget.exhausted().is_some());
+    }
+
+    #[test]
+    fn test_output_bytes() {
+        assert!(RunBudget::new(Some(Duration::from_secs(60)), None)
+            .written_bytes()
+            .is_none());
+
+        let budget = RunBudget::new(None, Some(10));
+        let written_bytes = budget.written_bytes().unwrap();
+        written_bytes.fetch_add(5, Ordering::Relaxed);
+        assert_eq!(budget.exhausted(), None);
+
+        written_bytes.fetch_add(15, Ordering::Relaxed);
+        assert!(budget.exhausted().is_some());
+    }
+
+    #[test]
+    fn test_output_bytes_writers() {
+        let dir = tempfile::tempdir().unwrap();
+        let budget = RunBudget::new(None, Some(10));
+        let mut langfiles = LangFilesDoc::new(dir.path(), None);
+        langfiles.set_written_bytes(budget.written_bytes().unwrap());
+
+        let doc = Document::new("foo".to_string(), HashMap::new(), Metadata::default());
+        langfiles
+            .write(&LanguageTag::parse("en".to_string()).unwrap(), vec![doc])
+            .unwrap();
+        assert!(budget.exhausted().is_some());
+        langfiles.close_all().unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+/*! Run checkpoints
+
+Periodically records the state of a run in `<dst>/checkpoint.json`, so that a crashed or preempted run
+can be resumed exactly where it stopped instead of being started over:
+
+- the ids of the shards whose documents are all written,
+- the position of the writer of each language, in each output tree (see [WriterPosition]).
+
+Shards are written while holding the checkpoint lock in shared mode, and checkpoints are taken in exclusive mode,
+so that they're never taken in the middle of a shard: every recorded shard is entirely before the recorded positions,
+and every other shard entirely after them.
+Checkpoints don't wait for shards being written, since rayon threads writing a shard may start writing another one
+while waiting for their own tasks: a due checkpoint is tried again after the next written shard.
+
+When resuming, recorded shards are skipped, parts are truncated to the recorded positions, parts started since are removed,
+and rebuild files only keep the records of recorded shards.
+In-memory state (such as pre-classification dedup digests) starts over.
+
+Checkpoints are written to a temporary file that is then renamed, so that a crash while checkpointing leaves the previous one.
+!*/
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock, RwLockReadGuard},
+    time::{Duration, Instant},
+};
+
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, io::WriterPosition};
+
+/// File name of checkpoints, in the destination.
+pub const CHECKPOINT_FILE: &str = "checkpoint.json";
+
+/// Default interval between checkpoints.
+pub const DEFAULT_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Positions of writers by language.
+pub type Positions = BTreeMap<String, WriterPosition>;
+
+/// State of a run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// ids of the shards whose documents are all written
+    pub shards: BTreeSet<usize>,
+    /// writer positions of the main, annotated and unknown trees
+    pub trees: BTreeMap<String, Positions>,
+    /// writer positions of each category
+    pub categories: BTreeMap<String, Positions>,
+}
+
+impl Checkpoint {
+    /// Load the checkpoint of a destination, if there's one.
+    pub fn load(dst: &Path) -> Result<Option<Self>, Error> {
+        let path = dst.join(CHECKPOINT_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_reader(File::open(path)?)?))
+    }
+
+    /// Write the checkpoint in `path`, syncing it to disk if `fsync` is set.
+    fn write(&self, path: &Path, fsync: bool) -> Result<(), Error> {
+        let tmp = path.with_extension("json.tmp");
+        serde_json::to_writer(File::create(&tmp)?, self)?;
+        if fsync {
+            crate::io::policy::sync_path(&tmp)?;
+        }
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Takes checkpoints of a run at a given interval.
+pub struct Checkpointer {
+    path: PathBuf,
+    interval: Duration,
+    last: Mutex<Instant>,
+    /// held in shared mode while shards are written, and in exclusive mode while checkpointing
+    lock: RwLock<()>,
+    /// ids of the shards whose documents are all written
+    shards: Mutex<BTreeSet<usize>>,
+}
+
+impl Checkpointer {
+    /// Create a new checkpointer in `dst`, starting from the shards written by a previous run.
+    pub fn new(dst: &Path, interval: Duration, shards: BTreeSet<usize>) -> Self {
+        Self {
+            path: dst.join(CHECKPOINT_FILE),
+            interval,
+            last: Mutex::new(Instant::now()),
+            lock: RwLock::new(()),
+            shards: Mutex::new(shards),
+        }
+    }
+
+    /// Hold while writing the documents of a shard, so that no checkpoint is taken meanwhile.
+    pub fn writing(&self) -> RwLockReadGuard<'_, ()> {
+        self.lock.read().unwrap()
+    }
+
+    /// Record that the documents of a shard are all written.
+    ///
+    /// Has to be called while still [writing](Self::writing), so that the shard is recorded along with its positions.
+    pub fn done(&self, shard_id: usize) {
+        self.shards.lock().unwrap().insert(shard_id);
+    }
+
+    /// Take a checkpoint if the checkpoint interval elapsed since the last one and no shard is being written,
+    /// returning true if it was taken.
+    ///
+    /// `positions` has to flush outputs and return the positions of the writers of output trees and categories.
+    pub fn save_if_due<F>(&self, positions: F, fsync: bool) -> Result<bool, Error>
+    where
+        F: FnOnce() -> Result<(BTreeMap<String, Positions>, BTreeMap<String, Positions>), Error>,
+    {
+        if self.last.lock().unwrap().elapsed() < self.interval {
+            return Ok(false);
+        }
+        let Ok(_lock) = self.lock.try_write() else {
+            debug!("Shards are being written, delaying checkpoint");
+            return Ok(false);
+        };
+        self.write(positions, fsync)?;
+        *self.last.lock().unwrap() = Instant::now();
+        Ok(true)
+    }
+
+    /// Take a checkpoint, waiting for shards being written.
+    ///
+    /// Must not be called while [writing](Self::writing), which would deadlock.
+    pub fn save<F>(&self, positions: F, fsync: bool) -> Result<(), Error>
+    where
+        F: FnOnce() -> Result<(BTreeMap<String, Positions>, BTreeMap<String, Positions>), Error>,
+    {
+        let _lock = self.lock.write().unwrap();
+        self.write(positions, fsync)
+    }
+
+    /// Write a checkpoint, the lock being held in exclusive mode.
+    fn write<F>(&self, positions: F, fsync: bool) -> Result<(), Error>
+    where
+        F: FnOnce() -> Result<(BTreeMap<String, Positions>, BTreeMap<String, Positions>), Error>,
+    {
+        let (trees, categories) = positions()?;
+        let checkpoint = Checkpoint {
+            shards: self.shards.lock().unwrap().clone(),
+            trees,
+            categories,
+        };
+        checkpoint.write(&self.path, fsync)?;
+        info!(
+            "Checkpoint: {} shards written (see {:?})",
+            checkpoint.shards.len(),
+            self.path
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        time::Duration,
+    };
+
+    use super::{Checkpoint, Checkpointer};
+
+    #[test]
+    fn test_save_load() {
+        let dst = tempfile::tempdir().unwrap();
+        assert_eq!(Checkpoint::load(dst.path()).unwrap(), None);
+
+        let positions = || Ok((BTreeMap::new(), BTreeMap::new()));
+        let checkpointer = Checkpointer::new(dst.path(), Duration::ZERO, BTreeSet::from([3]));
+        {
+            let _writing = checkpointer.writing();
+            checkpointer.done(1);
+            // not taken while a shard is being written
+            assert!(!checkpointer.save_if_due(positions, false).unwrap());
+        }
+        assert!(checkpointer.save_if_due(positions, false).unwrap());
+
+        let checkpoint = Checkpoint::load(dst.path()).unwrap().unwrap();
+        assert_eq!(checkpoint.shards, BTreeSet::from([1, 3]));
+        assert!(!dst.path().join("checkpoint.json.tmp").exists());
+    }
+}
@@ -0,0 +1,136 @@
+/*! WARC header retention
+
+Controls which WARC headers of a record are copied into its document.
+Dumping every header roughly doubles the size of the metadata of short documents,
+while most of them (content type, lengths, refers-to…) are of little use downstream.
+
+By default, only the URI, date, record id and digests are kept.
+Headers needed by enabled annotators (`WARC-IP-Address` for country/ASN annotations) are always kept.
+
+Rebuilt corpora are not affected, since headers are taken from the shards.
+!*/
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use warc::WarcHeader;
+
+use crate::error::Error;
+
+/// WARC headers kept in documents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderRetention {
+    All,
+    Only(HashSet<WarcHeader>),
+}
+
+impl HeaderRetention {
+    /// Also keep `header`.
+    pub fn with(self, header: WarcHeader) -> Self {
+        match self {
+            Self::All => Self::All,
+            Self::Only(mut headers) => {
+                headers.insert(header);
+                Self::Only(headers)
+            }
+        }
+    }
+
+    /// Remove headers that are not kept.
+    pub fn retain(&self, headers: &mut HashMap<WarcHeader, Vec<u8>>) {
+        if let Self::Only(kept) = self {
+            headers.retain(|header, _| kept.contains(header));
+        }
+    }
+}
+
+impl Default for HeaderRetention {
+    /// URI, date, record id and digests.
+    fn default() -> Self {
+        Self::Only(HashSet::from([
+            WarcHeader::TargetURI,
+            WarcHeader::Date,
+            WarcHeader::RecordID,
+            WarcHeader::BlockDigest,
+            WarcHeader::PayloadDigest,
+        ]))
+    }
+}
+
+impl FromStr for HeaderRetention {
+    type Err = Error;
+
+    /// Parse `all`, `default`, or a comma-separated list of header names (`warc-target-uri,warc-date`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "all" => Ok(Self::All),
+            "default" => Ok(Self::default()),
+            "" => Err(Error::Custom(
+                "empty header list (expected all, default or header names)".to_string(),
+            )),
+            headers => Ok(Self::Only(
+                headers
+                    .split(',')
+                    .map(|header| WarcHeader::from(header.trim()))
+                    .collect(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use warc::WarcHeader;
+
+    use super::HeaderRetention;
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(
+            "all".parse::<HeaderRetention>().unwrap(),
+            HeaderRetention::All
+        );
+        assert_eq!(
+            "default".parse::<HeaderRetention>().unwrap(),
+            HeaderRetention::default()
+        );
+        assert_eq!(
+            "WARC-Target-URI, warc-identified-content-language"
+                .parse::<HeaderRetention>()
+                .unwrap(),
+            HeaderRetention::Only(HashSet::from([
+                WarcHeader::TargetURI,
+                WarcHeader::Unknown("warc-identified-content-language".to_string())
+            ]))
+        );
+        assert!("".parse::<HeaderRetention>().is_err());
+    }
+
+    #[test]
+    fn test_retain() {
+        let headers = HashMap::from([
+            (WarcHeader::TargetURI, b"http://example.com".to_vec()),
+            (WarcHeader::RecordID, b"<urn:uuid:0>".to_vec()),
+            (WarcHeader::ContentType, b"text/plain".to_vec()),
+            (WarcHeader::IPAddress, b"127.0.0.1".to_vec()),
+        ]);
+
+        let mut all = headers.clone();
+        HeaderRetention::All.retain(&mut all);
+        assert_eq!(all, headers);
+
+        let mut retained = headers.clone();
+        HeaderRetention::default().retain(&mut retained);
+        assert_eq!(retained.len(), 2);
+        assert!(!retained.contains_key(&WarcHeader::ContentType));
+
+        let mut retained = headers;
+        HeaderRetention::default()
+            .with(WarcHeader::IPAddress)
+            .retain(&mut retained);
+        assert!(retained.contains_key(&WarcHeader::IPAddress));
+    }
+}
@@ -1,7 +1,12 @@
 //! OSCAR Schema v2.0 pipeline
+pub mod budget;
+pub mod checkpoint;
+pub mod headers;
 mod pipeline;
+pub mod stream;
 pub mod types;
 
+pub use budget::RunBudget;
 pub use pipeline::OscarDoc;
 // pub use types::Document;
 // pub use types::Metadata;
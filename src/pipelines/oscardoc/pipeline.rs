@@ -8,56 +8,189 @@
 //! Each record is composed of a metadata header and a body containing sentences.
 //!
 //! # Processing
+//! 1. Optionally, only selected records are processed (see [RecordSelection]).
+//! 1. Optionally, only records from allowlisted domains are processed (see [DomainAllowlist]).
+//! 1. Optionally, records whose payload digest or URI was already seen during the run are skipped.
 //! 1. Each record passes through a quality filter that by default checks the content distribution between
 //!   short and long sentences, discarding records where the content is primarly in short sentences. (sentence = newline-separated string)
 //! 1. The remaining ones get identified both by line and as a whole (we keep the language that has the most information (=bytes)).
+//! 1. Documents without a confident identification are dropped, or optionally written in `unknown/` (see [OscarDoc::set_unknown_sink]).
+//! 1. Optionally, only documents identified in selected languages are kept (see [LanguageSelection]).
 //! 1. We pass the records in the adult content annotator
 //! 1. We remove remaining short sentences at start/end[^1]
-//! 1. We then write documents in files.
+//! 1. Documents are kept, stripped of annotations or dropped depending on the [AnnotationPolicy]
+//! 1. We then write documents in files, optionally routing annotated ones in `annotated/` and copying categorized ones in `categories/<lang>/<category>/`.
+//!    Documents can also be written in the legacy text/metadata layout at the same time (see [OscarDoc::set_text_meta_dst]).
+//! 1. Optionally, discarded records are written in `discarded/`, tagged with the reason they were discarded (see [DiscardWriter]).
+//!
+//! Documents can also be consumed directly instead of being written (see [OscarDoc::stream]).
 //!
 //! [^1]: We should do this after step 1: better efficiency.
 use std::fs::File;
+use std::io::Write;
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
 
 use crate::error::Error;
-use crate::filtering::{record, Filter};
+use crate::filtering::{
+    allowlist::DomainAllowlist,
+    annotation::{self, AnnotationPolicy, AnnotationSelector},
+    hash::HashAlgorithm,
+    langs::LanguageSelection,
+    record,
+    selection::RecordSelection,
+    sentence::LineValidity,
+    Filter,
+};
 use crate::identifiers::identification::Identification;
-use crate::identifiers::model::{FastText, FastTextBuilder, Predict};
-use crate::identifiers::StrictMultilingual;
+#[cfg(feature = "fasttext")]
+use crate::identifiers::model::FastTextBuilder;
+use crate::identifiers::model::{
+    Backend, DocIdentification, Granularity, LanguageIdentifier, ModelKind,
+};
+use crate::identifiers::registry::Registry;
+#[cfg(feature = "onnx")]
+use crate::identifiers::OnnxOptions;
+use crate::identifiers::{
+    Cached, Calibrated, Calibration, Cascade, Combination, EnsembleIdentifier, LineThresholds,
+    StrictMultilingual, Thresholded,
+};
+#[cfg(feature = "object-store")]
+use crate::io::remote::RemoteDst;
+use crate::monitor::{webhook::Webhooks, Progress};
+use crate::pipelines::oscardoc::budget::RunBudget;
+use crate::pipelines::oscardoc::checkpoint::{
+    Checkpoint, Checkpointer, CHECKPOINT_FILE, DEFAULT_CHECKPOINT_INTERVAL,
+};
+use crate::pipelines::oscardoc::headers::HeaderRetention;
+use crate::pipelines::oscardoc::stream::{DocumentSender, DocumentStream};
 use crate::pipelines::oscardoc::types::Location;
 use crate::pipelines::oscardoc::types::RebuildWriters;
-use oscar_io::v3::{Document, Metadata, WriterTrait};
+use oscar_io::v3::{Document, Metadata};
 
-use crate::pipelines::oscardoc::types::{LocationBuilder, ShardResult};
+use crate::pipelines::oscardoc::types::{LocationBuilder, ShardResult, ShardStats};
 use crate::pipelines::pipeline::Pipeline;
-use crate::sources::commoncrawl::Wet;
+use crate::sources::commoncrawl::{local_shards, remote_shards, PathFilter, ShardInput, ShardSet};
 
 use crate::transformers::{
-    self, Annotate, Annotator, ContentDetector, Header, Noisy, ShortSentences, TinyDocument,
-    Transform, LSH,
+    self, Annotate, Annotator, CodeDetector, CompressionRatio, ContentDetector, GeoIp,
+    HarmfulContent, Header, MathDetector, Noisy, Readability, Register, RepeatedParagraphs,
+    ScriptDetector, ShortSentences, TinyDocument, Transform, LSH,
 };
 #[cfg(feature = "kenlm")]
 use crate::transformers::{AdultDetector, AdultDetectorBuilder, Models};
 use log::{debug, error, info, log_enabled, warn};
 use oxilangtag::LanguageTag;
 use rayon::prelude::*;
+use url::Url;
 use ut1_blocklist::MultipleBlocklist;
 use warc::BufferedBody;
 use warc::{Record, WarcHeader};
 
-use crate::io::LangFilesDoc;
+use crate::io::{
+    discarded::Discarded, CategoryFilesDoc, Compression, DiscardMode, DiscardReason, DiscardWriter,
+    FieldMapping, FilenameTemplate, LangFilesDoc, Layout, OpenWriters, OutputFormat, TextMetaFiles,
+    WritePolicy,
+};
 
 const DOC_THRESHOLD: f32 = 0.6f32;
 
+/// Shard id, documents along with their location, and statistics.
+type ProcessedShard = (usize, Vec<(Document, Location)>, ShardStats);
+
+/// Options of the identification of records.
+#[derive(Debug, Clone, Copy, Default)]
+struct IdentificationOptions {
+    granularity: Granularity,
+    /// number of language candidates to annotate documents with
+    candidates: Option<usize>,
+    /// keep documents without a confident identification, annotated with [UNKNOWN_ANNOTATION]
+    keep_unknown: bool,
+}
+
+/// Annotation of documents without a confident identification.
+const UNKNOWN_ANNOTATION: &str = "unknown";
+
 // TODO: Implement structopt directly here.
 pub struct OscarDoc {
-    src: PathBuf,
+    srcs: Vec<PathBuf>,
+    remote_paths: Option<(PathBuf, Url)>,
+    path_filter: PathFilter,
     dst: PathBuf,
+    #[cfg_attr(not(feature = "fasttext"), allow(dead_code))]
     lid_path: PathBuf,
     blocklist: Option<PathBuf>,
     kenlms_path: Option<PathBuf>,
+    geoip_dbs: Vec<PathBuf>,
+    repeated_paragraphs: Option<usize>,
+    compression_ratio: Option<(f64, f64)>,
+    readability: Option<usize>,
+    code_detection: Option<f64>,
+    math_detection: Option<f64>,
+    harmful_content: Option<(PathBuf, String, f32)>,
+    register: Option<(PathBuf, f32)>,
+    script_detection: Option<bool>,
+    line_validity: LineValidity,
+    shard_stats: bool,
+    progress: Arc<Progress>,
+    webhooks: Option<Arc<Webhooks>>,
+    retry_failed: bool,
+    lang_registry: Option<PathBuf>,
+    calibration: Option<PathBuf>,
+    backend: Backend,
+    line_thresholds: LineThresholds,
+    lid_k: i32,
+    identification: IdentificationOptions,
+    label_prefix: Option<String>,
+    #[cfg_attr(not(feature = "fasttext"), allow(dead_code))]
+    model_kind: ModelKind,
+    #[cfg(feature = "onnx")]
+    onnx_options: OnnxOptions,
+    fallback: Option<(Backend, f32)>,
+    ensemble: Option<(Vec<(Backend, f32)>, Combination)>,
+    lid_cache: Option<usize>,
+    identifier: Option<Arc<dyn LanguageIdentifier>>,
+    annotation_policy: AnnotationPolicy,
+    annotated_tree: Option<AnnotationSelector>,
+    category_split: bool,
+    write_policy: WritePolicy,
+    pre_dedup: bool,
+    hash_algorithm: HashAlgorithm,
+    record_selection: Option<(RecordSelection, Option<PathBuf>)>,
+    domain_allowlist: Option<DomainAllowlist>,
+    language_selection: LanguageSelection,
+    max_open_writers: Option<usize>,
+    field_mapping: Option<PathBuf>,
+    output_format: OutputFormat,
+    compression: Compression,
+    layout: Layout,
+    filename_template: Option<Arc<FilenameTemplate>>,
+    append: bool,
+    /// destination of the legacy text/metadata output, if any
+    text_meta_dst: Option<PathBuf>,
+    writer_shards: usize,
+    /// maximum number of shards, and of records per shard, to process
+    max_shards: Option<usize>,
+    max_records: Option<usize>,
+    /// part size limits (bytes, documents) of JSONL files
+    part_limits: (Option<u64>, Option<usize>),
+    /// object store URL and maximum number of retries of failed requests
+    #[cfg(feature = "object-store")]
+    upload: Option<(String, usize)>,
+    header_retention: HeaderRetention,
+    budget: RunBudget,
+    discarded: Option<DiscardMode>,
+    /// interval between checkpoints, if enabled
+    checkpoint_interval: Option<Duration>,
+    /// true if resuming from the checkpoint of a previous run
+    resume: bool,
 }
 
 impl OscarDoc {
@@ -74,79 +207,767 @@ impl OscarDoc {
 
         debug!("using blocklist {:?}", blocklist);
         Self {
-            src,
+            srcs: vec![src],
+            remote_paths: None,
+            path_filter: PathFilter::default(),
             dst,
             lid_path,
             blocklist,
             kenlms_path,
+            geoip_dbs: Vec::new(),
+            repeated_paragraphs: None,
+            compression_ratio: None,
+            readability: None,
+            code_detection: None,
+            math_detection: None,
+            harmful_content: None,
+            register: None,
+            script_detection: None,
+            line_validity: LineValidity::default(),
+            shard_stats: false,
+            progress: Arc::new(Progress::new()),
+            webhooks: None,
+            retry_failed: true,
+            lang_registry: None,
+            calibration: None,
+            backend: Backend::default(),
+            line_thresholds: LineThresholds::new(0.8),
+            lid_k: 1,
+            identification: IdentificationOptions::default(),
+            label_prefix: None,
+            model_kind: ModelKind::default(),
+            #[cfg(feature = "onnx")]
+            onnx_options: OnnxOptions::default(),
+            fallback: None,
+            ensemble: None,
+            lid_cache: None,
+            identifier: None,
+            annotation_policy: AnnotationPolicy::default(),
+            annotated_tree: None,
+            category_split: false,
+            write_policy: WritePolicy::default(),
+            pre_dedup: false,
+            hash_algorithm: HashAlgorithm::default(),
+            record_selection: None,
+            domain_allowlist: None,
+            language_selection: LanguageSelection::default(),
+            max_open_writers: None,
+            field_mapping: None,
+            output_format: OutputFormat::default(),
+            compression: Compression::default(),
+            layout: Layout::default(),
+            filename_template: None,
+            append: false,
+            text_meta_dst: None,
+            writer_shards: 1,
+            max_shards: None,
+            max_records: None,
+            part_limits: (None, None),
+            #[cfg(feature = "object-store")]
+            upload: None,
+            header_retention: HeaderRetention::default(),
+            budget: RunBudget::default(),
+            discarded: None,
+            checkpoint_interval: None,
+            resume: false,
         }
     }
 
-    /// list files in source folder,
-    /// filter out errors from fs and from gzip/wet.
+    /// Set MaxMind-format databases used to annotate documents with
+    /// the country/ASN of their `WARC-IP-Address`.
+    pub fn set_geoip_dbs(&mut self, geoip_dbs: Vec<PathBuf>) {
+        self.geoip_dbs = geoip_dbs;
+    }
+
+    /// Collapse paragraphs repeated at least `min_repetitions` times inside a document (see [RepeatedParagraphs]).
+    pub fn set_repeated_paragraphs(&mut self, min_repetitions: Option<usize>) {
+        self.repeated_paragraphs = min_repetitions;
+    }
+
+    /// Annotate documents whose compression ratio is below/above the given bounds
+    /// as `repetitive`/`incompressible` (see [CompressionRatio]).
+    pub fn set_compression_ratio(&mut self, bounds: Option<(f64, f64)>) {
+        self.compression_ratio = bounds;
+    }
+
+    /// Add the mean word length and type-token ratio over a window of `ttr_window` words
+    /// to the quality signals of documents (see [Readability]).
+    pub fn set_readability(&mut self, ttr_window: Option<usize>) {
+        self.readability = ttr_window;
+    }
+
+    /// Annotate documents whose proportion of code lines is at least `min_code_ratio` as `code[:<language>]`
+    /// (see [CodeDetector]).
+    pub fn set_code_detection(&mut self, min_code_ratio: Option<f64>) {
+        self.code_detection = min_code_ratio;
+    }
+
+    /// Annotate documents with at least `min_density` math tokens per word as `math:<form>`
+    /// (see [MathDetector]).
+    pub fn set_math_detection(&mut self, min_density: Option<f64>) {
+        self.math_detection = min_density;
+    }
+
+    /// Classify document content with the `(model, negative_label, min_score)` harmful content classifier,
+    /// adding harmful label scores to quality signals as `harmful_score:<label>` and flagging documents
+    /// with a label scoring at least `min_score` as `harmful:<label>` (see [HarmfulContent]).
+    pub fn set_harmful_content(&mut self, harmful_content: Option<(PathBuf, String, f32)>) {
+        self.harmful_content = harmful_content;
+    }
+
+    /// Classify documents by register with the `(model, min_score)` classifier,
+    /// annotating the most probable register as `register:<label>` if it scores at least `min_score`,
+    /// and adding its score to quality signals
+    /// (see [Register]).
+    pub fn set_register(&mut self, register: Option<(PathBuf, f32)>) {
+        self.register = register;
+    }
+
+    /// Annotate documents with their script as `script:<code>`, and if `refine_tags` is set,
+    /// add the script subtag to the tags of languages written in several scripts (see [ScriptDetector]).
+    pub fn set_script_detection(&mut self, refine_tags: Option<bool>) {
+        self.script_detection = refine_tags;
+    }
+
+    /// Set the predicate deciding which lines are valid (long enough, alphabetic enough, not mostly URLs).
     ///
-    /// This means that invalid gz files and invalid
-    /// wet files are discarded silently
-    fn get_paths_iter(&self) -> Result<impl Iterator<Item = PathBuf>, Error> {
-        let results = std::fs::read_dir(&self.src)?
-            .filter_map(|shard| {
-                shard.map_or_else(
-                    |e| {
-                        error!("error reading shard directory: {}", e);
-                        None
-                    },
-                    Some,
-                )
-            })
-            .map(|shard| shard.path());
-        Ok(results)
+    /// It is used both by the record-level quality filter and by the removal of short lines at start/end.
+    pub fn set_line_validity(&mut self, line_validity: LineValidity) {
+        self.line_validity = line_validity;
+    }
+
+    /// Write per-shard statistics (see [ShardStats]) in `<dst>/stats/<shard_id>.json`.
+    pub fn set_shard_stats(&mut self, shard_stats: bool) {
+        self.shard_stats = shard_stats;
+    }
+
+    /// Notify webhooks of completion, error budget breaches and milestones.
+    ///
+    /// Failures are notified by the caller, since the pipeline can't report errors it returns.
+    pub fn set_webhooks(&mut self, webhooks: Arc<Webhooks>) {
+        self.webhooks = Some(webhooks);
+    }
+
+    /// Retry shards that failed once, at the end of the run (default is `true`).
+    pub fn set_retry_failed(&mut self, retry_failed: bool) {
+        self.retry_failed = retry_failed;
+    }
+
+    /// Use a language registry file for label conversion and per-language thresholds (see [Registry]).
+    pub fn set_lang_registry(&mut self, lang_registry: Option<PathBuf>) {
+        self.lang_registry = lang_registry;
+    }
+
+    /// Calibrate line identification probabilities with a JSON calibration file before thresholding them
+    /// (see [Calibration]).
+    ///
+    /// The calibration is recorded with the identifier metadata, in `<dst>/identifier.json`.
+    pub fn set_calibration(&mut self, calibration: Option<PathBuf>) {
+        self.calibration = calibration;
+    }
+
+    /// Identify languages with `backend` (default is fastText, using the model at `lid_path`).
+    ///
+    /// The ONNX backend uses the model directory at `lid_path`.
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    /// Only keep line identifications whose probability reaches the threshold of their language
+    /// (default is `0.8` for every language).
+    pub fn set_line_thresholds(&mut self, line_thresholds: LineThresholds) {
+        self.line_thresholds = line_thresholds;
+    }
+
+    /// Set the number of fastText predictions per line (default is `1`).
+    pub fn set_lid_k(&mut self, k: i32) {
+        self.lid_k = k;
+    }
+
+    /// Add the confidence of the `k` most present languages of documents to their quality signals,
+    /// as `lid_candidate:<lang>` (see [DocIdentification::candidates] and [transformers::signals]).
+    pub fn set_lid_candidates(&mut self, k: Option<usize>) {
+        self.identification.candidates = k;
+    }
+
+    /// Identify documents by line (default), paragraph or as a whole (see [Granularity]).
+    ///
+    /// Other granularities than lines are recorded as a `lid_granularity:<granularity>` annotation.
+    pub fn set_granularity(&mut self, granularity: Granularity) {
+        self.identification.granularity = granularity;
+    }
+
+    /// Write documents without a confident identification in `<dst>/unknown/` rather than dropping them.
+    ///
+    /// They are identified as their most present language (`und` if there's none) with its confidence,
+    /// and annotated as `unknown`.
+    pub fn set_unknown_sink(&mut self, unknown_sink: bool) {
+        self.identification.keep_unknown = unknown_sink;
+    }
+
+    /// Set the prefix of fastText labels, for models that don't use `__label__`.
+    pub fn set_label_prefix(&mut self, label_prefix: Option<String>) {
+        self.label_prefix = label_prefix;
+    }
+
+    /// Set the label format of the fastText model (default is `lid.176.bin`'s, see [ModelKind]).
+    pub fn set_model_kind(&mut self, model_kind: ModelKind) {
+        self.model_kind = model_kind;
+    }
+
+    /// Set the batch size and threads of the ONNX backend.
+    #[cfg(feature = "onnx")]
+    pub fn set_onnx_options(&mut self, onnx_options: OnnxOptions) {
+        self.onnx_options = onnx_options;
+    }
+
+    /// Identify lines again with the `(backend, min_prob)` fallback when the main backend
+    /// has no prediction or one with a probability under `min_prob` (see [Cascade]).
+    pub fn set_fallback(&mut self, fallback: Option<(Backend, f32)>) {
+        self.fallback = fallback;
+    }
+
+    /// Identify lines with several weighted backends, combining their predictions (see [EnsembleIdentifier]).
+    ///
+    /// Takes precedence over the backend and fallback.
+    /// Documents are annotated with the most present language according to each backend,
+    /// as `lid_member:<backend>:<lang>`, whose confidence is added to quality signals under the same name.
+    pub fn set_ensemble(&mut self, ensemble: Option<(Vec<(Backend, f32)>, Combination)>) {
+        self.ensemble = ensemble;
+    }
+
+    /// Cache the identifications of about `capacity` lines, so that repeated lines are identified once (see [Cached]).
+    ///
+    /// Cache hit statistics are logged at the end of the run.
+    pub fn set_lid_cache(&mut self, capacity: Option<usize>) {
+        self.lid_cache = capacity;
+    }
+
+    /// Use `identifier` instead of building one from the backend.
+    pub fn set_identifier(&mut self, identifier: Arc<dyn LanguageIdentifier>) {
+        self.identifier = Some(identifier);
+    }
+
+    /// Keep, strip annotations from or drop annotated documents before writing them (see [AnnotationPolicy]).
+    pub fn set_annotation_policy(&mut self, annotation_policy: AnnotationPolicy) {
+        self.annotation_policy = annotation_policy;
+    }
+
+    /// Write documents selected by `annotated_tree` in `<dst>/annotated/` rather than in `<dst>`,
+    /// keeping the main tree free of flagged content.
+    pub fn set_annotated_tree(&mut self, annotated_tree: Option<AnnotationSelector>) {
+        self.annotated_tree = annotated_tree;
+    }
+
+    /// Additionally write documents in `<dst>/categories/<lang>/<category>/`, for each of their content categories
+    /// (see [ContentDetector]).
+    pub fn set_category_split(&mut self, category_split: bool) {
+        self.category_split = category_split;
+    }
+
+    /// Set when rebuild files are flushed and whether output files are synced to disk (see [WritePolicy]).
+    pub fn set_write_policy(&mut self, write_policy: WritePolicy) {
+        self.write_policy = write_policy;
+    }
+
+    /// Keep at most `max_open_writers` text/metadata files open, closing the least recently used ones
+    /// (see [OpenWriters]).
+    ///
+    /// Rebuild files are not capped, and stay open for each written language.
+    pub fn set_max_open_writers(&mut self, max_open_writers: Option<usize>) {
+        self.max_open_writers = max_open_writers;
+    }
+
+    /// Use a JSON field mapping file to rename/omit fields of written documents (see [FieldMapping]).
+    pub fn set_field_mapping(&mut self, field_mapping: Option<PathBuf>) {
+        self.field_mapping = field_mapping;
+    }
+
+    /// Write documents as JSONL (default) or Parquet (see [OutputFormat]).
+    ///
+    /// Field mappings only apply to JSONL documents.
+    pub fn set_output_format(&mut self, output_format: OutputFormat) {
+        self.output_format = output_format;
+    }
+
+    /// Compress JSONL files (see [Compression]).
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Write JSONL files in a flat destination (default) or in per-language directories (see [Layout]).
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+    }
+
+    /// Name JSONL parts from a template rather than from the layout (see [FilenameTemplate]).
+    pub fn set_filename_template(&mut self, filename_template: Option<FilenameTemplate>) {
+        self.filename_template = filename_template.map(Arc::new);
+    }
+
+    /// Resume writing after the JSONL parts of a previous run in the destination rather than overwriting them
+    /// (see [crate::io::writer::Writer::resume]).
+    ///
+    /// Rebuild files and stats are still overwritten.
+    pub fn set_append(&mut self, append: bool) {
+        self.append = append;
+    }
+
+    /// Write each language with `writer_shards` concurrent writers, numbering parts from a shared counter
+    /// (see [LangFilesDoc::set_shards]), so that threads writing high-resource languages don't wait for each other.
+    pub fn set_writer_shards(&mut self, writer_shards: usize) {
+        self.writer_shards = writer_shards;
+    }
+
+    /// Only process the first `max_shards` selected shards, if set.
+    pub fn set_max_shards(&mut self, max_shards: Option<usize>) {
+        self.max_shards = max_shards;
+    }
+
+    /// Only process the first `max_records` records of each shard, if set.
+    pub fn set_max_records(&mut self, max_records: Option<usize>) {
+        self.max_records = max_records;
+    }
+
+    /// Also write documents of the main tree in the legacy text/metadata layout in `text_meta_dst`
+    /// (see [crate::io::textmeta]), sharing identification with the document-oriented output.
+    pub fn set_text_meta_dst(&mut self, text_meta_dst: Option<PathBuf>) {
+        self.text_meta_dst = text_meta_dst;
+    }
+
+    /// Split JSONL files in parts of at most `part_size_bytes` bytes (checked between writes)
+    /// and/or `max_part_docs` documents.
+    pub fn set_part_limits(&mut self, part_size_bytes: Option<u64>, max_part_docs: Option<usize>) {
+        self.part_limits = (part_size_bytes, max_part_docs);
+    }
+
+    /// Upload JSONL files under an object store URL rather than writing them in the destination (see [RemoteDst]),
+    /// retrying failed requests up to `max_retries` times.
+    ///
+    /// Rebuild files, stats and other run files are still written in the destination.
+    #[cfg(feature = "object-store")]
+    pub fn set_upload(&mut self, url: Option<String>, max_retries: usize) {
+        self.upload = url.map(|url| (url, max_retries));
+    }
+
+    /// Set which WARC headers are kept in documents (see [HeaderRetention]).
+    pub fn set_header_retention(&mut self, header_retention: HeaderRetention) {
+        self.header_retention = header_retention;
+    }
+
+    /// Skip records whose payload digest or normalized URI was already seen during the run,
+    /// before classification (see [record::RecordDedup]).
+    pub fn set_pre_dedup(&mut self, pre_dedup: bool) {
+        self.pre_dedup = pre_dedup;
+    }
+
+    /// Set the hash algorithm used for dedup keys (see [HashAlgorithm]).
+    pub fn set_hash_algorithm(&mut self, hash_algorithm: HashAlgorithm) {
+        self.hash_algorithm = hash_algorithm;
+    }
+
+    /// Stop processing new shards once a wall-clock or output size budget is exhausted (see [RunBudget]).
+    pub fn set_budget(&mut self, budget: RunBudget) {
+        self.budget = budget;
     }
 
-    /// Extract shard number from a CC shard path.
-    fn get_shard_number(shard_path: &Path) -> Result<usize, Error> {
-        let shard_number = shard_path.file_stem();
-        let shard_number = shard_number
-            .and_then(|s| s.to_str())
-            .and_then(|s| s.split('.').next())
-            .map(|s| s.parse::<usize>());
+    /// Record the state of the run in `<dst>/checkpoint.json` every `checkpoint_interval` (see [Checkpointer]).
+    pub fn set_checkpoint_interval(&mut self, checkpoint_interval: Option<Duration>) {
+        self.checkpoint_interval = checkpoint_interval;
+    }
+
+    /// Resume from the checkpoint of a previous run, skipping its written shards and truncating its parts
+    /// to their recorded positions (see [super::checkpoint]). Checkpoints are then taken by default.
+    pub fn set_resume(&mut self, resume: bool) {
+        self.resume = resume;
+    }
+
+    /// Write discarded records in `<dst>/discarded/<reason>.jsonl`, in order to audit filters (see [DiscardWriter]).
+    pub fn set_discarded(&mut self, discarded: Option<DiscardMode>) {
+        self.discarded = discarded;
+    }
+
+    /// Get a handle on the run's live counters, to be read while the pipeline runs.
+    pub fn progress(&self) -> Arc<Progress> {
+        self.progress.clone()
+    }
+
+    /// Only process selected records.
+    ///
+    /// If `rebuild_dir` is provided, its rebuild files are used to only open shards holding selected records.
+    pub fn set_record_selection(
+        &mut self,
+        selection: RecordSelection,
+        rebuild_dir: Option<PathBuf>,
+    ) {
+        self.record_selection = Some((selection, rebuild_dir));
+    }
+
+    /// Only process records whose domain is allowlisted (see [DomainAllowlist]).
+    pub fn set_domain_allowlist(&mut self, domain_allowlist: Option<DomainAllowlist>) {
+        self.domain_allowlist = domain_allowlist;
+    }
+
+    /// Only keep documents identified in selected languages (see [LanguageSelection]).
+    pub fn set_language_selection(&mut self, language_selection: LanguageSelection) {
+        self.language_selection = language_selection;
+    }
+
+    /// Add sources (directories, shard files, tar archives of shards or glob patterns, see [local_shards]).
+    pub fn add_srcs(&mut self, srcs: Vec<PathBuf>) {
+        self.srcs.extend(srcs);
+    }
+
+    /// Also process the shards listed in a `wet.paths` file, streaming them from `base_url` (see [remote_shards]).
+    ///
+    /// Streamed shards are never written to disk.
+    pub fn set_remote_paths(&mut self, paths_file: PathBuf, base_url: Url) {
+        self.remote_paths = Some((paths_file, base_url));
+    }
+
+    /// Only stream the shards of the `wet.paths` file that are selected by `path_filter`.
+    pub fn set_path_filter(&mut self, path_filter: PathFilter) {
+        self.path_filter = path_filter;
+    }
+
+    /// Build an identifier of `backend`, keeping line predictions whose probability is at least `threshold`.
+    fn build_identifier(
+        &self,
+        backend: Backend,
+        threshold: f32,
+    ) -> Result<Arc<dyn LanguageIdentifier>, Error> {
+        let registry = self
+            .lang_registry
+            .as_deref()
+            .map(Registry::from_path)
+            .transpose()?;
+        Ok(match backend {
+            #[cfg(feature = "fasttext")]
+            Backend::FastText => {
+                let mut builder = FastTextBuilder::default();
+                builder
+                    .path(&self.lid_path)
+                    .k(self.lid_k)
+                    .threshold(threshold)
+                    .kind(self.model_kind);
+                if let Some(label_prefix) = &self.label_prefix {
+                    builder.label_prefix(label_prefix);
+                }
+                if let Some(registry) = registry {
+                    builder.registry(registry);
+                }
+                Arc::new(builder.build()?)
+            }
+            Backend::Whatlang => Arc::new(crate::identifiers::Whatlang::new(threshold, registry)),
+            #[cfg(feature = "cld3")]
+            Backend::Cld3 => Arc::new(crate::identifiers::Cld3::new(threshold, registry)?),
+            #[cfg(feature = "lingua")]
+            Backend::Lingua => Arc::new(crate::identifiers::Lingua::new(threshold, registry)),
+            #[cfg(feature = "onnx")]
+            Backend::Onnx => Arc::new(crate::identifiers::Onnx::new(
+                &self.lid_path,
+                self.onnx_options,
+                threshold,
+                registry,
+            )?),
+        })
+    }
+
+    /// Get the language identifier, building one from the backend(s) if none was set.
+    fn classifier(&self) -> Result<Arc<dyn LanguageIdentifier>, Error> {
+        if let (None, Some(path)) = (&self.identifier, &self.lang_registry) {
+            info!("Using language registry {:?}", path);
+        }
+        let calibration = match &self.calibration {
+            Some(path) => {
+                info!("Using calibration {:?}", path);
+                Some(Calibration::from_path(path)?)
+            }
+            None => None,
+        };
+        // identifiers keep predictions reaching the lowest threshold, per-language ones are applied afterwards.
+        // calibrated probabilities can be higher than raw ones, so calibrated identifiers keep every prediction.
+        let threshold = match calibration {
+            Some(_) => 0.0,
+            None => self.line_thresholds.min(),
+        };
+        let identifier: Arc<dyn LanguageIdentifier> = match (&self.identifier, self.fallback) {
+            (Some(identifier), _) => identifier.clone(),
+            // members keep every prediction, the ensemble thresholds combined ones
+            (None, _) if self.ensemble.is_some() => {
+                let (members, combination) = self.ensemble.as_ref().unwrap();
+                let members = members
+                    .iter()
+                    .map(|(backend, weight)| Ok((self.build_identifier(*backend, 0.0)?, *weight)))
+                    .collect::<Result<_, Error>>()?;
+                Arc::new(EnsembleIdentifier::new(members, *combination, threshold)?)
+            }
+            (None, None) => self.build_identifier(self.backend, threshold)?,
+            // the primary identifier keeps every prediction, the cascade replaces unsure ones
+            (None, Some((fallback, min_prob))) => Arc::new(Cascade::new(
+                self.build_identifier(self.backend, 0.0)?,
+                self.build_identifier(fallback, threshold)?,
+                min_prob,
+            )),
+        };
+        let identifier: Arc<dyn LanguageIdentifier> = match calibration {
+            Some(calibration) => Arc::new(Thresholded::new(
+                Arc::new(Calibrated::new(identifier, calibration)),
+                self.line_thresholds.clone(),
+            )),
+            None if self.line_thresholds.is_empty() => identifier,
+            None => Arc::new(Thresholded::new(identifier, self.line_thresholds.clone())),
+        };
+        // cached identifications are final ones, so the cache wraps every other identifier
+        let identifier: Arc<dyn LanguageIdentifier> = match self.lid_cache {
+            Some(capacity) => Arc::new(Cached::new(identifier, capacity)),
+            None => identifier,
+        };
+        info!("Using language identifier {:?}", identifier.metadata());
+        Ok(identifier)
+    }
+
+    /// Log cache hit statistics of the identifier, if it caches identifications.
+    fn log_cache_stats(cls: &dyn LanguageIdentifier) {
+        if let Some(stats) = cls.cache_stats() {
+            info!(
+                "Identification cache: {} hits, {} misses ({:.1}% hit rate)",
+                stats.hits,
+                stats.misses,
+                stats.hit_rate() * 100.0
+            );
+        }
+    }
+
+    /// Build the document annotator.
+    fn annotator(&self) -> Result<Annotator<Document>, Error> {
+        let mut annotator = Annotator::default();
+        annotator
+            .add(Box::new(TinyDocument::default()))
+            .add(Box::new(ShortSentences::default()))
+            .add(Box::new(Header::default()))
+            .add(Box::new(LSH::default()))
+            .add(Box::new(Noisy::default()));
+
+        // add ut1 blocklists for categories
+        if let Some(path) = &self.blocklist {
+            let bl = MultipleBlocklist::from_dir(&path)?;
+            annotator.add(Box::new(ContentDetector::new(bl)));
+        }
+
+        // add repetitive/incompressible annotations
+        if let Some((min_ratio, max_ratio)) = self.compression_ratio {
+            annotator.add(Box::new(CompressionRatio::new(min_ratio, max_ratio)));
+        }
 
-        match shard_number {
-            Some(Ok(sn)) => Ok(sn),
-            Some(Err(e)) => Err(Error::Custom(format!("{:?}", e))),
-            None => Err(Error::Custom(format!(
-                "Couldn't extract shard number from {:?}",
-                shard_path
-            ))),
+        // add readability signals
+        if let Some(ttr_window) = self.readability {
+            annotator.add(Box::new(Readability::new(ttr_window)));
         }
+
+        // add code annotations
+        if let Some(min_code_ratio) = self.code_detection {
+            annotator.add(Box::new(CodeDetector::new(min_code_ratio)));
+        }
+
+        // add math annotations
+        if let Some(min_density) = self.math_detection {
+            annotator.add(Box::new(MathDetector::new(min_density)));
+        }
+
+        // add content-based harmful annotations
+        if let Some((path, negative_label, min_score)) = &self.harmful_content {
+            info!("Using harmful content classifier {:?}", path);
+            annotator.add(Box::new(HarmfulContent::from_path(
+                path,
+                negative_label.clone(),
+                *min_score,
+            )?));
+        }
+
+        // add register annotations
+        if let Some((path, min_score)) = &self.register {
+            info!("Using register classifier {:?}", path);
+            annotator.add(Box::new(Register::from_path(path, *min_score)?));
+        }
+
+        // add script annotations, refining language tags before documents are sorted by language
+        if let Some(refine_tags) = self.script_detection {
+            annotator.add(Box::new(ScriptDetector::new(refine_tags)));
+        }
+
+        // add country/ASN annotations
+        if !self.geoip_dbs.is_empty() {
+            annotator.add(Box::new(GeoIp::from_paths(&self.geoip_dbs)?));
+        }
+
+        Ok(annotator)
+    }
+
+    /// WARC headers kept in documents, including the ones needed by annotators.
+    fn kept_headers(&self) -> HeaderRetention {
+        if self.geoip_dbs.is_empty() {
+            self.header_retention.clone()
+        } else {
+            self.header_retention.clone().with(WarcHeader::IPAddress)
+        }
+    }
+
+    /// List shards to process, only keeping those containing selected records if possible.
+    fn selected_paths(&self) -> Result<Vec<ShardInput>, Error> {
+        let mut paths = self.get_paths()?;
+        if let Some((selection, Some(rebuild_dir))) = &self.record_selection {
+            if let Some(shard_ids) = selection.indexed_shards(rebuild_dir)? {
+                paths.retain(|path| path.id().is_ok_and(|id| shard_ids.contains(&id)));
+                info!("Selected records are in {} shards", paths.len());
+            }
+        }
+        if let Some(max_shards) = self.max_shards {
+            if paths.len() > max_shards {
+                info!("Only processing {} of {} shards", max_shards, paths.len());
+                paths.truncate(max_shards);
+            }
+        }
+        Ok(paths)
+    }
+
+    /// list shards from all sources, local ones first.
+    ///
+    /// Errors if two shards have the same shard number, since it identifies shards in outputs.
+    /// Shards whose number can't be extracted are kept, and will fail on processing.
+    fn get_paths(&self) -> Result<Vec<ShardInput>, Error> {
+        let mut paths = local_shards(&self.srcs)?;
+        if let Some((paths_file, base_url)) = &self.remote_paths {
+            let remote = self
+                .path_filter
+                .apply(remote_shards(paths_file, base_url)?, |shard| match shard {
+                    ShardInput::Remote { url, .. } => url.path().trim_start_matches('/'),
+                    ShardInput::Local(path) | ShardInput::Archived { member: path, .. } => {
+                        path.to_str().unwrap_or_default()
+                    }
+                });
+            info!("Streaming {} shards from {:?}", remote.len(), paths_file);
+            paths.extend(remote);
+        }
+
+        let mut shard_numbers = HashMap::new();
+        for path in &paths {
+            if let Ok(shard_number) = path.id() {
+                if let Some(other) = shard_numbers.insert(shard_number, path) {
+                    return Err(Error::Custom(format!(
+                        "shard number {} is used by both {} and {}",
+                        shard_number, other, path
+                    )));
+                }
+            }
+        }
+
+        Ok(paths)
     }
 
     /// Process a shard.
     ///
     /// This opens the shard, filters/identifies all documents and then
     /// returns the shard id, along with a [Vec] of documents and their relative location (for rebuilding)
+    /// and processing statistics.
+    #[allow(clippy::too_many_arguments)]
     fn process_shard(
-        shard_path: &Path,
-        identifier: &FastText,
+        shard_path: &ShardInput,
+        identifier: &dyn LanguageIdentifier,
         filter: Option<record::FilterKind>,
+        length_filter: &transformers::RemoveShortSentences,
+        repeated_paragraphs: Option<&RepeatedParagraphs>,
         annotator: &Annotator<Document>,
-    ) -> Result<(usize, Vec<(Document, Location)>), Error> {
-        info!("working on shard: {:?}", shard_path);
+        dedup: Option<&record::RecordDedup>,
+        selection: Option<&RecordSelection>,
+        allowlist: Option<&DomainAllowlist>,
+        discard_writer: Option<&DiscardWriter>,
+        header_retention: &HeaderRetention,
+        identification: IdentificationOptions,
+        max_records: Option<usize>,
+    ) -> Result<ProcessedShard, Error> {
+        info!("working on shard: {}", shard_path);
+        let start = Instant::now();
 
         // get shard number
-        let shard_id = Self::get_shard_number(shard_path)?;
+        let shard_id = shard_path.id()?;
+
+        // write discarded records if asked to
+        let discard = |reason: DiscardReason, discarded: Option<Discarded>| {
+            if let (Some(writer), Some(discarded)) = (discard_writer, discarded) {
+                if let Err(e) = writer.write(reason, shard_id, &discarded) {
+                    error!(
+                        "Could not write discarded record {}: {:?}",
+                        discarded.record_id, e
+                    );
+                }
+            }
+        };
 
-        let shard = Wet::from_path_gzip(shard_path)?;
-        let record_iter = shard.iter.enumerate().par_bridge();
+        let shard = shard_path.open()?;
+        let record_iter = shard
+            .iter
+            .enumerate()
+            .take(max_records.unwrap_or(usize::MAX))
+            .par_bridge();
+
+        // counters for shard statistics
+        let nb_records = AtomicUsize::new(0);
+        let nb_read_errors = AtomicUsize::new(0);
+        let nb_identification_errors = AtomicUsize::new(0);
+        let nb_duplicates = AtomicUsize::new(0);
+        let unknown_labels = Mutex::new(BTreeMap::new());
 
         // only get valid records, print errors
-        let record_iter = record_iter.filter_map(|(idx, record)| match record {
-            Ok(r) => Some((idx, r)),
-            Err(e) => {
-                error!("{:?}", e);
-                None
+        let record_iter = record_iter.filter_map(|(idx, record)| {
+            nb_records.fetch_add(1, Ordering::Relaxed);
+            match record {
+                Ok(r) => Some((idx, r)),
+                Err(e) => {
+                    nb_read_errors.fetch_add(1, Ordering::Relaxed);
+                    error!("{:?}", e);
+                    None
+                }
             }
         });
 
+        // only keep selected records
+        let record_iter = record_iter.filter(|(_, record)| match selection {
+            Some(selection) if !selection.detect(record) => {
+                discard(
+                    DiscardReason::Unselected,
+                    discard_writer.map(|w| w.record(record)),
+                );
+                false
+            }
+            _ => true,
+        });
+
+        // only keep records from allowlisted domains
+        let record_iter = record_iter.filter(|(_, record)| match allowlist {
+            Some(allowlist) if !allowlist.detect(record) => {
+                discard(
+                    DiscardReason::DomainNotAllowed,
+                    discard_writer.map(|w| w.record(record)),
+                );
+                false
+            }
+            _ => true,
+        });
+
+        // skip records already seen during the run, before any processing
+        let record_iter = record_iter.filter(|(_, record)| match dedup {
+            Some(dedup) if !dedup.detect(record) => {
+                nb_duplicates.fetch_add(1, Ordering::Relaxed);
+                discard(
+                    DiscardReason::Duplicate,
+                    discard_writer.map(|w| w.record(record)),
+                );
+                false
+            }
+            _ => true,
+        });
+
         // begin creation of location
         // We fill what we can fill now: shard_id, location_in_shard and record_id.
         let record_iter = record_iter.map(|(idx, record)| {
@@ -159,12 +980,13 @@ impl OscarDoc {
         });
 
         // remove short sentences, discarding documents that only have short sentences
-        let length_filter = transformers::RemoveShortSentences::default();
         let record_iter = record_iter.filter_map(|(mut loc, mut record)| {
+            let original = discard_writer.map(|w| w.record(&record));
             let bounds = length_filter.transform(&mut record);
             match bounds.len() {
                 0 => {
                     debug!("record {} has no sentences kept", record.warc_id());
+                    discard(DiscardReason::TooShort, original);
                     None
                 }
                 1 => {
@@ -193,32 +1015,65 @@ impl OscarDoc {
             if f.detect(&record) {
                 Some((idx, record))
             } else {
+                discard(
+                    DiscardReason::LowQuality,
+                    discard_writer.map(|w| w.record(&record)),
+                );
                 None
             }
         });
 
         // identify
         let record_iter = record_iter
-            .map(|(loc, record)| (loc, Self::process_record(record, identifier)))
-            .filter_map(|(loc, res)| match res {
+            .map(|(loc, record)| {
+                let discarded = discard_writer.map(|w| w.record(&record));
+                (
+                    loc,
+                    discarded,
+                    Self::process_record(record, identifier, header_retention, identification),
+                )
+            })
+            .filter_map(|(loc, discarded, res)| match res {
                 Ok(Some(res)) => Some((loc, res)),
-                Ok(None) => None,
+                Ok(None) => {
+                    discard(DiscardReason::LowConfidence, discarded);
+                    None
+                }
+                // aggregated and reported once per shard
+                Err(Error::UnknownLang(label)) => {
+                    *unknown_labels.lock().unwrap().entry(label).or_insert(0) += 1;
+                    discard(DiscardReason::UnknownLabel, discarded);
+                    None
+                }
                 Err(e) => {
+                    nb_identification_errors.fetch_add(1, Ordering::Relaxed);
                     error!("{:?}", e);
                     None
                 }
             });
 
+        // collapse repeated paragraphs, recording kept lines for rebuilding
+        let record_iter = record_iter.map(|(mut loc, mut r)| {
+            if let Some(repeated_paragraphs) = repeated_paragraphs {
+                loc.set_kept_ranges(repeated_paragraphs.transform(&mut r));
+            }
+            (loc, r)
+        });
+
         // annotate
         let record_iter = record_iter.map(|(loc, mut r)| {
             annotator.annotate(&mut r);
             (r, loc.build().unwrap())
         });
 
-        // remove documents that are both tiny and noisy
+        // remove documents that are both tiny and noisy, whatever their other annotations
         let record_iter = record_iter.filter_map(|(r, loc): (Document, Location)| {
-            if r.metadata().annotation() == Some(&vec!["noisy".to_string(), "tiny".to_string()]) {
+            if annotation::has_types(&r, &["noisy", "tiny"]) {
                 debug!("removed document {:?} for noisy+tiny", r.warc_id());
+                discard(
+                    DiscardReason::NoisyTiny,
+                    discard_writer.map(|w| w.document(&r)),
+                );
                 None
             } else {
                 Some((r, loc))
@@ -228,7 +1083,33 @@ impl OscarDoc {
         let records: Vec<(_, _)> = record_iter.collect();
         info!("Shard {}: Got {} documents", shard_id, records.len());
 
-        Ok((shard_id, records))
+        let unknown_labels = unknown_labels.into_inner().unwrap();
+        if !unknown_labels.is_empty() {
+            warn!(
+                "Shard {}: discarded documents with unknown labels: {:?}",
+                shard_id, unknown_labels
+            );
+        }
+
+        // documents without a confident identification aren't kept, they're written apart
+        let nb_unknown = records
+            .iter()
+            .filter(|(doc, _)| Self::is_unknown(doc))
+            .count();
+
+        let mut stats = ShardStats::new(shard_id);
+        stats.set_unknown_labels(unknown_labels);
+        stats.set_duplicates(nb_duplicates.into_inner());
+        stats.set_unknown(nb_unknown);
+        stats.set_processing(
+            nb_records.into_inner(),
+            records.len() - nb_unknown,
+            nb_read_errors.into_inner(),
+            nb_identification_errors.into_inner(),
+            start.elapsed(),
+        );
+
+        Ok((shard_id, records, stats))
     }
 
     /// process a record
@@ -236,15 +1117,18 @@ impl OscarDoc {
     /// then compute the most present identification
     fn process_record(
         record: Record<BufferedBody>,
-        identifier: &FastText,
+        identifier: &dyn LanguageIdentifier,
+        header_retention: &HeaderRetention,
+        identification: IdentificationOptions,
     ) -> Result<Option<Document>, Error> {
         // get lines
-        let (headers, body) = record.into_raw_parts();
+        let (mut headers, body) = record.into_raw_parts();
+        header_retention.retain(&mut headers.headers);
         let body = String::from_utf8_lossy(&body);
         let lines = body.lines();
 
         // get the id for each line, the byte/prob count and the total byte count of the document
-        let w_ids = identifier.weighted_ids(lines)?;
+        let w_ids = identifier.weighted_ids_by(lines, identification.granularity)?;
         let ids = w_ids.line_ids();
         let lang_count = w_ids.lang_bins();
         let total_count = w_ids.total_size();
@@ -264,7 +1148,8 @@ impl OscarDoc {
                 Identification::new(LanguageTag::parse("multi".to_string())?, 0.5);
 
             let metadata = Metadata::new(&document_identification, ids.as_slice());
-            let doc = Document::new(body.into_owned(), headers.headers, metadata);
+            let mut doc = Document::new(body.into_owned(), headers.headers, metadata);
+            Self::annotate_identification(&mut doc, &w_ids, identification);
 
             return Ok(Some(doc));
         }
@@ -281,8 +1166,14 @@ impl OscarDoc {
                 id, lang_byte_count, total_count, confidence
             );
 
-            if confidence < &DOC_THRESHOLD {
-                return Ok(None);
+            let threshold = identifier
+                .registry()
+                .and_then(|registry| registry.threshold(id))
+                .unwrap_or(DOC_THRESHOLD);
+            if confidence < &threshold {
+                return Ok(identification.keep_unknown.then(|| {
+                    Self::unknown_document(body.into_owned(), headers.headers, &ids, &w_ids)
+                }));
             }
 
             // create id
@@ -290,7 +1181,8 @@ impl OscarDoc {
 
             // create doc and metadata
             let metadata = Metadata::new(&document_identification, ids.as_slice());
-            let doc = Document::new(body.into_owned(), headers.headers, metadata);
+            let mut doc = Document::new(body.into_owned(), headers.headers, metadata);
+            Self::annotate_identification(&mut doc, &w_ids, identification);
 
             debug!("{} : {:?}", doc.warc_id(), doc.identification());
             Ok(Some(doc))
@@ -306,7 +1198,72 @@ impl OscarDoc {
                 debug!("{:?}", &lang_count);
                 debug!("{}", &body);
             }
-            Ok(None)
+            Ok(identification
+                .keep_unknown
+                .then(|| Self::unknown_document(body.into_owned(), headers.headers, &ids, &w_ids)))
+        }
+    }
+
+    /// Build a document without a confident identification,
+    /// identified as its most present language (or `und`) and annotated with [UNKNOWN_ANNOTATION].
+    fn unknown_document(
+        content: String,
+        headers: HashMap<WarcHeader, Vec<u8>>,
+        ids: &[Option<oscar_io::common::Identification<String>>],
+        w_ids: &DocIdentification<String>,
+    ) -> Document {
+        let best_guess = w_ids.candidates(1).into_iter().next().unwrap_or_else(|| {
+            let und = LanguageTag::parse("und".to_string()).expect("und is a valid tag");
+            Identification::new(und, 0.0)
+        });
+
+        let metadata = Metadata::new(&best_guess, ids);
+        let mut doc = Document::new(content, headers, metadata);
+        doc.metadata_mut()
+            .add_annotation(UNKNOWN_ANNOTATION.to_string());
+
+        doc
+    }
+
+    /// Returns true if the document has no confident identification (see [Self::unknown_document]).
+    fn is_unknown(doc: &Document) -> bool {
+        doc.metadata()
+            .annotation()
+            .is_some_and(|annotations| annotations.iter().any(|a| a == UNKNOWN_ANNOTATION))
+    }
+
+    /// Annotate a document with its identification granularity (if not by line)
+    /// and the most present language according to each ensemble member,
+    /// and add their confidence and the one of its most present languages (if asked to) to its quality signals.
+    fn annotate_identification(
+        doc: &mut Document,
+        w_ids: &DocIdentification<String>,
+        identification: IdentificationOptions,
+    ) {
+        if identification.granularity != Granularity::Line {
+            doc.metadata_mut().add_annotation(format!(
+                "lid_granularity:{}",
+                identification.granularity.as_str()
+            ));
+        }
+        let mut signals = Vec::new();
+        for (backend, id) in w_ids.members() {
+            if let Some(id) = id {
+                let member = format!("lid_member:{}:{}", backend, id.label());
+                doc.metadata_mut().add_annotation(member.clone());
+                signals.push((member, f64::from(*id.prob())));
+            }
+        }
+        if let Some(k) = identification.candidates {
+            for candidate in w_ids.candidates(k) {
+                signals.push((
+                    format!("lid_candidate:{}", candidate.label()),
+                    f64::from(*candidate.prob()),
+                ));
+            }
+        }
+        if !signals.is_empty() {
+            transformers::signals::add_quality_signals(doc, signals);
         }
     }
 
@@ -357,32 +1314,177 @@ impl OscarDoc {
         }
     }
 
+    /// Remove documents identified in languages that aren't selected.
+    ///
+    /// Returns the number of removed documents, which are written in `discard_writer` if provided.
+    fn apply_language_selection(
+        selection: &LanguageSelection,
+        documents: &mut HashMap<LanguageTag<String>, Vec<(Document, Location)>>,
+        discard_writer: Option<&DiscardWriter>,
+        shard_id: usize,
+    ) -> usize {
+        let mut dropped = 0;
+        documents.retain(|lang, docs| {
+            if selection.detect(lang) {
+                return true;
+            }
+
+            debug!(
+                "shard {}: dropped {} {lang} documents",
+                shard_id,
+                docs.len()
+            );
+            if let Some(writer) = discard_writer {
+                for (doc, _) in docs.iter() {
+                    if let Err(e) =
+                        writer.write(DiscardReason::Language, shard_id, &writer.document(doc))
+                    {
+                        error!(
+                            "Could not write discarded record {}: {:?}",
+                            doc.warc_id(),
+                            e
+                        );
+                    }
+                }
+            }
+            dropped += docs.len();
+            false
+        });
+
+        dropped
+    }
+
+    /// Apply the annotation policy, removing dropped documents.
+    ///
+    /// Returns the number of dropped documents per annotation type.
+    /// Dropped documents are written in `discard_writer` if provided.
+    fn apply_annotation_policy(
+        policy: &AnnotationPolicy,
+        documents: &mut HashMap<LanguageTag<String>, Vec<(Document, Location)>>,
+        discard_writer: Option<&DiscardWriter>,
+        shard_id: usize,
+    ) -> BTreeMap<String, usize> {
+        let mut dropped = BTreeMap::new();
+        for docs in documents.values_mut() {
+            docs.retain_mut(|(doc, _)| match policy.apply(doc) {
+                Some(reason) => {
+                    debug!("record {}: dropped ({})", doc.warc_id(), reason);
+                    if let Some(writer) = discard_writer {
+                        let reason = DiscardReason::Annotation(reason.clone());
+                        if let Err(e) = writer.write(reason, shard_id, &writer.document(doc)) {
+                            error!(
+                                "Could not write discarded record {}: {:?}",
+                                doc.warc_id(),
+                                e
+                            );
+                        }
+                    }
+                    *dropped.entry(reason).or_insert(0) += 1;
+                    false
+                }
+                None => true,
+            });
+        }
+        documents.retain(|_, docs| !docs.is_empty());
+
+        dropped
+    }
+
+    /// Write copies of categorized documents in their categories' files.
+    ///
+    /// Returns the errors of categories/languages that couldn't be written.
+    fn write_categories(
+        categoryfiles: &CategoryFilesDoc,
+        documents: &HashMap<LanguageTag<String>, Vec<(Document, Location)>>,
+    ) -> Vec<Error> {
+        let mut categorized: HashMap<&str, HashMap<&LanguageTag<String>, Vec<Document>>> =
+            HashMap::new();
+        for (lang, docs) in documents {
+            for (doc, _) in docs {
+                for category in doc.metadata().categories().into_iter().flatten() {
+                    categorized
+                        .entry(category)
+                        .or_default()
+                        .entry(lang)
+                        .or_default()
+                        .push(doc.clone());
+                }
+            }
+        }
+
+        categorized
+            .into_iter()
+            .flat_map(|(category, langs)| {
+                langs
+                    .into_iter()
+                    .map(move |(lang, docs)| (category, lang.clone(), docs))
+            })
+            .filter_map(|(category, lang, docs)| {
+                debug!("[{}/{}]: {} documents", category, lang, docs.len());
+                categoryfiles.write(category, lang, docs).err()
+            })
+            .inspect(|e| error!("{:?}", e))
+            .collect()
+    }
+
+    /// Write documents in the legacy text/metadata layout.
+    ///
+    /// Returns the errors of languages that couldn't be written.
+    fn write_text_meta(
+        text_meta_files: &TextMetaFiles,
+        documents: &HashMap<LanguageTag<String>, Vec<(Document, Location)>>,
+    ) -> Vec<Error> {
+        documents
+            .iter()
+            .filter_map(|(lang, docs)| {
+                let docs: Vec<_> = docs.iter().map(|(doc, _)| doc.clone()).collect();
+                text_meta_files.write(lang, &docs).err()
+            })
+            .inspect(|e| error!("{:?}", e))
+            .collect()
+    }
+
+    /// Move documents selected by `selector` out of `documents`.
+    fn split_annotated(
+        selector: &AnnotationSelector,
+        documents: &mut HashMap<LanguageTag<String>, Vec<(Document, Location)>>,
+    ) -> HashMap<LanguageTag<String>, Vec<(Document, Location)>> {
+        let mut annotated = HashMap::new();
+        for (lang, docs) in documents.iter_mut() {
+            let (selected, kept) = std::mem::take(docs)
+                .into_iter()
+                .partition::<Vec<_>, _>(|(doc, _)| selector.matches(doc));
+            *docs = kept;
+            if !selected.is_empty() {
+                annotated.insert(lang.clone(), selected);
+            }
+        }
+        documents.retain(|_, docs| !docs.is_empty());
+
+        annotated
+    }
+
     /// concurrently write documets
+    ///
+    /// Returns the errors of languages that couldn't be written.
     fn write_documents<'a>(
         langfiles: &LangFilesDoc,
         avrowriters: &'a RebuildWriters<'a, File>,
         rebuild_root_dir: &Path,
         shard_id: usize,
         documents: HashMap<LanguageTag<String>, Vec<(Document, Location)>>,
-    ) -> Result<(), Error> {
+        write_policy: &WritePolicy,
+    ) -> Result<Vec<Error>, Error> {
         let errors: Vec<Error> = documents
             .into_par_iter()
             .map(|(lang, docs)| {
                 info!("[{}]: {} documents", lang, docs.len());
 
-                // check if langfiles has an opened file for provided language
-                if !langfiles.contains(&lang) {
-                    langfiles.insert_writer(lang.clone())?;
-                };
-                let writers = langfiles.writers();
-                let writer = writers.get(&lang).unwrap();
-
                 if !avrowriters.contains(&lang) {
                     avrowriters.insert(rebuild_root_dir, &lang)?;
                 }
                 let avrowriters_lock = avrowriters.writers();
                 let avrowriter = avrowriters_lock.get(&lang).unwrap();
-                let mut writer_lock = writer.lock().unwrap();
                 let mut avrowriter_lock = avrowriter.lock().unwrap();
 
                 // divide the documents iterator into two iterators
@@ -395,11 +1497,16 @@ impl OscarDoc {
                 sr.sort();
 
                 // write docs and rebuild files
-                writer_lock.write(docs)?;
+                langfiles.write(&lang, docs)?;
                 avrowriter_lock.append_ser(sr)?;
 
-                //TODO: not sure that we need the flush
-                avrowriter_lock.flush()?;
+                if write_policy.should_flush(avrowriter_lock.nb_appends()) {
+                    avrowriter_lock.flush()?;
+                    if write_policy.fsync() {
+                        langfiles.sync(&lang)?;
+                        RebuildWriters::sync(rebuild_root_dir, &lang)?;
+                    }
+                }
 
                 Ok(())
             })
@@ -410,10 +1517,122 @@ impl OscarDoc {
             })
             .collect();
 
-        for error in errors {
+        for error in &errors {
             error!("{:?}", error);
         }
 
+        Ok(errors)
+    }
+}
+
+#[allow(dead_code)]
+impl OscarDoc {
+    /// Run the pipeline in a background thread, yielding documents instead of writing them.
+    ///
+    /// Records go through filtering, identification, annotation and the [AnnotationPolicy],
+    /// but nothing is written: output trees, rebuild files, stats, discarded records and budgets are ignored.
+    /// KenLM annotations are not available either.
+    ///
+    /// At most `buffer` documents wait to be consumed. Dropping the stream stops the pipeline after the current shard.
+    pub fn stream(self, buffer: usize) -> DocumentStream {
+        let (sender, stream) = DocumentStream::with_buffer(buffer);
+        std::thread::spawn(move || {
+            if let Err(e) = self.send_documents(&sender) {
+                // the stream may have been dropped already
+                let _ = sender.send(Err(e));
+            }
+        });
+        stream
+    }
+
+    /// Process shards one at a time, sending documents until the receiving stream is dropped.
+    fn send_documents(&self, sender: &DocumentSender) -> Result<(), Error> {
+        let cls = self.classifier()?;
+        let annotator = self.annotator()?;
+        let paths = self.selected_paths()?;
+        self.progress.set_shards_total(paths.len());
+
+        let repeated_paragraphs = self.repeated_paragraphs.map(RepeatedParagraphs::new);
+        let length_filter =
+            transformers::RemoveShortSentences::with_line_validity(self.line_validity.clone());
+        let dedup = self
+            .pre_dedup
+            .then(|| record::RecordDedup::new(self.hash_algorithm));
+        let header_retention = self.kept_headers();
+
+        for (idx, shard) in paths.iter().enumerate() {
+            let processed = Self::process_shard(
+                shard,
+                cls.as_ref(),
+                Some(record::FilterKind::PFilter(
+                    record::PFilter::with_line_validity(self.line_validity.clone()),
+                )),
+                &length_filter,
+                repeated_paragraphs.as_ref(),
+                &annotator,
+                dedup.as_ref(),
+                self.record_selection
+                    .as_ref()
+                    .map(|(selection, _)| selection),
+                self.domain_allowlist.as_ref(),
+                None,
+                &header_retention,
+                // the unknown sink is only written by runs
+                IdentificationOptions {
+                    keep_unknown: false,
+                    ..self.identification
+                },
+                self.max_records,
+            );
+
+            let (shard_id, documents, mut stats) = match processed {
+                Ok(processed) => processed,
+                Err(e) => {
+                    self.progress.add_failed_shard();
+                    self.progress
+                        .add_error(format!("shard idx {}: {:?}", idx, e));
+                    if sender.send(Err(e)).is_err() {
+                        return Ok(());
+                    }
+                    continue;
+                }
+            };
+
+            let mut hm = Self::sort_by_lang(documents);
+            let mut dropped = BTreeMap::new();
+            if !self.language_selection.is_empty() {
+                let nb_dropped = Self::apply_language_selection(
+                    &self.language_selection,
+                    &mut hm,
+                    None,
+                    shard_id,
+                );
+                if nb_dropped > 0 {
+                    dropped.insert("language".to_string(), nb_dropped);
+                }
+            }
+            if !self.annotation_policy.is_empty() {
+                dropped.extend(Self::apply_annotation_policy(
+                    &self.annotation_policy,
+                    &mut hm,
+                    None,
+                    shard_id,
+                ));
+            }
+            stats.set_dropped(dropped);
+            stats.set_languages(&hm);
+            self.progress.add_shard(&stats);
+
+            for (doc, _) in hm.into_values().flatten() {
+                if sender.send(Ok(doc)).is_err() {
+                    debug!("Document stream dropped, stopping");
+                    return Ok(());
+                }
+            }
+        }
+
+        Self::log_cache_stats(cls.as_ref());
+        self.progress.finish();
         Ok(())
     }
 }
@@ -426,11 +1645,7 @@ impl Pipeline<()> for OscarDoc {
     fn run(&self) -> Result<(), Error> {
         // let errors;
 
-        let cls = FastTextBuilder::default()
-            .path(&self.lid_path)
-            .k(1)
-            .threshold(0.8)
-            .build()?;
+        let cls = self.classifier()?;
 
         if !self.dst.exists() {
             warn!("Destination file does not exist. Creating");
@@ -440,15 +1655,214 @@ impl Pipeline<()> for OscarDoc {
         if !self.dst.is_dir() {
             panic!("Destination has to be a directory: {:?}", self.dst);
         }
-        let results = self.get_paths_iter()?;
 
-        // convert to parallel iterator
-        // /!\: We use par_bridge, that is suboptimal
-        //      compared to implementing IntoParallelIterator
-        //      ourselves.
-        let results = results.enumerate().par_bridge();
+        // unfinished parts of a crashed run are overwritten, or left around if their language isn't written again
+        for orphan in crate::io::writer::orphaned_parts(&self.dst)? {
+            warn!("Unfinished part {:?} (left by a previous run?)", orphan);
+        }
+
+        // record the identifier (and its calibration) along with the corpus
+        let identifier_path = self.dst.join("identifier.json");
+        serde_json::to_writer_pretty(File::create(&identifier_path)?, &cls.metadata())?;
+
+        // state of the run to resume, if any. Other runs discard the checkpoint of a previous one.
+        let checkpointing = self.resume || self.checkpoint_interval.is_some();
+        let checkpoint = if self.resume {
+            let checkpoint = Checkpoint::load(&self.dst)?;
+            match &checkpoint {
+                Some(checkpoint) => {
+                    info!("Resuming after {} written shards", checkpoint.shards.len())
+                }
+                None => warn!("No checkpoint in {:?}, starting from scratch", self.dst),
+            }
+            checkpoint
+        } else {
+            let checkpoint_path = self.dst.join(CHECKPOINT_FILE);
+            if checkpoint_path.exists() {
+                std::fs::remove_file(checkpoint_path)?;
+            }
+            None
+        };
+
+        let mut results = self.selected_paths()?;
+        if let Some(checkpoint) = &checkpoint {
+            results.retain(|shard| !shard.id().is_ok_and(|id| checkpoint.shards.contains(&id)));
+            if self.pre_dedup {
+                warn!("Pre-classification dedup starts over: resumed shards aren't deduplicated against written ones");
+            }
+        }
+        self.progress.set_shards_total(results.len());
 
-        let langfiles = LangFilesDoc::new(&self.dst, None);
+        // biggest shards first, one task per shard (see [ShardSet])
+        let results = ShardSet::new(results).into_par_iter();
+
+        // shared between all output trees
+        let open_writers = self.max_open_writers.map(|max_open| {
+            info!("Keeping at most {} writers open", max_open);
+            Arc::new(OpenWriters::new(max_open))
+        });
+        let field_mapping = match &self.field_mapping {
+            Some(_) if self.output_format != OutputFormat::Jsonl => {
+                return Err(Error::Custom(
+                    "field mappings can only be used with the jsonl output format".to_string(),
+                ));
+            }
+            Some(path) => {
+                info!("Using field mapping {:?}", path);
+                Some(Arc::new(FieldMapping::from_path(path)?))
+            }
+            None => None,
+        };
+        if self.compression != Compression::None && !self.output_format.is_file() {
+            return Err(Error::Custom(
+                "compression can only be used with the jsonl and warc output formats".to_string(),
+            ));
+        }
+        if self.layout != Layout::Flat && !self.output_format.is_file() {
+            return Err(Error::Custom(
+                "layouts can only be set with the jsonl and warc output formats".to_string(),
+            ));
+        }
+        if let Some(filename_template) = &self.filename_template {
+            if !self.output_format.is_file() {
+                return Err(Error::Custom(
+                    "filename templates can only be used with the jsonl and warc output formats"
+                        .to_string(),
+                ));
+            }
+            if self.layout != Layout::Flat {
+                return Err(Error::Custom(
+                    "filename templates can't be combined with a layout".to_string(),
+                ));
+            }
+            info!("Naming parts from template {}", filename_template);
+        }
+        let (part_size_bytes, max_part_docs) = self.part_limits;
+        if (part_size_bytes.is_some() || max_part_docs.is_some()) && !self.output_format.is_file() {
+            return Err(Error::Custom(
+                "part sizes can only be set with the jsonl and warc output formats".to_string(),
+            ));
+        }
+        #[cfg(feature = "object-store")]
+        let remote = match &self.upload {
+            Some(_) if !self.output_format.is_file() => {
+                return Err(Error::Custom(
+                    "uploads can only be used with the jsonl and warc output formats".to_string(),
+                ));
+            }
+            Some(_) if self.append => {
+                return Err(Error::Custom("uploads can't be appended to".to_string()));
+            }
+            Some(_) if checkpointing => {
+                return Err(Error::Custom("uploads can't be checkpointed".to_string()));
+            }
+            Some((url, max_retries)) => {
+                info!("Uploading documents to {}", url);
+                Some(Arc::new(RemoteDst::new(url, &self.dst, *max_retries)?))
+            }
+            None => None,
+        };
+        if self.append {
+            if !self.output_format.is_file() {
+                return Err(Error::Custom(
+                    "only jsonl and warc files can be appended to".to_string(),
+                ));
+            }
+            info!("Resuming after existing parts of {:?}", self.dst);
+        }
+        if self.writer_shards > 1 {
+            if !self.output_format.is_file() {
+                return Err(Error::Custom(
+                    "writer shards can only be used with the jsonl and warc output formats"
+                        .to_string(),
+                ));
+            }
+            if self.append {
+                return Err(Error::Custom(
+                    "writer shards can't be combined with --append".to_string(),
+                ));
+            }
+            info!("Writing each language with {} writers", self.writer_shards);
+        }
+        if checkpointing {
+            if !self.output_format.is_file() {
+                return Err(Error::Custom(
+                    "checkpoints can only be used with the jsonl and warc output formats"
+                        .to_string(),
+                ));
+            }
+            if self.append {
+                return Err(Error::Custom(
+                    "checkpoints can't be combined with --append (use --resume)".to_string(),
+                ));
+            }
+            if self.writer_shards > 1 {
+                return Err(Error::Custom(
+                    "checkpoints can't be combined with writer shards".to_string(),
+                ));
+            }
+            if self.discarded.is_some() {
+                return Err(Error::Custom(
+                    "discarded records can't be checkpointed".to_string(),
+                ));
+            }
+        }
+        let text_meta_files = match &self.text_meta_dst {
+            Some(_) if self.append => {
+                return Err(Error::Custom(
+                    "the text/metadata output can't be appended to".to_string(),
+                ));
+            }
+            Some(_) if checkpointing => {
+                return Err(Error::Custom(
+                    "the text/metadata output can't be checkpointed".to_string(),
+                ));
+            }
+            Some(text_meta_dst) => {
+                info!("Also writing text/metadata files in {:?}", text_meta_dst);
+                Some(TextMetaFiles::new(text_meta_dst)?)
+            }
+            None => None,
+        };
+        // per-language writer settings
+        let registry = self
+            .lang_registry
+            .as_deref()
+            .map(Registry::from_path)
+            .transpose()?
+            .map(Arc::new);
+        let new_langfiles = |dst: &Path| {
+            let mut langfiles = LangFilesDoc::new(dst, part_size_bytes);
+            if let Some(open_writers) = &open_writers {
+                langfiles.set_open_writers(open_writers.clone());
+            }
+            if let Some(field_mapping) = &field_mapping {
+                langfiles.set_field_mapping(field_mapping.clone());
+            }
+            langfiles.set_format(self.output_format);
+            langfiles.set_compression(self.compression);
+            langfiles.set_layout(self.layout);
+            langfiles.set_append(self.append);
+            langfiles.set_fsync_parts(self.write_policy.fsync_parts());
+            if let Some(written_bytes) = self.budget.written_bytes() {
+                langfiles.set_written_bytes(written_bytes);
+            }
+            if let Some(registry) = &registry {
+                langfiles.set_registry(registry.clone());
+            }
+            langfiles.set_shards(self.writer_shards);
+            if let Some(filename_template) = &self.filename_template {
+                langfiles.set_template(filename_template.clone());
+            }
+            langfiles.set_max_part_docs(max_part_docs);
+            #[cfg(feature = "object-store")]
+            if let Some(remote) = &remote {
+                langfiles.set_remote(remote.clone());
+            }
+            langfiles
+        };
+
+        let langfiles = new_langfiles(&self.dst);
         #[cfg(feature = "kenlm")]
         let kenlms = if let Some(kenlms_path) = &self.kenlms_path {
             if !kenlms_path.is_dir() {
@@ -464,52 +1878,479 @@ impl Pipeline<()> for OscarDoc {
             panic!("No kenlms path provided but feature turned on!");
         };
 
-        let annotator = {
-            let mut annotator = Annotator::default();
-            annotator
-                .add(Box::new(TinyDocument::default()))
-                .add(Box::new(ShortSentences::default()))
-                .add(Box::new(Header::default()))
-                .add(Box::new(LSH::default()))
-                .add(Box::new(Noisy::default()));
+        let annotator = self.annotator()?;
+
+        let mut dst_rebuild = self.dst.clone();
+        dst_rebuild.push("rebuild");
 
-            // add ut1 blocklists for categories
-            if let Some(path) = &self.blocklist {
-                let bl = MultipleBlocklist::from_dir(&path)?;
-                annotator.add(Box::new(ContentDetector::new(bl)));
+        // rebuild files of a resumed run only keep the records of its written shards
+        let new_rebuild_files = |dst: &Path| match &checkpoint {
+            Some(checkpoint) => RebuildWriters::resume(dst, &checkpoint.shards),
+            None => RebuildWriters::with_dst(dst),
+        };
+        let rebuild_files = new_rebuild_files(&dst_rebuild)?;
+
+        // documents routed out of the main tree, with their own rebuild files.
+        let annotated_files = match &self.annotated_tree {
+            Some(_) => {
+                let dst_annotated = self.dst.join("annotated");
+                if !dst_annotated.exists() {
+                    std::fs::create_dir(&dst_annotated)?;
+                }
+                let dst_annotated_rebuild = dst_annotated.join("rebuild");
+                let annotated_rebuild_files = new_rebuild_files(&dst_annotated_rebuild)?;
+                Some((
+                    new_langfiles(&dst_annotated),
+                    annotated_rebuild_files,
+                    dst_annotated_rebuild,
+                ))
             }
+            None => None,
+        };
 
-            annotator
+        // documents without a confident identification, with their own rebuild files.
+        let unknown_files = if self.identification.keep_unknown {
+            let dst_unknown = self.dst.join("unknown");
+            if !dst_unknown.exists() {
+                std::fs::create_dir(&dst_unknown)?;
+            }
+            let dst_unknown_rebuild = dst_unknown.join("rebuild");
+            let unknown_rebuild_files = new_rebuild_files(&dst_unknown_rebuild)?;
+            Some((
+                new_langfiles(&dst_unknown),
+                unknown_rebuild_files,
+                dst_unknown_rebuild,
+            ))
+        } else {
+            None
         };
 
-        let mut dst_rebuild = self.dst.clone();
-        dst_rebuild.push("rebuild");
+        if self.category_split && self.blocklist.is_none() {
+            warn!("Category split requested without blocklist: no document will be categorized.");
+        }
+        let category_files = self.category_split.then(|| {
+            let mut category_files = CategoryFilesDoc::new(&self.dst.join("categories"));
+            if let Some(open_writers) = &open_writers {
+                category_files.set_open_writers(open_writers.clone());
+            }
+            if let Some(field_mapping) = &field_mapping {
+                category_files.set_field_mapping(field_mapping.clone());
+            }
+            category_files.set_format(self.output_format);
+            category_files.set_compression(self.compression);
+            category_files.set_layout(self.layout);
+            category_files.set_append(self.append);
+            category_files.set_fsync_parts(self.write_policy.fsync_parts());
+            if let Some(written_bytes) = self.budget.written_bytes() {
+                category_files.set_written_bytes(written_bytes);
+            }
+            if let Some(registry) = &registry {
+                category_files.set_registry(registry.clone());
+            }
+            category_files.set_shards(self.writer_shards);
+            if let Some(filename_template) = &self.filename_template {
+                category_files.set_template(filename_template.clone());
+            }
+            category_files.set_part_limits(part_size_bytes, max_part_docs);
+            #[cfg(feature = "object-store")]
+            if let Some(remote) = &remote {
+                category_files.set_remote(remote.clone());
+            }
+            category_files
+        });
 
-        let rebuild_files = RebuildWriters::with_dst(&dst_rebuild)?;
+        // restore writers at their checkpointed positions, and keep taking checkpoints
+        if let Some(checkpoint) = &checkpoint {
+            let positions = |tree: &str| checkpoint.trees.get(tree).cloned().unwrap_or_default();
+            langfiles.restore(&positions("main"))?;
+            if let Some((annotated_langfiles, _, _)) = &annotated_files {
+                annotated_langfiles.restore(&positions("annotated"))?;
+            }
+            if let Some((unknown_langfiles, _, _)) = &unknown_files {
+                unknown_langfiles.restore(&positions("unknown"))?;
+            }
+            if let Some(category_files) = &category_files {
+                category_files.restore(&checkpoint.categories)?;
+            }
+        }
+        let checkpointer = checkpointing.then(|| {
+            let interval = self
+                .checkpoint_interval
+                .unwrap_or(DEFAULT_CHECKPOINT_INTERVAL);
+            info!("Taking checkpoints every {}s", interval.as_secs());
+            Checkpointer::new(
+                &self.dst,
+                interval,
+                checkpoint
+                    .map(|checkpoint| checkpoint.shards)
+                    .unwrap_or_default(),
+            )
+        });
 
-        //iterate over shards
-        let shards_results =
-            results.map(|(idx, shard)| (idx, Self::process_shard(&shard, &cls, None, &annotator)));
+        let dst_stats = self.dst.join("stats");
+        if self.shard_stats && !dst_stats.exists() {
+            std::fs::create_dir(&dst_stats)?;
+        }
 
-        // for each shard result, sort by lang and write concurrently.
-        shards_results.for_each(|(idx, shard_result)| {
-            if let Ok((shard_id, shard_result)) = shard_result {
-                let mut hm = Self::sort_by_lang(shard_result);
+        let repeated_paragraphs = self.repeated_paragraphs.map(RepeatedParagraphs::new);
+        let length_filter =
+            transformers::RemoveShortSentences::with_line_validity(self.line_validity.clone());
+
+        let dedup = self
+            .pre_dedup
+            .then(|| record::RecordDedup::new(self.hash_algorithm));
+
+        let discard_writer = self
+            .discarded
+            .map(|mode| DiscardWriter::new(&self.dst.join("discarded"), mode))
+            .transpose()?;
+
+        let header_retention = self.kept_headers();
+
+        let process = |shard: &ShardInput| {
+            Self::process_shard(
+                shard,
+                cls.as_ref(),
+                Some(record::FilterKind::PFilter(
+                    record::PFilter::with_line_validity(self.line_validity.clone()),
+                )),
+                &length_filter,
+                repeated_paragraphs.as_ref(),
+                &annotator,
+                dedup.as_ref(),
+                self.record_selection
+                    .as_ref()
+                    .map(|(selection, _)| selection),
+                self.domain_allowlist.as_ref(),
+                discard_writer.as_ref(),
+                &header_retention,
+                self.identification,
+                self.max_records,
+            )
+        };
 
-                // run kenlms after identification so that shard results are already
-                // sorted by language.
-                #[cfg(feature = "kenlm")]
-                if let Some(kenlms_path) = &self.kenlms_path {
-                    Self::run_kenlms(&kenlms, kenlms_path, &mut hm);
+        // flush rebuild files (and sync output files) of all languages,
+        // for those that don't get written often enough to be flushed by the write policy
+        let last_flush = Mutex::new(Instant::now());
+        let flush_outputs = || -> Result<(), Error> {
+            let fsync = self.write_policy.fsync();
+            rebuild_files.flush_all(&dst_rebuild, fsync)?;
+            if fsync {
+                langfiles.sync_all()?;
+            }
+            for (tree_langfiles, tree_rebuild, dst) in
+                annotated_files.iter().chain(unknown_files.iter())
+            {
+                tree_rebuild.flush_all(dst, fsync)?;
+                if fsync {
+                    tree_langfiles.sync_all()?;
                 }
+            }
+            Ok(())
+        };
 
-                Self::write_documents(&langfiles, &rebuild_files, &dst_rebuild, shard_id, hm)
-                    .unwrap();
-            } else {
-                error!("Error with shard idx {}:{:?}", idx, shard_result);
+        // flush outputs and get the positions of the writers of all output trees, for checkpoints
+        let positions = || -> Result<_, Error> {
+            flush_outputs()?;
+            let mut trees = BTreeMap::new();
+            trees.insert("main".to_string(), langfiles.positions()?);
+            if let Some((annotated_langfiles, _, _)) = &annotated_files {
+                trees.insert("annotated".to_string(), annotated_langfiles.positions()?);
+            }
+            if let Some((unknown_langfiles, _, _)) = &unknown_files {
+                trees.insert("unknown".to_string(), unknown_langfiles.positions()?);
+            }
+            let categories = match &category_files {
+                Some(category_files) => category_files.positions()?,
+                None => BTreeMap::new(),
+            };
+            Ok((trees, categories))
+        };
+
+        // sort by lang and write concurrently.
+        let write = |(shard_id, shard_result, mut stats): ProcessedShard| {
+            let (unknown, shard_result): (Vec<_>, Vec<_>) = shard_result
+                .into_iter()
+                .partition(|(doc, _)| Self::is_unknown(doc));
+            let mut hm = Self::sort_by_lang(shard_result);
+
+            // drop unselected languages first, so that they're not further processed
+            let mut dropped = BTreeMap::new();
+            if !self.language_selection.is_empty() {
+                let nb_dropped = Self::apply_language_selection(
+                    &self.language_selection,
+                    &mut hm,
+                    discard_writer.as_ref(),
+                    shard_id,
+                );
+                if nb_dropped > 0 {
+                    dropped.insert("language".to_string(), nb_dropped);
+                }
+            }
+
+            // run kenlms after identification so that shard results are already
+            // sorted by language.
+            #[cfg(feature = "kenlm")]
+            if let Some(kenlms_path) = &self.kenlms_path {
+                Self::run_kenlms(&kenlms, kenlms_path, &mut hm);
+            }
+
+            // apply policy once all annotations are done
+            if !self.annotation_policy.is_empty() {
+                dropped.extend(Self::apply_annotation_policy(
+                    &self.annotation_policy,
+                    &mut hm,
+                    discard_writer.as_ref(),
+                    shard_id,
+                ));
+            }
+            stats.set_dropped(dropped);
+            stats.set_languages(&hm);
+
+            // no checkpoint is taken while the shard is written
+            let writing = checkpointer.as_ref().map(Checkpointer::writing);
+            let start = Instant::now();
+            let mut write_errors = match &category_files {
+                Some(category_files) => Self::write_categories(category_files, &hm),
+                None => Vec::new(),
+            };
+            if let (Some(selector), Some((annotated_langfiles, annotated_rebuild, dst))) =
+                (&self.annotated_tree, &annotated_files)
+            {
+                let annotated = Self::split_annotated(selector, &mut hm);
+                write_errors.extend(
+                    Self::write_documents(
+                        annotated_langfiles,
+                        annotated_rebuild,
+                        dst,
+                        shard_id,
+                        annotated,
+                        &self.write_policy,
+                    )
+                    .unwrap(),
+                );
+            }
+            if let Some((unknown_langfiles, unknown_rebuild, dst)) = &unknown_files {
+                write_errors.extend(
+                    Self::write_documents(
+                        unknown_langfiles,
+                        unknown_rebuild,
+                        dst,
+                        shard_id,
+                        Self::sort_by_lang(unknown),
+                        &self.write_policy,
+                    )
+                    .unwrap(),
+                );
+            }
+            if let Some(text_meta_files) = &text_meta_files {
+                write_errors.extend(Self::write_text_meta(text_meta_files, &hm));
+            }
+            write_errors.extend(
+                Self::write_documents(
+                    &langfiles,
+                    &rebuild_files,
+                    &dst_rebuild,
+                    shard_id,
+                    hm,
+                    &self.write_policy,
+                )
+                .unwrap(),
+            );
+            stats.set_writing(write_errors.len(), start.elapsed());
+            if let Some(checkpointer) = checkpointer.as_ref().filter(|_| write_errors.is_empty()) {
+                checkpointer.done(shard_id);
+            }
+            drop(writing);
+            self.progress.add_shard(&stats);
+            for e in write_errors {
+                self.progress
+                    .add_error(format!("shard {}: write error: {:?}", shard_id, e));
+            }
+
+            if self.write_policy.interval_elapsed(&last_flush) {
+                debug!("Flush interval elapsed, flushing all output files");
+                if let Err(e) = flush_outputs() {
+                    error!("Could not flush output files: {:?}", e);
+                }
+            }
+
+            if let Some(checkpointer) = &checkpointer {
+                if let Err(e) = checkpointer.save_if_due(positions, self.write_policy.fsync()) {
+                    error!("Could not write checkpoint: {:?}", e);
+                }
+            }
+
+            if self.shard_stats {
+                if let Err(e) = stats.write_to(&dst_stats) {
+                    error!("Could not write stats for shard {}: {:?}", shard_id, e);
+                }
+            }
+
+            if let Some(discard_writer) = &discard_writer {
+                if let Err(e) = discard_writer.flush() {
+                    error!("Could not flush discarded records: {:?}", e);
+                }
+            }
+        };
+
+        let fail = |idx: usize, e: Error| {
+            self.progress.add_failed_shard();
+            self.progress
+                .add_error(format!("shard idx {}: {:?}", idx, e));
+            error!("Error with shard idx {}:{:?}", idx, e);
+        };
+
+        let notify = || {
+            if let Some(webhooks) = &self.webhooks {
+                webhooks.on_shard(&self.progress.snapshot());
+            }
+        };
+
+        // shards that failed during the main pass
+        let failed = Mutex::new(Vec::new());
+        // shards that were not processed because the budget is exhausted
+        let remaining = Mutex::new(Vec::new());
+
+        //iterate over shards
+        results.for_each(|(idx, shard)| {
+            if self.budget.exhausted().is_some() {
+                remaining.lock().unwrap().push(shard);
+                return;
+            }
+            match process(&shard) {
+                Ok(processed) => {
+                    write(processed);
+                    notify();
+                }
+                Err(e) if self.retry_failed => {
+                    warn!(
+                        "Error with shard idx {}:{:?}, retrying at the end of the run",
+                        idx, e
+                    );
+                    failed.lock().unwrap().push((idx, shard));
+                }
+                Err(e) => {
+                    fail(idx, e);
+                    notify();
+                }
             }
         });
 
+        // retry failed shards once, one at a time.
+        let failed = failed.into_inner().unwrap();
+        if !failed.is_empty() {
+            info!("Retrying {} failed shards", failed.len());
+        }
+        for (idx, shard) in failed {
+            if self.budget.exhausted().is_some() {
+                remaining.lock().unwrap().push(shard);
+                continue;
+            }
+            match process(&shard) {
+                Ok(processed) => {
+                    info!("Shard idx {} succeeded on retry", idx);
+                    write(processed);
+                }
+                Err(e) => fail(idx, e),
+            }
+            notify();
+        }
+
+        // finish output files (Parquet files are only readable once closed)
+        // and flush what's left in rebuild files
+        let fsync = self.write_policy.fsync();
+        // the last checkpoint is kept, so that shards that failed or weren't processed can be processed by resuming it,
+        // and so that a crash while finishing parts doesn't process shards again
+        if let Some(checkpointer) = &checkpointer {
+            checkpointer.save(positions, fsync)?;
+        }
+        langfiles.close_all()?;
+        rebuild_files.flush_all(&dst_rebuild, fsync)?;
+        if fsync {
+            langfiles.sync_all()?;
+        }
+        if let Some((annotated_langfiles, annotated_rebuild, dst)) = &annotated_files {
+            annotated_langfiles.close_all()?;
+            annotated_rebuild.flush_all(dst, fsync)?;
+            if fsync {
+                annotated_langfiles.sync_all()?;
+            }
+        }
+        if let Some((unknown_langfiles, unknown_rebuild, dst)) = &unknown_files {
+            unknown_langfiles.close_all()?;
+            unknown_rebuild.flush_all(dst, fsync)?;
+            if fsync {
+                unknown_langfiles.sync_all()?;
+            }
+        }
+        if let Some(category_files) = &category_files {
+            category_files.close_all()?;
+        }
+
+        // per-language documents, bytes and parts written in each output tree
+        let mut writer_stats = serde_json::Map::new();
+        writer_stats.insert("main".to_string(), serde_json::to_value(langfiles.stats())?);
+        if let Some((annotated_langfiles, _, _)) = &annotated_files {
+            writer_stats.insert(
+                "annotated".to_string(),
+                serde_json::to_value(annotated_langfiles.stats())?,
+            );
+        }
+        if let Some((unknown_langfiles, _, _)) = &unknown_files {
+            writer_stats.insert(
+                "unknown".to_string(),
+                serde_json::to_value(unknown_langfiles.stats())?,
+            );
+        }
+        if let Some(category_files) = &category_files {
+            writer_stats.insert(
+                "categories".to_string(),
+                serde_json::to_value(category_files.stats())?,
+            );
+        }
+        let writer_stats_path = self.dst.join("writer_stats.json");
+        serde_json::to_writer_pretty(File::create(&writer_stats_path)?, &writer_stats)?;
+        info!("Wrote writer statistics to {:?}", writer_stats_path);
+
+        let mut remaining = remaining.into_inner().unwrap();
+        if !remaining.is_empty() {
+            let reason = self.budget.exhausted().unwrap_or_default();
+            remaining.sort();
+            let remaining_path = self.dst.join("remaining_shards.txt");
+            let mut remaining_file = File::create(&remaining_path)?;
+            for shard in &remaining {
+                writeln!(remaining_file, "{}", shard)?;
+            }
+            let snapshot = self.progress.snapshot();
+            warn!(
+                "Run stopped early ({}): {} shards processed, {} documents written, {} shards left (see {:?})",
+                reason,
+                snapshot.shards_done,
+                snapshot.documents,
+                remaining.len(),
+                remaining_path
+            );
+        }
+
+        if let Some(dedup) = &dedup {
+            info!(
+                "Pre-classification dedup: {} digests/URIs seen",
+                dedup.nb_seen()
+            );
+        }
+
+        let dropped = self.progress.snapshot().dropped;
+        if !dropped.is_empty() {
+            info!("Dropped documents: {:?}", dropped);
+        }
+        Self::log_cache_stats(cls.as_ref());
+
+        self.progress.finish();
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.on_completion(&self.progress.snapshot());
+        }
+
         Ok(())
     }
 }
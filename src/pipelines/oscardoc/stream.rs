@@ -0,0 +1,102 @@
+/*! Document streams
+
+Iterator over the documents produced by a pipeline (see [OscarDoc::stream]),
+for applications embedding ungoliant and storing documents themselves.
+
+The pipeline runs in a background thread, shard by shard (records of a shard being processed in parallel),
+and sends finished documents through a bounded channel: it waits when the caller doesn't keep up,
+and stops once the stream is dropped.
+
+Shards that can't be processed yield an error, and the stream goes on with the next ones.
+
+```no_run
+use std::path::PathBuf;
+use ungoliant::pipelines::OscarDocNew as OscarDoc;
+
+let pipeline = OscarDoc::new(
+    PathBuf::from("shards/"),
+    PathBuf::from("unused/"),
+    PathBuf::from("lid.176.bin"),
+    None,
+    None,
+);
+for doc in pipeline.stream(1024) {
+    match doc {
+        Ok(doc) => println!("{}: {}", doc.identification().label(), doc.warc_id()),
+        Err(e) => eprintln!("{:?}", e),
+    }
+}
+```
+!*/
+use std::sync::mpsc::{Receiver, SyncSender};
+
+use oscar_io::v3::Document;
+
+use crate::error::Error;
+#[cfg(doc)]
+use crate::pipelines::oscardoc::OscarDoc;
+
+/// Sending end of a [DocumentStream].
+pub(crate) type DocumentSender = SyncSender<Result<Document, Error>>;
+
+/// Documents produced by a pipeline.
+pub struct DocumentStream {
+    receiver: Receiver<Result<Document, Error>>,
+}
+
+impl DocumentStream {
+    /// Create a stream keeping at most `buffer` documents waiting to be consumed.
+    pub(crate) fn with_buffer(buffer: usize) -> (DocumentSender, Self) {
+        let (sender, receiver) = std::sync::mpsc::sync_channel(buffer);
+        (sender, Self { receiver })
+    }
+}
+
+impl Iterator for DocumentStream {
+    type Item = Result<Document, Error>;
+
+    /// Wait for the next document. Returns [None] once the pipeline is done.
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oscar_io::v3::{Document, Metadata};
+
+    use crate::error::Error;
+
+    use super::DocumentStream;
+
+    #[test]
+    fn test_stream() {
+        let (sender, stream) = DocumentStream::with_buffer(1);
+        let handle = std::thread::spawn(move || {
+            for content in ["foo", "bar"] {
+                let doc = Document::new(content.to_string(), HashMap::new(), Metadata::default());
+                sender.send(Ok(doc)).unwrap();
+            }
+            sender
+                .send(Err(Error::Custom("shard error".to_string())))
+                .unwrap();
+        });
+
+        let items: Vec<_> = stream.collect();
+        handle.join().unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].as_ref().unwrap().content(), "foo");
+        assert_eq!(items[1].as_ref().unwrap().content(), "bar");
+        assert!(items[2].is_err());
+    }
+
+    #[test]
+    fn test_dropped_stream() {
+        let (sender, stream) = DocumentStream::with_buffer(1);
+        drop(stream);
+        let doc = Document::new("foo".to_string(), HashMap::new(), Metadata::default());
+        assert!(sender.send(Ok(doc)).is_err());
+    }
+}
@@ -1,4 +1,7 @@
-use std::convert::{TryFrom, TryInto};
+use std::{
+    convert::{TryFrom, TryInto},
+    ops::RangeInclusive,
+};
 
 use serde::{Deserialize, Serialize};
 
@@ -33,6 +36,7 @@ pub struct LocationBuilder {
     line_start: Option<usize>,
     line_end: Option<usize>,
     loc_in_shard: Option<usize>,
+    kept_ranges: Option<Vec<RangeInclusive<usize>>>,
 }
 
 impl<'a> LocationBuilder {
@@ -61,6 +65,13 @@ impl<'a> LocationBuilder {
         self.loc_in_shard = Some(loc_in_shard);
     }
 
+    /// Only keep some ranges of lines, relative to line start (e.g. when lines are removed from a document).
+    ///
+    /// Lines between line start and line end that aren't in `kept_ranges` become removed lines of the location.
+    pub fn set_kept_ranges(&mut self, kept_ranges: Vec<RangeInclusive<usize>>) {
+        self.kept_ranges = Some(kept_ranges);
+    }
+
     /// Builds the location.
     ///
     /// Errors if a field is missing
@@ -77,6 +88,7 @@ impl<'a> Default for LocationBuilder {
             line_start: None,
             line_end: None,
             loc_in_shard: None,
+            kept_ranges: None,
         }
     }
 }
@@ -103,12 +115,24 @@ impl<'a> TryFrom<LocationBuilder> for Location {
             missing: LocationKind::LocInShard,
         })?;
 
+        let removed_lines = match value.kept_ranges {
+            Some(kept_ranges) => (line_start..=line_end)
+                .filter(|line| {
+                    !kept_ranges
+                        .iter()
+                        .any(|range| range.contains(&(line - line_start)))
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
         Ok(Location {
             shard_id,
             record_id,
             line_start,
             line_end,
             loc_in_shard,
+            removed_lines,
         })
     }
 }
@@ -117,6 +141,7 @@ impl<'a> TryFrom<LocationBuilder> for Location {
 /// - record_id is the record id :)
 /// - line_start/line_end are the boundaries of kept text (inclusive)
 /// - loc_in_shard is the record index _in_ shard.
+/// - removed_lines are the lines between line_start and line_end that are not kept (usually none).
 ///
 /// # Example
 /// If we're working on the 10th record of a shard that is shard 100,
@@ -129,6 +154,8 @@ pub struct Location {
     line_start: usize,
     line_end: usize,
     loc_in_shard: usize,
+    #[serde(default)]
+    removed_lines: Vec<usize>,
 }
 
 impl Location {
@@ -148,9 +175,15 @@ impl Location {
             line_start,
             line_end,
             loc_in_shard,
+            removed_lines: Vec::new(),
         }
     }
 
+    /// Set the location's removed lines.
+    pub fn set_removed_lines(&mut self, removed_lines: Vec<usize>) {
+        self.removed_lines = removed_lines;
+    }
+
     /// Get a reference to the location's shard id.
     pub fn shard_id(&self) -> usize {
         self.shard_id
@@ -175,6 +208,11 @@ impl Location {
     pub fn loc_in_shard(&self) -> usize {
         self.loc_in_shard
     }
+
+    /// Get a reference to the location's removed lines.
+    pub fn removed_lines(&self) -> &[usize] {
+        &self.removed_lines
+    }
 }
 
 impl Default for Location {
@@ -185,6 +223,7 @@ impl Default for Location {
             line_start: Default::default(),
             line_end: Default::default(),
             loc_in_shard: Default::default(),
+            removed_lines: Default::default(),
         }
     }
 }
@@ -220,4 +259,18 @@ mod tests {
 
         assert_eq!(location, loc_built);
     }
+
+    #[test]
+    fn location_build_kept_ranges() {
+        let mut lb = LocationBuilder::default();
+        lb.set_record_id("record_id".to_string());
+        lb.set_line_start(2);
+        lb.set_line_end(8);
+        lb.set_loc_in_shard(1);
+        lb.set_shard_id(4);
+        lb.set_kept_ranges(vec![0..=1, 3..=3, 5..=6]);
+        let loc_built = lb.build().unwrap();
+
+        assert_eq!(loc_built.removed_lines(), &[4, 6]);
+    }
 }
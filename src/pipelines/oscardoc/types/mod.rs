@@ -4,6 +4,7 @@
 // mod document;
 mod location;
 mod rebuild;
+mod stats;
 
 // pub use document::Document;
 // pub use document::Metadata;
@@ -13,3 +14,4 @@ pub use oscar_io::v3::Metadata;
 pub use rebuild::RebuildInformation;
 pub use rebuild::RebuildWriters;
 pub use rebuild::ShardResult;
+pub use stats::ShardStats;
@@ -11,18 +11,21 @@ Those slim documents contain:
 - record id,
 - line start/end for each WARC Record. Note that `line_start and line_end` are _included_,
 so a document that has `(line_start, line_end) == (10, 10)` has a single line that is at offset 10.
+- lines between line start/end that were removed from the document, if any (e.g. repeated paragraphs),
+  which rebuild files written before they were recorded don't have.
 
 !*/
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
     fs::File,
+    io::BufReader,
     path::{Path, PathBuf},
     sync::{Arc, Mutex, RwLock},
 };
 
 use avro_rs::{AvroResult, Codec, Schema, Writer};
-use log::error;
+use log::{error, info, warn};
 use oxilangtag::LanguageTag;
 use serde::Deserialize;
 use serde::Serialize;
@@ -71,7 +74,8 @@ lazy_static! {
     {"name": "line_start", "type":"long"},
     {"name": "line_end", "type":"long"},
     {"name": "loc_in_shard", "type":"long"},
-    {"name":"metadata", "type":"metadata_record"}
+    {"name":"metadata", "type":"metadata_record"},
+    {"name": "removed_lines", "type":["null", {"type": "array", "items":"long"}], "default": null}
   ]
 }
 "#;
@@ -109,6 +113,8 @@ pub struct RebuildInformation {
     line_end: usize,
     loc_in_shard: usize,
     metadata: Metadata,
+    #[serde(default)]
+    removed_lines: Option<Vec<usize>>,
 }
 
 impl RebuildInformation {
@@ -121,21 +127,22 @@ impl RebuildInformation {
             line_end: location.line_end(),
             loc_in_shard: location.loc_in_shard(),
             metadata,
+            removed_lines: (!location.removed_lines().is_empty())
+                .then(|| location.removed_lines().to_vec()),
         }
     }
 
     /// Convert into a ([Location], [Metadata]) tuple.
     pub fn into_raw_parts(self) -> (Location, Metadata) {
-        (
-            Location::new(
-                self.shard_id,
-                self.record_id,
-                self.line_start,
-                self.line_end,
-                self.loc_in_shard,
-            ),
-            self.metadata,
-        )
+        let mut location = Location::new(
+            self.shard_id,
+            self.record_id,
+            self.line_start,
+            self.line_end,
+            self.loc_in_shard,
+        );
+        location.set_removed_lines(self.removed_lines.unwrap_or_default());
+        (location, self.metadata)
     }
     /// Get a reference to the rebuild information's loc in shard.
     pub fn loc_in_shard(&self) -> usize {
@@ -157,6 +164,11 @@ impl RebuildInformation {
         self.line_end
     }
 
+    /// Get the lines between line start and line end that were removed from the document.
+    pub fn removed_lines(&self) -> &[usize] {
+        self.removed_lines.as_deref().unwrap_or_default()
+    }
+
     /// Get a reference to the rebuild information's metadata.
     pub fn metadata(&self) -> &Metadata {
         &self.metadata
@@ -215,6 +227,7 @@ impl ShardResult {
 pub struct RebuildWriter<'a, T> {
     schema: &'a Schema,
     writer: Writer<'a, T>,
+    nb_appends: usize,
 }
 
 impl<'a, T: std::io::Write> RebuildWriter<'a, T> {
@@ -223,6 +236,7 @@ impl<'a, T: std::io::Write> RebuildWriter<'a, T> {
         Self {
             schema,
             writer: Writer::with_codec(schema, writer, Codec::Snappy),
+            nb_appends: 0,
         }
     }
 
@@ -231,9 +245,15 @@ impl<'a, T: std::io::Write> RebuildWriter<'a, T> {
     /// This function is not guaranteed to perform a write operation
     /// See documentation of [avro_rs::Writer] for more information.
     pub fn append_ser<S: Serialize>(&mut self, value: S) -> AvroResult<usize> {
+        self.nb_appends += 1;
         self.writer.append_ser(value)
     }
 
+    /// Get the number of [Self::append_ser] calls.
+    pub fn nb_appends(&self) -> usize {
+        self.nb_appends
+    }
+
     /// Append from an interator of values, each implementing [Serialize].
     ///
     /// This function is not guaranteed to perform a write operation
@@ -292,6 +312,23 @@ impl<'a> RebuildWriters<'a, File> {
         p
     }
 
+    /// Sync the avro file of a language to disk.
+    pub fn sync(root_dir: &Path, k: &LanguageTag<String>) -> Result<(), Error> {
+        crate::io::policy::sync_path(&Self::forge_dst(root_dir, k))?;
+        Ok(())
+    }
+
+    /// Flush all writers, syncing their files to disk if `fsync` is set.
+    pub fn flush_all(&'a self, root_dir: &Path, fsync: bool) -> Result<(), Error> {
+        for (lang, writer) in self.writers().iter() {
+            writer.lock().unwrap().flush()?;
+            if fsync {
+                Self::sync(root_dir, lang)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn insert(&'a self, root_dir: &Path, k: &LanguageTag<String>) -> Result<(), Error> {
         let mut wlock = self.inner.write().unwrap();
         let (lang, new_writer) = Self::new_writer_mutex(root_dir, k.clone())?;
@@ -331,13 +368,70 @@ impl<'a> RebuildWriters<'a, File> {
             inner: Arc::new(RwLock::new(HashMap::new())),
         })
     }
+
+    /// Use `dst` as a root path for avro files storage, keeping the records of `shards` written by a previous run.
+    ///
+    /// Avro files can't be appended to, so existing `<dst>/<lang>.avro` files are rewritten with these records only,
+    /// dropping the ones of shards that are processed again. They're moved to `<dst>/<lang>.avro.prev` while being rewritten,
+    /// so that an interrupted rewrite is started over. A truncated block at the end of a crashed file is ignored.
+    pub fn resume(dst: &Path, shards: &BTreeSet<usize>) -> Result<Self, Error> {
+        std::fs::create_dir_all(dst)?;
+        let mut langs = BTreeSet::new();
+        for entry in std::fs::read_dir(dst)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if let Some(lang) = name
+                .strip_suffix(".avro.prev")
+                .or_else(|| name.strip_suffix(".avro"))
+            {
+                langs.insert(lang.to_string());
+            }
+        }
+
+        let mut writers = HashMap::new();
+        for lang in langs {
+            let lang = LanguageTag::parse(lang)?;
+            let path = Self::forge_dst(dst, &lang);
+            let previous = path.with_extension("avro.prev");
+            if !previous.exists() {
+                std::fs::rename(&path, &previous)?;
+            }
+
+            let mut writer = RebuildWriter::from_path(&path)?;
+            let mut nb_kept = 0;
+            // files are empty until their first flush
+            if let Ok(reader) = avro_rs::Reader::new(BufReader::new(File::open(&previous)?)) {
+                for value in reader {
+                    let shard_result: ShardResult =
+                        match value.and_then(|value| avro_rs::from_value(&value)) {
+                            Ok(shard_result) => shard_result,
+                            Err(e) => {
+                                warn!("Ignoring the end of {:?}: {}", previous, e);
+                                break;
+                            }
+                        };
+                    if shards.contains(&(shard_result.shard_id() as usize)) {
+                        writer.append_ser(shard_result)?;
+                        nb_kept += 1;
+                    }
+                }
+            }
+            writer.flush()?;
+            std::fs::remove_file(&previous)?;
+            info!("Kept {} shards in {:?}", nb_kept, path);
+            writers.insert(lang, Arc::new(Mutex::new(writer)));
+        }
+
+        Ok(RebuildWriters {
+            inner: Arc::new(RwLock::new(writers)),
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
     use std::{
-        collections::HashMap,
+        collections::{BTreeSet, HashMap},
         fs::File,
         sync::{Arc, RwLock},
     };
@@ -434,8 +528,10 @@ mod tests {
 
     #[test]
     fn test_ser() {
-        let meta = vec![Metadata::default()];
-        let loc = vec![Location::default()];
+        let mut removed = Location::new(0, "record".to_string(), 2, 8, 1);
+        removed.set_removed_lines(vec![4, 6]);
+        let meta = vec![Metadata::default(); 2];
+        let loc = vec![Location::default(), removed];
         let sr = ShardResult::new(0, loc, meta);
         println!("{:#?}", sr);
         println!("{:#?}", *super::SCHEMA);
@@ -451,6 +547,7 @@ mod tests {
             .collect();
         assert_eq!(result.len(), 1);
         assert_eq!(result[0], sr);
+        assert_eq!(result[0].rebuild_info()[1].removed_lines(), &[4, 6]);
     }
 
     #[test]
@@ -478,4 +575,45 @@ mod tests {
         rbw.insert(dir.path(), &lang).unwrap();
         assert!(rbw.contains(&lang));
     }
+
+    #[test]
+    fn test_rebuild_writers_resume() {
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let rbw = RebuildWriters::with_dst(dir.path()).unwrap();
+            rbw.insert(dir.path(), &lang).unwrap();
+            let writers = rbw.writers();
+            let mut writer = writers.get(&lang).unwrap().lock().unwrap();
+            for shard_id in 0..3 {
+                writer
+                    .append_ser(ShardResult::new(shard_id, Vec::new(), Vec::new()))
+                    .unwrap();
+            }
+            writer.flush().unwrap();
+        }
+
+        // shard 1 was processed after the checkpoint
+        let rbw = RebuildWriters::resume(dir.path(), &BTreeSet::from([0, 2])).unwrap();
+        assert!(rbw.contains(&lang));
+        {
+            let writers = rbw.writers();
+            let mut writer = writers.get(&lang).unwrap().lock().unwrap();
+            writer
+                .append_ser(ShardResult::new(1, Vec::new(), Vec::new()))
+                .unwrap();
+            writer.flush().unwrap();
+        }
+
+        let reader = avro_rs::Reader::new(File::open(dir.path().join("fr.avro")).unwrap()).unwrap();
+        let shard_ids: Vec<i64> = reader
+            .map(|r| {
+                avro_rs::from_value::<ShardResult>(&r.unwrap())
+                    .unwrap()
+                    .shard_id()
+            })
+            .collect();
+        assert_eq!(shard_ids, vec![0, 2, 1]);
+        assert!(!dir.path().join("fr.avro.prev").exists());
+    }
 }
@@ -0,0 +1,190 @@
+/*! Per-shard statistics
+
+Small JSON sidecar files (one per shard, `<dst>/stats/<shard_id>.json`) holding counts and timings
+of a shard's processing, meant to be consumed by monitoring/aggregation tools.
+
+```json
+{
+  "shard_id": 42,
+  "records": 31250,
+  "kept": 7302,
+  "duplicates": 412,
+  "unknown": 0,
+  "read_errors": 0,
+  "identification_errors": 0,
+  "write_errors": 0,
+  "unknown_labels": {"__label__xyz": 3},
+  "dropped": {"noisy": 120},
+  "languages": {"en": 5120, "fr": 311},
+  "processing_secs": 61.2,
+  "writing_secs": 0.8
+}
+```
+!*/
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    path::Path,
+    time::Duration,
+};
+
+use oxilangtag::LanguageTag;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShardStats {
+    shard_id: usize,
+    records: usize,
+    kept: usize,
+    duplicates: usize,
+    unknown: usize,
+    read_errors: usize,
+    identification_errors: usize,
+    write_errors: usize,
+    unknown_labels: BTreeMap<String, usize>,
+    dropped: BTreeMap<String, usize>,
+    languages: BTreeMap<String, usize>,
+    processing_secs: f64,
+    writing_secs: f64,
+}
+
+impl ShardStats {
+    pub fn new(shard_id: usize) -> Self {
+        Self {
+            shard_id,
+            ..Default::default()
+        }
+    }
+
+    /// Set the number of records read, kept and the number of errors encountered while processing the shard.
+    pub fn set_processing(
+        &mut self,
+        records: usize,
+        kept: usize,
+        read_errors: usize,
+        identification_errors: usize,
+        duration: Duration,
+    ) {
+        self.records = records;
+        self.kept = kept;
+        self.read_errors = read_errors;
+        self.identification_errors = identification_errors;
+        self.processing_secs = duration.as_secs_f64();
+    }
+
+    /// Set the number of records skipped because their digest/URI was already seen.
+    pub fn set_duplicates(&mut self, duplicates: usize) {
+        self.duplicates = duplicates;
+    }
+
+    /// Set the number of documents without a confident identification, written in the unknown sink.
+    pub fn set_unknown(&mut self, unknown: usize) {
+        self.unknown = unknown;
+    }
+
+    /// Set the number of occurrences of each classifier label that couldn't be converted to a language tag.
+    ///
+    /// Documents with such labels are discarded.
+    pub fn set_unknown_labels(&mut self, unknown_labels: BTreeMap<String, usize>) {
+        self.unknown_labels = unknown_labels;
+    }
+
+    /// Set the number of documents dropped by the annotation policy, per annotation type
+    /// (and by the language selection, as `language`).
+    ///
+    /// Dropped documents are removed from the kept ones.
+    pub fn set_dropped(&mut self, dropped: BTreeMap<String, usize>) {
+        self.kept -= dropped.values().sum::<usize>();
+        self.dropped = dropped;
+    }
+
+    /// Set per-language document counts from documents sorted by language.
+    pub fn set_languages<T>(&mut self, documents: &HashMap<LanguageTag<String>, Vec<T>>) {
+        self.languages = documents
+            .iter()
+            .map(|(lang, docs)| (lang.to_string(), docs.len()))
+            .collect();
+    }
+
+    /// Set the number of languages that couldn't be written and the writing time.
+    pub fn set_writing(&mut self, write_errors: usize, duration: Duration) {
+        self.write_errors = write_errors;
+        self.writing_secs = duration.as_secs_f64();
+    }
+
+    /// Write stats in `<dir>/<shard_id>.json`, overwriting any existing file.
+    pub fn write_to(&self, dir: &Path) -> Result<(), Error> {
+        let path = dir.join(format!("{}.json", self.shard_id));
+        let f = File::create(path)?;
+        serde_json::to_writer(f, self)?;
+        Ok(())
+    }
+
+    /// Get the number of records read.
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    /// Get the number of kept documents.
+    pub fn kept(&self) -> usize {
+        self.kept
+    }
+
+    /// Get the total number of errors (read, identification and write).
+    pub fn errors(&self) -> usize {
+        self.read_errors + self.identification_errors + self.write_errors
+    }
+
+    /// Get unknown label counts.
+    pub fn unknown_labels(&self) -> &BTreeMap<String, usize> {
+        &self.unknown_labels
+    }
+
+    /// Get dropped document counts.
+    pub fn dropped(&self) -> &BTreeMap<String, usize> {
+        &self.dropped
+    }
+
+    /// Get per-language document counts.
+    pub fn languages(&self) -> &BTreeMap<String, usize> {
+        &self.languages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use oxilangtag::LanguageTag;
+
+    use super::ShardStats;
+
+    #[test]
+    fn test_write_read() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut stats = ShardStats::new(42);
+        stats.set_processing(100, 12, 1, 2, Duration::from_secs(3));
+        stats.set_unknown(4);
+        stats.set_dropped([("noisy".to_string(), 2)].into_iter().collect());
+
+        let mut docs = HashMap::new();
+        docs.insert(LanguageTag::parse("en".to_string()).unwrap(), vec![(); 7]);
+        docs.insert(LanguageTag::parse("fr".to_string()).unwrap(), vec![(); 3]);
+        stats.set_languages(&docs);
+        stats.set_writing(0, Duration::from_millis(500));
+
+        stats.write_to(dir.path()).unwrap();
+
+        let f = std::fs::File::open(dir.path().join("42.json")).unwrap();
+        let read: ShardStats = serde_json::from_reader(f).unwrap();
+
+        assert_eq!(read, stats);
+        assert_eq!(read.languages.get("en"), Some(&7));
+        assert_eq!(read.kept(), 10);
+        assert_eq!(read.dropped().get("noisy"), Some(&2));
+        assert_eq!(read.writing_secs, 0.5);
+    }
+}
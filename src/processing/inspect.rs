@@ -0,0 +1,245 @@
+/*! Corpus inspection
+
+Pretty-prints documents of a given language of a corpus, for quick human QA.
+
+Either the first documents or a random sample of them are printed,
+with their main metadata (URI, date, identification, annotations, categories) and their truncated content.
+Random samples are drawn uniformly (using reservoir sampling) with a fixed seed, so that they are reproducible.
+
+Metadata is colored using ANSI escape codes, unless disabled or when not writing to a terminal.
+!*/
+use std::{io::Write, path::Path};
+
+use log::warn;
+use oscar_io::v3::{Document, Reader as DocReader};
+use oxilangtag::LanguageTag;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use warc::WarcHeader;
+
+use crate::{
+    error::Error,
+    processing::merge::{file_lang, sort_parts},
+};
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const CYAN: &str = "\x1b[36m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// Document formatting options.
+#[derive(Debug, Clone, Copy)]
+pub struct Style {
+    /// maximum number of content characters
+    pub max_chars: usize,
+    pub color: bool,
+}
+
+impl Style {
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.color {
+            format!("{code}{text}{RESET}")
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn field(&self, name: &str, value: &str) -> String {
+        format!("{} {}\n", self.paint(CYAN, &format!("{name}:")), value)
+    }
+
+    /// Format a document.
+    pub fn format(&self, doc: &Document) -> String {
+        let mut out = self.paint(BOLD, &format!("── {} ──", doc.warc_id()));
+        out.push('\n');
+
+        if let Some(uri) = doc.url() {
+            out += &self.field("uri", &uri);
+        }
+        if let Some(date) = doc.warc_headers().get(&WarcHeader::Date) {
+            out += &self.field("date", &String::from_utf8_lossy(date));
+        }
+        let id = doc.identification();
+        out += &self.field("lang", &format!("{} ({:.2})", id.label(), id.prob()));
+        if let Some(annotations) = doc.metadata().annotation() {
+            out += &self.field("annotations", &self.paint(YELLOW, &annotations.join(", ")));
+        }
+        if let Some(categories) = doc.metadata().categories() {
+            out += &self.field("categories", &categories.join(", "));
+        }
+
+        let content = doc.content();
+        let nb_chars = content.chars().count();
+        out += &self.field("content", &format!("{nb_chars} chars"));
+        match content.char_indices().nth(self.max_chars) {
+            Some((end, _)) => {
+                out += &content[..end];
+                out += &self.paint(
+                    DIM,
+                    &format!("… [{} more chars]", nb_chars - self.max_chars),
+                );
+            }
+            None => out += content,
+        }
+        out.push('\n');
+
+        out
+    }
+}
+
+/// Select `n` documents of `lang` in the corpus in `src`.
+///
+/// If `seed` is set, documents are sampled uniformly. Otherwise, the first ones are taken.
+pub fn select(
+    src: &Path,
+    lang: &LanguageTag<String>,
+    n: usize,
+    seed: Option<u64>,
+) -> Result<Vec<Document>, Error> {
+    // sort to keep part order
+    let mut paths: Vec<_> = std::fs::read_dir(src)?
+        .map(|entry| entry.map(|e| e.path()))
+        .filter(|path| match path {
+            Ok(path) => file_lang(path).as_ref() == Some(lang),
+            Err(_) => true,
+        })
+        .collect::<Result<_, _>>()?;
+    sort_parts(&mut paths);
+
+    if paths.is_empty() {
+        warn!("no {} files found in {:?}", lang, src);
+    }
+
+    let mut rng = seed.map(StdRng::seed_from_u64);
+    let mut selected = Vec::with_capacity(n);
+    let mut nb_seen = 0;
+    for path in paths {
+        for doc in DocReader::from_path(&path)? {
+            let doc = doc?;
+            nb_seen += 1;
+            if selected.len() < n {
+                selected.push(doc);
+                continue;
+            }
+            match &mut rng {
+                // replace a selected document with probability n/nb_seen
+                Some(rng) => {
+                    let idx = rng.gen_range(0..nb_seen);
+                    if idx < n {
+                        selected[idx] = doc;
+                    }
+                }
+                None => return Ok(selected),
+            }
+        }
+    }
+
+    Ok(selected)
+}
+
+/// Print `n` documents of `lang` in the corpus in `src` to `out`.
+pub fn inspect<W: Write>(
+    src: &Path,
+    lang: &LanguageTag<String>,
+    n: usize,
+    seed: Option<u64>,
+    style: Style,
+    out: &mut W,
+) -> Result<(), Error> {
+    for doc in select(src, lang, n, seed)? {
+        writeln!(out, "{}", style.format(&doc))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oscar_io::{
+        common::Identification,
+        v3::{Document, Metadata},
+    };
+    use oxilangtag::LanguageTag;
+    use warc::WarcHeader;
+
+    use crate::io::LangFilesDoc;
+
+    use super::{inspect, select, Style};
+
+    fn gen_doc(id: usize, content: &str) -> Document {
+        let lang = LanguageTag::parse("en".to_string()).unwrap();
+        let identification = Identification::new(lang, 0.9);
+        let mut metadata = Metadata::new(&identification, &[Some(identification.clone())]);
+        metadata.add_annotation("tiny".to_string());
+        let headers = HashMap::from([
+            (
+                WarcHeader::RecordID,
+                format!("<urn:uuid:{id}>").as_bytes().to_vec(),
+            ),
+            (
+                WarcHeader::TargetURI,
+                format!("http://example.com/{id}").as_bytes().to_vec(),
+            ),
+        ]);
+        Document::new(content.to_string(), headers, metadata)
+    }
+
+    #[test]
+    fn test_format() {
+        let style = Style {
+            max_chars: 5,
+            color: false,
+        };
+        let formatted = style.format(&gen_doc(0, "Hello World!"));
+        assert_eq!(
+            formatted,
+            "── <urn:uuid:0> ──
+uri: http://example.com/0
+lang: en (0.90)
+annotations: tiny
+content: 12 chars
+Hello… [7 more chars]
+"
+        );
+
+        let style = Style {
+            max_chars: 100,
+            color: true,
+        };
+        let formatted = style.format(&gen_doc(0, "Hello World!"));
+        assert!(formatted.contains("\x1b[33mtiny\x1b[0m"));
+        assert!(formatted.ends_with("Hello World!\n"));
+    }
+
+    #[test]
+    fn test_select() {
+        let dir = tempfile::tempdir().unwrap();
+        let lang = LanguageTag::parse("en".to_string()).unwrap();
+        let langfiles = LangFilesDoc::new(dir.path(), None);
+        let docs = (0..20).map(|id| gen_doc(id, "foo")).collect();
+        langfiles.write(&lang, docs).unwrap();
+        langfiles.close_all().unwrap();
+
+        let first = select(dir.path(), &lang, 3, None).unwrap();
+        let ids: Vec<_> = first.iter().map(|doc| doc.warc_id().into_owned()).collect();
+        assert_eq!(ids, ["<urn:uuid:0>", "<urn:uuid:1>", "<urn:uuid:2>"]);
+
+        let sampled = select(dir.path(), &lang, 3, Some(42)).unwrap();
+        let sampled_again = select(dir.path(), &lang, 3, Some(42)).unwrap();
+        assert_eq!(sampled.len(), 3);
+        assert_eq!(sampled, sampled_again);
+
+        let fr = LanguageTag::parse("fr".to_string()).unwrap();
+        assert!(select(dir.path(), &fr, 3, None).unwrap().is_empty());
+
+        let mut out = Vec::new();
+        let style = Style {
+            max_chars: 10,
+            color: false,
+        };
+        inspect(dir.path(), &lang, 2, None, style, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.matches("── <urn:uuid:").count(), 2);
+    }
+}
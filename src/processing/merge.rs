@@ -0,0 +1,258 @@
+/*! Multi-crawl merging
+
+Merges the outputs of several pipeline runs (usually one per crawl) into a single corpus,
+annotating each document with its crawl (`crawl:<crawl id>`) so that cumulative corpora keep their temporal provenance.
+
+Inputs are given as `<crawl id>=<pipeline output folder>`:
+
+- `<lang>_meta.jsonl` (and `<lang>_meta_part_<n>.jsonl`) files are merged by language into `<dst>/<lang>_meta.jsonl`,
+- rebuild files are copied in `<dst>/rebuild/<crawl id>/`, since they refer to the shards of a given crawl.
+
+Other subfolders (`annotated/`, `categories/`, `stats/`) are not merged.
+!*/
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use oscar_io::v3::{Reader as DocReader, WriterTrait};
+use oxilangtag::LanguageTag;
+
+use crate::{
+    error::Error,
+    io::{template::parse_part_number, LangFilesDoc},
+};
+
+/// Number of documents written at once.
+const BATCH_SIZE: usize = 1000;
+
+/// Parse a `<crawl id>=<folder>` input.
+pub fn parse_input(spec: &str) -> Result<(String, PathBuf), Error> {
+    match spec.split_once('=') {
+        Some((crawl, src))
+            if !crawl.is_empty() && !crawl.contains(['/', '\\']) && !src.is_empty() =>
+        {
+            Ok((crawl.to_string(), PathBuf::from(src)))
+        }
+        _ => Err(Error::Custom(format!(
+            "invalid merge input {spec} (expected <crawl id>=<folder>)"
+        ))),
+    }
+}
+
+/// Get the language of a `<lang>_meta.jsonl`/`<lang>_meta_part_<n>.jsonl` file.
+pub(crate) fn file_lang(path: &Path) -> Option<LanguageTag<String>> {
+    let filename = path.file_name()?.to_str()?;
+    if !filename.ends_with(".jsonl") {
+        return None;
+    }
+    let (lang, _) = filename.split_once("_meta")?;
+    LanguageTag::parse_and_normalize(lang).ok()
+}
+
+/// Sort `<lang>_meta.jsonl`/`<lang>_meta_part_<n>.jsonl` files by language, then by part number
+/// (sorting by name would put part 10 before part 2). Other files are sorted by name.
+pub(crate) fn sort_parts(paths: &mut [PathBuf]) {
+    paths.sort_by_cached_key(|path| {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        match name.split_once("_meta") {
+            Some((lang, _)) => {
+                let part = parse_part_number(name, &format!("{lang}_meta_part_"), ".jsonl");
+                (lang.to_string(), part.unwrap_or(0))
+            }
+            None => (name.to_string(), 0),
+        }
+    });
+}
+
+/// Merge pipeline outputs into `dst`, annotating documents with their crawl.
+pub fn merge(inputs: &[(String, PathBuf)], dst: &Path) -> Result<(), Error> {
+    std::fs::create_dir_all(dst)?;
+    let langfiles = LangFilesDoc::new(dst, None);
+
+    for (crawl, src) in inputs {
+        let annotation = format!("crawl:{crawl}");
+        let mut nb_docs = 0;
+
+        // sort to keep part order
+        let mut paths: Vec<_> = std::fs::read_dir(src)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<_, _>>()?;
+        sort_parts(&mut paths);
+
+        for path in paths {
+            let lang = match file_lang(&path) {
+                Some(lang) => lang,
+                None => continue,
+            };
+
+            if !langfiles.contains(&lang) {
+                langfiles.insert_writer(lang.clone())?;
+            }
+            let writer = langfiles.writer(&lang).unwrap();
+            let mut writer = writer.lock().unwrap();
+
+            let mut batch = Vec::with_capacity(BATCH_SIZE);
+            for doc in DocReader::from_path(&path)? {
+                let mut doc = doc?;
+                doc.metadata_mut().add_annotation(annotation.clone());
+                batch.push(doc);
+                if batch.len() == BATCH_SIZE {
+                    nb_docs += batch.len();
+                    writer.write(std::mem::take(&mut batch))?;
+                }
+            }
+            nb_docs += batch.len();
+            writer.write(batch)?;
+        }
+
+        // keep rebuild files, separated by crawl
+        let src_rebuild = src.join("rebuild");
+        if src_rebuild.is_dir() {
+            let dst_rebuild = dst.join("rebuild").join(crawl);
+            std::fs::create_dir_all(&dst_rebuild)?;
+            for entry in std::fs::read_dir(&src_rebuild)? {
+                let path = entry?.path();
+                if let Some(filename) = path.file_name() {
+                    std::fs::copy(&path, dst_rebuild.join(filename))?;
+                }
+            }
+        } else {
+            warn!("{}: no rebuild files in {:?}", crawl, src);
+        }
+
+        info!("{}: merged {} documents from {:?}", crawl, nb_docs, src);
+    }
+    langfiles.close_all()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+    };
+
+    use oscar_io::{
+        common::Identification,
+        v3::{Document, Metadata, Reader as DocReader, WriterTrait},
+    };
+    use oxilangtag::LanguageTag;
+
+    use crate::io::LangFilesDoc;
+
+    use super::{file_lang, merge, parse_input, sort_parts};
+
+    fn gen_output(dst: &Path, lang: &str, nb_docs: usize) {
+        let lang = LanguageTag::parse(lang.to_string()).unwrap();
+        let id = Identification::new(lang.clone(), 1.0);
+        let docs = (0..nb_docs)
+            .map(|i| {
+                let metadata = Metadata::new(&id, &[Some(id.clone())]);
+                Document::new(format!("doc {i}"), HashMap::new(), metadata)
+            })
+            .collect();
+
+        let langfiles = LangFilesDoc::new(dst, None);
+        langfiles.insert_writer(lang.clone()).unwrap();
+        langfiles
+            .writer(&lang)
+            .unwrap()
+            .lock()
+            .unwrap()
+            .write(docs)
+            .unwrap();
+        langfiles.close_all().unwrap();
+
+        std::fs::create_dir(dst.join("rebuild")).unwrap();
+        std::fs::write(dst.join("rebuild").join(format!("{lang}.avro")), b"avro").unwrap();
+    }
+
+    #[test]
+    fn test_parse_input() {
+        let (crawl, src) = parse_input("CC-MAIN-2023-06=out/2023-06").unwrap();
+        assert_eq!(crawl, "CC-MAIN-2023-06");
+        assert_eq!(src, Path::new("out/2023-06"));
+        assert!(parse_input("out/2023-06").is_err());
+        assert!(parse_input("../foo=out").is_err());
+    }
+
+    #[test]
+    fn test_file_lang() {
+        assert_eq!(file_lang(Path::new("a/en_meta.jsonl")).unwrap(), "en");
+        assert_eq!(
+            file_lang(Path::new("zh-Hans_meta_part_2.jsonl")).unwrap(),
+            "zh-Hans"
+        );
+        assert!(file_lang(Path::new("rebuild")).is_none());
+    }
+
+    #[test]
+    fn test_sort_parts() {
+        let mut paths: Vec<PathBuf> = [
+            "fr_meta.jsonl",
+            "en_meta_part_10.jsonl",
+            "rebuild",
+            "en_meta_part_2.jsonl",
+            "en_meta_part_1.jsonl",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        sort_parts(&mut paths);
+        assert_eq!(
+            paths,
+            [
+                "en_meta_part_1.jsonl",
+                "en_meta_part_2.jsonl",
+                "en_meta_part_10.jsonl",
+                "fr_meta.jsonl",
+                "rebuild"
+            ]
+            .iter()
+            .map(PathBuf::from)
+            .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b, dst) = (
+            dir.path().join("a"),
+            dir.path().join("b"),
+            dir.path().join("dst"),
+        );
+        std::fs::create_dir(&a).unwrap();
+        std::fs::create_dir(&b).unwrap();
+        gen_output(&a, "en", 3);
+        gen_output(&b, "en", 2);
+
+        merge(
+            &[
+                ("CC-MAIN-2023-06".to_string(), a),
+                ("CC-MAIN-2023-14".to_string(), b),
+            ],
+            &dst,
+        )
+        .unwrap();
+
+        let docs: Vec<Document> = DocReader::from_path(&dst.join("en_meta.jsonl"))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(docs.len(), 5);
+        assert_eq!(
+            docs[0].metadata().annotation(),
+            Some(&vec!["crawl:CC-MAIN-2023-06".to_string()])
+        );
+        assert_eq!(
+            docs[4].metadata().annotation(),
+            Some(&vec!["crawl:CC-MAIN-2023-14".to_string()])
+        );
+        assert!(dst.join("rebuild/CC-MAIN-2023-14/en.avro").exists());
+    }
+}
@@ -5,8 +5,16 @@ Contains structures and functions to transform and aggregate data from sources.
 This module is for now only compatible with CommonCrawl extracted content, but will be made generic when it is needed.
 !*/
 pub mod check;
+pub mod inspect;
+pub mod merge;
+pub mod neardup;
+pub mod paragraphs;
 //pub mod compress;
 //pub mod dedup;
 //pub mod package;
+pub mod paths;
 pub mod rebuild;
+pub mod sign;
+#[cfg(feature = "tokenizers")]
+pub mod tokstats;
 //pub mod split;
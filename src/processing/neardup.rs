@@ -0,0 +1,354 @@
+/*! Near-duplicate removal
+
+Removes near-duplicate documents of a corpus, using the TLSH hashes computed by the pipeline (see [crate::transformers::LSH]).
+
+Documents are processed in file order: a document is removed if its TLSH distance to an already kept document of the same language
+is below a threshold, and kept otherwise.
+Since comparing every pair of documents is not tractable, candidates are found by splitting TLSH codes in bands:
+only documents that share at least one band are compared. Some near-duplicates can thus be missed.
+
+Documents without a TLSH hash are always kept.
+
+Optionally, a cluster file (`<dst>/<lang>_clusters.jsonl`) maps each kept document to the provenance
+(record id, URI and distance) of the near-duplicates that were removed in its favor,
+in order to audit dedup aggressiveness and recover removed documents if needed.
+
+## Warning
+
+Rebuild files are not updated, and still refer to removed documents.
+!*/
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use log::{debug, info, warn};
+use oscar_io::v3::{Document, Reader as DocReader, WriterTrait};
+use serde::Serialize;
+use tlsh_fixed::Tlsh;
+
+use crate::{
+    error::Error,
+    io::LangFilesDoc,
+    processing::merge::{file_lang, sort_parts},
+};
+
+/// Number of hex characters per band.
+const BAND_SIZE: usize = 8;
+
+/// Number of documents written at once.
+const BATCH_SIZE: usize = 1000;
+
+/// Provenance of a document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Provenance {
+    pub record_id: String,
+    pub uri: Option<String>,
+}
+
+impl From<&Document> for Provenance {
+    fn from(doc: &Document) -> Self {
+        Self {
+            record_id: doc.warc_id().into_owned(),
+            uri: doc.url(),
+        }
+    }
+}
+
+/// Removed near-duplicate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Member {
+    #[serde(flatten)]
+    pub provenance: Provenance,
+    /// TLSH distance to the kept document
+    pub distance: usize,
+}
+
+/// Kept document and its removed near-duplicates.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Cluster {
+    pub kept: Provenance,
+    pub removed: Vec<Member>,
+}
+
+/// Near-duplicate detector for a single language.
+pub struct NearDup {
+    threshold: usize,
+    /// kept hashes and their clusters
+    kept: Vec<(Tlsh, Cluster)>,
+    /// (band index, band) -> kept documents
+    bands: HashMap<(usize, String), Vec<usize>>,
+}
+
+impl NearDup {
+    /// Create a new detector. Documents are near-duplicates if their TLSH distance is `<= threshold`.
+    pub fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            kept: Vec::new(),
+            bands: HashMap::new(),
+        }
+    }
+
+    /// Parse a `tlsh:<hash>` metadata value.
+    fn parse_tlsh(tlsh: &str) -> Option<Tlsh> {
+        Tlsh::from_str(tlsh.strip_prefix("tlsh:").unwrap_or(tlsh)).ok()
+    }
+
+    /// Split the codes of a hash (after the version, checksum, length and quartile header) in bands.
+    fn bands(hash: &str) -> Vec<(usize, String)> {
+        let codes = hash.get(hash.len().saturating_sub(128)..).unwrap_or(hash);
+        codes
+            .as_bytes()
+            .chunks(BAND_SIZE)
+            .enumerate()
+            .map(|(idx, band)| (idx, String::from_utf8_lossy(band).into_owned()))
+            .collect()
+    }
+
+    /// Returns true if the document has to be kept, adding it to the kept documents or to a cluster.
+    pub fn keep(&mut self, doc: &Document) -> bool {
+        let tlsh = match doc.metadata().tlsh().and_then(|t| Self::parse_tlsh(t)) {
+            Some(tlsh) => tlsh,
+            None => return true,
+        };
+        let bands = Self::bands(&tlsh.hash());
+
+        // closest candidate under threshold
+        let nearest = bands
+            .iter()
+            .filter_map(|band| self.bands.get(band))
+            .flatten()
+            .map(|idx| (*idx, self.kept[*idx].0.diff(&tlsh, true)))
+            .filter(|(_, distance)| *distance <= self.threshold)
+            .min_by_key(|(_, distance)| *distance);
+
+        match nearest {
+            Some((idx, distance)) => {
+                debug!(
+                    "record {}: near-duplicate of {} (distance {})",
+                    doc.warc_id(),
+                    self.kept[idx].1.kept.record_id,
+                    distance
+                );
+                self.kept[idx].1.removed.push(Member {
+                    provenance: doc.into(),
+                    distance,
+                });
+                false
+            }
+            None => {
+                let idx = self.kept.len();
+                for band in bands {
+                    self.bands.entry(band).or_default().push(idx);
+                }
+                self.kept.push((
+                    tlsh,
+                    Cluster {
+                        kept: doc.into(),
+                        removed: Vec::new(),
+                    },
+                ));
+                true
+            }
+        }
+    }
+
+    /// Get clusters that have removed members.
+    pub fn clusters(&self) -> impl Iterator<Item = &Cluster> {
+        self.kept
+            .iter()
+            .map(|(_, cluster)| cluster)
+            .filter(|cluster| !cluster.removed.is_empty())
+    }
+}
+
+/// Remove near-duplicates of the corpus in `src`, writing kept documents in `dst`,
+/// and cluster files if `clusters` is set.
+pub fn near_dedup(src: &Path, dst: &Path, threshold: usize, clusters: bool) -> Result<(), Error> {
+    std::fs::create_dir_all(dst)?;
+    let langfiles = LangFilesDoc::new(dst, None);
+    let mut detectors: HashMap<String, NearDup> = HashMap::new();
+
+    // sort to keep part order
+    let mut paths: Vec<_> = std::fs::read_dir(src)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    sort_parts(&mut paths);
+
+    for path in paths {
+        let lang = match file_lang(&path) {
+            Some(lang) => lang,
+            None => continue,
+        };
+        if !langfiles.contains(&lang) {
+            langfiles.insert_writer(lang.clone())?;
+        }
+        let writer = langfiles.writer(&lang).unwrap();
+        let mut writer = writer.lock().unwrap();
+        let detector = detectors
+            .entry(lang.to_string())
+            .or_insert_with(|| NearDup::new(threshold));
+
+        let (mut nb_docs, mut nb_removed) = (0, 0);
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        for doc in DocReader::from_path(&path)? {
+            let doc = doc?;
+            nb_docs += 1;
+            if !detector.keep(&doc) {
+                nb_removed += 1;
+                continue;
+            }
+            batch.push(doc);
+            if batch.len() == BATCH_SIZE {
+                writer.write(std::mem::take(&mut batch))?;
+            }
+        }
+        writer.write(batch)?;
+        info!(
+            "[{}] {:?}: removed {} near-duplicates out of {} documents",
+            lang, path, nb_removed, nb_docs
+        );
+    }
+
+    langfiles.close_all()?;
+    if detectors.is_empty() {
+        warn!("no corpus files found in {:?}", src);
+    }
+
+    if clusters {
+        for (lang, detector) in &detectors {
+            let path = dst.join(format!("{lang}_clusters.jsonl"));
+            let mut w = BufWriter::new(File::create(&path)?);
+            for cluster in detector.clusters() {
+                serde_json::to_writer(&mut w, cluster)?;
+                writeln!(w)?;
+            }
+            w.flush()?;
+            info!("[{}] wrote clusters to {:?}", lang, path);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, io::BufRead};
+
+    use oscar_io::{
+        common::Identification,
+        v3::{Document, Metadata, Reader as DocReader, WriterTrait},
+    };
+    use oxilangtag::LanguageTag;
+    use warc::WarcHeader;
+
+    use crate::{
+        io::LangFilesDoc,
+        transformers::{Annotate, LSH},
+    };
+
+    use super::{near_dedup, NearDup};
+
+    const CONTENT: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt ut labore et dolore magna aliqua.
+Ut enim ad minim veniam, quis nostrud exercitation ullamco laboris nisi ut aliquip ex ea commodo consequat.
+Duis aute irure dolor in reprehenderit in voluptate velit esse cillum dolore eu fugiat nulla pariatur.";
+
+    const OTHER: &str =
+        "The quick brown fox jumps over the lazy dog while the five boxing wizards jump quickly.
+Pack my box with five dozen liquor jugs, then sphinx of black quartz, judge my vow.
+How vexingly quick daft zebras jump, and bright vixens jump as dozy fowl quack.";
+
+    fn gen_doc(id: usize, content: &str) -> Document {
+        let lang = LanguageTag::parse("en".to_string()).unwrap();
+        let identification = Identification::new(lang, 1.0);
+        let metadata = Metadata::new(&identification, &[Some(identification.clone())]);
+        let headers = HashMap::from([
+            (
+                WarcHeader::RecordID,
+                format!("<urn:uuid:{id}>").as_bytes().to_vec(),
+            ),
+            (
+                WarcHeader::TargetURI,
+                format!("http://example.com/{id}").as_bytes().to_vec(),
+            ),
+        ]);
+        let mut doc = Document::new(content.to_string(), headers, metadata);
+        LSH::default().annotate(&mut doc);
+        doc
+    }
+
+    #[test]
+    fn test_keep() {
+        let mut neardup = NearDup::new(40);
+        assert!(neardup.keep(&gen_doc(0, CONTENT)));
+        assert!(!neardup.keep(&gen_doc(1, &CONTENT.replace("Lorem", "Lorum"))));
+        assert!(neardup.keep(&gen_doc(2, OTHER)));
+        assert!(!neardup.keep(&gen_doc(3, CONTENT)));
+
+        let clusters: Vec<_> = neardup.clusters().collect();
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].kept.record_id, "<urn:uuid:0>");
+        assert_eq!(clusters[0].removed.len(), 2);
+        assert_eq!(clusters[0].removed[1].distance, 0);
+        assert_eq!(
+            clusters[0].removed[0].provenance.uri.as_deref(),
+            Some("http://example.com/1")
+        );
+    }
+
+    #[test]
+    fn test_no_tlsh() {
+        let mut neardup = NearDup::new(40);
+        let lang = LanguageTag::parse("en".to_string()).unwrap();
+        let identification = Identification::new(lang, 1.0);
+        let metadata = Metadata::new(&identification, &[Some(identification.clone())]);
+        let doc = Document::new(CONTENT.to_string(), HashMap::new(), metadata);
+        assert!(neardup.keep(&doc));
+        assert!(neardup.keep(&doc));
+    }
+
+    #[test]
+    fn test_near_dedup() {
+        let dir = tempfile::tempdir().unwrap();
+        let (src, dst) = (dir.path().join("src"), dir.path().join("dst"));
+        std::fs::create_dir(&src).unwrap();
+
+        let lang = LanguageTag::parse("en".to_string()).unwrap();
+        let langfiles = LangFilesDoc::new(&src, None);
+        langfiles.insert_writer(lang.clone()).unwrap();
+        langfiles
+            .writer(&lang)
+            .unwrap()
+            .lock()
+            .unwrap()
+            .write(vec![
+                gen_doc(0, CONTENT),
+                gen_doc(1, OTHER),
+                gen_doc(2, CONTENT),
+            ])
+            .unwrap();
+        langfiles.close_all().unwrap();
+
+        near_dedup(&src, &dst, 40, true).unwrap();
+
+        let docs: Vec<Document> = DocReader::from_path(&dst.join("en_meta.jsonl"))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(docs.len(), 2);
+
+        let clusters = std::fs::File::open(dst.join("en_clusters.jsonl")).unwrap();
+        let clusters: Vec<serde_json::Value> = std::io::BufReader::new(clusters)
+            .lines()
+            .map(|l| serde_json::from_str(&l.unwrap()).unwrap())
+            .collect();
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0]["kept"]["record_id"], "<urn:uuid:0>");
+        assert_eq!(clusters[0]["removed"][0]["record_id"], "<urn:uuid:2>");
+        assert_eq!(clusters[0]["removed"][0]["distance"], 0);
+    }
+}
@@ -0,0 +1,236 @@
+/*! Paragraph segmentation
+
+Splits the documents of a corpus into paragraphs with their own language identification,
+to build paragraph-aligned multilingual datasets from mixed-language pages.
+
+Documents hold an identification per line (see [oscar_io::v3::Metadata::sentence_identifications]).
+A paragraph is a run of consecutive lines with the same identified language,
+its probability being the mean of its lines' probabilities, weighted by their length.
+Lines without identification are not part of any paragraph.
+
+Paragraphs are written as sub-records in `<dst>/<lang>_paragraphs.jsonl`, `<lang>` being the language of the paragraph,
+with the record id and URI of their document and their line bounds (inclusive) in it.
+!*/
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use log::{info, warn};
+use oscar_io::v3::{Document, Reader as DocReader};
+use serde::Serialize;
+
+use crate::{error::Error, processing::merge::file_lang};
+
+/// Paragraph of a document.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Paragraph {
+    pub record_id: String,
+    pub uri: Option<String>,
+    pub line_start: usize,
+    pub line_end: usize,
+    pub lang: String,
+    pub prob: f32,
+    pub content: String,
+}
+
+/// Split a document into paragraphs.
+///
+/// Returns an empty vector if the document's line identifications don't match its lines.
+pub fn segment(doc: &Document) -> Vec<Paragraph> {
+    let lines: Vec<&str> = doc.content().lines().collect();
+    let ids = doc.metadata().sentence_identifications();
+    if lines.len() != ids.len() {
+        warn!(
+            "record {}: {} lines but {} line identifications, skipping",
+            doc.warc_id(),
+            lines.len(),
+            ids.len()
+        );
+        return Vec::new();
+    }
+
+    let mut paragraphs = Vec::new();
+    let mut idx = 0;
+    while idx < lines.len() {
+        let id = match &ids[idx] {
+            Some(id) => id,
+            None => {
+                idx += 1;
+                continue;
+            }
+        };
+
+        // extend the paragraph while lines have the same language
+        let start = idx;
+        let (mut weighted_prob, mut nb_bytes) = (0.0, 0);
+        while let Some(Some(line_id)) = ids.get(idx) {
+            if line_id.label() != id.label() {
+                break;
+            }
+            let len = lines[idx].len().max(1);
+            weighted_prob += *line_id.prob() * len as f32;
+            nb_bytes += len;
+            idx += 1;
+        }
+
+        paragraphs.push(Paragraph {
+            record_id: doc.warc_id().into_owned(),
+            uri: doc.url(),
+            line_start: start,
+            line_end: idx - 1,
+            lang: id.label().to_string(),
+            prob: weighted_prob / nb_bytes as f32,
+            content: lines[start..idx].join("\n"),
+        });
+    }
+
+    paragraphs
+}
+
+/// Write the paragraphs of the corpus in `src` in `dst`.
+///
+/// If `multilingual_only` is set, only paragraphs of documents with paragraphs in several languages are written.
+pub fn paragraphs(src: &Path, dst: &Path, multilingual_only: bool) -> Result<(), Error> {
+    std::fs::create_dir_all(dst)?;
+    let mut writers: HashMap<String, BufWriter<File>> = HashMap::new();
+    let mut nb_paragraphs = 0;
+
+    let mut paths: Vec<_> = std::fs::read_dir(src)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+
+    for path in paths {
+        if file_lang(&path).is_none() {
+            continue;
+        }
+        info!("segmenting {:?}", path);
+
+        for doc in DocReader::from_path(&path)? {
+            let paragraphs = segment(&doc?);
+            if multilingual_only && paragraphs.iter().all(|p| p.lang == paragraphs[0].lang) {
+                continue;
+            }
+
+            for paragraph in paragraphs {
+                let writer = match writers.get_mut(&paragraph.lang) {
+                    Some(writer) => writer,
+                    None => {
+                        let path = dst.join(format!("{}_paragraphs.jsonl", paragraph.lang));
+                        writers
+                            .entry(paragraph.lang.clone())
+                            .or_insert(BufWriter::new(File::create(path)?))
+                    }
+                };
+                serde_json::to_writer(&mut *writer, &paragraph)?;
+                writeln!(writer)?;
+                nb_paragraphs += 1;
+            }
+        }
+    }
+
+    for writer in writers.values_mut() {
+        writer.flush()?;
+    }
+    info!(
+        "wrote {} paragraphs in {} languages",
+        nb_paragraphs,
+        writers.len()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oscar_io::{
+        common::Identification,
+        v3::{Document, Metadata},
+    };
+    use oxilangtag::LanguageTag;
+    use warc::WarcHeader;
+
+    use crate::io::LangFilesDoc;
+
+    use super::{paragraphs, segment};
+
+    fn id(lang: &str, prob: f32) -> Option<Identification<String>> {
+        Some(Identification::new(
+            LanguageTag::parse(lang.to_string()).unwrap(),
+            prob,
+        ))
+    }
+
+    fn gen_doc(content: &str, ids: &[Option<Identification<String>>]) -> Document {
+        let doc_id = ids.iter().flatten().next().unwrap().clone();
+        let metadata = Metadata::new(&doc_id, ids);
+        let headers = HashMap::from([(WarcHeader::RecordID, b"<urn:uuid:0>".to_vec())]);
+        Document::new(content.to_string(), headers, metadata)
+    }
+
+    #[test]
+    fn test_segment() {
+        let doc = gen_doc(
+            "Hello\nWorld!\n...\nBonjour\nHallo",
+            &[
+                id("en", 1.0),
+                id("en", 0.5),
+                None,
+                id("fr", 0.9),
+                id("de", 0.8),
+            ],
+        );
+        let paragraphs = segment(&doc);
+        assert_eq!(paragraphs.len(), 3);
+        assert_eq!(paragraphs[0].content, "Hello\nWorld!");
+        assert_eq!((paragraphs[0].line_start, paragraphs[0].line_end), (0, 1));
+        assert_eq!(paragraphs[0].prob, (5.0 + 0.5 * 6.0) / 11.0);
+        assert_eq!(paragraphs[1].lang, "fr");
+        assert_eq!((paragraphs[1].line_start, paragraphs[1].line_end), (3, 3));
+        assert_eq!(paragraphs[2].lang, "de");
+    }
+
+    #[test]
+    fn test_segment_mismatch() {
+        let doc = gen_doc("Hello\nWorld!", &[id("en", 1.0)]);
+        assert!(segment(&doc).is_empty());
+    }
+
+    #[test]
+    fn test_paragraphs() {
+        let dir = tempfile::tempdir().unwrap();
+        let (src, dst) = (dir.path().join("src"), dir.path().join("dst"));
+        std::fs::create_dir(&src).unwrap();
+
+        let lang = LanguageTag::parse("en".to_string()).unwrap();
+        let langfiles = LangFilesDoc::new(&src, None);
+        langfiles
+            .write(
+                &lang,
+                vec![
+                    gen_doc("Hello\nBonjour", &[id("en", 1.0), id("fr", 1.0)]),
+                    gen_doc("Hello\nWorld", &[id("en", 1.0), id("en", 1.0)]),
+                ],
+            )
+            .unwrap();
+        langfiles.close_all().unwrap();
+
+        paragraphs(&src, &dst, true).unwrap();
+        let en = std::fs::read_to_string(dst.join("en_paragraphs.jsonl")).unwrap();
+        assert_eq!(en.lines().count(), 1);
+        let fr = std::fs::read_to_string(dst.join("fr_paragraphs.jsonl")).unwrap();
+        let fr: serde_json::Value = serde_json::from_str(fr.trim()).unwrap();
+        assert_eq!(fr["content"], "Bonjour");
+        assert_eq!(fr["record_id"], "<urn:uuid:0>");
+        assert_eq!(fr["line_start"], 1);
+
+        paragraphs(&src, &dst, false).unwrap();
+        let en = std::fs::read_to_string(dst.join("en_paragraphs.jsonl")).unwrap();
+        assert_eq!(en.lines().count(), 2);
+    }
+}
@@ -0,0 +1,249 @@
+/*! `wet.paths` validation and normalization
+
+Checks a `wet.paths` file before downloading:
+
+- line format: each line has to be a `crawl-data/<crawl>/segments/<segment>/wet/<file>.warc.wet.gz` path,
+  possibly prefixed by a base URL,
+- duplicates,
+- crawl consistency: all paths have to come from the same crawl.
+
+Valid paths can then be rewritten as full URLs for a chosen mirror (or kept relative), without duplicates.
+!*/
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use log::{error, info, warn};
+use url::Url;
+
+use crate::error::Error;
+
+/// Issue found on a given line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    Empty,
+    InvalidUrl,
+    InvalidFormat,
+    Duplicate,
+}
+
+/// Components of a valid path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct WetPath {
+    /// path relative to the base URL (`crawl-data/...`)
+    path: String,
+    crawl: String,
+    segment: String,
+}
+
+impl WetPath {
+    /// Parse a line, stripping the base URL if there's one.
+    fn parse(line: &str) -> Result<Self, Issue> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Err(Issue::Empty);
+        }
+
+        let path = if line.contains("://") {
+            let url = Url::parse(line).map_err(|_| Issue::InvalidUrl)?;
+            url.path().trim_start_matches('/').to_string()
+        } else {
+            line.trim_start_matches('/').to_string()
+        };
+
+        match path.split('/').collect::<Vec<_>>()[..] {
+            ["crawl-data", crawl, "segments", segment, "wet", file]
+                if crawl.starts_with("CC-MAIN-")
+                    && !segment.is_empty()
+                    && file.ends_with(".warc.wet.gz") =>
+            {
+                Ok(Self {
+                    crawl: crawl.to_string(),
+                    segment: segment.to_string(),
+                    path,
+                })
+            }
+            _ => Err(Issue::InvalidFormat),
+        }
+    }
+}
+
+/// Result of a `wet.paths` check.
+#[derive(Debug, Default)]
+pub struct PathsReport {
+    /// number of lines
+    pub total: usize,
+    /// valid, unique paths, in file order
+    pub paths: Vec<String>,
+    /// number of paths per crawl
+    pub crawls: BTreeMap<String, usize>,
+    /// number of distinct segments
+    pub segments: usize,
+    /// (line number, line, issue)
+    pub issues: Vec<(usize, String, Issue)>,
+}
+
+impl PathsReport {
+    /// Check each line of a `wet.paths` file.
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, Error> {
+        let mut report = Self::default();
+        let mut seen = HashSet::new();
+        let mut segments = BTreeSet::new();
+
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line?;
+            report.total += 1;
+            match WetPath::parse(&line) {
+                Ok(wet_path) if !seen.insert(wet_path.path.clone()) => {
+                    report.issues.push((idx + 1, line, Issue::Duplicate));
+                }
+                Ok(wet_path) => {
+                    *report.crawls.entry(wet_path.crawl).or_insert(0) += 1;
+                    segments.insert(wet_path.segment);
+                    report.paths.push(wet_path.path);
+                }
+                Err(issue) => report.issues.push((idx + 1, line, issue)),
+            }
+        }
+
+        report.segments = segments.len();
+        Ok(report)
+    }
+
+    /// Returns true if there's no invalid line and a single crawl.
+    ///
+    /// Duplicates are not considered invalid since they are removed from [PathsReport::paths].
+    pub fn is_valid(&self) -> bool {
+        self.crawls.len() <= 1
+            && self
+                .issues
+                .iter()
+                .all(|(_, _, issue)| *issue == Issue::Duplicate)
+    }
+
+    /// Write valid paths, one per line, prefixed by `mirror` if provided.
+    pub fn write_paths<W: Write>(&self, mut w: W, mirror: Option<&str>) -> Result<(), Error> {
+        let base = match mirror {
+            Some(mirror) if mirror.ends_with('/') => Some(Url::parse(mirror)?),
+            Some(mirror) => Some(Url::parse(&format!("{mirror}/"))?),
+            None => None,
+        };
+
+        for path in &self.paths {
+            match &base {
+                Some(base) => writeln!(w, "{}", base.join(path)?)?,
+                None => writeln!(w, "{path}")?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Log totals and issues.
+    pub fn log(&self) {
+        info!(
+            "{} lines, {} valid paths, {} segments, {} issues",
+            self.total,
+            self.paths.len(),
+            self.segments,
+            self.issues.len()
+        );
+        for (crawl, count) in &self.crawls {
+            info!("crawl {crawl}: {count} paths");
+        }
+        if self.crawls.len() > 1 {
+            error!("paths come from {} different crawls", self.crawls.len());
+        }
+        for (line_nb, line, issue) in &self.issues {
+            match issue {
+                Issue::Duplicate => warn!("line {line_nb}: duplicate path {line}"),
+                issue => error!("line {line_nb}: {issue:?}: {line:?}"),
+            }
+        }
+    }
+}
+
+/// Check a `wet.paths` file, optionally writing normalized paths in `dst`.
+///
+/// Errors if the file is invalid (see [PathsReport::is_valid]), in which case nothing is written.
+pub fn check_paths(src: &Path, dst: Option<&Path>, mirror: Option<&str>) -> Result<(), Error> {
+    let report = PathsReport::from_reader(BufReader::new(File::open(src)?))?;
+    report.log();
+
+    if !report.is_valid() {
+        return Err(Error::Custom(format!(
+            "{src:?} is not a valid wet.paths file"
+        )));
+    }
+
+    if let Some(dst) = dst {
+        let mut w = BufWriter::new(File::create(dst)?);
+        report.write_paths(&mut w, mirror)?;
+        w.flush()?;
+        info!("wrote {} paths to {:?}", report.paths.len(), dst);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Issue, PathsReport};
+
+    const PATHS: &str = "crawl-data/CC-MAIN-2023-06/segments/1674764494826.88/wet/CC-MAIN-20230126210844-20230127000844-00000.warc.wet.gz
+https://data.commoncrawl.org/crawl-data/CC-MAIN-2023-06/segments/1674764494826.88/wet/CC-MAIN-20230126210844-20230127000844-00001.warc.wet.gz
+crawl-data/CC-MAIN-2023-06/segments/1674764494826.88/wet/CC-MAIN-20230126210844-20230127000844-00000.warc.wet.gz
+crawl-data/CC-MAIN-2023-06/segments/1674764494827.00/wet/CC-MAIN-20230126210844-20230127000844-00002.warc.wet.gz
+";
+
+    #[test]
+    fn test_valid() {
+        let report = PathsReport::from_reader(PATHS.as_bytes()).unwrap();
+        assert_eq!(report.total, 4);
+        assert_eq!(report.paths.len(), 3);
+        assert_eq!(report.segments, 2);
+        assert_eq!(report.crawls.get("CC-MAIN-2023-06"), Some(&3));
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].0, 3);
+        assert_eq!(report.issues[0].2, Issue::Duplicate);
+        assert!(report.is_valid());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let paths = format!(
+            "{PATHS}\ncrawl-data/CC-MAIN-2023-06/segments/1/warc/foo.warc.gz\ncrawl-data/CC-MAIN-2022-49/segments/1/wet/foo.warc.wet.gz\n"
+        );
+        let report = PathsReport::from_reader(paths.as_bytes()).unwrap();
+        let issues: Vec<_> = report.issues.iter().map(|(_, _, i)| i.clone()).collect();
+        assert_eq!(
+            issues,
+            vec![Issue::Duplicate, Issue::Empty, Issue::InvalidFormat]
+        );
+        assert_eq!(report.crawls.len(), 2);
+        assert!(!report.is_valid());
+    }
+
+    #[test]
+    fn test_write_paths() {
+        let report = PathsReport::from_reader(PATHS.as_bytes()).unwrap();
+
+        let mut relative = Vec::new();
+        report.write_paths(&mut relative, None).unwrap();
+        let relative = String::from_utf8(relative).unwrap();
+        assert!(relative.lines().all(|l| l.starts_with("crawl-data/")));
+
+        let mut mirrored = Vec::new();
+        report
+            .write_paths(&mut mirrored, Some("https://mirror.example.org/cc"))
+            .unwrap();
+        let mirrored = String::from_utf8(mirrored).unwrap();
+        assert_eq!(mirrored.lines().count(), 3);
+        assert!(mirrored
+            .lines()
+            .all(|l| l.starts_with("https://mirror.example.org/cc/crawl-data/CC-MAIN-2023-06/")));
+    }
+}
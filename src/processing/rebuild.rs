@@ -120,10 +120,14 @@ where
             // Since bounds are inclusive, for a document that starts at x and ends at y we have to skip to x
             // and then take y-x+1.
             let nb_take = rb_info.line_end() - rb_info.line_start() + 1;
+            let removed_lines = rb_info.removed_lines();
             let body = String::from_utf8_lossy(&body)
                 .lines()
+                .enumerate()
                 .skip(nb_skip)
                 .take(nb_take)
+                .filter(|(idx, _)| !removed_lines.contains(idx))
+                .map(|(_, line)| line)
                 .join("\n");
 
             // compute body length to update content-length
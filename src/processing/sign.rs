@@ -0,0 +1,262 @@
+/*! Release signing
+
+Produces detached signatures for a packaged corpus, since distribution mirrors require authenticity verification.
+
+Signed files are archives (`.gz`, `.zst`, `.xz`, `.tar`) and checksum manifests (`<lang>_sha256.txt`),
+found recursively in the corpus folder.
+Signatures are produced by an external command, next to each signed file:
+
+- [minisign](https://jedisct1.github.io/minisign/): `<file>.minisig`. The secret key has to be passwordless, since signing is not interactive.
+- GPG: `<file>.asc` (ASCII-armored), using the default key or a given key id.
+
+Signatures can then be checked with `minisign -V -p <public key> -m <file>` or `gpg --verify <file>.asc <file>`.
+!*/
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+
+use log::{debug, info};
+
+use crate::error::Error;
+
+/// Extensions of files that are signed.
+const ARCHIVE_EXTENSIONS: [&str; 4] = ["gz", "zst", "xz", "tar"];
+
+/// Suffix of checksum manifests.
+const MANIFEST_SUFFIX: &str = "_sha256.txt";
+
+/// External signing tool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Signer {
+    /// minisign, with a secret key file.
+    Minisign { secret_key: PathBuf },
+    /// GPG, with an optional key id (default key otherwise).
+    Gpg { key: Option<String> },
+}
+
+impl Signer {
+    /// Create a signer from a tool name (`minisign` or `gpg`) and an optional key.
+    ///
+    /// minisign requires a secret key file.
+    pub fn new(tool: &str, key: Option<String>) -> Result<Self, Error> {
+        match (tool.parse::<SignerKind>()?, key) {
+            (SignerKind::Minisign, Some(key)) => Ok(Self::Minisign {
+                secret_key: PathBuf::from(key),
+            }),
+            (SignerKind::Minisign, None) => Err(Error::Custom(
+                "minisign requires a secret key file".to_string(),
+            )),
+            (SignerKind::Gpg, key) => Ok(Self::Gpg { key }),
+        }
+    }
+
+    /// Get the signature path of a file.
+    pub fn signature_path(&self, path: &Path) -> PathBuf {
+        let extension = match self {
+            Self::Minisign { .. } => "minisig",
+            Self::Gpg { .. } => "asc",
+        };
+        let mut signature = path.as_os_str().to_owned();
+        signature.push(".");
+        signature.push(extension);
+        PathBuf::from(signature)
+    }
+
+    /// Build the signing command of a file.
+    fn command(&self, path: &Path) -> Command {
+        let signature = self.signature_path(path);
+        match self {
+            Self::Minisign { secret_key } => {
+                let mut cmd = Command::new("minisign");
+                cmd.arg("-S")
+                    .arg("-s")
+                    .arg(secret_key)
+                    .arg("-m")
+                    .arg(path)
+                    .arg("-x")
+                    .arg(signature);
+                cmd
+            }
+            Self::Gpg { key } => {
+                let mut cmd = Command::new("gpg");
+                cmd.args(["--batch", "--yes", "--armor", "--detach-sign"]);
+                if let Some(key) = key {
+                    cmd.arg("--local-user").arg(key);
+                }
+                cmd.arg("--output").arg(signature).arg(path);
+                cmd
+            }
+        }
+    }
+
+    /// Sign a file, returning the signature path.
+    pub fn sign(&self, path: &Path) -> Result<PathBuf, Error> {
+        let mut cmd = self.command(path);
+        debug!("running {:?}", cmd);
+        let output = cmd.output()?;
+        if !output.status.success() {
+            return Err(Error::Custom(format!(
+                "could not sign {:?} ({}): {}",
+                path,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        Ok(self.signature_path(path))
+    }
+}
+
+/// Signing tools, used to parse [Signer] from the command line.
+enum SignerKind {
+    Minisign,
+    Gpg,
+}
+
+impl FromStr for SignerKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "minisign" => Ok(Self::Minisign),
+            "gpg" => Ok(Self::Gpg),
+            other => Err(Error::Custom(format!(
+                "unknown signing tool {other} (expected minisign or gpg)"
+            ))),
+        }
+    }
+}
+
+/// Returns true if the file is an archive or a checksum manifest.
+fn is_signable(path: &Path) -> bool {
+    let is_archive = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ARCHIVE_EXTENSIONS.contains(&ext));
+    let is_manifest = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(MANIFEST_SUFFIX));
+
+    is_archive || is_manifest
+}
+
+/// Recursively get the files to sign in `src`, sorted.
+fn signable_files(src: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(src)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(signable_files(&path)?);
+        } else if is_signable(&path) {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Sign archives and checksum manifests of a packaged corpus, returning signature paths.
+pub fn sign_corpus(src: &Path, signer: &Signer) -> Result<Vec<PathBuf>, Error> {
+    let files = signable_files(src)?;
+    info!("signing {} files in {:?}", files.len(), src);
+
+    let signatures = files
+        .iter()
+        .map(|file| {
+            debug!("signing {:?}", file);
+            signer.sign(file)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    info!("wrote {} signatures", signatures.len());
+    Ok(signatures)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, path::Path};
+
+    use super::{is_signable, signable_files, Signer};
+
+    #[test]
+    fn test_new() {
+        assert_eq!(Signer::new("gpg", None).unwrap(), Signer::Gpg { key: None });
+        assert_eq!(
+            Signer::new("minisign", Some("key.sec".to_string())).unwrap(),
+            Signer::Minisign {
+                secret_key: "key.sec".into()
+            }
+        );
+        assert!(Signer::new("minisign", None).is_err());
+        assert!(Signer::new("ssh", None).is_err());
+    }
+
+    #[test]
+    fn test_signature_path() {
+        let path = Path::new("en/en_meta.jsonl.gz");
+        assert_eq!(
+            Signer::Gpg { key: None }.signature_path(path),
+            Path::new("en/en_meta.jsonl.gz.asc")
+        );
+        assert_eq!(
+            Signer::Minisign {
+                secret_key: "key.sec".into()
+            }
+            .signature_path(path),
+            Path::new("en/en_meta.jsonl.gz.minisig")
+        );
+    }
+
+    #[test]
+    fn test_command() {
+        let cmd = Signer::Gpg {
+            key: Some("0xABCD".to_string()),
+        }
+        .command(Path::new("en_sha256.txt"));
+        assert_eq!(cmd.get_program(), "gpg");
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(
+            args,
+            [
+                "--batch",
+                "--yes",
+                "--armor",
+                "--detach-sign",
+                "--local-user",
+                "0xABCD",
+                "--output",
+                "en_sha256.txt.asc",
+                "en_sha256.txt"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_signable_files() {
+        assert!(is_signable(Path::new("en_meta.jsonl.gz")));
+        assert!(is_signable(Path::new("en_sha256.txt")));
+        assert!(!is_signable(Path::new("en_meta.jsonl")));
+        assert!(!is_signable(Path::new("en_meta.jsonl.gz.asc")));
+
+        let dir = tempfile::tempdir().unwrap();
+        let en = dir.path().join("en");
+        std::fs::create_dir(&en).unwrap();
+        for path in [
+            en.join("en_meta.jsonl.gz"),
+            en.join("en_sha256.txt"),
+            en.join("en_meta.jsonl.gz.asc"),
+            dir.path().join("README.md"),
+        ] {
+            File::create(path).unwrap();
+        }
+
+        assert_eq!(
+            signable_files(dir.path()).unwrap(),
+            vec![en.join("en_meta.jsonl.gz"), en.join("en_sha256.txt")]
+        );
+    }
+}
@@ -0,0 +1,274 @@
+/*! Token statistics
+
+Tokenizes a sample of the documents of a corpus with a user-provided tokenizer,
+and reports tokens per language and bytes per token to guide data-mixture decisions.
+
+Tokenizers are loaded from HuggingFace `tokenizer.json` files.
+SentencePiece models have to be converted to that format first (e.g. with `transformers`' `convert_slow_tokenizer`).
+
+Documents are sampled independently with a given probability, using a fixed seed so that reports are reproducible.
+Total token counts are estimated by extrapolating sampled counts to the number of documents of each language.
+
+Only available with the `tokenizers` feature.
+!*/
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use log::{debug, info, warn};
+use oscar_io::v3::Reader as DocReader;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Serialize;
+use tokenizers::Tokenizer;
+
+use crate::{error::Error, processing::merge::file_lang};
+
+/// Number of sampled documents tokenized at once.
+const BATCH_SIZE: usize = 256;
+
+/// Token statistics of a given language.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct LangTokenStats {
+    /// number of documents
+    pub docs: usize,
+    /// number of sampled documents
+    pub sampled_docs: usize,
+    /// number of bytes in sampled documents
+    pub sampled_bytes: usize,
+    /// number of tokens in sampled documents
+    pub sampled_tokens: usize,
+}
+
+impl LangTokenStats {
+    /// Average number of bytes per token. [None] if no token has been seen.
+    pub fn bytes_per_token(&self) -> Option<f64> {
+        (self.sampled_tokens > 0).then(|| self.sampled_bytes as f64 / self.sampled_tokens as f64)
+    }
+
+    /// Estimated number of tokens of the language. [None] if no document has been sampled.
+    pub fn estimated_tokens(&self) -> Option<f64> {
+        (self.sampled_docs > 0)
+            .then(|| self.sampled_tokens as f64 * self.docs as f64 / self.sampled_docs as f64)
+    }
+}
+
+/// Samples and tokenizes documents.
+pub struct TokenStats {
+    tokenizer: Tokenizer,
+    sample_rate: f64,
+    rng: StdRng,
+    stats: BTreeMap<String, LangTokenStats>,
+}
+
+impl TokenStats {
+    /// Load a tokenizer file. `sample_rate` has to be in `]0, 1]`.
+    pub fn new(tokenizer: &Path, sample_rate: f64, seed: u64) -> Result<Self, Error> {
+        if !(sample_rate > 0.0 && sample_rate <= 1.0) {
+            return Err(Error::Custom(format!(
+                "invalid sample rate {sample_rate} (expected ]0, 1])"
+            )));
+        }
+        let tokenizer = Tokenizer::from_file(tokenizer)
+            .map_err(|e| Error::Custom(format!("could not load tokenizer {tokenizer:?}: {e}")))?;
+
+        Ok(Self {
+            tokenizer,
+            sample_rate,
+            rng: StdRng::seed_from_u64(seed),
+            stats: BTreeMap::new(),
+        })
+    }
+
+    /// Count tokens of a batch of documents.
+    fn count(&self, lang_stats: &mut LangTokenStats, batch: Vec<String>) -> Result<(), Error> {
+        lang_stats.sampled_docs += batch.len();
+        lang_stats.sampled_bytes += batch.iter().map(String::len).sum::<usize>();
+        let encodings = self
+            .tokenizer
+            .encode_batch(batch, false)
+            .map_err(|e| Error::Custom(format!("tokenization error: {e}")))?;
+        lang_stats.sampled_tokens += encodings.iter().map(|e| e.len()).sum::<usize>();
+        Ok(())
+    }
+
+    /// Sample and tokenize documents of a `<lang>_meta.jsonl` file.
+    pub fn add_file(&mut self, lang: &str, path: &Path) -> Result<(), Error> {
+        debug!("[{}] sampling {:?}", lang, path);
+        let mut lang_stats = self.stats.remove(lang).unwrap_or_default();
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        for doc in DocReader::from_path(path)? {
+            let doc = doc?;
+            lang_stats.docs += 1;
+            if self.rng.gen_bool(self.sample_rate) {
+                batch.push(doc.content().to_string());
+            }
+            if batch.len() == BATCH_SIZE {
+                self.count(&mut lang_stats, std::mem::take(&mut batch))?;
+            }
+        }
+        self.count(&mut lang_stats, batch)?;
+
+        self.stats.insert(lang.to_string(), lang_stats);
+        Ok(())
+    }
+
+    /// Get statistics by language.
+    pub fn stats(&self) -> &BTreeMap<String, LangTokenStats> {
+        &self.stats
+    }
+}
+
+/// Compute token statistics of the corpus in `src`, optionally writing them as JSON in `dst`.
+pub fn token_stats(
+    src: &Path,
+    tokenizer: &Path,
+    sample_rate: f64,
+    seed: u64,
+    dst: Option<&Path>,
+) -> Result<BTreeMap<String, LangTokenStats>, Error> {
+    let mut token_stats = TokenStats::new(tokenizer, sample_rate, seed)?;
+
+    // sort to keep sampling reproducible
+    let mut paths: Vec<_> = std::fs::read_dir(src)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+
+    for path in paths {
+        if let Some(lang) = file_lang(&path) {
+            token_stats.add_file(lang.as_str(), &path)?;
+        }
+    }
+
+    let stats = token_stats.stats;
+    if stats.is_empty() {
+        warn!("no corpus files found in {:?}", src);
+    }
+    for (lang, lang_stats) in &stats {
+        info!(
+            "[{}] {} docs ({} sampled): {} tokens, {:.2} bytes/token, ~{:.0} tokens",
+            lang,
+            lang_stats.docs,
+            lang_stats.sampled_docs,
+            lang_stats.sampled_tokens,
+            lang_stats.bytes_per_token().unwrap_or(0.0),
+            lang_stats.estimated_tokens().unwrap_or(0.0)
+        );
+    }
+
+    if let Some(dst) = dst {
+        let mut w = BufWriter::new(File::create(dst)?);
+        serde_json::to_writer_pretty(&mut w, &stats)?;
+        w.flush()?;
+        info!("wrote token statistics to {:?}", dst);
+    }
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path};
+
+    use oscar_io::{
+        common::Identification,
+        v3::{Document, Metadata, WriterTrait},
+    };
+    use oxilangtag::LanguageTag;
+    use tokenizers::{models::wordlevel::WordLevel, pre_tokenizers::whitespace::Whitespace};
+
+    use crate::io::LangFilesDoc;
+
+    use super::{token_stats, LangTokenStats, TokenStats};
+
+    fn gen_tokenizer(path: &Path) {
+        let vocab = [("[UNK]", 0), ("hello", 1), ("world", 2)]
+            .into_iter()
+            .map(|(token, id)| (token.to_string(), id))
+            .collect();
+        let model = WordLevel::builder()
+            .vocab(vocab)
+            .unk_token("[UNK]".to_string())
+            .build()
+            .unwrap();
+        let mut tokenizer = tokenizers::Tokenizer::new(model);
+        tokenizer.with_pre_tokenizer(Some(Whitespace {}));
+        tokenizer.save(path, false).unwrap();
+    }
+
+    fn gen_corpus(dst: &Path, lang: &str, contents: &[&str]) {
+        let lang = LanguageTag::parse(lang.to_string()).unwrap();
+        let id = Identification::new(lang.clone(), 1.0);
+        let docs = contents
+            .iter()
+            .map(|content| {
+                let metadata = Metadata::new(&id, &[Some(id.clone())]);
+                Document::new(content.to_string(), HashMap::new(), metadata)
+            })
+            .collect();
+
+        let langfiles = LangFilesDoc::new(dst, None);
+        langfiles.insert_writer(lang.clone()).unwrap();
+        langfiles
+            .writer(&lang)
+            .unwrap()
+            .lock()
+            .unwrap()
+            .write(docs)
+            .unwrap();
+        langfiles.close_all().unwrap();
+    }
+
+    #[test]
+    fn test_lang_token_stats() {
+        let stats = LangTokenStats {
+            docs: 10,
+            sampled_docs: 2,
+            sampled_bytes: 30,
+            sampled_tokens: 6,
+        };
+        assert_eq!(stats.bytes_per_token(), Some(5.0));
+        assert_eq!(stats.estimated_tokens(), Some(30.0));
+        assert_eq!(LangTokenStats::default().bytes_per_token(), None);
+        assert_eq!(LangTokenStats::default().estimated_tokens(), None);
+    }
+
+    #[test]
+    fn test_invalid_sample_rate() {
+        let dir = tempfile::tempdir().unwrap();
+        let tokenizer = dir.path().join("tokenizer.json");
+        gen_tokenizer(&tokenizer);
+        assert!(TokenStats::new(&tokenizer, 0.0, 0).is_err());
+        assert!(TokenStats::new(&tokenizer, 1.5, 0).is_err());
+        assert!(TokenStats::new(&dir.path().join("missing.json"), 1.0, 0).is_err());
+    }
+
+    #[test]
+    fn test_token_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let tokenizer = dir.path().join("tokenizer.json");
+        gen_tokenizer(&tokenizer);
+        let corpus = dir.path().join("corpus");
+        std::fs::create_dir(&corpus).unwrap();
+        gen_corpus(&corpus, "en", &["hello world", "hello hello world"]);
+        gen_corpus(&corpus, "fr", &["bonjour"]);
+
+        let report = dir.path().join("tokens.json");
+        let stats = token_stats(&corpus, &tokenizer, 1.0, 42, Some(&report)).unwrap();
+
+        assert_eq!(
+            stats.get("en"),
+            Some(&LangTokenStats {
+                docs: 2,
+                sampled_docs: 2,
+                sampled_bytes: 28,
+                sampled_tokens: 5,
+            })
+        );
+        assert_eq!(stats.get("fr").unwrap().sampled_tokens, 1);
+        assert!(report.exists());
+    }
+}
@@ -0,0 +1,191 @@
+//! Tar archives of shards
+//!
+//! Some mirrors deliver crawls as tar archives (`.tar`, `.tar.gz` or `.tgz`) of shards.
+//! Shards are read from archives without extracting them:
+//!
+//! - members of plain tar archives are read in place,
+//! - gzipped archives can't be seeked into, so they are decompressed up to the member
+//!   by a background thread, for each member.
+//!   Prefer plain tar archives when they contain many shards.
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+};
+
+use flate2::read::MultiGzDecoder;
+use log::debug;
+use tar::{Archive, Entries};
+
+use crate::error::Error;
+
+use super::remote::{ChunkReader, CHUNK_SIZE, READ_AHEAD};
+
+/// Check if `path` is a tar archive, from its extension.
+pub fn is_archive(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    name.ends_with(".tar") || is_gzipped(path)
+}
+
+fn is_gzipped(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    name.ends_with(".tar.gz") || name.ends_with(".tgz")
+}
+
+/// List the files of an archive, in archive order.
+pub fn archive_members(archive: &Path) -> Result<Vec<PathBuf>, Error> {
+    let file = File::open(archive)?;
+    if is_gzipped(archive) {
+        files(Archive::new(MultiGzDecoder::new(file)).entries()?)
+    } else {
+        files(Archive::new(file).entries_with_seek()?)
+    }
+}
+
+fn files<R: Read>(entries: Entries<R>) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.header().entry_type().is_file() {
+            files.push(entry.path()?.into_owned());
+        }
+    }
+    Ok(files)
+}
+
+/// Open `member` of `archive`.
+///
+/// For gzipped archives, a missing member is only reported when reading.
+pub fn open_member(archive: &Path, member: &Path) -> Result<Box<dyn Read + Send>, Error> {
+    if is_gzipped(archive) {
+        return Ok(Box::new(spawn_member_reader(
+            archive.to_path_buf(),
+            member.to_path_buf(),
+        )));
+    }
+
+    let mut tar = Archive::new(File::open(archive)?);
+    for entry in tar.entries_with_seek()? {
+        let entry = entry?;
+        if entry.path()? == member {
+            let mut file = File::open(archive)?;
+            file.seek(SeekFrom::Start(entry.raw_file_position()))?;
+            return Ok(Box::new(file.take(entry.size())));
+        }
+    }
+    Err(Error::Custom(format!(
+        "{:?} not found in {:?}",
+        member, archive
+    )))
+}
+
+/// Decompress `archive` up to `member` in a background thread, sending the member's content.
+fn spawn_member_reader(archive: PathBuf, member: PathBuf) -> ChunkReader {
+    let (sender, receiver) = mpsc::sync_channel(READ_AHEAD);
+    thread::spawn(move || {
+        let send_member = || -> io::Result<()> {
+            let mut tar = Archive::new(MultiGzDecoder::new(File::open(&archive)?));
+            for entry in tar.entries()? {
+                let mut entry = entry?;
+                if entry.path()? != member {
+                    continue;
+                }
+                loop {
+                    let mut chunk = vec![0; CHUNK_SIZE];
+                    match entry.read(&mut chunk) {
+                        Ok(0) => return Ok(()),
+                        Ok(n) => chunk.truncate(n),
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(e) => return Err(e),
+                    }
+                    if sender.send(Ok(chunk)).is_err() {
+                        debug!("reader of {:?} in {:?} dropped, stopping", member, archive);
+                        return Ok(());
+                    }
+                }
+            }
+            Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{:?} not found in {:?}", member, archive),
+            ))
+        };
+        if let Err(e) = send_member() {
+            let _ = sender.send(Err(e));
+        }
+    });
+    ChunkReader::new(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        io::{Read, Write},
+        path::{Path, PathBuf},
+    };
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::{archive_members, is_archive, open_member};
+
+    /// Append `members` (path, content) to an archive.
+    fn append<W: Write>(builder: &mut tar::Builder<W>, members: &[(&str, &[u8])]) {
+        for (name, content) in members {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, *content).unwrap();
+        }
+    }
+
+    /// Write an archive of `members`, gzipped or not.
+    fn write_archive(path: &Path, members: &[(&str, &[u8])]) {
+        let file = File::create(path).unwrap();
+        if path.to_string_lossy().ends_with(".tar") {
+            let mut builder = tar::Builder::new(file);
+            append(&mut builder, members);
+            builder.finish().unwrap();
+        } else {
+            let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+            append(&mut builder, members);
+            builder.into_inner().unwrap().finish().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_is_archive() {
+        assert!(is_archive(Path::new("shards/crawl.tar")));
+        assert!(is_archive(Path::new("shards/crawl.tar.gz")));
+        assert!(is_archive(Path::new("shards/crawl.tgz")));
+        assert!(!is_archive(Path::new("shards/0.txt.gz")));
+    }
+
+    #[test]
+    fn test_members() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["crawl.tar", "crawl.tar.gz"] {
+            let archive = dir.path().join(name);
+            write_archive(
+                &archive,
+                &[("wet/0.txt.gz", b"first"), ("wet/1.txt.gz", b"second")],
+            );
+
+            assert_eq!(
+                archive_members(&archive).unwrap(),
+                vec![PathBuf::from("wet/0.txt.gz"), PathBuf::from("wet/1.txt.gz")]
+            );
+            let mut content = String::new();
+            open_member(&archive, Path::new("wet/1.txt.gz"))
+                .unwrap()
+                .read_to_string(&mut content)
+                .unwrap();
+            assert_eq!(content, "second");
+
+            let missing = open_member(&archive, Path::new("wet/2.txt.gz"))
+                .and_then(|mut member| Ok(member.read_to_end(&mut Vec::new())?));
+            assert!(missing.is_err());
+        }
+    }
+}
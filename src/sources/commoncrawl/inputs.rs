@@ -0,0 +1,476 @@
+//! Shard inputs
+//!
+//! Resolves pipeline sources into a single list of shard files. A source can be:
+//!
+//! - a directory: every entry of the directory is considered a shard,
+//! - a file: a single shard,
+//! - a glob pattern (`shards/*.txt.gz`): every matched file is considered a shard.
+//!
+//! Shards present in several sources are only listed once.
+//! Tar archives (see [super::archive]) are expanded into the shards they contain, which are read in place.
+//!
+//! Shards can also be streamed from their URL (see [remote_shards]) instead of being read from disk.
+//!
+//! Shards are processed in parallel through a [ShardSet], that balances shards of different sizes between threads.
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    fmt::Display,
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use flate2::read::MultiGzDecoder;
+use log::{error, info, warn};
+use rayon::{
+    iter::{IndexedParallelIterator, IntoParallelIterator, MapWith, MaxLen, ParallelIterator},
+    range,
+};
+use url::Url;
+
+use crate::error::Error;
+
+use super::{archive, remote::RemoteReader, Wet};
+
+/// Base URL of CommonCrawl's HTTPS endpoint.
+pub const BASE_URL: &str = "https://data.commoncrawl.org/";
+
+/// Shard to process.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ShardInput {
+    /// Shard file, whose number is its file stem (`<number>.txt.gz`).
+    Local(PathBuf),
+    /// Shard read from its URL, with its number.
+    Remote { id: usize, url: Url },
+    /// Shard file inside a tar archive, numbered like [ShardInput::Local] ones.
+    Archived { archive: PathBuf, member: PathBuf },
+}
+
+impl ShardInput {
+    /// Get the shard number, that identifies the shard in outputs.
+    pub fn id(&self) -> Result<usize, Error> {
+        match self {
+            Self::Local(path) => path_id(path),
+            Self::Remote { id, .. } => Ok(*id),
+            Self::Archived { member, .. } => path_id(member),
+        }
+    }
+
+    /// Open the (gzipped) shard, requesting remote ones.
+    pub fn open(&self) -> Result<Wet<Box<dyn BufRead + Send>>, Error> {
+        let reader: Box<dyn Read + Send> = match self {
+            Self::Local(path) => Box::new(File::open(path)?),
+            Self::Remote { url, .. } => Box::new(RemoteReader::get(url.clone())?),
+            Self::Archived { archive, member } => archive::open_member(archive, member)?,
+        };
+
+        // shards are multipart gzip files
+        Ok(Wet::new(Box::new(BufReader::new(MultiGzDecoder::new(
+            reader,
+        )))))
+    }
+}
+
+impl ShardInput {
+    /// Get the size of the (gzipped) shard, if it is known without reading it: only local files are measured.
+    pub fn size(&self) -> Option<u64> {
+        match self {
+            Self::Local(path) => std::fs::metadata(path).ok().map(|m| m.len()),
+            Self::Remote { .. } | Self::Archived { .. } => None,
+        }
+    }
+}
+
+/// Shards to process in parallel, along with their index in the listing.
+///
+/// Shards are ordered by decreasing size, shards of unknown size coming last in listing order.
+/// Rayon splits tasks in halves and idle threads steal the far halves, which would hold the smallest shards,
+/// so tasks don't get a shard of their own: each one takes the next shard of a shared queue when it starts.
+/// Shards are then started biggest first, whatever thread runs them,
+/// and a few threads don't end up with the biggest ones at the end of the run.
+pub struct ShardSet(Vec<(usize, ShardInput)>);
+
+/// Shards of a [ShardSet], handed out in order.
+pub struct ShardQueue {
+    shards: Vec<(usize, ShardInput)>,
+    next: AtomicUsize,
+}
+
+impl ShardQueue {
+    /// Take the next shard. Must not be called more times than there are shards.
+    fn take(queue: &mut Arc<Self>, _task: usize) -> (usize, ShardInput) {
+        let next = queue.next.fetch_add(1, Ordering::Relaxed);
+        queue.shards[next].clone()
+    }
+}
+
+impl ShardSet {
+    pub fn new(shards: Vec<ShardInput>) -> Self {
+        let mut shards: Vec<_> = shards.into_iter().enumerate().collect();
+        shards.sort_by_cached_key(|(_, shard)| Reverse(shard.size()));
+        Self(shards)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// One task per shard, each taking the next shard of the queue.
+type ShardTasks = MapWith<
+    MaxLen<range::Iter<usize>>,
+    Arc<ShardQueue>,
+    fn(&mut Arc<ShardQueue>, usize) -> (usize, ShardInput),
+>;
+
+impl IntoParallelIterator for ShardSet {
+    type Iter = ShardTasks;
+    type Item = (usize, ShardInput);
+
+    fn into_par_iter(self) -> Self::Iter {
+        let tasks = (0..self.len()).into_par_iter().with_max_len(1);
+        let queue = Arc::new(ShardQueue {
+            shards: self.0,
+            next: AtomicUsize::new(0),
+        });
+        tasks.map_with(
+            queue,
+            ShardQueue::take as fn(&mut Arc<ShardQueue>, usize) -> _,
+        )
+    }
+}
+
+impl Display for ShardInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Local(path) => write!(f, "{}", path.display()),
+            Self::Remote { url, .. } => write!(f, "{}", url),
+            Self::Archived { archive, member } => {
+                write!(f, "{} in {}", member.display(), archive.display())
+            }
+        }
+    }
+}
+
+/// Get the shard number of a shard file (`<number>.txt.gz`).
+fn path_id(path: &Path) -> Result<usize, Error> {
+    let shard_number = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .and_then(|s| s.split('.').next())
+        .map(|s| s.parse::<usize>());
+
+    match shard_number {
+        Some(Ok(sn)) => Ok(sn),
+        Some(Err(e)) => Err(Error::Custom(format!("{:?}", e))),
+        None => Err(Error::Custom(format!(
+            "Couldn't extract shard number from {:?}",
+            path
+        ))),
+    }
+}
+
+/// Get remote shards from a `wet.paths` file.
+///
+/// Relative paths are resolved against `base_url`, and full URLs are kept as is.
+/// Shards are numbered by their line in the file (starting from 0), like downloaded ones.
+pub fn remote_shards(paths_file: &Path, base_url: &Url) -> Result<Vec<ShardInput>, Error> {
+    let reader = BufReader::new(File::open(paths_file)?);
+    let mut shards = Vec::new();
+    for (id, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let url = match Url::parse(line) {
+            Ok(url) => url,
+            Err(_) => base_url.join(line)?,
+        };
+        shards.push(ShardInput::Remote { id, url });
+    }
+
+    Ok(shards)
+}
+
+/// Get local shards from sources, in source order, expanding tar archives.
+pub fn local_shards(srcs: &[PathBuf]) -> Result<Vec<ShardInput>, Error> {
+    let mut shards = Vec::new();
+    for path in shard_paths(srcs)? {
+        if archive::is_archive(&path) {
+            let members = archive::archive_members(&path)?;
+            info!("Found {} shards in {:?}", members.len(), path);
+            shards.extend(members.into_iter().map(|member| ShardInput::Archived {
+                archive: path.clone(),
+                member,
+            }));
+        } else {
+            shards.push(ShardInput::Local(path));
+        }
+    }
+    Ok(shards)
+}
+
+/// Get shard files from sources, in source order.
+pub fn shard_paths(srcs: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+    let mut paths = Vec::new();
+    for src in srcs {
+        if src.is_dir() {
+            for shard in std::fs::read_dir(src)? {
+                match shard {
+                    Ok(shard) => paths.push(shard.path()),
+                    Err(e) => error!("error reading shard directory: {}", e),
+                }
+            }
+        } else if src.is_file() {
+            paths.push(src.clone());
+        } else {
+            let pattern = src
+                .to_str()
+                .ok_or_else(|| Error::Custom(format!("invalid source {src:?}")))?;
+            let nb_paths = paths.len();
+            for shard in glob::glob(pattern)? {
+                let shard = shard?;
+                if shard.is_file() {
+                    paths.push(shard);
+                }
+            }
+            if paths.len() == nb_paths {
+                warn!("source {:?} does not match any shard", src);
+            }
+        }
+    }
+
+    let mut seen = HashSet::new();
+    paths.retain(|path| seen.insert(path.clone()));
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        io::{Read, Write},
+        net::TcpListener,
+        path::PathBuf,
+        sync::Mutex,
+    };
+
+    use flate2::{write::GzEncoder, Compression};
+    use url::Url;
+
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+    use super::{local_shards, remote_shards, shard_paths, ShardInput, ShardSet};
+
+    #[test]
+    fn test_shard_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        std::fs::create_dir(&a).unwrap();
+        std::fs::create_dir(&b).unwrap();
+        for path in [
+            a.join("0.txt.gz"),
+            a.join("1.txt.gz"),
+            b.join("2.txt.gz"),
+            b.join("3.txt"),
+        ] {
+            File::create(path).unwrap();
+        }
+
+        let mut paths = shard_paths(&[
+            a.clone(),
+            b.join("2.txt.gz"),
+            b.join("*.txt.gz"),
+            dir.path().join("nothing/*"),
+        ])
+        .unwrap();
+        paths.sort();
+
+        assert_eq!(
+            paths,
+            vec![a.join("0.txt.gz"), a.join("1.txt.gz"), b.join("2.txt.gz")]
+        );
+    }
+
+    #[test]
+    fn test_remote_shards() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths_file = dir.path().join("wet.paths");
+        std::fs::write(
+            &paths_file,
+            "crawl-data/CC-MAIN-2023-06/segments/0/wet/0.warc.wet.gz\n\nhttp://mirror.example/1.warc.wet.gz\n",
+        )
+        .unwrap();
+
+        let base_url = Url::parse(super::BASE_URL).unwrap();
+        let shards = remote_shards(&paths_file, &base_url).unwrap();
+        assert_eq!(shards.len(), 2);
+        assert_eq!(
+            shards[0].to_string(),
+            "https://data.commoncrawl.org/crawl-data/CC-MAIN-2023-06/segments/0/wet/0.warc.wet.gz"
+        );
+        assert_eq!(shards[0].id().unwrap(), 0);
+        assert_eq!(shards[1].to_string(), "http://mirror.example/1.warc.wet.gz");
+        assert_eq!(shards[1].id().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_local_id() {
+        assert_eq!(
+            ShardInput::Local(PathBuf::from("shards/12.txt.gz"))
+                .id()
+                .unwrap(),
+            12
+        );
+        assert!(ShardInput::Local(PathBuf::from("shards/foo.txt.gz"))
+            .id()
+            .is_err());
+    }
+
+    #[test]
+    fn test_open_remote() {
+        let record = "WARC/1.0\r\nWARC-Type: conversion\r\nWARC-Record-ID: <urn:uuid:0>\r\nWARC-Date: 2023-01-01T00:00:00Z\r\nContent-Length: 5\r\n\r\nhello\r\n\r\n";
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(record.as_bytes()).unwrap();
+        let body = encoder.finish().unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/0.txt.gz",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = stream.read(&mut buf).unwrap();
+            let headers = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            stream.write_all(headers.as_bytes()).unwrap();
+            stream.write_all(&body).unwrap();
+        });
+
+        let shard = ShardInput::Remote { id: 0, url };
+        let records: Vec<_> = shard
+            .open()
+            .unwrap()
+            .iter
+            .map(|record| record.unwrap())
+            .collect();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].body(), b"hello");
+    }
+
+    #[test]
+    fn test_archived_shards() {
+        let record = "WARC/1.0\r\nWARC-Type: conversion\r\nWARC-Record-ID: <urn:uuid:0>\r\nWARC-Date: 2023-01-01T00:00:00Z\r\nContent-Length: 5\r\n\r\nhello\r\n\r\n";
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(record.as_bytes()).unwrap();
+        let shard = encoder.finish().unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("crawl.tar");
+        let mut builder = tar::Builder::new(File::create(&archive).unwrap());
+        for name in ["wet/3.txt.gz", "wet/4.txt.gz"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(shard.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, &shard[..]).unwrap();
+        }
+        builder.finish().unwrap();
+        File::create(dir.path().join("0.txt.gz")).unwrap();
+
+        let mut shards = local_shards(&[dir.path().to_path_buf()]).unwrap();
+        shards.sort();
+        assert_eq!(shards.len(), 3);
+        assert_eq!(shards[0], ShardInput::Local(dir.path().join("0.txt.gz")));
+        assert_eq!(
+            shards[2],
+            ShardInput::Archived {
+                archive: archive.clone(),
+                member: PathBuf::from("wet/4.txt.gz")
+            }
+        );
+        assert_eq!(shards[2].id().unwrap(), 4);
+
+        let records: Vec<_> = shards[2]
+            .open()
+            .unwrap()
+            .iter
+            .map(|record| record.unwrap())
+            .collect();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].body(), b"hello");
+    }
+
+    #[test]
+    fn test_shard_set() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut shards = Vec::new();
+        for (id, size) in [(0, 10), (1, 30), (2, 20)] {
+            let path = dir.path().join(format!("{id}.txt.gz"));
+            std::fs::write(&path, vec![0; size]).unwrap();
+            shards.push(ShardInput::Local(path));
+        }
+        shards.insert(
+            1,
+            ShardInput::Remote {
+                id: 3,
+                url: Url::parse("https://example.com/3.txt.gz").unwrap(),
+            },
+        );
+
+        let set = ShardSet::new(shards);
+        assert_eq!(set.len(), 4);
+        let order: Vec<_> = set.0.iter().map(|(_, shard)| shard.id().unwrap()).collect();
+        assert_eq!(order, vec![1, 2, 0, 3]);
+
+        // listing indices are kept
+        let mut processed: Vec<_> = set
+            .into_par_iter()
+            .map(|(idx, shard)| (idx, shard.id().unwrap()))
+            .collect();
+        processed.sort();
+        assert_eq!(processed, vec![(0, 0), (1, 3), (2, 1), (3, 2)]);
+    }
+
+    #[test]
+    fn test_shard_set_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut shards = Vec::new();
+        for (id, size) in [(0, 10), (1, 30), (2, 20), (3, 40), (4, 50)] {
+            let path = dir.path().join(format!("{id}.txt.gz"));
+            std::fs::write(&path, vec![0; size]).unwrap();
+            shards.push(ShardInput::Local(path));
+        }
+
+        // shards are started biggest first, whatever the order rayon runs tasks in
+        let started = Mutex::new(Vec::new());
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        pool.install(|| {
+            ShardSet::new(shards)
+                .into_par_iter()
+                .for_each(|(_, shard)| {
+                    started.lock().unwrap().push(shard.id().unwrap());
+                })
+        });
+        assert_eq!(started.into_inner().unwrap(), vec![4, 3, 1, 2, 0]);
+    }
+}
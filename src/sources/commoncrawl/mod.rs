@@ -1,6 +1,13 @@
 /*!
 Contains files relative to CommonCrawl.
 !*/
+mod archive;
+mod inputs;
+mod paths_filter;
+mod remote;
 mod shard;
 
+pub use inputs::{local_shards, remote_shards, shard_paths, ShardInput, ShardSet, BASE_URL};
+pub use paths_filter::PathFilter;
+pub use remote::RemoteReader;
 pub use shard::Wet;
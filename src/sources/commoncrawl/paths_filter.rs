@@ -0,0 +1,132 @@
+//! Selection of `wet.paths` entries
+//!
+//! Large crawls can be partitioned across machines by keeping only some of the paths of a `wet.paths` file:
+//!
+//! - by segment: segments are numbered in their order of appearance in the file (starting from 0),
+//!   and a range of segment numbers is kept (e.g. `0..100`),
+//! - by a regular expression matching paths.
+//!
+//! Shards keep their number (their line in the file), so that outputs of partitions don't collide.
+use std::{collections::HashMap, ops::Range};
+
+use regex::Regex;
+
+use crate::error::Error;
+
+/// Selection of paths, by segment range and/or pattern.
+#[derive(Debug, Default, Clone)]
+pub struct PathFilter {
+    segments: Option<Range<usize>>,
+    pattern: Option<Regex>,
+}
+
+impl PathFilter {
+    /// Create a filter from an optional segment range (`start..end`, `start..` or `..end`) and an optional regular expression.
+    pub fn new(segments: Option<&str>, pattern: Option<&str>) -> Result<Self, Error> {
+        let segments = segments.map(parse_range).transpose()?;
+        let pattern = pattern
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| Error::Custom(format!("invalid path pattern: {}", e)))?;
+        Ok(Self { segments, pattern })
+    }
+
+    /// Check if the filter keeps every path.
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_none() && self.pattern.is_none()
+    }
+
+    /// Keep the items whose path is selected, in order.
+    ///
+    /// Items have to be in the order of the `wet.paths` file, since segments are numbered by appearance.
+    pub fn apply<T>(&self, items: Vec<T>, path: impl Fn(&T) -> &str) -> Vec<T> {
+        let mut segment_numbers = HashMap::new();
+        items
+            .into_iter()
+            .filter(|item| {
+                let path = path(item);
+                if let Some(segments) = &self.segments {
+                    let next = segment_numbers.len();
+                    let number = *segment_numbers
+                        .entry(segment(path).map(String::from))
+                        .or_insert(next);
+                    if !segments.contains(&number) {
+                        return false;
+                    }
+                }
+                self.pattern
+                    .as_ref()
+                    .is_none_or(|pattern| pattern.is_match(path))
+            })
+            .collect()
+    }
+}
+
+/// get the segment of a path (`crawl-data/<crawl>/segments/<segment>/wet/<file>`), if any.
+fn segment(path: &str) -> Option<&str> {
+    path.split('/')
+        .skip_while(|part| *part != "segments")
+        .nth(1)
+}
+
+/// parse a `start..end` range, whose bounds are optional.
+fn parse_range(range: &str) -> Result<Range<usize>, Error> {
+    let invalid = || Error::Custom(format!("invalid range {:?} (expected start..end)", range));
+    let (start, end) = range.split_once("..").ok_or_else(invalid)?;
+    let start = match start.trim() {
+        "" => 0,
+        start => start.parse().map_err(|_| invalid())?,
+    };
+    let end = match end.trim() {
+        "" => usize::MAX,
+        end => end.parse().map_err(|_| invalid())?,
+    };
+    Ok(start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATHS: [&str; 5] = [
+        "crawl-data/CC-MAIN-2023-50/segments/100.1/wet/0.warc.wet.gz",
+        "crawl-data/CC-MAIN-2023-50/segments/100.1/wet/1.warc.wet.gz",
+        "crawl-data/CC-MAIN-2023-50/segments/200.2/wet/2.warc.wet.gz",
+        "crawl-data/CC-MAIN-2023-50/segments/300.3/wet/3.warc.wet.gz",
+        "crawl-data/CC-MAIN-2023-50/segments/300.3/wet/4.warc.wet.gz",
+    ];
+
+    fn apply(filter: &PathFilter) -> Vec<usize> {
+        let items: Vec<_> = PATHS.iter().enumerate().collect();
+        filter
+            .apply(items, |(_, path)| path)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    #[test]
+    fn test_segments() {
+        let filter = PathFilter::new(Some("1..3"), None).unwrap();
+        assert_eq!(apply(&filter), vec![2, 3, 4]);
+        let filter = PathFilter::new(Some("..1"), None).unwrap();
+        assert_eq!(apply(&filter), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_pattern() {
+        let filter = PathFilter::new(None, Some(r"/[34]\.warc")).unwrap();
+        assert_eq!(apply(&filter), vec![3, 4]);
+        let filter = PathFilter::new(Some("1.."), Some(r"/[14]\.warc")).unwrap();
+        assert_eq!(apply(&filter), vec![4]);
+        assert!(PathFilter::default().is_empty());
+        assert_eq!(apply(&PathFilter::default()), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(PathFilter::new(Some("1-3"), None).is_err());
+        assert!(PathFilter::new(Some("a..3"), None).is_err());
+        assert!(PathFilter::new(None, Some("(")).is_err());
+    }
+}
@@ -0,0 +1,310 @@
+//! Remote shards
+//!
+//! Reads shards directly from their URL, so that crawls can be processed without storing them first.
+//!
+//! Responses are read by a background thread, a few chunks ahead of the reader,
+//! so that network latency is hidden behind record processing.
+//! The background thread stops once the reader is dropped.
+//!
+//! If the connection is reset (or closed before the whole body is received),
+//! the rest of the body is requested again with a `Range` header, a few times before giving up.
+use std::{
+    io::{self, Read},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::Duration,
+};
+
+use log::{debug, warn};
+use reqwest::{
+    blocking::{Client, Response},
+    header::RANGE,
+    StatusCode,
+};
+use url::Url;
+
+use crate::error::Error;
+
+/// Size of read chunks.
+pub(super) const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Number of chunks read ahead.
+pub(super) const READ_AHEAD: usize = 16;
+
+/// Number of times an interrupted response is resumed.
+const MAX_RESUMES: usize = 3;
+
+/// Delay before resuming, multiplied by the number of resumes so far.
+const RESUME_DELAY: Duration = Duration::from_millis(500);
+
+/// Chunks sent by a background thread, implementing [Read].
+///
+/// The thread should stop once sending fails, which means that the reader has been dropped.
+pub(super) struct ChunkReader {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl ChunkReader {
+    pub(super) fn new(receiver: Receiver<io::Result<Vec<u8>>>) -> Self {
+        Self {
+            receiver,
+            chunk: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.receiver.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                }
+                // everything has been sent
+                Err(_) => return Ok(0),
+            }
+        }
+
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Body of an HTTP(S) response, implementing [Read].
+pub struct RemoteReader(ChunkReader);
+
+impl RemoteReader {
+    /// Request `url`, returning once response headers are received.
+    ///
+    /// Errors if the request fails or if the response status isn't a success.
+    pub fn get(url: Url) -> Result<Self, Error> {
+        let (sender, receiver) = mpsc::sync_channel(READ_AHEAD);
+        let (status_sender, status_receiver) = mpsc::sync_channel(1);
+
+        let url_str = url.to_string();
+        // blocking requests can't be made from an async context, so we use a dedicated thread.
+        thread::spawn(move || {
+            let client = Client::new();
+            let mut resp = match client.get(url).send().and_then(|r| r.error_for_status()) {
+                Ok(resp) => {
+                    let _ = status_sender.send(Ok(()));
+                    resp
+                }
+                Err(e) => {
+                    let _ = status_sender.send(Err(e));
+                    return;
+                }
+            };
+            let url = resp.url().clone();
+            let len = resp.content_length();
+            let mut received = 0;
+            let mut resumes = 0;
+
+            loop {
+                let mut chunk = vec![0; CHUNK_SIZE];
+                let read = match resp.read(&mut chunk) {
+                    Ok(0) if len.is_none_or(|len| received >= len) => return,
+                    Ok(0) => Err(io::ErrorKind::UnexpectedEof.into()),
+                    Ok(n) => {
+                        chunk.truncate(n);
+                        received += n as u64;
+                        Ok(chunk)
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => Err(e),
+                };
+                let read = match read {
+                    Err(e) if resumes < MAX_RESUMES => {
+                        resumes += 1;
+                        warn!(
+                            "{}: {} after {} bytes, resuming ({}/{})",
+                            url, e, received, resumes, MAX_RESUMES
+                        );
+                        thread::sleep(RESUME_DELAY * resumes as u32);
+                        match resume(&client, &url, received) {
+                            Ok(r) => {
+                                resp = r;
+                                continue;
+                            }
+                            Err(e) => Err(e),
+                        }
+                    }
+                    read => read,
+                };
+                let failed = read.is_err();
+                if sender.send(read).is_err() {
+                    debug!("reader of {} dropped, stopping", url);
+                    return;
+                }
+                if failed {
+                    return;
+                }
+            }
+        });
+
+        match status_receiver.recv() {
+            Ok(Ok(())) => Ok(Self(ChunkReader::new(receiver))),
+            Ok(Err(e)) => Err(e.into()),
+            Err(_) => Err(Error::Custom(format!("could not request {url_str}"))),
+        }
+    }
+}
+
+/// Request the body of `url` from byte `offset` onwards.
+fn resume(client: &Client, url: &Url, offset: u64) -> io::Result<Response> {
+    let resp = client
+        .get(url.clone())
+        .header(RANGE, format!("bytes={}-", offset))
+        .send()
+        .map_err(io::Error::other)?;
+    if resp.status() != StatusCode::PARTIAL_CONTENT {
+        return Err(io::Error::other(format!(
+            "could not resume {}: got status {}",
+            url,
+            resp.status()
+        )));
+    }
+    Ok(resp)
+}
+
+impl Read for RemoteReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    use flate2::{write::GzEncoder, Compression};
+    use url::Url;
+
+    use super::RemoteReader;
+    use crate::sources::commoncrawl::Wet;
+
+    /// Serve a single response with `status` and `body`.
+    fn serve(status: &'static str, body: Vec<u8>) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/0.txt.gz",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = stream.read(&mut buf).unwrap();
+            let headers = format!(
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                body.len()
+            );
+            stream.write_all(headers.as_bytes()).unwrap();
+            let _ = stream.write_all(&body);
+        });
+        url
+    }
+
+    /// Serve `body`, closing the first connection halfway through.
+    ///
+    /// The second connection has to ask for the rest of the body.
+    fn serve_interrupted(body: Vec<u8>) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/0.txt.gz",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        std::thread::spawn(move || {
+            let half = body.len() / 2;
+            let mut buf = [0u8; 4096];
+
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut buf).unwrap();
+            let headers = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            stream.write_all(headers.as_bytes()).unwrap();
+            stream.write_all(&body[..half]).unwrap();
+            drop(stream);
+
+            let (mut stream, _) = listener.accept().unwrap();
+            let n = stream.read(&mut buf).unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+            assert!(request.contains(&format!("range: bytes={}-", half)));
+            let headers = format!(
+                "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len() - half
+            );
+            stream.write_all(headers.as_bytes()).unwrap();
+            let _ = stream.write_all(&body[half..]);
+        });
+        url
+    }
+
+    #[test]
+    fn test_read() {
+        // spans several chunks
+        let body: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+        let url = serve("200 OK", body.clone());
+
+        let mut read = Vec::new();
+        RemoteReader::get(url)
+            .unwrap()
+            .read_to_end(&mut read)
+            .unwrap();
+        assert_eq!(read, body);
+    }
+
+    #[test]
+    fn test_not_found() {
+        let url = serve("404 Not Found", Vec::new());
+        assert!(RemoteReader::get(url).is_err());
+    }
+
+    #[test]
+    fn test_resume() {
+        let body: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+        let url = serve_interrupted(body.clone());
+
+        let mut read = Vec::new();
+        RemoteReader::get(url)
+            .unwrap()
+            .read_to_end(&mut read)
+            .unwrap();
+        assert_eq!(read, body);
+    }
+
+    #[test]
+    fn test_wet_from_url() {
+        // one gzip member per record, as in CommonCrawl shards
+        let mut shard = Vec::new();
+        for i in 0..100 {
+            let body = format!("record {}", i);
+            let record = format!("WARC/1.0\r\nWARC-Type: conversion\r\nWARC-Record-ID: <urn:uuid:{}>\r\nWARC-Date: 2023-01-01T00:00:00Z\r\nContent-Length: {}\r\n\r\n{}\r\n\r\n", i, body.len(), body);
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(record.as_bytes()).unwrap();
+            shard.extend(encoder.finish().unwrap());
+        }
+        let url = serve_interrupted(shard);
+
+        let bodies: Vec<_> = Wet::from_url_gzip(url)
+            .unwrap()
+            .iter
+            .map(|record| String::from_utf8(record.unwrap().body().to_vec()).unwrap())
+            .collect();
+        assert_eq!(bodies.len(), 100);
+        assert_eq!(bodies[99], "record 99");
+    }
+}
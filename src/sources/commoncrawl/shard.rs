@@ -8,9 +8,12 @@ use std::{fs::File, io::BufReader, path::Path};
 use crate::error::Error;
 use flate2::read::MultiGzDecoder;
 use std::io::BufRead;
+use url::Url;
 use warc::RecordIter;
 use warc::WarcReader;
 
+use super::remote::RemoteReader;
+
 /// Wet/Shard instance, generic over reader type.
 ///
 /// This genericity enables Ungoliant to potentially
@@ -47,7 +50,17 @@ impl Wet<BufReader<MultiGzDecoder<File>>> {
     }
 }
 
-#[allow(dead_code)]
+/// Wet reader using [MultiGzDecoder] over a remote file.
+impl Wet<BufReader<MultiGzDecoder<RemoteReader>>> {
+    /// Create a new reader streaming a gzipped WET file from its URL, without storing it.
+    ///
+    /// Interrupted responses are resumed where they stopped (see [RemoteReader]).
+    pub fn from_url_gzip(url: Url) -> Result<Self, Error> {
+        let gzip_stream = MultiGzDecoder::new(RemoteReader::get(url)?);
+        Ok(Self::new(BufReader::new(gzip_stream)))
+    }
+}
+
 impl<T: BufRead> Wet<T> {
     pub fn new(reader: T) -> Self {
         let reader = WarcReader::new(reader);
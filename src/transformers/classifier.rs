@@ -0,0 +1,90 @@
+/*! Document classifiers
+
+Models giving label scores to whole documents, used by classification annotators
+(see [super::HarmfulContent] and [super::Register]).
+
+Models are either fastText ones (a `.bin` file, `fasttext` feature) or ONNX sequence classification
+ones (a directory, `onnx` feature, see [crate::identifiers::Onnx] for the expected layout).
+!*/
+use std::path::Path;
+
+use crate::error::Error;
+
+/// fastText label prefix, stripped from labels.
+#[cfg(feature = "fasttext")]
+const LABEL_PREFIX: &str = "__label__";
+
+/// Model giving a score to each label of a document.
+pub trait ContentClassifier: Send + Sync {
+    fn scores(&self, content: &str) -> Result<Vec<(String, f32)>, Error>;
+}
+
+#[cfg(feature = "fasttext")]
+impl ContentClassifier for fasttext::FastText {
+    /// fastText classifies single lines, so newlines are replaced by spaces.
+    fn scores(&self, content: &str) -> Result<Vec<(String, f32)>, Error> {
+        let content = content.replace('\n', " ");
+        Ok(self
+            .predict(&content, -1, 0.0)?
+            .into_iter()
+            .map(|pred| {
+                let label = pred.label.strip_prefix(LABEL_PREFIX).unwrap_or(&pred.label);
+                (label.to_string(), pred.prob)
+            })
+            .collect())
+    }
+}
+
+#[cfg(feature = "onnx")]
+impl ContentClassifier for crate::identifiers::OnnxClassifier {
+    /// Documents are truncated to the model's maximum number of tokens.
+    fn scores(&self, content: &str) -> Result<Vec<(String, f32)>, Error> {
+        let probs = self.probabilities(&[content])?;
+        Ok(self
+            .labels()
+            .iter()
+            .cloned()
+            .zip(probs.into_iter().next().unwrap_or_default())
+            .collect())
+    }
+}
+
+#[cfg(feature = "onnx")]
+fn load_onnx(path: &Path) -> Result<Box<dyn ContentClassifier>, Error> {
+    let classifier =
+        crate::identifiers::OnnxClassifier::new(path, crate::identifiers::OnnxOptions::default())?;
+    Ok(Box::new(classifier))
+}
+
+#[cfg(not(feature = "onnx"))]
+fn load_onnx(path: &Path) -> Result<Box<dyn ContentClassifier>, Error> {
+    Err(Error::Custom(format!(
+        "{path:?} is an ONNX model directory, which needs the onnx feature"
+    )))
+}
+
+#[cfg(feature = "fasttext")]
+fn load_fasttext(path: &Path) -> Result<Box<dyn ContentClassifier>, Error> {
+    let path = path
+        .to_str()
+        .ok_or(Error::Custom("Could not parse path.".to_string()))?;
+    let mut classifier = fasttext::FastText::new();
+    classifier.load_model(path)?;
+    Ok(Box::new(classifier))
+}
+
+#[cfg(not(feature = "fasttext"))]
+fn load_fasttext(path: &Path) -> Result<Box<dyn ContentClassifier>, Error> {
+    Err(Error::Custom(format!(
+        "{path:?} is a fastText model, which needs the fasttext feature"
+    )))
+}
+
+/// Load a fastText model file or an ONNX model directory.
+pub fn load(path: &Path) -> Result<Box<dyn ContentClassifier>, Error> {
+    if path.is_dir() {
+        load_onnx(path)
+    } else {
+        load_fasttext(path)
+    }
+}
@@ -0,0 +1,285 @@
+/*! Source code detection
+
+Annotates documents dominated by source code (code listings, snippets, configuration dumps),
+since they pollute natural-language corpora.
+
+Lines are considered as code when they look like statements or blocks (ending with `;`, `{` or `}`,
+starting with comments or common keywords), or when they are dense in symbols (`{}()[];=<>` …).
+Documents whose proportion of code lines is above a threshold are annotated with `code:<language>`,
+the language being guessed by counting language-specific keywords and idioms in code lines,
+or with `code` if no language stands out.
+
+This is a heuristic (similar in spirit to guesslang's features, without a model):
+short snippets embedded in prose are not detected, and guessed languages are indicative.
+!*/
+use super::Annotate;
+use crate::pipelines::oscardoc::types::Document;
+
+/// Keywords and idioms of some languages.
+const SIGNATURES: &[(&str, &[&str])] = &[
+    (
+        "python",
+        &[
+            "def ", "import ", "elif ", "self.", "print(", "__init__", "None", "lambda ",
+        ],
+    ),
+    (
+        "javascript",
+        &[
+            "function",
+            "const ",
+            "var ",
+            "=>",
+            "console.",
+            "document.",
+            "require(",
+            "===",
+        ],
+    ),
+    (
+        "c",
+        &[
+            "#include", "printf(", "int main", "malloc(", "sizeof(", "->",
+        ],
+    ),
+    (
+        "cpp",
+        &["std::", "cout", "template<", "namespace ", "nullptr"],
+    ),
+    (
+        "java",
+        &[
+            "public class",
+            "public static",
+            "System.out",
+            "private ",
+            "import java",
+            "@Override",
+        ],
+    ),
+    (
+        "php",
+        &["<?php", "$this->", "echo $", "$_GET", "$_POST", "->"],
+    ),
+    (
+        "rust",
+        &["fn ", "let mut ", "impl ", "pub fn", "use std::", "::new("],
+    ),
+    (
+        "sql",
+        &[
+            "SELECT ",
+            "FROM ",
+            "WHERE ",
+            "INSERT INTO",
+            "CREATE TABLE",
+            "JOIN ",
+        ],
+    ),
+    (
+        "shell",
+        &["#!/bin/", "sudo ", "apt-get ", "export ", "echo \"", "$("],
+    ),
+    (
+        "html",
+        &["<div", "</div>", "<script", "<a href", "<span", "<html"],
+    ),
+];
+
+/// Line prefixes of statements and comments.
+const CODE_PREFIXES: &[&str] = &[
+    "//",
+    "/*",
+    "*/",
+    "#include",
+    "#define",
+    "#!",
+    "<?",
+    "}",
+    "def ",
+    "class ",
+    "import ",
+    "return ",
+    "if (",
+    "for (",
+    "while (",
+    "else {",
+    "var ",
+    "let ",
+    "const ",
+    "function ",
+    "public ",
+    "private ",
+];
+
+/// Symbols frequent in code and rare in prose.
+const CODE_SYMBOLS: &[char] = &[
+    '{', '}', '(', ')', '[', ']', ';', '=', '<', '>', '$', '_', '|', '&',
+];
+
+pub struct CodeDetector {
+    min_code_ratio: f64,
+    min_lines: usize,
+    min_symbol_density: f64,
+}
+
+impl CodeDetector {
+    /// Documents whose proportion of code lines is `>= min_code_ratio` are annotated.
+    pub fn new(min_code_ratio: f64) -> Self {
+        Self {
+            min_code_ratio,
+            ..Default::default()
+        }
+    }
+
+    /// Returns true if the line looks like code.
+    fn is_code_line(&self, line: &str) -> bool {
+        let line = line.trim();
+        if line.is_empty() {
+            return false;
+        }
+
+        if line.ends_with([';', '{', '}'])
+            || CODE_PREFIXES.iter().any(|prefix| line.starts_with(prefix))
+        {
+            return true;
+        }
+
+        let nb_chars = line.chars().filter(|c| !c.is_whitespace()).count();
+        let nb_symbols = line.chars().filter(|c| CODE_SYMBOLS.contains(c)).count();
+        nb_chars >= 10 && nb_symbols as f64 / nb_chars as f64 >= self.min_symbol_density
+    }
+
+    /// Guess the language of some code lines, if one stands out.
+    fn guess_language(lines: &[&str]) -> Option<&'static str> {
+        let mut scores: Vec<(&str, usize)> = SIGNATURES
+            .iter()
+            .map(|(lang, patterns)| {
+                let score = lines
+                    .iter()
+                    .map(|line| patterns.iter().filter(|p| line.contains(*p)).count())
+                    .sum();
+                (*lang, score)
+            })
+            .collect();
+        scores.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+
+        match scores.as_slice() {
+            [(lang, best), (_, second), ..] if *best >= 2 && best > second => Some(lang),
+            _ => None,
+        }
+    }
+}
+
+impl Default for CodeDetector {
+    /// Annotate documents with at least 3 lines, half of them being code.
+    fn default() -> Self {
+        Self {
+            min_code_ratio: 0.5,
+            min_lines: 3,
+            min_symbol_density: 0.2,
+        }
+    }
+}
+
+impl Annotate<Document> for CodeDetector {
+    fn annotate(&self, doc: &mut Document) {
+        let lines: Vec<&str> = doc
+            .content()
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect();
+        if lines.len() < self.min_lines {
+            return;
+        }
+
+        let code_lines: Vec<&str> = lines
+            .iter()
+            .copied()
+            .filter(|line| self.is_code_line(line))
+            .collect();
+        if (code_lines.len() as f64 / lines.len() as f64) < self.min_code_ratio {
+            return;
+        }
+
+        let annotation = match Self::guess_language(&code_lines) {
+            Some(lang) => format!("code:{lang}"),
+            None => "code".to_string(),
+        };
+        doc.metadata_mut().add_annotation(annotation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        pipelines::oscardoc::types::{Document, Metadata},
+        transformers::Annotate,
+    };
+
+    use super::CodeDetector;
+
+    fn annotate(content: &str) -> Option<Vec<String>> {
+        let mut doc = Document::new(content.to_string(), HashMap::new(), Metadata::default());
+        CodeDetector::default().annotate(&mut doc);
+        doc.metadata().annotation().cloned()
+    }
+
+    #[test]
+    fn test_is_code_line() {
+        let detector = CodeDetector::default();
+        assert!(detector.is_code_line("    x = foo(bar[0]);"));
+        assert!(detector.is_code_line("def main():"));
+        assert!(detector.is_code_line("if (a == b) {"));
+        assert!(detector.is_code_line("x=[i*2 for i in range(10) if i>3]"));
+        assert!(!detector.is_code_line("The cat sat on the mat (and slept)."));
+        assert!(!detector.is_code_line(""));
+    }
+
+    #[test]
+    fn test_python() {
+        let content = "import os
+
+def main():
+    path = os.getcwd()
+    print(path)
+
+if __name__ == \"__main__\":
+    main()";
+        assert_eq!(annotate(content), Some(vec!["code:python".to_string()]));
+    }
+
+    #[test]
+    fn test_c() {
+        let content = "#include <stdio.h>
+
+int main(void) {
+    char *buf = malloc(sizeof(char) * 10);
+    printf(\"%s\\n\", buf);
+    return 0;
+}";
+        assert_eq!(annotate(content), Some(vec!["code:c".to_string()]));
+    }
+
+    #[test]
+    fn test_unknown_language() {
+        let content = "a = b;\nc = d;\ne = f;";
+        assert_eq!(annotate(content), Some(vec!["code".to_string()]));
+    }
+
+    #[test]
+    fn test_prose() {
+        let content = "Lorem ipsum dolor sit amet, consectetur adipiscing elit.
+Ut enim ad minim veniam, quis nostrud exercitation ullamco laboris.
+Duis aute irure dolor in reprehenderit (in voluptate) velit esse cillum dolore.
+To install it, run the following command: make install;";
+        assert_eq!(annotate(content), None);
+    }
+
+    #[test]
+    fn test_short() {
+        assert_eq!(annotate("x = 1;\ny = 2;"), None);
+    }
+}
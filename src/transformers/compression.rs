@@ -0,0 +1,129 @@
+/*! Annotates documents with an extreme compression ratio
+
+The compression ratio (compressed size / original size) is a cheap quality signal:
+
+- highly repetitive content (boilerplate, repeated lists) compresses very well, and is annotated as `repetitive`,
+- random-looking content (base64 dumps, hashes, encoded data) barely compresses, and is annotated as `incompressible`.
+
+Content is compressed in memory using the fastest deflate level.
+Short documents are skipped, since compression overhead dominates their ratio.
+!*/
+use std::io::Write;
+
+use flate2::{write::DeflateEncoder, Compression};
+use log::debug;
+
+use super::Annotate;
+use crate::pipelines::oscardoc::types::Document;
+
+pub struct CompressionRatio {
+    min_ratio: f64,
+    max_ratio: f64,
+    min_size: usize,
+}
+
+impl CompressionRatio {
+    /// Documents with a ratio below `min_ratio` are `repetitive`, documents with a ratio above `max_ratio` are `incompressible`.
+    pub fn new(min_ratio: f64, max_ratio: f64) -> Self {
+        Self {
+            min_ratio,
+            max_ratio,
+            ..Default::default()
+        }
+    }
+
+    /// Get the compression ratio of some content.
+    fn ratio(content: &str) -> std::io::Result<f64> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(content.as_bytes())?;
+        let compressed = encoder.finish()?;
+        Ok(compressed.len() as f64 / content.len() as f64)
+    }
+}
+
+impl Default for CompressionRatio {
+    /// Natural language text usually has a ratio between 0.3 and 0.5.
+    fn default() -> Self {
+        Self {
+            min_ratio: 0.2,
+            max_ratio: 0.7,
+            min_size: 500,
+        }
+    }
+}
+
+impl Annotate<Document> for CompressionRatio {
+    fn annotate(&self, doc: &mut Document) {
+        if doc.content().len() < self.min_size {
+            return;
+        }
+
+        let ratio = match Self::ratio(doc.content()) {
+            Ok(ratio) => ratio,
+            Err(e) => {
+                debug!("record {}: could not compress: {:?}", doc.warc_id(), e);
+                return;
+            }
+        };
+
+        if ratio < self.min_ratio {
+            doc.metadata_mut().add_annotation("repetitive".to_string());
+        } else if ratio > self.max_ratio {
+            doc.metadata_mut()
+                .add_annotation("incompressible".to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        pipelines::oscardoc::types::{Document, Metadata},
+        transformers::Annotate,
+    };
+
+    use super::CompressionRatio;
+
+    fn annotate(content: String) -> Option<Vec<String>> {
+        let mut doc = Document::new(content, HashMap::new(), Metadata::default());
+        CompressionRatio::default().annotate(&mut doc);
+        doc.metadata().annotation().cloned()
+    }
+
+    #[test]
+    fn test_repetitive() {
+        let content = "Home | About | Contact\n".repeat(50);
+        assert_eq!(annotate(content), Some(vec!["repetitive".to_string()]));
+    }
+
+    #[test]
+    fn test_incompressible() {
+        // pseudo-random base64-like content
+        let alphabet = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut state: u64 = 42;
+        let content: String = (0..2000)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                alphabet[(state >> 58) as usize] as char
+            })
+            .collect();
+        assert_eq!(annotate(content), Some(vec!["incompressible".to_string()]));
+    }
+
+    #[test]
+    fn test_text() {
+        let content = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt ut labore et dolore magna aliqua.
+Ut enim ad minim veniam, quis nostrud exercitation ullamco laboris nisi ut aliquip ex ea commodo consequat.
+Duis aute irure dolor in reprehenderit in voluptate velit esse cillum dolore eu fugiat nulla pariatur.
+Excepteur sint occaecat cupidatat non proident, sunt in culpa qui officia deserunt mollit anim id est laborum.
+Curabitur pretium tincidunt lacus, nulla gravida orci a odio, nullam varius turpis et commodo pharetra.".to_string();
+        assert_eq!(annotate(content), None);
+    }
+
+    #[test]
+    fn test_short() {
+        assert_eq!(annotate("aaaa".repeat(10)), None);
+    }
+}
@@ -0,0 +1,170 @@
+/*! GeoIP annotator
+
+Maps the `WARC-IP-Address` header of a [Document] through one or more MaxMind-format databases
+(GeoLite2/GeoIP2 Country, City or ASN), adding `country:<iso code>` and/or `asn:<number>` annotations.
+
+Documents that have no (or an unparseable) `WARC-IP-Address` header, or whose address is not found
+in the databases, are left untouched.
+!*/
+use std::{net::IpAddr, path::Path};
+
+use log::{debug, info};
+use maxminddb::{geoip2, Reader};
+use warc::WarcHeader;
+
+use crate::{error::Error, pipelines::oscardoc::types::Document};
+
+use super::Annotate;
+
+/// Kind of database, inferred from its `database_type` metadata field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DatabaseKind {
+    Country,
+    Asn,
+}
+
+impl DatabaseKind {
+    fn from_database_type(database_type: &str) -> Self {
+        if database_type.contains("ASN") {
+            Self::Asn
+        } else {
+            Self::Country
+        }
+    }
+}
+
+pub struct GeoIp {
+    readers: Vec<(DatabaseKind, Reader<Vec<u8>>)>,
+}
+
+impl GeoIp {
+    /// Open each provided database.
+    ///
+    /// Country and City databases add `country:` annotations, ASN databases add `asn:` ones.
+    pub fn from_paths<P: AsRef<Path>>(paths: &[P]) -> Result<Self, Error> {
+        let mut readers = Vec::with_capacity(paths.len());
+        for path in paths {
+            let reader = Reader::open_readfile(path)?;
+            let kind = DatabaseKind::from_database_type(&reader.metadata.database_type);
+            info!(
+                "Using GeoIP database {:?} ({})",
+                path.as_ref(),
+                reader.metadata.database_type
+            );
+            readers.push((kind, reader));
+        }
+
+        Ok(Self { readers })
+    }
+
+    /// Attempt to parse the `WARC-IP-Address` header.
+    fn ip_address(doc: &Document) -> Option<IpAddr> {
+        doc.warc_headers()
+            .get(&WarcHeader::IPAddress)
+            .and_then(|ip| String::from_utf8_lossy(ip).trim().parse().ok())
+    }
+
+    /// Get annotations for a given address from all databases.
+    fn lookup(&self, ip: IpAddr) -> Vec<String> {
+        let mut annotations = Vec::new();
+        for (kind, reader) in &self.readers {
+            match kind {
+                DatabaseKind::Country => match reader.lookup::<geoip2::Country>(ip) {
+                    Ok(country) => {
+                        if let Some(iso_code) = country.country.and_then(|c| c.iso_code) {
+                            annotations.push(format!("country:{iso_code}"));
+                        }
+                    }
+                    Err(e) => debug!("No country found for {ip}: {e:?}"),
+                },
+                DatabaseKind::Asn => match reader.lookup::<geoip2::Asn>(ip) {
+                    Ok(asn) => {
+                        if let Some(number) = asn.autonomous_system_number {
+                            annotations.push(format!("asn:{number}"));
+                        }
+                    }
+                    Err(e) => debug!("No ASN found for {ip}: {e:?}"),
+                },
+            }
+        }
+
+        annotations
+    }
+}
+
+impl Annotate<Document> for GeoIp {
+    fn annotate(&self, doc: &mut Document) {
+        if let Some(ip) = Self::ip_address(doc) {
+            for annotation in self.lookup(ip) {
+                doc.metadata_mut().add_annotation(annotation);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use warc::WarcHeader;
+
+    use crate::{
+        pipelines::oscardoc::types::{Document, Metadata},
+        transformers::Annotate,
+    };
+
+    use super::{DatabaseKind, GeoIp};
+
+    fn gen_document(ip: Option<&str>) -> Document {
+        let mut headers = HashMap::new();
+        if let Some(ip) = ip {
+            headers.insert(WarcHeader::IPAddress, ip.as_bytes().to_vec());
+        }
+        Document::new(String::new(), headers, Metadata::default())
+    }
+
+    #[test]
+    fn test_database_kind() {
+        assert_eq!(
+            DatabaseKind::from_database_type("GeoLite2-ASN"),
+            DatabaseKind::Asn
+        );
+        assert_eq!(
+            DatabaseKind::from_database_type("GeoLite2-Country"),
+            DatabaseKind::Country
+        );
+        assert_eq!(
+            DatabaseKind::from_database_type("GeoIP2-City"),
+            DatabaseKind::Country
+        );
+    }
+
+    #[test]
+    fn test_ip_address() {
+        let doc = gen_document(Some("192.0.2.1"));
+        assert_eq!(
+            GeoIp::ip_address(&doc),
+            Some("192.0.2.1".parse().unwrap())
+        );
+
+        let doc = gen_document(Some("not an ip"));
+        assert_eq!(GeoIp::ip_address(&doc), None);
+
+        let doc = gen_document(None);
+        assert_eq!(GeoIp::ip_address(&doc), None);
+    }
+
+    #[test]
+    fn test_nonexisting_db() {
+        assert!(GeoIp::from_paths(&["nonexisting.mmdb"]).is_err());
+    }
+
+    #[test]
+    fn test_no_databases() {
+        let annotator = GeoIp::from_paths::<&str>(&[]).unwrap();
+        let mut doc = gen_document(Some("192.0.2.1"));
+        annotator.annotate(&mut doc);
+
+        assert!(doc.metadata().annotation().is_none());
+    }
+}
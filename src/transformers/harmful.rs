@@ -0,0 +1,145 @@
+/*! Harmful content annotator
+
+Complements UT1 blocklists (see [super::ContentDetector]), which only know about URLs,
+by classifying the document text with a model trained to tell adult/harmful content apart.
+
+Models are loaded as [ContentClassifier]s.
+Every label but the negative one (`safe` by default) is considered harmful:
+the score of each harmful label is added to the quality signals of documents as `harmful_score:<label>`
+(see [super::signals]), and documents get a `harmful:<label>` annotation for labels scoring at least the minimum score,
+so that flagged documents can be dropped with an annotation policy (`harmful=drop`).
+!*/
+use std::path::Path;
+
+use log::debug;
+
+use crate::{error::Error, pipelines::oscardoc::types::Document};
+
+use super::{
+    classifier::{self, ContentClassifier},
+    signals::add_quality_signals,
+    Annotate,
+};
+
+pub struct HarmfulContent {
+    classifier: Box<dyn ContentClassifier>,
+    negative_label: String,
+    min_score: f32,
+}
+
+impl HarmfulContent {
+    /// Documents with a harmful label scoring at least `min_score` are flagged.
+    pub fn new(
+        classifier: Box<dyn ContentClassifier>,
+        negative_label: String,
+        min_score: f32,
+    ) -> Self {
+        Self {
+            classifier,
+            negative_label,
+            min_score,
+        }
+    }
+
+    /// Load a fastText model file or an ONNX model directory.
+    pub fn from_path(path: &Path, negative_label: String, min_score: f32) -> Result<Self, Error> {
+        Ok(Self::new(
+            classifier::load(path)?,
+            negative_label,
+            min_score,
+        ))
+    }
+}
+
+impl Annotate<Document> for HarmfulContent {
+    fn annotate(&self, doc: &mut Document) {
+        let mut scores = match self.classifier.scores(doc.content()) {
+            Ok(scores) => scores,
+            Err(e) => {
+                debug!("Could not classify document content: {e:?}");
+                return;
+            }
+        };
+        scores.retain(|(label, _)| *label != self.negative_label);
+        scores.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        for (label, _) in scores.iter().filter(|(_, score)| *score >= self.min_score) {
+            doc.metadata_mut()
+                .add_annotation(format!("harmful:{label}"));
+        }
+        add_quality_signals(
+            doc,
+            scores
+                .into_iter()
+                .map(|(label, score)| (format!("harmful_score:{label}"), f64::from(score))),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::{json, Value};
+
+    use crate::{
+        error::Error,
+        pipelines::oscardoc::types::{Document, Metadata},
+        transformers::{signals::quality_signals, Annotate},
+    };
+
+    use super::{ContentClassifier, HarmfulContent};
+
+    /// Scores documents mentioning casinos as gambling.
+    struct Casino;
+
+    impl ContentClassifier for Casino {
+        fn scores(&self, content: &str) -> Result<Vec<(String, f32)>, Error> {
+            let gambling = if content.contains("casino") { 0.9 } else { 0.1 };
+            Ok(vec![
+                ("safe".to_string(), 1.0 - gambling),
+                ("gambling".to_string(), gambling),
+                ("adult".to_string(), 0.0),
+            ])
+        }
+    }
+
+    /// Get the annotations and quality signals of an annotated document.
+    fn annotate(content: &str) -> (Option<Vec<String>>, Value) {
+        let annotator = HarmfulContent::new(Box::new(Casino), "safe".to_string(), 0.5);
+        let mut doc = Document::new(content.to_string(), HashMap::new(), Metadata::default());
+        annotator.annotate(&mut doc);
+        (
+            doc.metadata().annotation().cloned(),
+            Value::Object(quality_signals(&doc)),
+        )
+    }
+
+    #[test]
+    fn test_flagged() {
+        let (annotations, signals) = annotate("Best online casino bonuses!");
+        assert_eq!(annotations, Some(vec!["harmful:gambling".to_string()]));
+        assert_eq!(
+            signals,
+            json!({"harmful_score:adult": 0.0, "harmful_score:gambling": 0.9})
+        );
+    }
+
+    #[test]
+    fn test_not_flagged() {
+        let (annotations, signals) = annotate("A recipe for apple pie.");
+        assert_eq!(annotations, None);
+        assert_eq!(
+            signals,
+            json!({"harmful_score:adult": 0.0, "harmful_score:gambling": 0.1})
+        );
+    }
+
+    #[test]
+    fn test_missing_model() {
+        assert!(
+            HarmfulContent::from_path("does/not/exist.bin".as_ref(), "safe".to_string(), 0.5)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,197 @@
+/*! Math content detection
+
+Annotates documents dominated by mathematical content, in order to both exclude them from general corpora
+and extract math-focused subsets.
+
+Math tokens are counted in three forms:
+
+- LaTeX markup: commands (`\frac`, `\alpha`…) and `$`/`$$` delimiters,
+- MathML markup: elements such as `<math>`, `<mi>` or `<mrow>`,
+- Unicode mathematical symbols (`∑`, `∫`, `≤`, `±`, mathematical alphanumerics…).
+
+Documents with enough math tokens relative to their number of words are annotated with
+`math:latex`, `math:mathml` or `math:symbols`, depending on the most present form.
+!*/
+use super::Annotate;
+use crate::pipelines::oscardoc::types::Document;
+
+/// MathML element prefixes (without `<`).
+const MATHML_TAGS: &[&str] = &[
+    "math", "mi>", "mn>", "mo>", "mrow", "msup", "msub", "mfrac", "msqrt", "mtext",
+];
+
+pub struct MathDetector {
+    min_density: f64,
+    min_tokens: usize,
+}
+
+/// Math tokens of a document, by form.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct MathTokens {
+    latex: usize,
+    mathml: usize,
+    symbols: usize,
+}
+
+impl MathTokens {
+    fn total(&self) -> usize {
+        self.latex + self.mathml + self.symbols
+    }
+
+    /// Most present form.
+    fn form(&self) -> &'static str {
+        if self.latex >= self.mathml && self.latex >= self.symbols {
+            "latex"
+        } else if self.mathml >= self.symbols {
+            "mathml"
+        } else {
+            "symbols"
+        }
+    }
+}
+
+impl MathDetector {
+    /// Documents with at least `min_density` math tokens per word are annotated.
+    pub fn new(min_density: f64) -> Self {
+        Self {
+            min_density,
+            ..Default::default()
+        }
+    }
+
+    /// Returns true if the character is a mathematical symbol.
+    fn is_math_symbol(c: char) -> bool {
+        matches!(c,
+            '±' | '×' | '÷' | '¬'
+            | '\u{2200}'..='\u{22FF}' // mathematical operators
+            | '\u{27C0}'..='\u{27EF}' // miscellaneous mathematical symbols-A
+            | '\u{2980}'..='\u{2AFF}' // miscellaneous mathematical symbols-B, supplemental operators
+            | '\u{1D400}'..='\u{1D7FF}' // mathematical alphanumeric symbols
+        )
+    }
+
+    /// Count math tokens.
+    fn count(content: &str) -> MathTokens {
+        let mut tokens = MathTokens::default();
+
+        let mut chars = content.char_indices().peekable();
+        while let Some((idx, c)) = chars.next() {
+            match c {
+                // LaTeX command
+                '\\' if chars
+                    .peek()
+                    .is_some_and(|(_, next)| next.is_ascii_alphabetic()) =>
+                {
+                    tokens.latex += 1;
+                    while chars.next_if(|(_, c)| c.is_ascii_alphabetic()).is_some() {}
+                }
+                // escaped character (\$, \{…)
+                '\\' => {
+                    chars.next();
+                }
+                // LaTeX delimiter ($$ counts once)
+                '$' => {
+                    tokens.latex += 1;
+                    chars.next_if(|(_, c)| *c == '$');
+                }
+                // MathML element
+                '<' => {
+                    let tag = content[idx + 1..].trim_start_matches('/');
+                    if MATHML_TAGS.iter().any(|t| tag.starts_with(t)) {
+                        tokens.mathml += 1;
+                    }
+                }
+                c if Self::is_math_symbol(c) => tokens.symbols += 1,
+                _ => (),
+            }
+        }
+
+        tokens
+    }
+}
+
+impl Default for MathDetector {
+    /// At least 10 math tokens, and 1 math token every 10 words.
+    fn default() -> Self {
+        Self {
+            min_density: 0.1,
+            min_tokens: 10,
+        }
+    }
+}
+
+impl Annotate<Document> for MathDetector {
+    fn annotate(&self, doc: &mut Document) {
+        let tokens = Self::count(doc.content());
+        if tokens.total() < self.min_tokens {
+            return;
+        }
+
+        let nb_words = doc.content().split_whitespace().count();
+        if (tokens.total() as f64 / nb_words as f64) < self.min_density {
+            return;
+        }
+
+        doc.metadata_mut()
+            .add_annotation(format!("math:{}", tokens.form()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        pipelines::oscardoc::types::{Document, Metadata},
+        transformers::Annotate,
+    };
+
+    use super::{MathDetector, MathTokens};
+
+    fn annotate(content: &str) -> Option<Vec<String>> {
+        let mut doc = Document::new(content.to_string(), HashMap::new(), Metadata::default());
+        MathDetector::default().annotate(&mut doc);
+        doc.metadata().annotation().cloned()
+    }
+
+    #[test]
+    fn test_count() {
+        assert_eq!(
+            MathDetector::count("Let $\\alpha \\leq \\frac{1}{2}$ and $$x^2$$, price 10\\$."),
+            MathTokens {
+                latex: 7,
+                mathml: 0,
+                symbols: 0
+            }
+        );
+        assert_eq!(
+            MathDetector::count("<math><mi>x</mi><mo>≤</mo></math> < 3"),
+            MathTokens {
+                latex: 0,
+                mathml: 6,
+                symbols: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_latex() {
+        let content = "The Gaussian integral is \\begin{equation}
+\\int_{-\\infty}^{\\infty} e^{-x^2} dx = \\sqrt{\\pi}
+\\end{equation}
+and the sum $\\sum_{n=1}^{\\infty} \\frac{1}{n^2} = \\frac{\\pi^2}{6}$.";
+        assert_eq!(annotate(content), Some(vec!["math:latex".to_string()]));
+    }
+
+    #[test]
+    fn test_symbols() {
+        let content = "∀ε > 0, ∃δ > 0 : |x − a| < δ ⇒ |f(x) − f(a)| < ε, ∑ ∫ ∂ ∇ ≤ ≥";
+        assert_eq!(annotate(content), Some(vec!["math:symbols".to_string()]));
+    }
+
+    #[test]
+    fn test_prose() {
+        let content = "This costs $10, or $15 with shipping. See the C:\\Users folder. ".repeat(3);
+        assert_eq!(annotate(&content), None);
+    }
+}
@@ -8,11 +8,22 @@ Transformers can either (or both) [Annotate] content or [Transform] it:
 !*/
 
 mod annotate;
+mod classifier;
+mod code;
+mod compression;
 mod content_detector;
+mod geoip;
+mod harmful;
 mod header;
 
 mod lsh;
+mod math;
 mod noisy;
+mod readability;
+mod register;
+mod repeated_paragraphs;
+mod script;
+pub mod signals;
 
 #[cfg(feature = "kenlm")]
 mod kenlm;
@@ -22,7 +33,12 @@ mod tiny;
 mod transform;
 pub use annotate::Annotate;
 pub use annotate::Annotator;
+pub use classifier::ContentClassifier;
+pub use code::CodeDetector;
+pub use compression::CompressionRatio;
 pub use content_detector::ContentDetector;
+pub use geoip::GeoIp;
+pub use harmful::HarmfulContent;
 pub use header::Header;
 pub use lsh::LSH;
 #[cfg(feature = "kenlm")]
@@ -31,7 +47,12 @@ pub use kenlm::AdultDetector;
 pub use kenlm::AdultDetectorBuilder;
 #[cfg(feature = "kenlm")]
 pub use kenlm::Models;
+pub use math::MathDetector;
 pub use noisy::Noisy;
+pub use readability::Readability;
+pub use register::Register;
+pub use repeated_paragraphs::RepeatedParagraphs;
+pub use script::ScriptDetector;
 pub use sentence_filter::Conv;
 pub use sentence_filter::RemoveShortSentences;
 pub use sentence_filter::ShortSentences;
@@ -0,0 +1,153 @@
+/*! Readability and lexical diversity metrics
+
+Adds simple readability proxies to the quality signals of documents (see [super::signals]),
+for consumers building difficulty-stratified corpora:
+
+- `mean_word_length`: mean number of codepoints per word,
+- `ttr`: moving-average type-token ratio (MATTR), i.e. the mean ratio of distinct words over a sliding window of words.
+
+They're signals rather than annotations, since every document gets them.
+
+Words are segmented following Unicode word boundaries (UAX #29) and lowercased,
+so that metrics are comparable across languages using the same script.
+For scripts without spaces (e.g. CJK), words are mostly single characters and metrics should only be compared within a language.
+
+The sliding window makes the type-token ratio independent of document length.
+Documents shorter than the window get a plain type-token ratio.
+!*/
+use std::collections::HashMap;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::{signals::add_quality_signals, Annotate};
+use crate::pipelines::oscardoc::types::Document;
+
+pub struct Readability {
+    window: usize,
+}
+
+impl Readability {
+    /// Create a new annotator, with a type-token ratio window of `window` words (clamped to 1).
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+        }
+    }
+
+    /// Get the mean word length of some words, in codepoints.
+    fn mean_word_length(words: &[String]) -> f64 {
+        let nb_chars: usize = words.iter().map(|w| w.chars().count()).sum();
+        nb_chars as f64 / words.len() as f64
+    }
+
+    /// Get the moving-average type-token ratio of some words.
+    fn type_token_ratio(&self, words: &[String]) -> f64 {
+        let window = self.window.min(words.len());
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for word in &words[..window] {
+            *counts.entry(word).or_insert(0) += 1;
+        }
+
+        let mut nb_types = counts.len();
+        for (old, new) in words.iter().zip(&words[window..]) {
+            if let Some(count) = counts.get_mut(old.as_str()) {
+                *count -= 1;
+                if *count == 0 {
+                    counts.remove(old.as_str());
+                }
+            }
+            *counts.entry(new).or_insert(0) += 1;
+            nb_types += counts.len();
+        }
+
+        let nb_windows = words.len() - window + 1;
+        nb_types as f64 / (nb_windows * window) as f64
+    }
+}
+
+impl Default for Readability {
+    /// Uses a 100 words window.
+    fn default() -> Self {
+        Self::new(100)
+    }
+}
+
+impl Annotate<Document> for Readability {
+    fn annotate(&self, doc: &mut Document) {
+        let words: Vec<String> = doc
+            .content()
+            .unicode_words()
+            .map(str::to_lowercase)
+            .collect();
+        if words.is_empty() {
+            return;
+        }
+
+        add_quality_signals(
+            doc,
+            [
+                (
+                    "mean_word_length".to_string(),
+                    Self::mean_word_length(&words),
+                ),
+                ("ttr".to_string(), self.type_token_ratio(&words)),
+            ],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use crate::{
+        pipelines::oscardoc::types::{Document, Metadata},
+        transformers::{signals::quality_signals, Annotate},
+    };
+
+    use super::Readability;
+
+    fn words(content: &str) -> Vec<String> {
+        content.split(' ').map(String::from).collect()
+    }
+
+    #[test]
+    fn test_mean_word_length() {
+        assert_eq!(Readability::mean_word_length(&words("a bb ccc")), 2.0);
+        assert_eq!(Readability::mean_word_length(&words("été")), 3.0);
+    }
+
+    #[test]
+    fn test_type_token_ratio() {
+        let r = Readability::new(2);
+        assert_eq!(r.type_token_ratio(&words("a b c d")), 1.0);
+        assert_eq!(r.type_token_ratio(&words("a a a a")), 0.5);
+        // windows: (a b), (b a), (a a)
+        assert_eq!(r.type_token_ratio(&words("a b a a")), 5.0 / 6.0);
+
+        // shorter than window
+        let r = Readability::default();
+        assert_eq!(r.type_token_ratio(&words("a b a b")), 0.5);
+    }
+
+    #[test]
+    fn test_annotate() {
+        let mut doc = Document::new(
+            "The cat sat on the mat.".to_string(),
+            HashMap::new(),
+            Metadata::default(),
+        );
+        Readability::default().annotate(&mut doc);
+        assert_eq!(
+            serde_json::Value::Object(quality_signals(&doc)),
+            json!({"mean_word_length": 2.833, "ttr": 0.833})
+        );
+        assert_eq!(doc.metadata().annotation(), None);
+
+        let mut doc = Document::new("...".to_string(), HashMap::new(), Metadata::default());
+        Readability::default().annotate(&mut doc);
+        assert!(quality_signals(&doc).is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+/*! Register annotator
+
+Classifies documents by register/genre (news, forum, legal, code…) with a [ContentClassifier],
+so that register-filtered subsets can be extracted.
+
+Documents get a `register:<label>` annotation for their most probable register,
+if its score is at least the minimum score, and the score is added to their quality signals as `register:<label>`
+(see [super::signals]).
+Labels are the model's ones (without fastText's `__label__` prefix).
+!*/
+use std::path::Path;
+
+use log::debug;
+
+use crate::{error::Error, pipelines::oscardoc::types::Document};
+
+use super::{
+    classifier::{self, ContentClassifier},
+    signals::add_quality_signals,
+    Annotate,
+};
+
+pub struct Register {
+    classifier: Box<dyn ContentClassifier>,
+    min_score: f32,
+}
+
+impl Register {
+    /// Documents whose most probable register scores at least `min_score` are annotated.
+    pub fn new(classifier: Box<dyn ContentClassifier>, min_score: f32) -> Self {
+        Self {
+            classifier,
+            min_score,
+        }
+    }
+
+    /// Load a fastText model file or an ONNX model directory.
+    pub fn from_path(path: &Path, min_score: f32) -> Result<Self, Error> {
+        Ok(Self::new(classifier::load(path)?, min_score))
+    }
+}
+
+impl Annotate<Document> for Register {
+    fn annotate(&self, doc: &mut Document) {
+        let scores = match self.classifier.scores(doc.content()) {
+            Ok(scores) => scores,
+            Err(e) => {
+                debug!("Could not classify document register: {e:?}");
+                return;
+            }
+        };
+
+        if let Some((label, score)) = scores
+            .into_iter()
+            .filter(|(_, score)| *score >= self.min_score)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        {
+            let register = format!("register:{label}");
+            doc.metadata_mut().add_annotation(register.clone());
+            add_quality_signals(doc, [(register, f64::from(score))]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::{json, Value};
+
+    use crate::{
+        error::Error,
+        pipelines::oscardoc::types::{Document, Metadata},
+        transformers::{signals::quality_signals, Annotate, ContentClassifier},
+    };
+
+    use super::Register;
+
+    /// Scores documents containing "forum" as forums, and others as news.
+    struct Keyword;
+
+    impl ContentClassifier for Keyword {
+        fn scores(&self, content: &str) -> Result<Vec<(String, f32)>, Error> {
+            let forum = if content.contains("forum") { 0.8 } else { 0.4 };
+            Ok(vec![
+                ("news".to_string(), 1.0 - forum),
+                ("forum".to_string(), forum),
+            ])
+        }
+    }
+
+    /// Get the annotations and quality signals of an annotated document.
+    fn annotate(content: &str, min_score: f32) -> (Option<Vec<String>>, Value) {
+        let annotator = Register::new(Box::new(Keyword), min_score);
+        let mut doc = Document::new(content.to_string(), HashMap::new(), Metadata::default());
+        annotator.annotate(&mut doc);
+        (
+            doc.metadata().annotation().cloned(),
+            Value::Object(quality_signals(&doc)),
+        )
+    }
+
+    #[test]
+    fn test_register() {
+        assert_eq!(
+            annotate("Welcome to the forum, please introduce yourself.", 0.5),
+            (
+                Some(vec!["register:forum".to_string()]),
+                json!({"register:forum": 0.8})
+            )
+        );
+        assert_eq!(
+            annotate("The minister announced the budget.", 0.5),
+            (
+                Some(vec!["register:news".to_string()]),
+                json!({"register:news": 0.6})
+            )
+        );
+    }
+
+    #[test]
+    fn test_min_score() {
+        assert_eq!(
+            annotate("The minister announced the budget.", 0.7),
+            (None, json!({}))
+        );
+    }
+}
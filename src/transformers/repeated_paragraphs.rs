@@ -0,0 +1,202 @@
+/*! Repeated paragraph removal
+
+Collapses paragraphs that are repeated many times inside a single document (pagination artifacts, calendar widgets...),
+only keeping their first occurrence.
+
+Since WET content holds one paragraph per line, paragraphs are (non-empty) lines here.
+
+Kept line ranges are returned, so that removed lines are recorded in rebuild files
+and rebuilt documents match written ones.
+!*/
+use std::{collections::HashMap, ops::RangeInclusive};
+
+use itertools::Itertools;
+use log::debug;
+
+use crate::pipelines::oscardoc::types::{Document, Metadata};
+
+use super::Transform;
+
+/// Removes paragraphs that are present at least `min_repetitions` times in a document, keeping the first occurrence.
+///
+/// The number of removed lines is added as a `repeated_paragraphs:<n>` annotation,
+/// and sentence identifications are kept in sync with the new content.
+pub struct RepeatedParagraphs {
+    min_repetitions: usize,
+}
+
+impl RepeatedParagraphs {
+    pub fn new(min_repetitions: usize) -> Self {
+        Self { min_repetitions }
+    }
+
+    /// Get indices of lines to keep.
+    fn kept_lines(&self, content: &str) -> Vec<usize> {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for line in content.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            *counts.entry(line).or_insert(0) += 1;
+        }
+
+        let mut seen: HashMap<&str, bool> = HashMap::new();
+        content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| {
+                let line = line.trim();
+                if counts.get(line).copied().unwrap_or(0) < self.min_repetitions {
+                    return true;
+                }
+
+                // keep the first occurrence only
+                let already_seen = seen.entry(line).or_insert(false);
+                !std::mem::replace(already_seen, true)
+            })
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// Group sorted line indices into contiguous ranges.
+    fn to_ranges(indices: &[usize]) -> Vec<RangeInclusive<usize>> {
+        let mut ranges: Vec<RangeInclusive<usize>> = Vec::new();
+        for idx in indices {
+            match ranges.last_mut() {
+                Some(range) if range.end() + 1 == *idx => *range = *range.start()..=*idx,
+                _ => ranges.push(*idx..=*idx),
+            }
+        }
+        ranges
+    }
+}
+
+impl Default for RepeatedParagraphs {
+    /// Paragraphs are removed if they are present 3 times or more.
+    fn default() -> Self {
+        Self { min_repetitions: 3 }
+    }
+}
+
+impl Transform<Document> for RepeatedParagraphs {
+    fn transform(&self, doc: &mut Document) -> Vec<RangeInclusive<usize>> {
+        let nb_lines = doc.content().lines().count();
+        let kept = self.kept_lines(doc.content());
+        let ranges = Self::to_ranges(&kept);
+
+        let nb_removed = nb_lines - kept.len();
+        if nb_removed == 0 {
+            return ranges;
+        }
+
+        debug!(
+            "record {}: removed {} repeated lines",
+            doc.warc_id(),
+            nb_removed
+        );
+
+        let lines: Vec<&str> = doc.content().lines().collect();
+        let content = kept.iter().map(|idx| lines[*idx]).join("\n");
+
+        // keep sentence identifications aligned with content
+        let sentence_ids = doc.metadata().sentence_identifications();
+        let sentence_ids: Vec<_> = kept
+            .iter()
+            .filter_map(|idx| sentence_ids.get(*idx).cloned())
+            .collect();
+        let old = doc.metadata();
+        let mut metadata = Metadata::new(doc.identification(), &sentence_ids);
+        metadata.set_categories(old.categories().cloned());
+        metadata.set_harmful_pp(old.harmful_pp());
+        metadata.set_tlsh(old.tlsh().cloned());
+        if let Some(annotations) = old.annotation() {
+            for annotation in annotations {
+                metadata.add_annotation(annotation.clone());
+            }
+        }
+        metadata.add_annotation(format!("repeated_paragraphs:{nb_removed}"));
+
+        doc.set_content(content);
+        *doc.metadata_mut() = metadata;
+
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oscar_io::common::Identification;
+    use oxilangtag::LanguageTag;
+
+    use crate::{
+        pipelines::oscardoc::types::{Document, Metadata},
+        transformers::Transform,
+    };
+
+    use super::RepeatedParagraphs;
+
+    fn gen_document(content: &str) -> Document {
+        let id = Identification::new(LanguageTag::parse("en".to_string()).unwrap(), 1.0);
+        let ids = vec![Some(id.clone()); content.lines().count()];
+        let metadata = Metadata::new(&id, &ids);
+        Document::new(content.to_string(), HashMap::new(), metadata)
+    }
+
+    #[test]
+    fn test_no_repetition() {
+        let content = "foo\nbar\nbaz";
+        let mut doc = gen_document(content);
+        let ranges = RepeatedParagraphs::default().transform(&mut doc);
+
+        assert_eq!(doc.content(), content);
+        assert_eq!(ranges, vec![0..=2]);
+        assert!(doc.metadata().annotation().is_none());
+    }
+
+    #[test]
+    fn test_collapse() {
+        let content = "Next page\nfoo\nNext page\nbar\nNext page\nbaz";
+        let mut doc = gen_document(content);
+        let ranges = RepeatedParagraphs::default().transform(&mut doc);
+
+        assert_eq!(doc.content(), "Next page\nfoo\nbar\nbaz");
+        assert_eq!(ranges, vec![0..=1, 3..=3, 5..=5]);
+        assert_eq!(doc.metadata().sentence_identifications().len(), 4);
+        assert_eq!(
+            doc.metadata().annotation(),
+            Some(&vec!["repeated_paragraphs:2".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_metadata_kept() {
+        let mut doc = gen_document("Next page\nfoo\nNext page\nbar\nNext page\nbaz");
+        doc.metadata_mut()
+            .set_categories(Some(vec!["news".to_string()]));
+        doc.metadata_mut().set_harmful_pp(Some(12.5));
+        doc.metadata_mut().set_tlsh(Some("T1ABC".to_string()));
+        RepeatedParagraphs::default().transform(&mut doc);
+
+        assert_eq!(doc.content(), "Next page\nfoo\nbar\nbaz");
+        assert_eq!(doc.metadata().categories(), Some(&vec!["news".to_string()]));
+        assert_eq!(doc.metadata().harmful_pp(), Some(12.5));
+        assert_eq!(doc.metadata().tlsh(), Some(&"T1ABC".to_string()));
+    }
+
+    #[test]
+    fn test_below_threshold() {
+        let content = "Next page\nfoo\nNext page\nbar";
+        let mut doc = gen_document(content);
+        RepeatedParagraphs::default().transform(&mut doc);
+
+        assert_eq!(doc.content(), content);
+    }
+
+    #[test]
+    fn test_empty_lines_kept() {
+        let content = "foo\n\nbar\n\nbaz\n\nquux";
+        let mut doc = gen_document(content);
+        RepeatedParagraphs::new(2).transform(&mut doc);
+
+        assert_eq!(doc.content(), content);
+    }
+}
@@ -0,0 +1,235 @@
+/*! Script detection
+
+Annotates documents with their dominant Unicode script, as an ISO 15924 code (`script:Latn`, `script:Cyrl`, `script:Arab`…).
+Nearly every document gets one, so it isn't a quality flag (see [crate::filtering::annotation::QUALITY_FLAGS]):
+it neither routes documents to the annotated tree nor prevents noisy+tiny documents from being removed.
+
+Han text is told apart as `Hans` (simplified) or `Hant` (traditional) by counting characters
+that only exist in one of the forms (`Hani` if there's none), and as `Jpan` if it holds kana.
+
+Languages written in several scripts (see [MULTI_SCRIPT]) can optionally get their tag refined with the detected script
+(e.g. `sr` becomes `sr-Cyrl` or `sr-Latn`, `zh` becomes `zh-Hans` or `zh-Hant`),
+so that they are written in separate files. Tags that already have a script subtag are kept as is.
+!*/
+use log::debug;
+use oxilangtag::LanguageTag;
+use whatlang::Script;
+
+use super::Annotate;
+use crate::pipelines::oscardoc::types::{Document, Metadata};
+use oscar_io::common::Identification;
+
+/// Languages commonly written in more than one script, whose tags are refined.
+pub const MULTI_SCRIPT: &[&str] = &["az", "bs", "kk", "ku", "mn", "pa", "sr", "uz", "zh"];
+
+/// Frequent characters that only exist in simplified Chinese.
+const SIMPLIFIED: &str = "这个们来说时会国对为发后学过还没样经动长开关问见点现么书车马门话让东实";
+
+/// Their traditional counterparts.
+const TRADITIONAL: &str = "這個們來說時會國對為發後學過還沒樣經動長開關問見點現麼書車馬門話讓東實";
+
+pub struct ScriptDetector {
+    refine_tags: bool,
+}
+
+impl ScriptDetector {
+    /// Create a new detector, refining the tags of [MULTI_SCRIPT] languages if `refine_tags` is set.
+    pub fn new(refine_tags: bool) -> Self {
+        Self { refine_tags }
+    }
+
+    /// Get the ISO 15924 code of the dominant script of `content`.
+    fn detect(content: &str) -> Option<&'static str> {
+        let script = whatlang::detect_script(content)?;
+        Some(match script {
+            Script::Arabic => "Arab",
+            Script::Armenian => "Armn",
+            Script::Bengali => "Beng",
+            Script::Cyrillic => "Cyrl",
+            Script::Devanagari => "Deva",
+            Script::Ethiopic => "Ethi",
+            Script::Georgian => "Geor",
+            Script::Greek => "Grek",
+            Script::Gujarati => "Gujr",
+            Script::Gurmukhi => "Guru",
+            Script::Hangul => "Hang",
+            Script::Hebrew => "Hebr",
+            Script::Hiragana | Script::Katakana => "Jpan",
+            Script::Kannada => "Knda",
+            Script::Khmer => "Khmr",
+            Script::Latin => "Latn",
+            Script::Malayalam => "Mlym",
+            Script::Mandarin => Self::han_variant(content),
+            Script::Myanmar => "Mymr",
+            Script::Oriya => "Orya",
+            Script::Sinhala => "Sinh",
+            Script::Tamil => "Taml",
+            Script::Telugu => "Telu",
+            Script::Thai => "Thai",
+        })
+    }
+
+    /// Tell apart Japanese, simplified and traditional Chinese in Han text.
+    fn han_variant(content: &str) -> &'static str {
+        let (mut simplified, mut traditional) = (0, 0);
+        for c in content.chars() {
+            if matches!(c, '\u{3040}'..='\u{30ff}') {
+                return "Jpan";
+            }
+            if SIMPLIFIED.contains(c) {
+                simplified += 1;
+            } else if TRADITIONAL.contains(c) {
+                traditional += 1;
+            }
+        }
+
+        match simplified.cmp(&traditional) {
+            std::cmp::Ordering::Greater => "Hans",
+            std::cmp::Ordering::Less => "Hant",
+            std::cmp::Ordering::Equal => "Hani",
+        }
+    }
+
+    /// Add the script subtag to `tag` if its language is written in several scripts and it has no script yet.
+    fn refine(tag: &LanguageTag<String>, script: &str) -> Option<LanguageTag<String>> {
+        if tag.script().is_some() || !MULTI_SCRIPT.contains(&tag.primary_language()) {
+            return None;
+        }
+        // Hani/Jpan don't tell a Chinese variant apart
+        if tag.primary_language() == "zh" && !matches!(script, "Hans" | "Hant") {
+            return None;
+        }
+
+        // the script subtag directly follows the language subtags
+        let (language, rest) = tag.as_str().split_at(tag.full_language().len());
+        match LanguageTag::parse_and_normalize(&format!("{language}-{script}{rest}")) {
+            Ok(refined) => Some(refined),
+            Err(e) => {
+                debug!("Could not add script {script} to {tag}: {e:?}");
+                None
+            }
+        }
+    }
+
+    /// Replace the document identification label.
+    ///
+    /// Identifications can't be changed in [Metadata], so it is rebuilt.
+    fn set_label(doc: &mut Document, label: LanguageTag<String>) {
+        let old = doc.metadata();
+        let identification = Identification::new(label, *doc.identification().prob());
+        let mut metadata = Metadata::new(&identification, old.sentence_identifications());
+        metadata.set_categories(old.categories().cloned());
+        metadata.set_harmful_pp(old.harmful_pp());
+        metadata.set_tlsh(old.tlsh().cloned());
+        for annotation in old.annotation().into_iter().flatten() {
+            metadata.add_annotation(annotation.clone());
+        }
+
+        *doc.metadata_mut() = metadata;
+    }
+}
+
+impl Default for ScriptDetector {
+    /// Only annotates documents.
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+impl Annotate<Document> for ScriptDetector {
+    fn annotate(&self, doc: &mut Document) {
+        let script = match Self::detect(doc.content()) {
+            Some(script) => script,
+            None => return,
+        };
+
+        if self.refine_tags {
+            if let Some(label) = Self::refine(doc.identification().label(), script) {
+                Self::set_label(doc, label);
+            }
+        }
+        doc.metadata_mut()
+            .add_annotation(format!("script:{script}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oscar_io::common::Identification;
+    use oxilangtag::LanguageTag;
+
+    use crate::{
+        filtering::annotation::{has_types, AnnotationSelector},
+        pipelines::oscardoc::types::{Document, Metadata},
+        transformers::Annotate,
+    };
+
+    use super::ScriptDetector;
+
+    fn doc(content: &str, lang: &str) -> Document {
+        let id = Identification::new(LanguageTag::parse(lang.to_string()).unwrap(), 0.9);
+        Document::new(
+            content.to_string(),
+            HashMap::new(),
+            Metadata::new(&id, &[Some(id.clone())]),
+        )
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(ScriptDetector::detect("Hello, world!"), Some("Latn"));
+        assert_eq!(ScriptDetector::detect("Здраво свете"), Some("Cyrl"));
+        assert_eq!(ScriptDetector::detect("مرحبا بالعالم"), Some("Arab"));
+        assert_eq!(ScriptDetector::detect("这个国家的人们说"), Some("Hans"));
+        assert_eq!(ScriptDetector::detect("這個國家的人們說"), Some("Hant"));
+        assert_eq!(ScriptDetector::detect("日本語のテキストです"), Some("Jpan"));
+        assert_eq!(ScriptDetector::detect("1234 !?"), None);
+    }
+
+    #[test]
+    fn test_annotate() {
+        let mut d = doc("Ово је реченица на српском језику.", "sr");
+        ScriptDetector::default().annotate(&mut d);
+        assert_eq!(d.identification().label().as_str(), "sr");
+        assert_eq!(
+            d.metadata().annotation(),
+            Some(&vec!["script:Cyrl".to_string()])
+        );
+        assert!(!AnnotationSelector::default().matches(&d));
+
+        let mut d = doc("Hello", "en");
+        d.metadata_mut().add_annotation("noisy".to_string());
+        d.metadata_mut().add_annotation("tiny".to_string());
+        ScriptDetector::default().annotate(&mut d);
+        assert!(has_types(&d, &["noisy", "tiny"]));
+    }
+
+    #[test]
+    fn test_refine() {
+        let detector = ScriptDetector::new(true);
+
+        let mut d = doc("Ovo je rečenica na srpskom jeziku.", "sr");
+        d.metadata_mut().add_annotation("noisy".to_string());
+        detector.annotate(&mut d);
+        assert_eq!(d.identification().label().as_str(), "sr-Latn");
+        assert_eq!(*d.identification().prob(), 0.9);
+        assert_eq!(
+            d.metadata().annotation(),
+            Some(&vec!["noisy".to_string(), "script:Latn".to_string()])
+        );
+
+        let mut d = doc("這個國家的人們說", "zh-TW");
+        detector.annotate(&mut d);
+        assert_eq!(d.identification().label().as_str(), "zh-Hant-TW");
+
+        // single-script languages and tags with a script are kept
+        let mut d = doc("This is English.", "en");
+        detector.annotate(&mut d);
+        assert_eq!(d.identification().label().as_str(), "en");
+        let mut d = doc("Ovo je rečenica.", "sr-Cyrl");
+        detector.annotate(&mut d);
+        assert_eq!(d.identification().label().as_str(), "sr-Cyrl");
+    }
+}
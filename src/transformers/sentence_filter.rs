@@ -8,7 +8,10 @@ use warc::BufferedBody;
 use warc::Record;
 
 use crate::{
-    filtering::{sentence::Length, Filter},
+    filtering::{
+        sentence::{Length, LineValidity},
+        Filter,
+    },
     pipelines::oscardoc::types::Document,
 };
 
@@ -176,17 +179,22 @@ impl Default for Conv {
 /// xxxxxxxxxxxxxxxxxxxxxxxxxxxxxx
 /// ```
 pub struct RemoveShortSentences {
-    filter: Length,
+    filter: LineValidity,
 }
 
 impl RemoveShortSentences {
     /// Use a custom min_length for long sentences.
     fn new(min_length: usize) -> Self {
         Self {
-            filter: Length::with_min_size(min_length),
+            filter: LineValidity::with_min_chars(min_length),
         }
     }
 
+    /// Use a custom [LineValidity] predicate to decide which start/end lines are removed.
+    pub fn with_line_validity(filter: LineValidity) -> Self {
+        Self { filter }
+    }
+
     /// extracts indices of the document content, ignoring short lines at start/end.
     fn extract_indices<'a>(&self, lines: std::str::Lines<'a>) -> Vec<(usize, &'a str)> {
         let s: Vec<(usize, &str)> = lines
@@ -219,7 +227,7 @@ impl RemoveShortSentences {
     }
     /// get filter detection threshold
     fn filter_min_length(&self) -> &usize {
-        self.filter.min_size()
+        self.filter.min_chars()
     }
 }
 
@@ -263,7 +271,7 @@ impl Default for RemoveShortSentences {
 mod tests {
     use std::collections::HashMap;
 
-    use crate::filtering::sentence::Length;
+    use crate::filtering::sentence::{Length, LineValidity};
     use crate::pipelines::oscardoc::types::{Document, Metadata};
     use crate::transformers::{Annotate, Transform};
 
@@ -392,6 +400,21 @@ baz
         let rss = RemoveShortSentences::default();
         assert_eq!(rss.filter_min_length(), &100);
     }
+
+    #[test]
+    fn test_rss_line_validity() {
+        let content = "12/04/2022 13:45:00 +0200\nthis one is long enough\nthis one too, really\n12/04/2022 13:45:00 +0200".to_string();
+        let mut doc = Document::new(content, HashMap::new(), Metadata::default());
+        let rss = RemoveShortSentences::with_line_validity(LineValidity::new(10, Some(0.5), None));
+
+        let ranges = rss.transform(&mut doc);
+
+        assert_eq!(ranges, vec![1..=2]);
+        assert_eq!(
+            doc.content(),
+            "this one is long enough\nthis one too, really"
+        );
+    }
     #[test]
     fn test_rss() {
         let (mut doc, expected_content) = gen_valid();
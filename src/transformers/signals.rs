@@ -0,0 +1,93 @@
+/*! Quality signals
+
+Numeric metrics of documents (readability, classifier scores…), kept apart from annotations.
+
+Annotations are quality warnings: the noisy+tiny removal, annotation policies and the annotated tree all look at them,
+so metrics that are present on every document don't belong there.
+Since [Metadata](crate::pipelines::oscardoc::types::Metadata) has no room for other fields,
+signals are stored as a JSON object in the `quality-signals` header of documents:
+
+```json
+"warc_headers": {
+    "quality-signals": "{\"mean_word_length\":4.21,\"ttr\":0.613}",
+    ...
+}
+```
+
+Signals are added after WARC headers are filtered, so they're kept whatever the header retention.
+Rebuilt corpora don't have them, since headers are taken from shards.
+!*/
+use serde_json::{Map, Number, Value};
+use warc::WarcHeader;
+
+use crate::pipelines::oscardoc::types::Document;
+
+/// Name of the header holding quality signals.
+pub const QUALITY_SIGNALS_HEADER: &str = "quality-signals";
+
+fn header() -> WarcHeader {
+    WarcHeader::Unknown(QUALITY_SIGNALS_HEADER.to_string())
+}
+
+/// Get the quality signals of a document (empty if it has none).
+pub fn quality_signals(doc: &Document) -> Map<String, Value> {
+    doc.warc_headers()
+        .get(&header())
+        .and_then(|signals| serde_json::from_slice(signals).ok())
+        .unwrap_or_default()
+}
+
+/// Add quality signals to a document, replacing the ones with the same name.
+///
+/// Values are rounded to 3 decimals to keep headers small, and values that aren't finite are skipped.
+/// Headers can't be changed in place, so the document is rebuilt.
+pub fn add_quality_signals<I>(doc: &mut Document, signals: I)
+where
+    I: IntoIterator<Item = (String, f64)>,
+{
+    let mut all = quality_signals(doc);
+    for (name, value) in signals {
+        if let Some(value) = Number::from_f64((value * 1000.0).round() / 1000.0) {
+            all.insert(name, Value::Number(value));
+        }
+    }
+
+    let mut headers = doc.warc_headers().clone();
+    headers.insert(header(), Value::Object(all).to_string().into_bytes());
+    *doc = Document::new(doc.content().clone(), headers, doc.metadata().clone());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use crate::pipelines::oscardoc::types::{Document, Metadata};
+
+    use super::{add_quality_signals, quality_signals};
+
+    #[test]
+    fn test_add_quality_signals() {
+        let mut doc = Document::new("foo".to_string(), HashMap::new(), Metadata::default());
+        assert!(quality_signals(&doc).is_empty());
+
+        add_quality_signals(&mut doc, [("ttr".to_string(), 0.5)]);
+        assert_eq!(quality_signals(&doc)["ttr"], 0.5);
+        add_quality_signals(
+            &mut doc,
+            [
+                ("ttr".to_string(), 0.2504),
+                ("mean_word_length".to_string(), 3.0),
+                ("nan".to_string(), f64::NAN),
+            ],
+        );
+
+        assert_eq!(
+            serde_json::Value::Object(quality_signals(&doc)),
+            json!({"ttr": 0.25, "mean_word_length": 3.0})
+        );
+        assert_eq!(doc.content(), "foo");
+        assert!(doc.metadata().annotation().is_none());
+    }
+}